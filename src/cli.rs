@@ -1,6 +1,66 @@
 //! コマンドラインインターフェース
+//!
+//! オプションの優先順位: コマンドライン > モデルの `solve:` ブロック > 環境変数
+//! `OPTICA_ARGS` > 設定ファイル `.optica.toml`（カレントディレクトリ、無ければ `$HOME`）
+//! > 組み込みの既定値。
 
 use crate::config;
+use crate::solver::ConstraintMode;
+use std::path::PathBuf;
+
+/// 設定ファイル名
+const CONFIG_FILE: &str = ".optica.toml";
+/// 既定オプションを渡す環境変数
+const ARGS_ENV: &str = "OPTICA_ARGS";
+/// 値を1つ取るフラグ
+const VALUE_FLAGS: &[&str] = &[
+    "-m",
+    "--method",
+    "-i",
+    "--iter",
+    "-t",
+    "--threads",
+    "--islands",
+    "--cp-fallback",
+    "--trace",
+    "--dump-solution",
+    "--seed",
+    "--warm-start",
+    "--warm-start-method",
+    "--warm-csv",
+    "--methods",
+    "--seeds",
+    "--iters",
+    "--stability",
+    "--format",
+    "--solution-format",
+    "--restart-fraction",
+    "--diversity-threshold",
+    "--restart-threshold",
+    "--restart-interval",
+    "--migration-interval",
+    "--resample",
+    "--hybrid-split",
+    "--binding-tol",
+    "--scenario-dir",
+    "--data",
+    "--objective-history-window",
+    "--objective-bound",
+    "--stream-interval",
+    "--progress-every",
+    "--timeout",
+    "--patience",
+    "--constraint-mode",
+    "--pareto",
+    "--pop-size",
+    "--de-f",
+    "--de-cr",
+    "--pso-w",
+    "--pso-c1",
+    "--pso-c2",
+    "--lower-bound",
+    "--max-generations-without-feasible",
+];
 
 /// コマンドライン引数
 #[derive(Debug, Clone)]
@@ -8,106 +68,863 @@ pub struct Args {
     pub command: Command,
     pub method: String,
     pub max_iter: usize,
+    /// 経過時間の上限（`--timeout <秒>`）。反復数の上限と早い方で止める
+    pub time_limit: Option<std::time::Duration>,
+    /// DE/PSO: 最良値が改善しないまま続いたら打ち切る世代数
+    pub patience: Option<usize>,
+    /// DEの置き換えでの制約の扱い（`--constraint-mode penalty|deb`）
+    pub constraint_mode: ConstraintMode,
+    /// DEの集団サイズとPSOの粒子数（未指定なら `config` の既定値）
+    pub pop_size: Option<usize>,
+    /// DEの差分の重み F と交叉率 CR
+    pub de_f: Option<f64>,
+    pub de_cr: Option<f64>,
+    /// PSOの慣性の初期値と加速係数
+    pub pso_w: Option<f64>,
+    pub pso_c1: Option<f64>,
+    pub pso_c2: Option<f64>,
     pub threads: usize,
+    /// スレッド数が明示された（`-t N`、設定ファイル・環境変数・`solve:` ブロックを含む）か。
+    /// 未指定と `-t auto` では問題の大きさから決める
+    pub threads_explicit: bool,
+    /// DEの島数（0 = スレッド数に合わせる）
+    pub islands: usize,
     pub verbose: bool,
     pub quiet: bool,
+    /// CP-SATが使えない場合の挙動（auto, none, de, pso）
+    pub cp_fallback: String,
+    /// 乱数の種（`Some(0)` はOSのエントロピーから毎回引く、未指定は固定の既定値）
+    pub seed: Option<u64>,
+    /// 初期解のJSON
+    pub warm_start: Option<String>,
+    /// 初期集団のCSV（1行 = 1候補解）
+    pub warm_csv: Option<String>,
+    /// ウォームスタート時に使うメソッド（未指定なら `method` と同じ）
+    pub warm_start_method: Option<String>,
+    /// DEの多様性リスタート: 再初期化する個体の割合と発動する多様性（どちらかの指定で有効）
+    pub restart_fraction: Option<f64>,
+    pub diversity_threshold: Option<f64>,
+    /// DEの多様性リスタート: 多様性を調べる間隔（世代数）
+    pub restart_interval: Option<usize>,
+    /// 島モデルDE: 島の間で最良解をやりとりする間隔（世代数、0なら行わない）
+    pub migration_interval: usize,
+    /// DE: 結果をスレッド数に依存させない（島数を固定し、移住しない）
+    pub deterministic: bool,
+    /// DE/PSOの選択で評価を平均する回数（ノイズのある目的向け）
+    pub resample: usize,
+    /// hybrid: 前段（DEによる探索）に割り当てる反復の割合
+    pub hybrid_split: f64,
+    /// hybrid: 最後に Nelder-Mead で仕上げる
+    pub polish: bool,
+    /// 多目的: スカラー化せずパレートフロント全体を求める（`--pareto front`）
+    pub pareto_front: bool,
+    /// 収束状況（converged / slowly improving / stalled）を判定する窓の反復数
+    pub history_window: Option<usize>,
+    /// 既知の目的値の限界（最小化なら下界、最大化なら上界）。指定すると最適性ギャップを表示する
+    pub objective_bound: Option<f64>,
+    /// 実行可能な個体が現れないまま経過したら打ち切る世代数（未指定なら打ち切らない）
+    pub max_gen_without_feasible: Option<usize>,
+    /// 適合度をキャッシュする（決定的で重い目的向け）
+    pub cache_fitness: bool,
+    /// 反復ごとの最良目的値をCSVで書き出す先
+    pub trace: Option<String>,
+    /// 最良解を `{name: value}` のJSONで書き出す先（`--warm-start` でそのまま読める）
+    pub dump_solution: Option<String>,
+    /// 求解中の進捗と最終結果をJSONL（1行1レコード）で標準出力へ流す
+    pub stream_json: bool,
+    /// `stream_json` の進捗レコードの間隔（反復数）
+    pub stream_interval: usize,
+    /// 進捗行（標準エラー）の間隔。未指定なら `-v` のときだけ既定の間隔で出す
+    pub progress_every: Option<usize>,
+    /// 最良解での制約ごとのペナルティ内訳を表示する
+    pub penalty_report: bool,
+    /// 最良解での目的関数・制約左辺の勾配を表示する
+    pub gradient_report: bool,
+    /// 最良解で等号が成り立つ（binding）不等式制約を表示する
+    pub binding_report: bool,
+    /// binding とみなす |左辺 - 右辺| の閾値
+    pub binding_tol: f64,
+    /// 最良解が実行不能なら終了コード2で終える
+    pub fail_on_infeasible: bool,
+    /// 目的の無いモデル（既定の Sphere 関数で解かれる）をエラーにする
+    pub require_objective: bool,
+    /// CP-SATの前に短いヒューリスティック探索を行い、その解をヒントにする
+    pub heuristic_then_exact: bool,
+    /// bench: 次元を掃引してスケーリング曲線を出す
+    pub solver_scaling: bool,
+    /// bench / experiment: 結果をCSVで出力する
+    pub csv: bool,
+    /// fmt: 標準出力ではなくファイルを書き換える
+    pub write: bool,
+    /// experiment: 試すメソッド・種・反復数（空なら通常の指定を1つだけ使う）
+    pub methods: Vec<String>,
+    pub seeds: Vec<u64>,
+    pub iters: Vec<usize>,
+    /// scenarios: シナリオJSONを置いたディレクトリ
+    pub scenario_dir: Option<String>,
+    /// パラメータのJSON（サイドカーJSONの後に読み、同じパラメータは上書きする）
+    pub data: Option<String>,
+    /// 種を変えて解く回数（0 = 通常の1回の求解）
+    pub stability: usize,
+    /// レポートの出力形式
+    pub format: OutputFormat,
+    /// 求解結果（解）の出力形式
+    pub solution_format: SolutionFormat,
+    /// 適用済みのフラグ層（既定値層, コマンドライン層）。モデル側の指定を差し込む際に使う
+    layers: (Vec<String>, Vec<String>),
+}
+
+/// 出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// 求解結果の出力形式（`flat` は変数順の値だけを空白区切りで1行に出す）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolutionFormat {
+    Text,
+    Flat,
 }
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Solve { file: String },
     Bench { dim: usize },
+    Experiment { file: String },
+    Scenarios { file: String },
+    Fmt { file: String },
     Repl,
     Version,
     Help,
 }
 
 impl Args {
-    pub fn parse(args: &[String]) -> Result<Self, String> {
-        if args.is_empty() {
-            return Ok(Args {
-                command: Command::Help,
-                method: "auto".to_string(),
-                max_iter: config::DEFAULT_MAX_ITER,
-                threads: num_cpus(),
-                verbose: false,
-                quiet: false,
-            });
+    /// 組み込みの既定値
+    fn with_command(command: Command) -> Self {
+        Args {
+            command,
+            method: "auto".to_string(),
+            max_iter: config::DEFAULT_MAX_ITER,
+            time_limit: None,
+            patience: None,
+            constraint_mode: ConstraintMode::Penalty,
+            pop_size: None,
+            de_f: None,
+            de_cr: None,
+            pso_w: None,
+            pso_c1: None,
+            pso_c2: None,
+            threads: num_cpus(),
+            threads_explicit: false,
+            islands: 0,
+            verbose: false,
+            quiet: false,
+            cp_fallback: "auto".to_string(),
+            seed: None,
+            warm_start: None,
+            warm_csv: None,
+            warm_start_method: None,
+            restart_fraction: None,
+            diversity_threshold: None,
+            restart_interval: None,
+            migration_interval: 0,
+            deterministic: false,
+            resample: 1,
+            hybrid_split: config::HYBRID_SPLIT,
+            polish: false,
+            pareto_front: false,
+            history_window: None,
+            objective_bound: None,
+            max_gen_without_feasible: None,
+            cache_fitness: false,
+            trace: None,
+            dump_solution: None,
+            stream_json: false,
+            stream_interval: config::STREAM_INTERVAL,
+            progress_every: None,
+            penalty_report: false,
+            gradient_report: false,
+            binding_report: false,
+            binding_tol: config::BINDING_TOL,
+            fail_on_infeasible: false,
+            require_objective: false,
+            heuristic_then_exact: false,
+            solver_scaling: false,
+            csv: false,
+            write: false,
+            methods: Vec::new(),
+            seeds: Vec::new(),
+            iters: Vec::new(),
+            scenario_dir: None,
+            data: None,
+            stability: 0,
+            format: OutputFormat::Text,
+            solution_format: SolutionFormat::Text,
+            layers: (Vec::new(), Vec::new()),
         }
+    }
+
+    /// 設定ファイル・環境変数を既定値として適用したうえでコマンドラインを解析
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let env = std::env::var(ARGS_ENV).ok();
+        let defaults = default_flags(config_file_flags()?, env.as_deref());
+        Self::parse_layered(args, &defaults)
+    }
 
-        let cmd_str = &args[0];
-        let command = match cmd_str.as_str() {
-            "solve" => {
-                if args.len() < 2 {
-                    return Err("error: no input file".to_string());
+    /// `defaults` を先に適用し、`args` のフラグで上書きする
+    ///
+    /// フラグと位置引数は順不同（`optica -v model.optica` と `optica model.optica -v` は同じ）。
+    pub(crate) fn parse_layered(args: &[String], defaults: &[String]) -> Result<Self, String> {
+        // 位置引数とフラグを分離（値を取るフラグは次の引数も消費する）
+        let mut positional: Vec<&String> = Vec::new();
+        let mut flags: Vec<String> = Vec::new();
+        let mut i = 0;
+        while i < args.len() {
+            let a = &args[i];
+            if a.starts_with('-') && a.len() > 1 {
+                flags.push(a.clone());
+                if VALUE_FLAGS.contains(&a.as_str()) {
+                    if let Some(v) = args.get(i + 1) {
+                        flags.push(v.clone());
+                        i += 1;
+                    }
                 }
-                Command::Solve {
-                    file: args[1].clone(),
+            } else {
+                positional.push(a);
+            }
+            i += 1;
+        }
+
+        let command = match positional.first().map(|s| s.as_str()) {
+            None => {
+                // 位置引数なし: `-v`/`--version` 単独はバージョン表示、それ以外はヘルプ
+                if flags.iter().any(|f| f == "-v" || f == "--version") {
+                    Command::Version
+                } else {
+                    Command::Help
                 }
             }
-            "bench" => {
-                let dim = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(100);
+            Some("solve") => match positional.get(1) {
+                Some(file) => Command::Solve {
+                    file: (*file).clone(),
+                },
+                None => return Err("error: no input file".to_string()),
+            },
+            Some("bench") => {
+                let dim = positional
+                    .get(1)
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(100);
                 Command::Bench { dim }
             }
-            "repl" => Command::Repl,
-            "version" | "-v" | "--version" => Command::Version,
-            "help" | "-h" | "--help" => Command::Help,
-            _ => Command::Solve {
-                file: cmd_str.clone(),
+            Some("experiment") => match positional.get(1) {
+                Some(file) => Command::Experiment {
+                    file: (*file).clone(),
+                },
+                None => return Err("error: no input file".to_string()),
+            },
+            Some("scenarios") => match positional.get(1) {
+                Some(file) => Command::Scenarios {
+                    file: (*file).clone(),
+                },
+                None => return Err("error: no input file".to_string()),
+            },
+            Some("fmt") => match positional.get(1) {
+                Some(file) => Command::Fmt {
+                    file: (*file).clone(),
+                },
+                None => return Err("error: no input file".to_string()),
+            },
+            Some("repl") => Command::Repl,
+            Some("version") => Command::Version,
+            Some("help") => Command::Help,
+            Some(file) => Command::Solve {
+                file: file.to_string(),
             },
         };
+        if flags.iter().any(|f| f == "-h" || f == "--help") {
+            return Ok(Self::with_command(Command::Help));
+        }
+        if flags.iter().any(|f| f == "--version") {
+            return Ok(Self::with_command(Command::Version));
+        }
 
-        let mut method = "auto".to_string();
-        let mut max_iter = config::DEFAULT_MAX_ITER;
-        let mut threads = num_cpus();
-        let mut verbose = false;
-        let mut quiet = false;
+        let mut parsed = Self::with_command(command);
+        parsed.apply_flags(defaults)?;
+        parsed.apply_flags(&flags)?;
+        parsed.layers = (defaults.to_vec(), flags);
+        Ok(parsed)
+    }
 
-        let start_idx = match command {
-            Command::Solve { .. } if !args[0].starts_with('-') => 2,
-            _ => 1,
-        };
+    /// モデルファイル側の既定値（`solve:` ブロック）を差し込んだ引数を作る
+    ///
+    /// 優先順位: コマンドライン > モデル > 環境変数 > 設定ファイル > 組み込み既定値
+    pub fn with_model_defaults(&self, model_flags: &[String]) -> Result<Self, String> {
+        let mut merged = Self::with_command(self.command.clone());
+        merged.apply_flags(&self.layers.0)?;
+        merged.apply_flags(model_flags)?;
+        merged.apply_flags(&self.layers.1)?;
+        merged.layers = self.layers.clone();
+        Ok(merged)
+    }
 
-        let mut i = start_idx;
-        while i < args.len() {
-            match args[i].as_str() {
+    /// フラグ列を順に適用（後のものが優先）
+    fn apply_flags(&mut self, flags: &[String]) -> Result<(), String> {
+        let mut i = 0;
+        while i < flags.len() {
+            match flags[i].as_str() {
                 "-m" | "--method" => {
-                    method = args
+                    self.method = flags
                         .get(i + 1)
                         .cloned()
                         .unwrap_or_else(|| "auto".to_string());
                     i += 1;
                 }
                 "-i" | "--iter" => {
-                    max_iter = args
+                    self.max_iter = flags
                         .get(i + 1)
                         .and_then(|s| s.parse().ok())
                         .unwrap_or(config::DEFAULT_MAX_ITER);
                     i += 1;
                 }
                 "-t" | "--threads" => {
-                    threads = args
-                        .get(i + 1)
-                        .and_then(|s| s.parse().ok())
-                        .unwrap_or(num_cpus());
+                    match flags.get(i + 1).and_then(|s| s.parse().ok()) {
+                        Some(n) => {
+                            self.threads = n;
+                            self.threads_explicit = true;
+                        }
+                        // auto（または解釈できない値）
+                        None => {
+                            self.threads = num_cpus();
+                            self.threads_explicit = false;
+                        }
+                    }
+                    i += 1;
+                }
+                "--islands" => {
+                    self.islands = flags.get(i + 1).and_then(|s| s.parse().ok()).unwrap_or(0);
+                    i += 1;
+                }
+                "--cp-fallback" => {
+                    let v = flags.get(i + 1).cloned().unwrap_or_default();
+                    if !matches!(v.as_str(), "auto" | "none" | "de" | "pso") {
+                        return Err(format!(
+                            "error: invalid --cp-fallback '{}' (expected auto, none, de, pso)",
+                            v
+                        ));
+                    }
+                    self.cp_fallback = v;
+                    i += 1;
+                }
+                "--seed" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    self.seed = Some(match v {
+                        "random" => 0,
+                        _ => v.parse().map_err(|_| {
+                            format!("error: invalid --seed '{}' (expected integer or random)", v)
+                        })?,
+                    });
+                    i += 1;
+                }
+                "--methods" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    let methods: Vec<String> = parse_list(v, "--methods")?;
+                    // experiment はヒューリスティックを単一スレッドで回すだけなので cp-sat 等は不可
+                    if let Some(m) = methods
+                        .iter()
+                        .find(|m| *m != "auto" && !optica::HEURISTIC_METHODS.contains(&m.as_str()))
+                    {
+                        return Err(format!(
+                            "error: invalid --methods value '{}' (expected auto, {})",
+                            m,
+                            optica::HEURISTIC_METHODS.join(", ")
+                        ));
+                    }
+                    self.methods = methods;
+                    i += 1;
+                }
+                "--seeds" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    self.seeds = match v.split_once(':') {
+                        Some((a, b)) => match (a.trim().parse::<u64>(), b.trim().parse::<u64>()) {
+                            (Ok(a), Ok(b)) if a <= b => (a..=b).collect(),
+                            _ => return Err(format!("error: invalid --seeds value '{}'", v)),
+                        },
+                        None => parse_list(v, "--seeds")?,
+                    };
+                    i += 1;
+                }
+                "--iters" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    self.iters = parse_list(v, "--iters")?;
+                    i += 1;
+                }
+                "--warm-start" => {
+                    self.warm_start = flags.get(i + 1).cloned();
+                    i += 1;
+                }
+                "--warm-csv" => {
+                    self.warm_csv = flags.get(i + 1).cloned();
+                    i += 1;
+                }
+                "--warm-start-method" => {
+                    let v = flags.get(i + 1).cloned().unwrap_or_default();
+                    let known = matches!(v.as_str(), "auto" | "cp-sat")
+                        || optica::HEURISTIC_METHODS.contains(&v.as_str());
+                    if !known {
+                        return Err(format!(
+                            "error: invalid --warm-start-method '{}' (expected auto, {}, cp-sat)",
+                            v,
+                            optica::HEURISTIC_METHODS.join(", ")
+                        ));
+                    }
+                    self.warm_start_method = Some(v);
+                    i += 1;
+                }
+                "--stability" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    self.stability = v
+                        .parse()
+                        .map_err(|_| format!("error: invalid --stability '{}'", v))?;
+                    i += 1;
+                }
+                "--solution-format" => {
+                    self.solution_format = match flags.get(i + 1).map(|s| s.as_str()) {
+                        Some("text") => SolutionFormat::Text,
+                        Some("flat") => SolutionFormat::Flat,
+                        v => {
+                            return Err(format!(
+                                "error: invalid --solution-format '{}' (expected text, flat)",
+                                v.unwrap_or("")
+                            ))
+                        }
+                    };
+                    i += 1;
+                }
+                "--format" => {
+                    self.format = match flags.get(i + 1).map(|s| s.as_str()) {
+                        Some("text") => OutputFormat::Text,
+                        Some("json") => OutputFormat::Json,
+                        Some("csv") => OutputFormat::Csv,
+                        v => {
+                            return Err(format!(
+                                "error: invalid --format '{}' (expected text, json, csv)",
+                                v.unwrap_or("")
+                            ))
+                        }
+                    };
+                    i += 1;
+                }
+                "--restart-fraction" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(f) if f > 0.0 && f <= 1.0 => self.restart_fraction = Some(f),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --restart-fraction '{}' (expected 0 < f <= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--diversity-threshold" | "--restart-threshold" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(d) if d.is_finite() && d >= 0.0 => self.diversity_threshold = Some(d),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid {} '{}' (expected d >= 0)",
+                                flags[i], v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--migration-interval" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(k) => self.migration_interval = k,
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --migration-interval '{}' (expected integer >= 0)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--restart-interval" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(k) if k >= 1 => self.restart_interval = Some(k),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --restart-interval '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--resample" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(k) if k >= 1 => self.resample = k,
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --resample '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--hybrid-split" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(f) if f > 0.0 && f < 1.0 => self.hybrid_split = f,
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --hybrid-split '{}' (expected 0 < f < 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--binding-tol" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(t) if t.is_finite() && t >= 0.0 => self.binding_tol = t,
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --binding-tol '{}' (expected t >= 0)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--scenario-dir" => {
+                    self.scenario_dir = flags.get(i + 1).cloned();
+                    i += 1;
+                }
+                "--data" => {
+                    self.data = flags.get(i + 1).cloned();
+                    i += 1;
+                }
+                "--objective-history-window" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(w) if w >= 1 => self.history_window = Some(w),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --objective-history-window '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--objective-bound" | "--lower-bound" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(b) if b.is_finite() => self.objective_bound = Some(b),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid {} '{}' (expected a finite number)",
+                                flags[i], v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--max-generations-without-feasible" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(n) if n >= 1 => self.max_gen_without_feasible = Some(n),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --max-generations-without-feasible '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--trace" => {
+                    self.trace = flags.get(i + 1).cloned();
+                    i += 1;
+                }
+                "--dump-solution" => {
+                    self.dump_solution = flags.get(i + 1).cloned();
+                    i += 1;
+                }
+                "--timeout" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(t) if t.is_finite() && t > 0.0 => {
+                            self.time_limit = Some(std::time::Duration::from_secs_f64(t))
+                        }
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --timeout '{}' (expected seconds > 0)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--patience" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(n) if n >= 1 => self.patience = Some(n),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --patience '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
                     i += 1;
                 }
-                "-v" | "--verbose" => verbose = true,
-                "-q" | "--quiet" => quiet = true,
-                _ => {}
+                "--constraint-mode" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    self.constraint_mode = match v {
+                        "penalty" => ConstraintMode::Penalty,
+                        "deb" => ConstraintMode::Deb,
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --constraint-mode '{}' (expected penalty or deb)",
+                                v
+                            ))
+                        }
+                    };
+                    i += 1;
+                }
+                "--pareto" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    if v != "front" {
+                        return Err(format!("error: invalid --pareto '{}' (expected front)", v));
+                    }
+                    self.pareto_front = true;
+                    i += 1;
+                }
+                "--pop-size" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(n) if n >= 4 => self.pop_size = Some(n),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --pop-size '{}' (expected integer >= 4)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--de-f" | "--pso-w" | "--pso-c1" | "--pso-c2" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    let x = match v.parse::<f64>() {
+                        Ok(x) if x.is_finite() && x > 0.0 => Some(x),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid {} '{}' (expected a number > 0)",
+                                flags[i], v
+                            ))
+                        }
+                    };
+                    match flags[i].as_str() {
+                        "--de-f" => self.de_f = x,
+                        "--pso-w" => self.pso_w = x,
+                        "--pso-c1" => self.pso_c1 = x,
+                        _ => self.pso_c2 = x,
+                    }
+                    i += 1;
+                }
+                "--de-cr" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<f64>() {
+                        Ok(c) if (0.0..=1.0).contains(&c) => self.de_cr = Some(c),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --de-cr '{}' (expected 0 <= cr <= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--stream-interval" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(n) if n >= 1 => self.stream_interval = n,
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --stream-interval '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                "--progress-every" => {
+                    let v = flags.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+                    match v.parse::<usize>() {
+                        Ok(n) if n >= 1 => self.progress_every = Some(n),
+                        _ => {
+                            return Err(format!(
+                                "error: invalid --progress-every '{}' (expected integer >= 1)",
+                                v
+                            ))
+                        }
+                    }
+                    i += 1;
+                }
+                flag => {
+                    if let Some((field, on)) = self.switch(flag)? {
+                        *field = on;
+                    }
+                }
             }
             i += 1;
         }
+        Ok(())
+    }
 
-        Ok(Args {
-            command,
-            method,
-            max_iter,
-            threads,
-            verbose,
-            quiet,
+    /// 真偽値のフラグを (対象のフィールド, 値) に解釈する
+    ///
+    /// `--flag` は真、`--no-flag` は偽、`--flag=true` / `--flag=false` は明示した値。
+    /// 設定ファイルや `OPTICA_ARGS` で有効にしたフラグをコマンドラインで打ち消すのに使う。
+    fn switch(&mut self, flag: &str) -> Result<Option<(&mut bool, bool)>, String> {
+        if let Some((name, value)) = flag.split_once('=') {
+            if self.bool_field(name).is_none() {
+                return Ok(None);
+            }
+            let on = match value {
+                "true" => true,
+                "false" => false,
+                _ => {
+                    return Err(format!(
+                        "error: invalid {} '{}' (expected true or false)",
+                        name, value
+                    ))
+                }
+            };
+            return Ok(self.bool_field(name).map(|f| (f, on)));
+        }
+        // `--no-default-objective` はそれ自体が `--require-objective` の別名なので先に引く
+        if self.bool_field(flag).is_some() {
+            return Ok(self.bool_field(flag).map(|f| (f, true)));
+        }
+        Ok(flag
+            .strip_prefix("--no-")
+            .and_then(|name| self.bool_field(&format!("--{}", name)))
+            .map(|f| (f, false)))
+    }
+
+    /// 真偽値のフラグが設定するフィールド
+    fn bool_field(&mut self, flag: &str) -> Option<&mut bool> {
+        Some(match flag {
+            "--stream-json" | "--partial-json" => &mut self.stream_json,
+            "--constraint-penalty-per-constraint" => &mut self.penalty_report,
+            "--gradient" => &mut self.gradient_report,
+            "--heuristic-then-exact" => &mut self.heuristic_then_exact,
+            "--fail-on-infeasible" => &mut self.fail_on_infeasible,
+            "--require-objective" | "--no-default-objective" => &mut self.require_objective,
+            "--report-binding-constraints" => &mut self.binding_report,
+            "--solver-scaling" => &mut self.solver_scaling,
+            "--csv" => &mut self.csv,
+            "--cache-fitness" => &mut self.cache_fitness,
+            "--polish" => &mut self.polish,
+            "--deterministic" => &mut self.deterministic,
+            "-w" | "--write" => &mut self.write,
+            "-v" | "--verbose" => &mut self.verbose,
+            "-q" | "--quiet" => &mut self.quiet,
+            _ => return None,
+        })
+    }
+}
+
+/// カンマ区切りの値列を解析
+fn parse_list<T: std::str::FromStr>(value: &str, flag: &str) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|_| format!("error: invalid {} value '{}'", flag, s))
         })
+        .collect()
+}
+
+/// 既定値のフラグ列（設定ファイルの後に環境変数を並べ、環境変数を優先する）
+fn default_flags(mut config: Vec<String>, env: Option<&str>) -> Vec<String> {
+    if let Some(env) = env {
+        config.extend(env.split_whitespace().map(|s| s.to_string()));
+    }
+    config
+}
+
+/// 設定ファイルを探す（カレントディレクトリ優先、次に `$HOME`）
+fn config_file_path() -> Option<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.is_file() {
+        return Some(local);
+    }
+    let home = std::env::var_os("HOME")?;
+    let p = PathBuf::from(home).join(CONFIG_FILE);
+    p.is_file().then_some(p)
+}
+
+/// 設定ファイルをフラグ列に変換
+///
+/// `key = value` 形式の行のみを解釈する（`#` コメントと `[section]` 行は無視）。
+/// 例: `method = "de"`, `iter = 2000`, `threads = 4`, `verbose = true`
+fn config_file_flags() -> Result<Vec<String>, String> {
+    let Some(path) = config_file_path() else {
+        return Ok(Vec::new());
+    };
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("error: {}", e))?;
+    parse_config(&text).map_err(|e| format!("error: {}:{}", path.display(), e))
+}
+
+/// 設定ファイルの本文をフラグ列に変換（先頭の UTF-8 BOM と CRLF の改行は読み飛ばす）
+fn parse_config(text: &str) -> Result<Vec<String>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut flags = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() || line.starts_with('[') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("{}: expected `key = value`", n + 1));
+        };
+        let key = key.trim();
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+        match key {
+            "method" => flags.extend(["--method".to_string(), value.to_string()]),
+            "iter" | "max_iter" => flags.extend(["--iter".to_string(), value.to_string()]),
+            "threads" => flags.extend(["--threads".to_string(), value.to_string()]),
+            "islands" => flags.extend(["--islands".to_string(), value.to_string()]),
+            "cp_fallback" => flags.extend(["--cp-fallback".to_string(), value.to_string()]),
+            "seed" => flags.extend(["--seed".to_string(), value.to_string()]),
+            "verbose" | "quiet" | "require_objective" => {
+                if !matches!(value, "true" | "false") {
+                    return Err(format!("{}: expected true or false for '{}'", n + 1, key));
+                }
+                flags.push(format!("--{}={}", key.replace('_', "-"), value));
+            }
+            _ => return Err(format!("{}: unknown key '{}'", n + 1, key)),
+        }
     }
+    Ok(flags)
 }
 
 fn num_cpus() -> usize {
@@ -115,3 +932,208 @@ fn num_cpus() -> usize {
         .map(|n| n.get())
         .unwrap_or(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(xs: &[&str]) -> Vec<String> {
+        xs.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse_layered(&strings(args), &[])
+    }
+
+    #[test]
+    fn seeds_accept_lists_and_inclusive_ranges() {
+        assert_eq!(
+            parse(&["experiment", "m.optica", "--seeds", "1:4"])
+                .unwrap()
+                .seeds,
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(
+            parse(&["experiment", "m.optica", "--seeds", "7:7"])
+                .unwrap()
+                .seeds,
+            vec![7]
+        );
+        assert_eq!(
+            parse(&["experiment", "m.optica", "--seeds", "3,1,2"])
+                .unwrap()
+                .seeds,
+            vec![3, 1, 2]
+        );
+    }
+
+    #[test]
+    fn seeds_reject_open_or_reversed_ranges() {
+        for v in ["5:", ":5", "5:3", "a:3", ":"] {
+            let err = parse(&["experiment", "m.optica", "--seeds", v]).unwrap_err();
+            assert!(
+                err.starts_with("error: invalid --seeds value"),
+                "{}: {}",
+                v,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn methods_reject_non_heuristic_names() {
+        let a = parse(&["experiment", "m.optica", "--methods", "de,auto,pso"]).unwrap();
+        assert_eq!(a.methods, strings(&["de", "auto", "pso"]));
+        for v in ["foo,cp-sat", "de,cp-sat", "de,DE"] {
+            let err = parse(&["experiment", "m.optica", "--methods", v]).unwrap_err();
+            assert!(
+                err.starts_with("error: invalid --methods value"),
+                "{}: {}",
+                v,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn cp_fallback_accepts_auto_and_names_it_in_errors() {
+        assert_eq!(parse(&["solve", "m.optica"]).unwrap().cp_fallback, "auto");
+        for v in ["auto", "none", "de", "pso"] {
+            let a = parse(&["solve", "m.optica", "--cp-fallback", v]).unwrap();
+            assert_eq!(a.cp_fallback, v);
+        }
+        assert_eq!(
+            parse(&["solve", "m.optica", "--cp-fallback", "sa"]).unwrap_err(),
+            "error: invalid --cp-fallback 'sa' (expected auto, none, de, pso)"
+        );
+    }
+
+    #[test]
+    fn warm_start_methods_follow_the_heuristic_list() {
+        let method = |v: &str| {
+            parse(&["solve", "m.optica", "--warm-start-method", v]).map(|a| a.warm_start_method)
+        };
+        for &m in optica::HEURISTIC_METHODS.iter().chain(&["auto", "cp-sat"]) {
+            assert_eq!(method(m).unwrap().as_deref(), Some(m));
+        }
+        let err = method("simplex").unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "error: invalid --warm-start-method 'simplex' (expected auto, {}, cp-sat)",
+                optica::HEURISTIC_METHODS.join(", ")
+            )
+        );
+    }
+
+    /// 設定ファイル・`OPTICA_ARGS`・コマンドラインを重ねて解析する
+    fn layered(config: &str, env: &str, cli: &[&str]) -> Args {
+        let defaults = default_flags(parse_config(config).unwrap(), Some(env));
+        Args::parse_layered(&strings(cli), &defaults).unwrap()
+    }
+
+    #[test]
+    fn boolean_flags_can_be_switched_off() {
+        let a = parse(&["m.optica", "--polish", "--no-polish"]).unwrap();
+        assert!(!a.polish);
+        let a = parse(&["m.optica", "--verbose=false", "--quiet=true"]).unwrap();
+        assert!(!a.verbose && a.quiet);
+        // `--no-default-objective` は `--require-objective` の別名のまま
+        assert!(
+            parse(&["m.optica", "--no-default-objective"])
+                .unwrap()
+                .require_objective
+        );
+        let err = parse(&["m.optica", "--verbose=yes"]).unwrap_err();
+        assert!(err.starts_with("error: invalid --verbose 'yes'"), "{}", err);
+    }
+
+    #[test]
+    fn command_line_overrides_env_which_overrides_config() {
+        let config = "verbose = true\nrequire_objective = true\nmethod = \"de\"\n";
+
+        let a = layered(config, "", &["m.optica"]);
+        assert!(a.verbose && a.require_objective);
+        assert_eq!(a.method, "de");
+
+        // 環境変数は設定ファイルを打ち消し、コマンドラインは環境変数を打ち消す
+        let a = layered(config, "--no-verbose -m pso --quiet", &["m.optica"]);
+        assert!(!a.verbose && a.quiet && a.require_objective);
+        assert_eq!(a.method, "pso");
+        let a = layered(
+            config,
+            "--no-verbose -m pso --quiet",
+            &[
+                "m.optica",
+                "--verbose",
+                "--quiet=false",
+                "--require-objective=false",
+                "-m",
+                "nm",
+            ],
+        );
+        assert!(a.verbose && !a.quiet && !a.require_objective);
+        assert_eq!(a.method, "nm");
+    }
+
+    #[test]
+    fn config_booleans_must_be_true_or_false() {
+        assert_eq!(
+            parse_config("verbose = false\nquiet = true\n").unwrap(),
+            strings(&["--verbose=false", "--quiet=true"])
+        );
+        assert_eq!(
+            parse_config("require_objective = yes\n").unwrap_err(),
+            "1: expected true or false for 'require_objective'"
+        );
+    }
+
+    #[test]
+    fn config_accepts_bom_and_crlf() {
+        assert_eq!(
+            parse_config("\u{feff}# 既定値\r\nmethod = \"de\"\r\niter = 500\r\nverbose = true\r\n")
+                .unwrap(),
+            strings(&["--method", "de", "--iter", "500", "--verbose=true"])
+        );
+    }
+
+    fn solve_file(a: &Args) -> Option<&str> {
+        match &a.command {
+            Command::Solve { file } => Some(file),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn flags_may_come_before_or_after_the_file() {
+        for argv in [
+            &["-v", "model.optica"][..],
+            &["model.optica", "-v"],
+            &["solve", "-v", "model.optica"],
+            &["-v", "solve", "model.optica"],
+        ] {
+            let a = parse(argv).unwrap();
+            assert_eq!(solve_file(&a), Some("model.optica"), "{:?}", argv);
+            assert!(a.verbose, "{:?}", argv);
+        }
+
+        // 値を取るフラグは次の引数を消費し、ファイル名と取り違えない
+        let a = parse(&["-m", "de", "model.optica", "-i", "50"]).unwrap();
+        assert_eq!(solve_file(&a), Some("model.optica"));
+        assert_eq!((a.method.as_str(), a.max_iter), ("de", 50));
+        let a = parse(&["--seed", "5", "experiment", "m.optica"]).unwrap();
+        assert!(matches!(&a.command, Command::Experiment { file } if file == "m.optica"));
+    }
+
+    #[test]
+    fn flags_without_a_file_show_version_or_help() {
+        assert!(matches!(parse(&["-v"]).unwrap().command, Command::Version));
+        assert!(matches!(parse(&[]).unwrap().command, Command::Help));
+        assert!(matches!(parse(&["-q"]).unwrap().command, Command::Help));
+        assert!(matches!(
+            parse(&["model.optica", "--help"]).unwrap().command,
+            Command::Help
+        ));
+        assert_eq!(parse(&["solve", "-v"]).unwrap_err(), "error: no input file");
+    }
+}
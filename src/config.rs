@@ -7,19 +7,91 @@ pub const DEFAULT_MAX_ITER: usize = 1000;
 pub const POP_SIZE: usize = 50;
 pub const N_PARTICLES: usize = 50;
 
+/// 乱数の種（`--seed` 未指定時）。PSOは `DEFAULT_SEED + PSO_SEED_OFFSET` を使う
+pub const DEFAULT_SEED: u64 = 12345;
+pub const PSO_SEED_OFFSET: u64 = 55545;
+
 /// ソルバーパラメータ
 pub const DE_F: f64 = 0.8;
 pub const DE_CR: f64 = 0.9;
+pub const JDE_TAU: f64 = 0.1; // jDE: 個体の f / cr を世代ごとに引き直す確率
+pub const JDE_F_MIN: f64 = 0.1; // jDE: 引き直した f の範囲 [JDE_F_MIN, JDE_F_MAX)
+pub const JDE_F_MAX: f64 = 1.0;
+pub const SHADE_MEMORY: usize = 10; // SHADE: 成功した f / cr の履歴の数
+pub const SHADE_P_MAX: f64 = 0.2; // SHADE: pbest を選ぶ上位の割合の上限（下限は 2/集団）
+pub const SHADE_SCALE: f64 = 0.1; // SHADE: f（コーシー）/ cr（正規）を履歴から引くときの尺度
 pub const PSO_C1: f64 = 2.0;
 pub const PSO_C2: f64 = 2.0;
 pub const PSO_W_INIT: f64 = 0.9;
 pub const PSO_W_MIN: f64 = 0.4;
 pub const PSO_W_DECAY: f64 = 0.995;
+pub const SA_MOVES: usize = 50; // 焼きなまし: 1反復（1温度）あたりの近傍提案数
+pub const SA_STEP: f64 = 0.1; // 焼きなまし: 近傍の標準偏差（変数の範囲に対する割合、終盤ほど縮める）
+pub const SA_T_FINAL: f64 = 1e-8; // 焼きなまし: 最終温度 / 初期温度（幾何冷却の比はここから決まる）
+pub const SA_INIT_SAMPLES: usize = 20; // 焼きなまし: 初期温度を決める試行移動の数
+pub const CMAES_MAX_DIM: usize = 100; // CMA-ES: これを超える次元は O(n²) の行列演算が重いのでDEで解く
+pub const CMAES_SIGMA0: f64 = 0.3; // CMA-ES: 初期ステップ幅（変数の範囲を [0, 1] に正規化した空間で）
+pub const NM_STEP: f64 = 0.05; // Nelder-Mead: 初期単体の辺（変数の範囲に対する割合）
+pub const NM_POLISH_ITERS: usize = 1000; // Nelder-Mead: hybrid の仕上げ（--polish）に使う反復数
+pub const LBFGS_MEMORY: usize = 8; // L-BFGS: 保持する (s, y) の組の数
+pub const LBFGS_H: f64 = 1e-7; // L-BFGS: 差分勾配の刻み（変数の範囲に対する割合）
+pub const LBFGS_GTOL: f64 = 1e-10; // L-BFGS: 射影勾配の最大成分がこれ未満なら収束
+pub const LBFGS_ARMIJO: f64 = 1e-4; // L-BFGS: 直線探索の十分減少条件の係数
+pub const LBFGS_BACKTRACKS: usize = 40; // L-BFGS: 直線探索で歩幅を半分にする最大回数
+pub const NSGA_ETA_C: f64 = 20.0; // NSGA-II: SBX交叉の分布指数
+pub const NSGA_ETA_M: f64 = 20.0; // NSGA-II: 多項式突然変異の分布指数
+pub const NSGA_CROSSOVER: f64 = 0.9; // NSGA-II: 親の組に交叉を行う確率
+pub const COORD_GRID: usize = 16; // 座標降下: 初期ブラケット用の格子点数
+pub const COORD_GS_ITERS: usize = 60; // 座標降下: 黄金分割の反復回数
+
+/// 多様性リスタート（DE）: 再初期化する個体の割合と、発動する多様性（範囲で正規化した標準偏差の平均）
+pub const RESTART_FRACTION: f64 = 0.2;
+pub const DIVERSITY_THRESHOLD: f64 = 1e-3;
+
+/// hybrid: 前段（DEによる探索）に割り当てる反復の割合（--hybrid-split の既定値）
+pub const HYBRID_SPLIT: f64 = 0.5;
+
+/// ヒューリスティック→CP-SAT: ヒント用の事前探索に使う反復数の割合（max_iter / N）
+pub const HINT_ITER_DIVISOR: usize = 10;
+
+/// 安定性レポート: 標準偏差が変数の範囲のこの割合を超えたら unstable
+pub const STABILITY_REL_STD: f64 = 0.01;
+
+/// 有効制約レポート: |左辺 - 右辺| がこれ未満の不等式制約を binding とみなす（既定値）
+pub const BINDING_TOL: f64 = 1e-6;
+
+/// --stream-json: 進捗レコードを出す間隔（反復数、--stream-interval の既定値）
+pub const STREAM_INTERVAL: usize = 10;
 
-/// 収束判定
+/// --patience: 最良値がこの相対量以上下がった世代を「改善」とみなす
+pub const PATIENCE_REL_TOL: f64 = 1e-9;
+
+/// -v: 求解中の進捗行（反復数と最良値）を出す間隔（--progress-every の既定値）
+pub const PROGRESS_EVERY: usize = 100;
+
+/// 収束状況の判定: 直近の窓で最良値がこの相対量以上改善していれば「改善中」
+pub const PROGRESS_WINDOW: usize = 50;
+pub const PROGRESS_REL_TOL: f64 = 1e-6;
+
+/// 適合度キャッシュ（--cache-fitness）: 保持する点の数と、キーにする座標の量子化幅
+/// （量子化幅より近い点は同じ値を返すので、細かい改善を追う問題では精度と引き換えになる）
+pub const FITNESS_CACHE_CAPACITY: usize = 100_000;
+pub const FITNESS_CACHE_QUANTUM: f64 = 1e-9;
+
+/// 収束判定（適合度の絶対値がこれ未満なら打ち切る。最大化で適合度が負になっても誤って止めない）
 pub const TOLERANCE: f64 = 1e-10;
 pub const DISPLAY_TOLERANCE: f64 = 1e-6;
 
+/// ベンチマーク
+pub const BENCH_ITER: usize = 500;
+pub const SCALING_DIMS: &[usize] = &[10, 20, 50, 100, 200, 500]; // bench --solver-scaling の次元列
+
+/// 自動スレッド数: 1スレッドに割り当てる推定作業量（(次元 + 制約数) × 集団 × 反復）の目安
+pub const THREAD_MIN_WORK: usize = 1_000_000;
+
 /// 並列化の閾値
 pub const PARALLEL_MIN_DIM: usize = 50;
 pub const PARALLEL_MIN_ITER: usize = 200;
+
+/// --deterministic: 島数の指定が無いときのDEの島数（スレッド数に依存させない）
+pub const DETERMINISTIC_ISLANDS: usize = 4;
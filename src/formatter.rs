@@ -0,0 +1,445 @@
+//! モデルファイルの整形（`optica fmt`）
+//!
+//! 式は書かれたまま残し、次の点だけを正規化する:
+//! - 行内の連続する空白を1つに（文字列リテラル内と行末コメントはそのまま）
+//! - インデント（トップレベルは0、`subject to:` などのブロック内は4、制約ラベルの下は8、
+//!   `forall ...:` の本体はさらに1段下げる）
+//! - セクション見出しの前の空行（連続する空行は1つに）
+//! - 連続する `var` / `param` 宣言の名前の後ろと行末コメントの桁揃え
+//!
+//! 宣言の順序は意味を持つ（パラメータは使う前に定義する）ので並べ替えない。
+//! 整形前後でパース結果が変わらないことを確かめ、変わる場合はエラーにする。
+
+use crate::parser::{self, Model};
+use std::collections::BTreeMap;
+
+/// 宣言・セクションの開始とみなす行頭
+const TOP_LEVEL: &[&str] = &[
+    "model ",
+    "problem ",
+    "set ",
+    "stage ",
+    "state ",
+    "decision ",
+    "param ",
+    "const ",
+    "var ",
+    "maximize",
+    "minimize",
+    "subject to",
+    "objectives:",
+    "data:",
+    "solve:",
+];
+
+/// 中身を整形せずそのまま残すブロック（動的計画法の記述など、パーサーが読み飛ばすもの）
+const OPAQUE: &[&str] = &["transition:", "def ", "bellman ", "terminal ", "initial:"];
+
+/// 前に空行を入れるセクション見出し
+const SECTIONS: &[&str] = &[
+    "subject to",
+    "objectives:",
+    "data:",
+    "solve:",
+    "maximize",
+    "minimize",
+];
+
+const INDENT: &str = "    ";
+
+/// 整形中のブロック（パーサーの状態遷移に合わせる）
+#[derive(Default)]
+struct State {
+    subject_to: bool,
+    objectives: bool,
+    data: bool,
+    solve: bool,
+    opaque: bool,
+    /// 制約ラベル行（objectivesブロックでは `:` で終わる見出し行）の元のインデント
+    label: Option<usize>,
+    /// 本体を次の行に書く `forall ...:` 行の元のインデント
+    forall: Option<usize>,
+}
+
+impl State {
+    /// トップレベルの行でブロックを切り替える
+    fn enter(&mut self, line: &str) {
+        self.opaque = false;
+        self.label = None;
+        self.forall = None;
+        self.data = false;
+        self.solve = false;
+        if line.starts_with("subject to") {
+            self.subject_to = true;
+            self.objectives = false;
+        } else if line.starts_with("objectives:") {
+            self.objectives = true;
+            self.subject_to = false;
+        } else if line.starts_with("solve:") {
+            self.solve = true;
+            self.subject_to = false;
+        } else if line.starts_with("data:") {
+            self.data = true;
+        }
+    }
+
+    fn in_block(&self) -> bool {
+        self.subject_to || self.objectives || self.data || self.solve
+    }
+}
+
+/// ソースを整形して返す（整形で意味が変わる場合はエラー）
+pub fn format_source(source: &str) -> Result<String, String> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let before = parser::parse(source).map_err(|e| e.to_string())?;
+
+    let mut out: Vec<String> = Vec::new();
+    let mut state = State::default();
+    for raw in source.lines() {
+        let line = raw.trim();
+        let indent = raw.len() - raw.trim_start().len();
+        if line.is_empty() {
+            if out.last().is_some_and(|l| !l.is_empty()) {
+                out.push(String::new());
+            }
+            continue;
+        }
+        let comment = line.starts_with('#') || line.starts_with("//");
+
+        // objectivesブロックの中の minimize / weight 等はブロックの中身
+        let top = !comment
+            && if state.objectives {
+                line.starts_with("subject to")
+            } else {
+                TOP_LEVEL.iter().any(|k| line.starts_with(k))
+            };
+        if top {
+            if SECTIONS.iter().any(|k| line.starts_with(k)) {
+                blank_before_section(&mut out);
+            }
+            state.enter(line);
+            out.push(normalize(line));
+            continue;
+        }
+        if !comment && OPAQUE.iter().any(|k| line.starts_with(k)) {
+            state.opaque = true;
+            state.label = None;
+            out.push(normalize(line));
+            continue;
+        }
+        if state.opaque {
+            out.push(raw.trim_end().to_string());
+            continue;
+        }
+        if !state.in_block() {
+            out.push(normalize(line));
+            continue;
+        }
+
+        // 制約ラベルのグループ: ラベルより深い行を1段下げる
+        let mut depth = 1;
+        if state.objectives {
+            // `minimize name:` や `epsilon:` に続く行も同様に1段下げる
+            match state.label {
+                _ if !comment && line.ends_with(':') => state.label = Some(indent),
+                Some(l) if indent > l => depth = 2,
+                Some(_) if comment => {}
+                _ => state.label = None,
+            }
+        } else if state.subject_to && !state.data && !state.solve {
+            match state.label {
+                _ if !comment && is_label(line) => state.label = Some(indent),
+                Some(l) if indent > l => depth = 2,
+                Some(_) if comment => {}
+                _ => state.label = None,
+            }
+            match state.forall {
+                Some(f) if indent > f => depth += 1,
+                Some(_) if comment => {}
+                _ => state.forall = None,
+            }
+            if !comment && is_forall_head(line) {
+                state.forall = Some(indent);
+            }
+        }
+        out.push(format!("{}{}", INDENT.repeat(depth), normalize(line)));
+    }
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+    align_declarations(&mut out);
+
+    let mut formatted = out.join("\n");
+    formatted.push('\n');
+    let after = parser::parse(&formatted)
+        .map_err(|e| format!("error: formatting broke the model ({})", e))?;
+    if fingerprint(&before) != fingerprint(&after) {
+        return Err("error: formatting would change the model; file left unformatted".to_string());
+    }
+    Ok(formatted)
+}
+
+/// セクション見出しの前に空行を入れる（直前のコメント行は見出しと一緒に送る）
+fn blank_before_section(out: &mut Vec<String>) {
+    let mut at = out.len();
+    while at > 0 && out[at - 1].starts_with('#') {
+        at -= 1;
+    }
+    if at > 0 && !out[at - 1].is_empty() {
+        out.insert(at, String::new());
+    }
+}
+
+/// 制約ブロックの `label:` だけの行か
+fn is_label(line: &str) -> bool {
+    line.strip_suffix(':').is_some_and(|l| {
+        !l.is_empty()
+            && l.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    })
+}
+
+/// 本体を次の行に書く `forall i in S:`（`name: forall i in S:`）の行か
+fn is_forall_head(line: &str) -> bool {
+    line.ends_with(':') && (line.starts_with("forall ") || line.contains(": forall "))
+}
+
+/// 行内の連続する空白を1つにする（文字列リテラル内と行末コメントはそのまま）
+fn normalize(line: &str) -> String {
+    let (code, comment) = split_comment(line);
+    let mut s = String::with_capacity(code.len());
+    let mut quoted = false;
+    let mut space = false;
+    for c in code.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            space = true;
+            continue;
+        }
+        if space && !s.is_empty() {
+            s.push(' ');
+        }
+        space = false;
+        s.push(c);
+    }
+    match comment {
+        Some(c) if s.is_empty() => c.to_string(),
+        Some(c) => format!("{}  {}", s, c),
+        None => s,
+    }
+}
+
+/// 行末コメント（空白の後の `#`、文字列リテラルの外）を分ける
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quoted = false;
+    let mut prev_space = true;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && prev_space => return (line[..i].trim_end(), Some(&line[i..])),
+            _ => {}
+        }
+        prev_space = c.is_whitespace();
+    }
+    (line, None)
+}
+
+/// 同じキーワードで連続する `var` / `param` 宣言で、名前の後ろと行末コメントの桁を揃える
+fn align_declarations(out: &mut [String]) {
+    let mut start = 0;
+    while start < out.len() {
+        // 同じキーワード（var / param）の宣言が続く範囲
+        let keyword = out[start].split(' ').next().unwrap_or("").to_string();
+        let mut end = start;
+        while end < out.len()
+            && declaration(&out[end]).is_some()
+            && out[end].starts_with(&format!("{} ", keyword))
+        {
+            end += 1;
+        }
+        if end - start >= 2 {
+            align_run(&mut out[start..end]);
+        }
+        start = end.max(start + 1);
+    }
+}
+
+fn align_run(run: &mut [String]) {
+    let parts: Vec<(String, String, Option<String>)> = run
+        .iter()
+        .filter_map(|l| declaration(l))
+        .map(|(head, rest, comment)| {
+            (
+                head.to_string(),
+                rest.to_string(),
+                comment.map(String::from),
+            )
+        })
+        .collect();
+    let head_w = parts.iter().map(|p| p.0.chars().count()).max().unwrap_or(0);
+    let code: Vec<String> = parts
+        .iter()
+        .map(|(head, rest, _)| {
+            if rest.is_empty() {
+                head.clone()
+            } else {
+                format!("{:<w$} {}", head, rest, w = head_w)
+            }
+        })
+        .collect();
+    let code_w = code.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+    for ((line, code), (_, _, comment)) in run.iter_mut().zip(code).zip(&parts) {
+        *line = match comment {
+            Some(c) => format!("{:<w$}  {}", code, c, w = code_w),
+            None => code,
+        };
+    }
+}
+
+/// 宣言行を (キーワード + 名前, 残り, 行末コメント) に分ける
+fn declaration(line: &str) -> Option<(&str, &str, Option<&str>)> {
+    if !(line.starts_with("var ") || line.starts_with("param ")) {
+        return None;
+    }
+    let (code, comment) = split_comment(line);
+    let name_start = code.find(' ')? + 1;
+    // 名前は括弧の外の最初の空白まで（`x[i in S]` や `cost{i in Items}` を含む）
+    let mut depth = 0i32;
+    let mut name_end = code.len();
+    for (i, c) in code[name_start..].char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ' ' if depth == 0 => {
+                name_end = name_start + i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    Some((&code[..name_end], code[name_end..].trim(), comment))
+}
+
+/// パース結果の比較用の文字列（式の空白の違いは無視する）
+///
+/// 変数名・集合の要素・パラメータのキーは空白も含めてそのまま比べる（`'New  York'` の空白が
+/// 詰まるとキーが変わる）。
+fn fingerprint(m: &Model) -> String {
+    let params: BTreeMap<_, BTreeMap<_, _>> = m
+        .params
+        .iter()
+        .map(|(k, v)| (k, v.iter().map(|(i, x)| (i, x.to_bits())).collect()))
+        .collect();
+    let consts: BTreeMap<_, _> = m.consts.iter().map(|(k, v)| (k, v.to_bits())).collect();
+    let sets: BTreeMap<_, _> = m.sets.iter().collect();
+    let keys = format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{:?}|{:?}|{:?}",
+        m.name, m.var_names, m.lb, m.ub, m.var_types, params, m.maximize, consts, sets, m.key_sets,
+    );
+    let exprs = format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+        m.objective_expr, m.constraints, m.objectives, m.pareto, m.cp_globals, m.hints,
+    );
+    let exprs = exprs.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("{}|{}", keys, exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_labels_and_forall_bodies_are_indented() {
+        // 入れ子は元のインデントの深さの関係だけで決まる（幅はそろっていなくてよい）
+        let src = "var x[1..2] >= 0, <= 10\n\
+                   objectives:\n\
+                   minimize cost:\n\
+                   \x20 x[1] + x[2]\n\
+                   maximize gain: x[1]\n\
+                   pareto method: \"weighted_sum\"\n\
+                   \x20 weight cost: 0.5\n\
+                   subject to:\n\
+                   \x20 caps:\n\
+                   \x20           x[1] <= 5\n\
+                   \x20     x[2] <= 5\n\
+                   \x20 forall i in 1..2:\n\
+                   \x20   x[i] >= 1\n\
+                   total:   x[1]  +  x[2] >= 3\n";
+        let expected = "var x[1..2] >= 0, <= 10\n\
+                        \n\
+                        objectives:\n\
+                        \x20   minimize cost:\n\
+                        \x20       x[1] + x[2]\n\
+                        \x20   maximize gain: x[1]\n\
+                        \x20   pareto method: \"weighted_sum\"\n\
+                        \x20   weight cost: 0.5\n\
+                        \n\
+                        subject to:\n\
+                        \x20   caps:\n\
+                        \x20       x[1] <= 5\n\
+                        \x20       x[2] <= 5\n\
+                        \x20   forall i in 1..2:\n\
+                        \x20       x[i] >= 1\n\
+                        \x20   total: x[1] + x[2] >= 3\n";
+        assert_eq!(format_source(src).unwrap(), expected);
+    }
+
+    #[test]
+    fn declarations_align_names_and_trailing_comments() {
+        let src = "set S = {\"a  b\", c}\n\
+                   param cost[S] = {\"a  b\": 1, c: 2};  # 費用\n\
+                   param w = 3   # 重み\n\
+                   var x >= 0, <= 1 # 短い\n\
+                   var longer_name[S]   >= 0, <= 10\n\
+                   var y >= -1, <= 1   # 最後\n\
+                   minimize: x + y\n";
+        let expected = "set S = {\"a  b\", c}\n\
+                        param cost[S] = {\"a  b\": 1, c: 2};  # 費用\n\
+                        param w       = 3                   # 重み\n\
+                        var x              >= 0, <= 1   # 短い\n\
+                        var longer_name[S] >= 0, <= 10\n\
+                        var y              >= -1, <= 1  # 最後\n\
+                        \n\
+                        minimize: x + y\n";
+        assert_eq!(format_source(src).unwrap(), expected);
+    }
+
+    #[test]
+    fn formatting_is_idempotent_on_the_examples() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut checked = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            // 大規模分解の例は展開に数秒かかるので除く
+            let large = path.ends_with("13_largescale_decomposition.optica");
+            if large || path.extension().is_none_or(|e| e != "optica") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            let once = format_source(&source).unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+            assert_eq!(format_source(&once).unwrap(), once, "{:?}", path);
+            checked += 1;
+        }
+        assert!(checked >= 10, "{}", checked);
+    }
+
+    #[test]
+    fn changes_to_the_parsed_model_are_refused() {
+        // 単引用符の要素は文字列リテラル扱いされず空白が詰まるので、キーが変わる
+        let src = "set C = {'New  York', 'Paris'}\n\
+                   var x[C] >= 0, <= 1\n\
+                   minimize: sum(c in C) x[c]\n";
+        assert_eq!(
+            format_source(src).unwrap_err(),
+            "error: formatting would change the model; file left unformatted"
+        );
+        // 二重引用符の中の空白は保つので整形できる
+        let src = src.replace('\'', "\"");
+        assert_eq!(
+            format_source(&src).unwrap(),
+            src.replace("\nminimize", "\n\nminimize")
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Optica - 超高速最適化DSL（ライブラリ）
+//!
+//! CLIを経由せずにモデルを解く入口。
+//!
+//! ```no_run
+//! let source = std::fs::read_to_string("model.optica").unwrap();
+//! let model = optica::parser::parse(&source).unwrap();
+//! let result = optica::solve(&model, &optica::SolveOptions::default());
+//! println!("{} {:?}", result.objective, result.status);
+//! ```
+
+pub mod config;
+pub mod formatter;
+pub mod parser;
+pub mod presolve;
+pub mod solver;
+
+use config::*;
+use parser::Model;
+use std::time::Duration;
+
+/// `solve` の実行オプション
+#[derive(Debug, Clone)]
+pub struct SolveOptions {
+    /// 手法（auto, de, jde, shade, pso, hybrid, coord, sa, cmaes, nm, lbfgs, cp-sat）
+    pub method: String,
+    pub max_iter: usize,
+    pub threads: usize,
+    pub seed: u64,
+    /// 打ち切り時間（None なら反復数だけで止める）
+    pub timeout: Option<Duration>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self {
+            method: "auto".to_string(),
+            max_iter: DEFAULT_MAX_ITER,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: DEFAULT_SEED,
+            timeout: None,
+        }
+    }
+}
+
+impl SolveOptions {
+    /// ソルバー共通のオプションへ変換（集団サイズ等はモデルの `solve:` ブロックを反映）
+    pub fn solver_options(&self, model: &Model) -> solver::Options {
+        let mut opts = solver::Options::new(self.max_iter, self.threads);
+        opts.seed = self.seed;
+        opts.deadline = self.timeout.map(solver::Deadline::new);
+        let h = &model.hints;
+        opts.de.pop_size = h.pop_size.unwrap_or(opts.de.pop_size);
+        opts.de.f = h.de_f.unwrap_or(opts.de.f);
+        opts.de.cr = h.de_cr.unwrap_or(opts.de.cr);
+        opts.pso.particles = h.pop_size.unwrap_or(opts.pso.particles);
+        opts.pso.w_init = h.w_init.unwrap_or(opts.pso.w_init);
+        opts.pso.w_min = h.w_min.unwrap_or(opts.pso.w_min);
+        opts.pso.w_decay = h.w_decay.unwrap_or(opts.pso.w_decay);
+        opts.pso.c1 = h.c1.unwrap_or(opts.pso.c1);
+        opts.pso.c2 = h.c2.unwrap_or(opts.pso.c2);
+        opts
+    }
+}
+
+/// 結果の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Optimal,
+    Feasible,
+    Infeasible,
+}
+
+impl Status {
+    /// 適合度と制約違反の合計から判定する
+    pub fn classify(fitness: f64, violation: f64) -> Self {
+        if violation > 0.0 {
+            Status::Infeasible
+        } else if fitness.abs() < TOLERANCE {
+            Status::Optimal
+        } else {
+            Status::Feasible
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Optimal => "optimal",
+            Status::Feasible => "feasible",
+            Status::Infeasible => "infeasible",
+        }
+    }
+}
+
+/// `solve` の結果
+#[derive(Debug, Clone)]
+pub struct SolveResult {
+    /// 最良解（整数変数は丸め済み、モデルの変数の順。プリソルブ済みなら `Model::expand_solution` で元に戻す）。
+    /// CP-SATが実行不能を証明した場合は空
+    pub best: Vec<f64>,
+    /// 目的値（ペナルティを含まない、目的関数の向きのまま）
+    pub objective: f64,
+    /// ソルバーが最小化した適合度（ペナルティ込み）
+    pub fitness: f64,
+    pub iterations: usize,
+    pub status: Status,
+}
+
+impl SolveResult {
+    /// ソルバーの生の結果 (解, 適合度, 反復数) から作る（`best` はプリソルブ後の変数空間）
+    pub fn new(model: &Model, mut best: Vec<f64>, fitness: f64, iterations: usize) -> Self {
+        model.round_integers(&mut best);
+        let (scalar, _, violation) = model.evaluate_all(&model.expand_solution(&best));
+        Self {
+            objective: if model.maximize { -scalar } else { scalar },
+            status: Status::classify(fitness, violation),
+            best,
+            fitness,
+            iterations,
+        }
+    }
+
+    /// CP-SATの結果から作る。実行不能の証明は解を持たない `Status::Infeasible` の結果になる
+    /// （目的値は NaN、適合度は無限大）
+    pub fn from_cp(model: &Model, outcome: solver::CpOutcome) -> Self {
+        match outcome {
+            solver::CpOutcome::Optimal(best, fitness, iterations) => {
+                let mut r = Self::new(model, best, fitness, iterations);
+                if r.status != Status::Infeasible {
+                    r.status = Status::Optimal;
+                }
+                r
+            }
+            solver::CpOutcome::Feasible(best, fitness, iterations) => {
+                Self::new(model, best, fitness, iterations)
+            }
+            solver::CpOutcome::Infeasible => Self {
+                best: Vec::new(),
+                objective: f64::NAN,
+                fitness: f64::INFINITY,
+                iterations: 0,
+                status: Status::Infeasible,
+            },
+        }
+    }
+}
+
+/// モデルを解く
+///
+/// `auto` ではCPグローバル制約があればCP-SAT、無ければモデルの性質に応じたヒューリスティックを使う。
+pub fn solve(model: &Model, opts: &SolveOptions) -> SolveResult {
+    let method = if opts.method == "auto" && !model.cp_globals.is_empty() {
+        "cp-sat"
+    } else {
+        opts.method.as_str()
+    };
+    solve_with(model, method, &opts.solver_options(model))
+}
+
+/// 手法とソルバーのオプションを直接指定して解く
+///
+/// `cp-sat` が使えない（feature 無効など）ときは `auto` のヒューリスティックで解く。
+/// CP-SATが最適性を証明した解は `Status::Optimal`、実行不能を証明したときは
+/// ヒューリスティックを回さず、解の無い `Status::Infeasible` を返す（`SolveResult::from_cp`）。
+pub fn solve_with(model: &Model, method: &str, opts: &solver::Options) -> SolveResult {
+    let cp = (method == "cp-sat")
+        .then(|| solver::solve_cp_entry(model, opts.threads, None, opts.deadline.as_ref()))
+        .flatten();
+    match cp {
+        Some(outcome) => SolveResult::from_cp(model, outcome),
+        None => {
+            let (best, fitness, iterations) =
+                solve_heuristic(model, resolve_method(model, method), opts);
+            SolveResult::new(model, best, fitness, iterations)
+        }
+    }
+}
+
+/// `solve_heuristic` が扱うメソッド名
+pub const HEURISTIC_METHODS: &[&str] = &[
+    "de", "jde", "shade", "pso", "hybrid", "coord", "sa", "cmaes", "nm", "lbfgs",
+];
+
+/// `auto` をモデルの性質に応じて具体的なメソッドへ解決
+pub fn resolve_method<'a>(model: &Model, method: &'a str) -> &'a str {
+    match method {
+        m if HEURISTIC_METHODS.contains(&m) => m,
+        _ => {
+            if model.is_separable() {
+                "coord"
+            } else if model.dim <= 20 {
+                "pso"
+            } else {
+                "de"
+            }
+        }
+    }
+}
+
+/// ヒューリスティック手法で解く。戻り値は (解, 適合度, 反復数)
+pub fn solve_heuristic(
+    model: &Model,
+    method: &str,
+    opts: &solver::Options,
+) -> (Vec<f64>, f64, usize) {
+    match method {
+        "jde" => solver::jde(model, opts),
+        "shade" => solver::shade(model, opts),
+        "pso" => solver::pso(model, opts),
+        "hybrid" => solver::hybrid(model, opts),
+        "coord" => solver::coordinate_descent(model, opts),
+        "sa" => solver::sa(model, opts),
+        "nm" => solver::nm(model, opts),
+        "lbfgs" => solver::lbfgs(model, opts),
+        "cmaes" => {
+            if model.dim > CMAES_MAX_DIM {
+                eprintln!(
+                    "warning: cmaes: {} variables exceed {}; solving with de",
+                    model.dim, CMAES_MAX_DIM
+                );
+            }
+            solver::cmaes(model, opts)
+        }
+        _ => solver::de(model, opts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `y` は等式 `y - x - 1 == 0` からプリソルブで消去される
+    const ELIMINATED: &str = "\
+var x >= 0, <= 10
+var y >= -100, <= 100
+
+minimize: (x - 3)^2 + y^2
+
+subject to:
+    y - x - 1 == 0
+";
+
+    fn options(method: &str) -> SolveOptions {
+        SolveOptions {
+            method: method.to_string(),
+            max_iter: 300,
+            threads: 1,
+            ..SolveOptions::default()
+        }
+    }
+
+    #[test]
+    fn solve_evaluates_presolved_model_in_full_space() {
+        let mut model = parser::parse(ELIMINATED).unwrap();
+        let report = presolve::presolve(&mut model);
+        assert_eq!(report.eliminated, vec!["y".to_string()]);
+
+        for method in ["auto", "pso", "de", "nm"] {
+            let r = solve(&model, &options(method));
+            assert_eq!(
+                r.best.len(),
+                1,
+                "{}: best stays in the reduced space",
+                method
+            );
+            let full = model.expand_solution(&r.best);
+            assert!((full[0] - 1.0).abs() < 1e-3, "{}: x = {}", method, full[0]);
+            assert!((full[1] - 2.0).abs() < 1e-3, "{}: y = {}", method, full[1]);
+            assert!(
+                (r.objective - 8.0).abs() < 1e-4,
+                "{}: {}",
+                method,
+                r.objective
+            );
+            assert_ne!(r.status, Status::Infeasible, "{}", method);
+        }
+    }
+
+    #[test]
+    fn proven_infeasibility_carries_no_solution() {
+        let model = parser::parse("var x >= 0, <= 10\nminimize: x\n").unwrap();
+        let r = SolveResult::from_cp(&model, solver::CpOutcome::Infeasible);
+        assert_eq!(r.status, Status::Infeasible);
+        assert!(r.best.is_empty());
+        assert!(r.objective.is_nan());
+        assert_eq!(r.iterations, 0);
+    }
+
+    #[test]
+    fn cp_outcomes_keep_their_status() {
+        let model =
+            parser::parse("var x >= 0, <= 10\nminimize: x\nsubject to:\n    x >= 2\n").unwrap();
+        let r = SolveResult::from_cp(&model, solver::CpOutcome::Optimal(vec![2.0], 2.0, 5));
+        assert_eq!(r.status, Status::Optimal);
+        assert_eq!(r.objective, 2.0);
+        assert_eq!(r.iterations, 5);
+        let r = SolveResult::from_cp(&model, solver::CpOutcome::Feasible(vec![3.0], 3.0, 5));
+        assert_eq!(r.status, Status::Feasible);
+        // 証明済みの最適でも、解が制約を破っていれば実行不能として扱う
+        let r = SolveResult::from_cp(&model, solver::CpOutcome::Optimal(vec![1.0], 1.0, 5));
+        assert_eq!(r.status, Status::Infeasible);
+    }
+
+    #[cfg(not(feature = "cp-sat"))]
+    #[test]
+    fn cp_sat_without_the_feature_falls_back_to_a_heuristic() {
+        let model = parser::parse("var x >= 0, <= 10\nminimize: (x - 4)^2\n").unwrap();
+        let r = solve_with(&model, "cp-sat", &options("cp-sat").solver_options(&model));
+        assert_eq!(r.best.len(), 1);
+        assert!((r.best[0] - 4.0).abs() < 1e-3, "{:?}", r.best);
+    }
+
+    #[test]
+    fn auto_picks_coord_for_separable_models() {
+        let separable = parser::parse(
+            "var x >= -5, <= 5\nvar y >= -5, <= 5\n\nminimize: (x - 1)^2 + abs(y + 2)\n",
+        )
+        .unwrap();
+        assert!(separable.is_separable());
+        assert_eq!(resolve_method(&separable, "auto"), "coord");
+        // 明示したメソッドはそのまま
+        assert_eq!(resolve_method(&separable, "de"), "de");
+
+        // 交差項や制約があれば分離可能ではない
+        let coupled =
+            parser::parse("var x >= -5, <= 5\nvar y >= -5, <= 5\n\nminimize: (x - 1)^2 + x * y\n")
+                .unwrap();
+        assert_eq!(resolve_method(&coupled, "auto"), "pso");
+        let constrained = parser::parse(
+            "var x >= -5, <= 5\nvar y >= -5, <= 5\n\nminimize: x^2 + y^2\n\nsubject to:\n    x + y >= 1\n",
+        )
+        .unwrap();
+        assert_eq!(resolve_method(&constrained, "auto"), "pso");
+    }
+
+    #[test]
+    fn auto_picks_de_for_large_non_separable_models() {
+        let vars: String = (0..21)
+            .map(|i| format!("var x{} >= -5, <= 5\n", i))
+            .collect();
+        let chain: Vec<String> = (0..20).map(|i| format!("x{} * x{}", i, i + 1)).collect();
+        let model = parser::parse(&format!("{}\nminimize: {}\n", vars, chain.join(" + "))).unwrap();
+        assert_eq!(model.dim, 21);
+        assert_eq!(resolve_method(&model, "auto"), "de");
+    }
+
+    /// 品目 (名前, 価値, 重さ)。容量 11 の最適は A + C の 125
+    const ITEMS: [(&str, f64, f64); 4] = [
+        ("A", 50.0, 5.0),
+        ("B", 40.0, 4.0),
+        ("C", 75.0, 6.0),
+        ("D", 30.0, 3.0),
+    ];
+
+    fn knapsack_built_in_rust() -> Model {
+        let mut model = Model::new();
+        let names: Vec<&str> = ITEMS.iter().map(|item| item.0).collect();
+        model.add_set("ITEMS", &names);
+        for (name, value, weight) in ITEMS {
+            model.add_param("value", name, value).unwrap();
+            model.add_param("weight", name, weight).unwrap();
+            model
+                .add_variable(&format!("x[{}]", name), 0.0, 1.0, parser::VarType::Binary)
+                .unwrap();
+        }
+        model.set_objective("sum(i in ITEMS) value[i] * x[i]", true);
+        model.add_constraint(
+            "sum(i in ITEMS) weight[i] * x[i]",
+            parser::ConstraintOp::Le,
+            11.0,
+        );
+        model
+    }
+
+    #[test]
+    fn builder_knapsack_matches_the_dsl_model() {
+        let mut built = knapsack_built_in_rust();
+        assert_eq!(built.dim, 4);
+        assert!(built
+            .add_variable("x[A]", 0.0, 1.0, parser::VarType::Binary)
+            .is_err());
+
+        let parsed = parser::parse(
+            "set ITEMS = {\"A\", \"B\", \"C\", \"D\"}\n\
+             param value[ITEMS] = {A: 50, B: 40, C: 75, D: 30};\n\
+             param weight[ITEMS] = {A: 5, B: 4, C: 6, D: 3};\n\
+             var x[ITEMS] binary\n\
+             maximize: sum(i in ITEMS) value[i] * x[i]\n\
+             subject to:\n    sum(i in ITEMS) weight[i] * x[i] <= 11\n",
+        )
+        .unwrap();
+
+        for model in [&built, &parsed] {
+            let result = solve(model, &options("de"));
+            assert_eq!(result.objective, 125.0);
+            assert_eq!(result.best, vec![1.0, 0.0, 1.0, 0.0]);
+            assert_ne!(result.status, Status::Infeasible);
+        }
+    }
+}
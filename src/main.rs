@@ -1,20 +1,31 @@
 //! Optica - 超高速最適化DSL
 
 mod cli;
-mod config;
-mod parser;
-mod solver;
+
+use optica::solver::CpOutcome;
+use optica::{config, formatter, parser, presolve, solver};
+use optica::{resolve_method, solve_heuristic, SolveResult, Status};
 
 use std::fs;
 use std::io::{self, BufRead, Write};
 use std::time::Instant;
 
-use cli::{Args, Command};
+use cli::{Args, Command, OutputFormat, SolutionFormat};
 use config::*;
 use parser::parse;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// モデルを標準入力から読むときのファイル名（`cat model.optica | optica solve -`）
+const STDIN_FILE: &str = "-";
+
+/// `--fail-on-infeasible` で最良解が実行不能だった場合の終了コード
+const EXIT_INFEASIBLE: i32 = 2;
+
+/// REPLの `plot` で描くグラフの大きさ
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 12;
+
 fn main() {
     let args: Vec<String> = std::env::args().skip(1).collect();
 
@@ -27,8 +38,23 @@ fn main() {
     };
 
     match &args.command {
-        Command::Solve { file } => cmd_solve(file, &args),
-        Command::Bench { dim } => cmd_bench(*dim, args.threads),
+        Command::Solve { file } => {
+            if args.stability > 0 {
+                cmd_stability(file, &args);
+            } else {
+                cmd_solve(file, &args);
+            }
+        }
+        Command::Bench { dim } => {
+            if args.solver_scaling {
+                cmd_bench_scaling(&args);
+            } else {
+                cmd_bench(*dim, args.threads);
+            }
+        }
+        Command::Experiment { file } => cmd_experiment(file, &args),
+        Command::Scenarios { file } => cmd_scenarios(file, &args),
+        Command::Fmt { file } => cmd_fmt(file, &args),
         Command::Repl => cmd_repl(),
         Command::Version => println!("optica {}", VERSION),
         Command::Help => print_help(),
@@ -40,27 +66,197 @@ fn print_help() {
         r#"optica - Ultra-fast Optimization DSL
 
 USAGE:
-    optica <file.optica> [OPTIONS]
+    optica [OPTIONS] <file.optica> [OPTIONS]
     optica solve <file.optica> [OPTIONS]
+    optica solve - [OPTIONS]   (read the model from stdin; no sidecar JSON)
     optica bench [DIM]
-    optica repl
+    optica experiment <file.optica> [--methods de,pso] [--seeds 1:10]
+                      [--iters 500,1000] [--csv]
+    optica bench --solver-scaling [-m METHOD] [--csv]
+    optica scenarios <file.optica> --scenario-dir <DIR>
+    optica fmt <file.optica|-> [-w]
+    optica repl             (commands: solve/run <file>, plot, bench, quit)
 
 OPTIONS:
-    -m, --method <METHOD>   de, pso, hybrid (default: auto)
+    -m, --method <METHOD>   de, jde, shade, pso, hybrid, coord, sa, cmaes, nm,
+                            lbfgs (default: auto)
     -i, --iter <N>          Max iterations (default: 1000)
-    -t, --threads <N>       Threads (default: auto)
-    -v, --verbose           Verbose output
+    --timeout <SECS>        Wall-clock limit; DE/PSO/SA/CMA-ES/coord return the best so
+                            far when it passes (whichever of -i and --timeout
+                            comes first); also the CP-SAT time limit
+    --patience <N>          DE/PSO/SA/CMA-ES: stop when the best objective has not
+                            improved (relative 1e-9) for N generations; each
+                            DE island counts its own
+    --constraint-mode <M>   DE replacement rule for constraints: penalty (objective
+                            + violation x OPTICA_PENALTY) or deb (feasible beats
+                            infeasible; infeasible compare by total violation)
+                            (default: penalty)
+    --pop-size <N>          DE population / PSO swarm size (default: 50)
+    --de-f <F>              DE differential weight (default: 0.8)
+    --de-cr <CR>            DE crossover rate, 0..1 (default: 0.9)
+    --pso-w <W>             PSO initial inertia (default: 0.9)
+    --pso-c1 <C>, --pso-c2 <C>
+                            PSO cognitive / social coefficients (default: 2.0)
+    -t, --threads <N|auto>  Threads (default: auto = from dim x constraints x
+                            iterations, capped at the number of cores); also
+                            the CP-SAT search workers (-t 1 is reproducible)
+    --islands <N>           DE sub-populations scheduled on the threads
+                            (default: same as --threads)
+    --cp-fallback <M>       auto, none, de, pso: behavior when CP-SAT is unavailable
+                            (default: heuristic by -m; none = error)
+    --seed <N|random>       RNG seed (0 or random: fresh seed, printed)
+    --warm-start <FILE>     Initial point from JSON ({{name: value}} or result JSON)
+    --warm-csv <FILE>       Initial population from CSV (one candidate per row;
+                            header of variable names or var order)
+    --warm-start-method <M> Method that consumes the warm start: auto, de, jde,
+                            shade, pso, hybrid, coord, sa, cmaes, nm, lbfgs,
+                            cp-sat
+                            (default: same as -m)
+    --stability <N>         Solve with N seeds; report per-variable mean/std
+    --format <FMT>          text, json, csv (solve result / stability report; json
+                            lists every variable, including zeros; csv: solve
+                            only, `variable,value` rows plus a `# objective=...`
+                            summary line that -q omits)
+    --solution-format <FMT> text, flat (flat: values of all variables in
+                            declaration order, space-separated, one line)
+    --restart-fraction <F>  DE: fraction of the population reinitialized when
+                            diversity collapses (default: 0.2)
+    --diversity-threshold <D>, --restart-threshold <D>
+                            DE: diversity (mean std / range) that triggers a
+                            restart (default: 1e-3)
+    --deterministic         DE: same result for any --threads (islands fixed to
+                            --islands or 4 instead of the thread count, no
+                            migration); parallelism is capped at the island
+                            count. --timeout and --cache-fitness can still
+                            make runs differ
+    --migration-interval <K>
+                            DE islands: every K generations each island shares
+                            its best and adopts the best of all islands in
+                            place of its worst individual (default: 0 = off)
+    --restart-interval <K>  DE: check the diversity every K generations
+                            (default: 1). Restarts are off unless one of
+                            these restart options is given; the best
+                            individual is always kept
+    --resample <K>          DE/PSO: average K evaluations per fitness in the
+                            selection step (noisy objectives; K x the cost)
+    --hybrid-split <F>      hybrid: fraction of the iterations given to the DE
+                            exploration phase; PSO (L-BFGS for continuous
+                            models without CP constraints) refines with the rest
+                            (default: 0.5)
+    --pareto front          Multi-objective: return the Pareto front (NSGA-II;
+                            population --pop-size, generations -i) instead of
+                            one scalarized solution
+    --polish                hybrid: finish with Nelder-Mead (1000 simplex
+                            steps) from the best point
+    --cache-fitness         Cache fitness by candidate (coordinates quantized to
+                            1e-9; LRU of 100000 points). For deterministic,
+                            expensive objectives; -v reports the hit rate
+    --objective-history-window <N>
+                            Classify the run as converged / slowly improving /
+                            stalled from the last N iterations (default with
+                            -v: 50)
+    --objective-bound <V>   Known bound on the objective (lower bound when
+                            minimizing, upper when maximizing); prints the gap
+                            |objective - V| / |V| (alias: --lower-bound)
+    --max-generations-without-feasible <N>
+                            DE/PSO/hybrid: stop after N generations without
+                            any feasible individual (exit status 2)
+    --trace <FILE>          Write per-iteration best objective as CSV
+    --dump-solution <FILE>  Write the best solution as JSON ({{name: value}}, every
+                            variable) for a later --warm-start
+    --stream-json           Stream JSON lines to stdout while solving (iteration,
+                            best objective, feasible), ending with a result
+                            record instead of the text output (alias:
+                            --partial-json)
+    --stream-interval <N>   Iterations between --stream-json progress records
+                            (default: 10)
+    --heuristic-then-exact  Seed CP-SAT with a short heuristic solution
+    --fail-on-infeasible    Exit with status 2 if the best solution is infeasible
+    --require-objective     Reject models without minimize/maximize instead of
+                            solving the default sphere objective (alias:
+                            --no-default-objective)
+    --report-binding-constraints
+                            List inequality constraints at their bound
+                            (|lhs - rhs| < --binding-tol) vs. slack/violated
+    --binding-tol <T>       Tolerance for --report-binding-constraints
+                            (default: 1e-6)
+    --gradient              Print the objective gradient and each constraint's
+                            lhs gradient at the best point (analytic; central
+                            differences for if/comparisons)
+    --constraint-penalty-per-constraint
+                            Print each constraint's penalty at the best point
+    --solver-scaling        bench: sweep dimensions and report time/fitness
+    --methods <LIST>        experiment: comma-separated heuristic methods
+                            (auto, de, jde, shade, pso, hybrid, coord, sa,
+                            cmaes, nm, lbfgs)
+    --seeds <LIST|A:B>      experiment: seeds as a list or inclusive range (A <= B)
+    --iters <LIST>          experiment: comma-separated iteration counts
+    --data <FILE>           Parameter JSON. Loaded after the sidecar JSON
+                            (model.json next to model.optica), so its values
+                            win for params in both; scenario files win over it
+    --scenario-dir <DIR>    scenarios: solve once per JSON file in DIR, merging
+                            it into the model params (JSON array output)
+    --csv                   bench/experiment: print results as CSV
+    -w, --write             fmt: rewrite the file in place (default: stdout)
+    -v, --verbose           Verbose output (includes a progress line every 100
+                            iterations on stderr)
+    --progress-every <N>    Progress line (iteration, best objective) every N
+                            iterations on stderr, with or without -v
     -q, --quiet             Quiet mode
 
+EXIT STATUS:
+    0  success
+    1  error (invalid arguments, I/O, parse error, ...)
+    2  best solution is infeasible (only with --fail-on-infeasible), or no
+       feasible point within --max-generations-without-feasible
+
+DEFAULTS:
+    Options are layered: command line > model `solve:` block > OPTICA_ARGS env
+    > .optica.toml (current dir, then $HOME) > built-in defaults.
+    .optica.toml keys: method, iter, threads, islands, cp_fallback, seed,
+    verbose, quiet, require_objective
+    Boolean flags can be turned off again with --no-<flag> or --<flag>=false
+    (e.g. --no-verbose, --require-objective=false).
+
 EXAMPLES:
     optica model.optica
     optica solve model.optica -m de -i 2000
-    optica bench 100"#
+    optica bench 100
+    optica bench --solver-scaling -m de --csv"#
     );
 }
 
-fn cmd_solve(file: &str, args: &Args) {
-    let source = match fs::read_to_string(file) {
+/// パースエラーを該当行と列の目印付きで表示する
+fn print_parse_error(source: &str, e: &parser::ParseError) {
+    eprintln!("{}", e);
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let Some(line) = e.line.checked_sub(1).and_then(|i| source.lines().nth(i)) else {
+        return;
+    };
+    let number = e.line.to_string();
+    eprintln!("  {} | {}", number, line.trim_end());
+    eprintln!(
+        "  {} | {}^",
+        " ".repeat(number.len()),
+        " ".repeat(e.col.saturating_sub(1))
+    );
+}
+
+/// モデルのソースを読む（`-` なら標準入力をすべて読む）
+fn read_source(file: &str) -> io::Result<String> {
+    if file == STDIN_FILE {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    }
+}
+
+/// モデルファイルを読み込み、サイドカーJSONと `--data` のJSONを取り込む（失敗時は終了）
+///
+/// 標準入力（`-`）から読んだモデルにはサイドカーJSONを探さない。`--data` はサイドカーの後に
+/// 読むので、同じパラメータは `--data` の値になる。
+fn load_model(file: &str, data: Option<&str>) -> parser::Model {
+    let source = match read_source(file) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("error: {}", e);
@@ -68,18 +264,16 @@ fn cmd_solve(file: &str, args: &Args) {
         }
     };
 
-    let start = Instant::now();
-
     // パース
     let mut model = match parse(&source) {
         Ok(m) => m,
         Err(e) => {
-            eprintln!("parse error: {}", e);
+            print_parse_error(&source, &e);
             std::process::exit(1);
         }
     };
     // サイドカーJSON読み込み（同名 .json があれば取り込む）
-    if let Some(json_path) = sidecar_json_path(file) {
+    if let Some(json_path) = sidecar_json_path(file).filter(|_| file != STDIN_FILE) {
         if json_path.exists() {
             if let Err(e) = parser::load_json_into(&mut model, &json_path) {
                 eprintln!(
@@ -90,40 +284,458 @@ fn cmd_solve(file: &str, args: &Args) {
             }
         }
     }
+    if let Some(path) = data {
+        if let Err(e) = parser::load_json_into(&mut model, std::path::Path::new(path)) {
+            eprintln!("error: data {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
 
     if model.dim == 0 {
         eprintln!("error: no variables");
         std::process::exit(1);
     }
+    model
+}
+
+/// `--require-objective` のとき、目的の無いモデルを拒否する
+fn check_objective(file: &str, model: &parser::Model, required: bool) -> Result<(), String> {
+    if required && !model.has_objective() {
+        return Err(format!(
+            "{}: no objective (add `minimize: ...` / `maximize: ...`; \
+             for a feasibility-only model write `minimize: 0`)",
+            file
+        ));
+    }
+    Ok(())
+}
+
+/// モデルを解いて結果を表示し、反復ごとの最良目的値の推移を返す
+fn cmd_solve(file: &str, args: &Args) -> Vec<f64> {
+    let start = Instant::now();
+    let mut model = load_model(file, args.data.as_deref());
+
+    // 結果の表示は元のモデル（変数消去前）で行う
+    let original = model.clone();
+    let presolved = presolve::presolve(&mut model);
+
+    // モデルの solve: ブロックをコマンドラインより弱い既定値として反映
+    let mut resolved = match args.with_model_defaults(&model.hints.to_flags()) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // スレッド数の指定が無ければ推定作業量から決める（自動モードの threads はコア数）
+    let cores = resolved.threads;
+    let auto = (!resolved.threads_explicit).then(|| auto_threads(&model, &resolved));
+    if let Some((n, _)) = auto {
+        resolved.threads = n;
+    }
+    let args = &resolved;
+    if args.deterministic && args.migration_interval > 0 {
+        eprintln!("warning: --migration-interval has no effect with --deterministic");
+    }
+
+    // 目的の書き忘れを既定の Sphere 関数で「解けた」ことにしない
+    if let Err(e) = check_objective(file, &model, args.require_objective) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+
+    let seed = resolve_seed(args.seed);
+
+    // ウォームスタート（元の変数空間で読み、縮約後の座標に射影）
+    let warm = match &args.warm_start {
+        Some(path) => match parser::load_warm_start(&original, std::path::Path::new(path)) {
+            Ok(w) => Some(model.reduce_point(&w)),
+            Err(e) => {
+                eprintln!("error: warm start {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            if args.warm_start_method.is_some() {
+                eprintln!("warning: --warm-start-method has no effect without --warm-start");
+            }
+            None
+        }
+    };
+    // 初期集団のCSV（ヒューリスティックの集団・群に入れる）
+    let warm_pool: Vec<Vec<f64>> = match &args.warm_csv {
+        Some(path) => match parser::load_warm_csv(&original, std::path::Path::new(path)) {
+            Ok(rows) => rows.iter().map(|w| model.reduce_point(w)).collect(),
+            Err(e) => {
+                eprintln!("error: warm csv {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
+    // ウォームスタート時は --warm-start-method が -m より優先する（cp-sat なら厳密解法へ引き継ぐ）
+    let warm_method = args.warm_start_method.as_deref().filter(|_| warm.is_some());
+    let method_arg = match warm_method {
+        Some(m) if m != "cp-sat" => m,
+        _ => args.method.as_str(),
+    };
+    let use_cp = match warm_method {
+        Some(m) => m == "cp-sat",
+        None => !model.cp_globals.is_empty(),
+    };
 
     if args.verbose {
+        eprintln!("[optica] seed={}", seed);
         eprintln!(
             "[optica] dim={}, method={}, threads={}",
-            model.dim, args.method, args.threads
+            model.dim,
+            if use_cp {
+                "cp-sat"
+            } else {
+                resolve_method(&model, method_arg)
+            },
+            args.threads
         );
+        if !use_cp && resolve_method(&model, method_arg) == "hybrid" {
+            let (explore, refine) = crate::solver::hybrid_phases(args.max_iter, args.hybrid_split);
+            eprintln!(
+                "[optica] hybrid: {} DE + {} PSO iterations (split {})",
+                explore, refine, args.hybrid_split
+            );
+        }
+        if let Some((n, work)) = auto {
+            eprintln!(
+                "[optica] threads: auto -> {} (estimated work {} / {} per thread, max {})",
+                n, work, THREAD_MIN_WORK, cores
+            );
+        }
+        if !warm_pool.is_empty() {
+            eprintln!("[optica] warm csv: {} initial point(s)", warm_pool.len());
+        }
+        eprintln!(
+            "[optica] presolve: folded {} bound constraint(s)",
+            presolved.folded_bounds
+        );
+        if !presolved.eliminated.is_empty() {
+            eprintln!(
+                "[optica] presolve: eliminated {} variable(s): {}",
+                presolved.eliminated.len(),
+                presolved.eliminated.join(", ")
+            );
+        }
+    }
+
+    let mut opts = solver_options(&model, args);
+    opts.seed = seed;
+    let trace = crate::solver::Trace::new();
+    opts.trace = Some(trace.clone());
+    if args.stream_json {
+        let maximize = model.maximize;
+        opts.progress = Some(crate::solver::Progress::new(
+            args.stream_interval,
+            move |iter, fit, feasible| {
+                print_json_line(&serde_json::json!({
+                    "type": "progress",
+                    "iteration": iter,
+                    "objective": if maximize { -fit } else { fit },
+                    "feasible": feasible,
+                }));
+            },
+        ));
+    } else if let Some(every) = args
+        .progress_every
+        .or(args.verbose.then_some(PROGRESS_EVERY))
+    {
+        let maximize = model.maximize;
+        opts.progress = Some(crate::solver::Progress::new(
+            every,
+            move |iter, fit, feasible| {
+                eprintln!(
+                    "[optica] iter {}: best {:.6e}{}",
+                    iter,
+                    if maximize { -fit } else { fit },
+                    if feasible { "" } else { " (infeasible)" }
+                );
+            },
+        ));
+    }
+
+    opts.warm.extend(warm.clone());
+    opts.warm.extend(warm_pool.iter().cloned());
+
+    // 多目的のパレートフロント（--pareto front / `pareto method: front`）
+    if args.pareto_front || matches!(model.pareto, parser::ParetoMethod::Front) {
+        if model.objectives.len() < 2 {
+            eprintln!("error: --pareto front needs at least two objectives (objectives: block)");
+            std::process::exit(1);
+        }
+        let front: Vec<(Vec<f64>, Vec<f64>)> = crate::solver::pareto_front(&model, &opts)
+            .into_iter()
+            .map(|(mut x, raw)| {
+                model.round_integers(&mut x);
+                (model.expand_solution(&x).into_owned(), raw)
+            })
+            .collect();
+        print_front(&original, &front, start.elapsed(), args);
+        return Vec::new();
     }
 
     // CP制約があればCP-SATで解く
-    let has_cp = !model.cp_globals.is_empty();
-    let (best, fitness, iters) = if has_cp {
-        if let Some(res) = crate::solver::solve_cp_entry(&model, args.max_iter, args.threads) {
-            res
-        } else {
-            eprintln!("cp-sat unavailable; fallback to heuristic");
-            solve_heuristic(&model, args)
+    let mut fallback: Option<&str> = None;
+    let result = if use_cp {
+        // 初期ヒント: ウォームスタートの点、または短いヒューリスティック探索の解
+        let hint = warm.clone().or_else(|| {
+            args.heuristic_then_exact.then(|| {
+                let mut short = opts.clone();
+                short.max_iter = (args.max_iter / HINT_ITER_DIVISOR).max(1);
+                short.trace = None;
+                short.progress = None;
+                solve_heuristic(&model, resolve_method(&model, method_arg), &short).0
+            })
+        });
+        let hint = hint.map(|mut x| {
+            model.round_integers(&mut x);
+            x
+        });
+        match crate::solver::solve_cp_entry(
+            &model,
+            args.threads,
+            hint.as_deref(),
+            opts.deadline.as_ref(),
+        ) {
+            Some(
+                CpOutcome::Optimal(x, fitness, iters) | CpOutcome::Feasible(x, fitness, iters),
+            ) => SolveResult::new(&model, x, fitness, iters),
+            // 実行不能の証明はヒューリスティックの「解」で覆い隠さずにそのまま伝える
+            Some(CpOutcome::Infeasible) => {
+                report_proven_infeasible(&original, start.elapsed(), args);
+                std::process::exit(EXIT_INFEASIBLE);
+            }
+            None => {
+                let method = match args.cp_fallback.as_str() {
+                    "none" => {
+                        eprintln!("error: cp-sat unavailable (--cp-fallback none)");
+                        std::process::exit(1);
+                    }
+                    "auto" => resolve_method(&model, method_arg),
+                    m => m,
+                };
+                eprintln!("warning: cp-sat unavailable; falling back to {}", method);
+                fallback = Some(method);
+                optica::solve_with(&model, method, &opts)
+            }
         }
     } else {
-        solve_heuristic(&model, args)
+        optica::solve_with(&model, method_arg, &opts)
     };
 
+    let SolveResult {
+        best,
+        fitness,
+        iterations: iters,
+        ..
+    } = result;
+    let best = model.expand_solution(&best).into_owned();
     let elapsed = start.elapsed();
-    let obj = if model.maximize { -fitness } else { fitness };
+    if let (true, Some(c)) = (args.verbose, &opts.fitness_cache) {
+        let (hits, lookups) = c.stats();
+        eprintln!(
+            "[optica] fitness cache: {} hits / {} lookups ({:.1}%)",
+            hits,
+            lookups,
+            100.0 * hits as f64 / lookups.max(1) as f64
+        );
+    }
+    let non_finite = opts.non_finite_evaluations();
+    if args.verbose && non_finite > 0 {
+        eprintln!(
+            "[optica] warning: {} evaluation(s) gave NaN/inf (treated as the worst fitness)",
+            non_finite
+        );
+    }
+    if let (true, Some(m)) = (args.verbose, &opts.migration) {
+        eprintln!(
+            "[optica] migration: {} adoption(s) of the shared best",
+            m.adoptions()
+        );
+    }
+    if let (true, Some(r)) = (args.verbose, &opts.restart) {
+        let at = r.iterations();
+        let list: Vec<String> = at.iter().map(|i| i.to_string()).collect();
+        eprintln!(
+            "[optica] restarts: {}{}",
+            at.len(),
+            if at.is_empty() {
+                String::new()
+            } else {
+                format!(" (at iterations {})", list.join(", "))
+            }
+        );
+    }
+    // --timeout 指定時は止まった理由（時間 / 反復数）を示す
+    let stop_reason = opts.deadline.as_ref().map(|d| {
+        if d.reached() {
+            format!("time limit ({}s)", d.limit.as_secs_f64())
+        } else if iters < args.max_iter {
+            "converged".to_string()
+        } else {
+            "iteration limit".to_string()
+        }
+    });
+    // 実行不能な解でもペナルティを除いた目的値を表示する（違反量は別に示す）
+    let (obj, violation) = split_objective(&original, &best);
 
-    if args.quiet {
+    // 機械可読の結果（--format json / --stream-json の最終レコード）。表示閾値で省かず全変数を含む
+    let record = || {
+        let (feasible, _) = original.check_constraints(&best);
+        let vars: serde_json::Map<String, serde_json::Value> = original
+            .var_names
+            .iter()
+            .zip(&best)
+            .map(|(n, v)| (n.clone(), serde_json::json!(v)))
+            .collect();
+        let mut r = serde_json::json!({
+            "model": original.name,
+            "status": Status::classify(fitness, violation).as_str(),
+            "objective": obj,
+            "feasible": feasible,
+            "violation": violation,
+            "iterations": iters,
+            "time_seconds": elapsed.as_secs_f64(),
+            "variables": vars,
+        });
+        if let Some(method) = fallback {
+            r["fallback"] = serde_json::json!(method);
+        }
+        if let Some(bound) = args.objective_bound {
+            r["gap"] = serde_json::json!(
+                optimality_gap(obj, bound, model.maximize).and_then(|(_, rel)| rel)
+            );
+        }
+        if let Some(stop) = &stop_reason {
+            r["stopped"] = serde_json::json!(stop);
+        }
+        r
+    };
+
+    if args.stream_json {
+        let mut r = record();
+        r["type"] = serde_json::json!("result");
+        print_json_line(&r);
+    } else if args.solution_format == SolutionFormat::Flat {
+        println!("{}", flat_solution(&best));
+    } else if args.format == OutputFormat::Csv {
+        // 表計算向け: 全変数を var_names の順に1行ずつ（--quiet なら末尾の要約コメントを省く）
+        println!("variable,value");
+        for (name, v) in original.var_names.iter().zip(&best) {
+            println!("{},{}", csv_field(name), v);
+        }
+        if !args.quiet {
+            println!("# objective={},iterations={}", obj, iters);
+        }
+    } else if args.quiet {
         println!("{:.6e}", obj);
+    } else if args.format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&record()).unwrap_or_default()
+        );
     } else {
-        print_result(&model, &best, obj, fitness, iters, elapsed);
+        print_result(
+            &original, &best, obj, fitness, violation, iters, elapsed, fallback,
+        );
+        if let Some(stop) = stop_reason.as_deref() {
+            println!("Stopped: {}", stop);
+        }
+        if let Some(bound) = args.objective_bound {
+            print_gap(obj, bound, model.maximize);
+        }
+    }
+    if args.penalty_report {
+        print_penalty_report(&original, &best);
+    }
+    if args.binding_report {
+        print_binding_report(&original, &best, args.binding_tol);
+    }
+    if args.gradient_report {
+        print_gradient_report(&original, &best);
+    }
+
+    // 推移は目的関数の向きに戻して扱う
+    let history: Vec<f64> = trace
+        .values()
+        .into_iter()
+        .map(|f| if model.maximize { -f } else { f })
+        .collect();
+    // 収束状況: -v または --objective-history-window で表示（CP-SAT は推移が無いので対象外）
+    let window = args
+        .history_window
+        .or(args.verbose.then_some(PROGRESS_WINDOW));
+    let quiet = args.quiet || args.stream_json || args.format != OutputFormat::Text;
+    if let (Some(window), false, false) = (window, quiet, history.is_empty()) {
+        let fits = trace.values();
+        let feasible = crate::solver::penalty_breakdown(&original, &best)
+            .iter()
+            .all(|(_, p)| *p <= 0.0);
+        let early = fits.len() < args.max_iter;
+        let (status, rate) = classify_progress(&fits, window, feasible, early);
+        println!(
+            "\nProgress: {} (last {} iterations: {:.3e} per iteration)",
+            status,
+            window.min(fits.len().saturating_sub(1)),
+            rate
+        );
+    }
+    if let Some(path) = &args.trace {
+        let mut csv = String::from("iter,best\n");
+        for (i, v) in history.iter().enumerate() {
+            csv.push_str(&format!("{},{}\n", i + 1, v));
+        }
+        if let Err(e) = fs::write(path, csv) {
+            eprintln!("warning: failed to write trace {}: {}", path, e);
+        }
+    }
+    // 最良解の保存（--warm-start で読み直して再開・別の手法の初期解にする）
+    if let Some(path) = &args.dump_solution {
+        let vars: serde_json::Map<String, serde_json::Value> = original
+            .var_names
+            .iter()
+            .zip(&best)
+            .map(|(n, v)| (n.clone(), serde_json::json!(v)))
+            .collect();
+        let json = serde_json::to_string_pretty(&vars).unwrap_or_default();
+        if let Err(e) = fs::write(path, json + "\n") {
+            eprintln!("warning: failed to write solution {}: {}", path, e);
+        }
+    }
+
+    // 実行可能解が出ないまま打ち切った場合は専用の終了コードで終える（解は表示済み）
+    if let Some(w) = opts.feasible_watch.as_ref().filter(|w| w.aborted()) {
+        if !quiet {
+            println!(
+                "\nStatus: infeasible within budget (no feasible point in {} generations)",
+                w.generations
+            );
+        }
+        std::process::exit(EXIT_INFEASIBLE);
     }
+
+    // CI向け: 最良解が実行不能なら専用の終了コードで終える（解は表示済み）
+    if args.fail_on_infeasible {
+        let violated: Vec<String> = crate::solver::penalty_breakdown(&original, &best)
+            .into_iter()
+            .filter(|(_, p)| *p > 0.0)
+            .map(|(name, _)| name)
+            .collect();
+        if !violated.is_empty() {
+            eprintln!(
+                "error: best solution is infeasible (violated: {})",
+                violated.join(", ")
+            );
+            std::process::exit(EXIT_INFEASIBLE);
+        }
+    }
+    history
 }
 
 fn sidecar_json_path(file: &str) -> Option<std::path::PathBuf> {
@@ -135,75 +747,472 @@ fn sidecar_json_path(file: &str) -> Option<std::path::PathBuf> {
     Some(pb)
 }
 
-fn solve_heuristic(model: &parser::Model, args: &Args) -> (Vec<f64>, f64, usize) {
-    match args.method.as_str() {
-        "de" => crate::solver::de(model, args.max_iter, args.threads),
-        "pso" => crate::solver::pso(model, args.max_iter),
-        "hybrid" => crate::solver::hybrid(model, args.max_iter, args.threads),
-        _ => {
-            if model.dim <= 20 {
-                crate::solver::pso(model, args.max_iter)
-            } else {
-                crate::solver::de(model, args.max_iter, args.threads)
-            }
+/// 実際に使う乱数の種（`--seed 0` / `random` はOSのエントロピーから引き、再現用に表示する。
+/// 未指定なら固定の既定値）
+fn resolve_seed(seed: Option<u64>) -> u64 {
+    match seed {
+        Some(0) => {
+            let s = entropy_seed();
+            eprintln!("seed: {}", s);
+            s
+        }
+        Some(s) => s,
+        None => DEFAULT_SEED,
+    }
+}
+
+/// OSのエントロピー（ハッシュの乱択キー）と現在時刻から0以外の種を作る
+fn entropy_seed() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    let mut h = RandomState::new().build_hasher();
+    if let Ok(d) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        h.write_u128(d.as_nanos());
+    }
+    h.write_u32(std::process::id());
+    h.finish().max(1)
+}
+
+/// 自動スレッド数: 推定作業量 (次元 + 制約数) × 集団 × 反復 を `THREAD_MIN_WORK` で割り、
+/// `args.threads`（自動モードではコア数）で頭打ちにする。戻り値は (スレッド数, 推定作業量)
+fn auto_threads(model: &parser::Model, args: &Args) -> (usize, usize) {
+    let work = (model.dim + model.constraints.len())
+        .saturating_mul(args.pop_size.or(model.hints.pop_size).unwrap_or(POP_SIZE))
+        .saturating_mul(args.max_iter);
+    ((work / THREAD_MIN_WORK).clamp(1, args.threads.max(1)), work)
+}
+
+fn solver_options(model: &parser::Model, args: &Args) -> crate::solver::Options {
+    let mut opts = crate::solver::Options::new(args.max_iter, args.threads);
+    opts.islands = args.islands;
+    opts.resample = args.resample;
+    opts.hybrid_split = args.hybrid_split;
+    opts.polish = args.polish;
+    if args.cache_fitness {
+        opts.fitness_cache = Some(crate::solver::FitnessCache::new(
+            FITNESS_CACHE_CAPACITY,
+            FITNESS_CACHE_QUANTUM,
+        ));
+    }
+    opts.feasible_watch = args
+        .max_gen_without_feasible
+        .map(crate::solver::FeasibleWatch::new);
+    opts.deadline = args.time_limit.map(crate::solver::Deadline::new);
+    opts.patience = args.patience;
+    opts.constraint_mode = args.constraint_mode;
+    // コマンドラインの指定を `solve:` ブロックより優先する
+    let h = &model.hints;
+    let dp = &mut opts.de;
+    dp.pop_size = args.pop_size.or(h.pop_size).unwrap_or(dp.pop_size);
+    dp.f = args.de_f.or(h.de_f).unwrap_or(dp.f);
+    dp.cr = args.de_cr.or(h.de_cr).unwrap_or(dp.cr);
+    let pp = &mut opts.pso;
+    pp.particles = args.pop_size.or(h.pop_size).unwrap_or(pp.particles);
+    pp.w_init = args.pso_w.or(h.w_init).unwrap_or(pp.w_init);
+    pp.w_min = h.w_min.unwrap_or(pp.w_min);
+    pp.w_decay = h.w_decay.unwrap_or(pp.w_decay);
+    pp.c1 = args.pso_c1.or(h.c1).unwrap_or(pp.c1);
+    pp.c2 = args.pso_c2.or(h.c2).unwrap_or(pp.c2);
+    if args.restart_fraction.is_some()
+        || args.diversity_threshold.is_some()
+        || args.restart_interval.is_some()
+    {
+        let mut r = crate::solver::Restart::new(
+            args.restart_fraction.unwrap_or(RESTART_FRACTION),
+            args.diversity_threshold.unwrap_or(DIVERSITY_THRESHOLD),
+        );
+        r.interval = args.restart_interval.unwrap_or(1);
+        opts.restart = Some(r);
+    }
+    if args.migration_interval > 0 {
+        opts.migration = Some(crate::solver::Migration::new(args.migration_interval));
+    }
+    opts.deterministic = args.deterministic;
+    opts
+}
+
+/// CP-SATが実行不能を証明したときの報告（解は無いので状態だけを出す）
+fn report_proven_infeasible(model: &parser::Model, elapsed: std::time::Duration, args: &Args) {
+    let record = serde_json::json!({
+        "model": model.name,
+        "status": Status::Infeasible.as_str(),
+        "proven": true,
+        "time_seconds": elapsed.as_secs_f64(),
+    });
+    if args.stream_json {
+        let mut r = record;
+        r["type"] = serde_json::json!("result");
+        print_json_line(&r);
+    } else if args.format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&record).unwrap_or_default()
+        );
+    } else if !args.quiet {
+        println!("Status: infeasible (proven by cp-sat)");
+        println!("Time: {:.3}s", elapsed.as_secs_f64());
+    }
+}
+
+/// 解の目的値（ペナルティを含まない、目的関数の向きのまま）と制約違反の合計
+///
+/// 多目的では重み付き和などのスカラー値。違反はペナルティ係数を掛けない量（`Model::total_violation`）。
+fn split_objective(model: &parser::Model, x: &[f64]) -> (f64, f64) {
+    let (scalar, _, _) = model.evaluate_all(x);
+    let violation = model.total_violation(x);
+    (if model.maximize { -scalar } else { scalar }, violation)
+}
+
+/// パレートフロントの表示（テキストは目的値の表、JSONは変数も含める）
+fn print_front(
+    model: &parser::Model,
+    front: &[(Vec<f64>, Vec<f64>)],
+    elapsed: std::time::Duration,
+    args: &Args,
+) {
+    let names: Vec<&str> = model.objectives.iter().map(|o| o.name.as_str()).collect();
+    if args.format == OutputFormat::Json || args.stream_json {
+        let points: Vec<serde_json::Value> = front
+            .iter()
+            .map(|(x, raw)| {
+                let objectives: serde_json::Map<String, serde_json::Value> = names
+                    .iter()
+                    .zip(raw)
+                    .map(|(n, v)| (n.to_string(), serde_json::json!(v)))
+                    .collect();
+                let vars: serde_json::Map<String, serde_json::Value> = model
+                    .var_names
+                    .iter()
+                    .zip(x)
+                    .map(|(n, v)| (n.clone(), serde_json::json!(v)))
+                    .collect();
+                serde_json::json!({
+                    "objectives": objectives,
+                    "feasible": model.check_constraints(x).0,
+                    "variables": vars,
+                })
+            })
+            .collect();
+        let mut r = serde_json::json!({
+            "model": model.name,
+            "status": "pareto_front",
+            "objectives": names,
+            "front": points,
+            "time_seconds": elapsed.as_secs_f64(),
+        });
+        if args.stream_json {
+            r["type"] = serde_json::json!("result");
+            print_json_line(&r);
+        } else {
+            println!("{}", serde_json::to_string_pretty(&r).unwrap_or_default());
+        }
+        return;
+    }
+    if args.quiet {
+        for (_, raw) in front {
+            let row: Vec<String> = raw.iter().map(|v| format!("{:.6e}", v)).collect();
+            println!("{}", row.join(" "));
         }
+        return;
+    }
+
+    println!();
+    if let Some(name) = &model.name {
+        println!("Model: {}", name);
     }
+    println!("Pareto front: {} solution(s)", front.len());
+    println!("Time: {:.3}s", elapsed.as_secs_f64());
+    println!();
+    let head: Vec<String> = names.iter().map(|n| format!("{:>14}", n)).collect();
+    println!("{:>4}  {}", "#", head.join(" "));
+    let mut infeasible = false;
+    for (i, (x, raw)) in front.iter().enumerate() {
+        let row: Vec<String> = raw.iter().map(|v| format!("{:>14.6e}", v)).collect();
+        let mark = if model.check_constraints(x).0 {
+            ""
+        } else {
+            infeasible = true;
+            "  *"
+        };
+        println!("{:>4}  {}{}", i + 1, row.join(" "), mark);
+    }
+    if infeasible {
+        println!("\n* violates constraints (no feasible point found)");
+    }
+    println!("\nUse --format json for the variables of each solution.");
 }
 
+#[allow(clippy::too_many_arguments)]
 fn print_result(
     model: &parser::Model,
     best: &[f64],
     obj: f64,
     fitness: f64,
+    violation: f64,
     iters: usize,
     elapsed: std::time::Duration,
+    fallback: Option<&str>,
 ) {
-    println!(
-        "\nStatus: {}",
-        if fitness.abs() < TOLERANCE {
-            "optimal"
-        } else {
-            "feasible"
-        }
-    );
-    println!("Objective: {:.6e}", obj);
+    println!();
+    if let Some(name) = &model.name {
+        println!("Model: {}", name);
+    }
+    println!("Status: {}", Status::classify(fitness, violation).as_str());
+    if violation > 0.0 {
+        // 適合度はペナルティを含むので、目的値と違反量を分けて示す
+        println!("Objective: {:.6e} (penalty excluded)", obj);
+        println!("Violation: {:.6e}", violation);
+    } else {
+        println!("Objective: {:.6e}", obj);
+    }
     println!("Time: {:.3}s", elapsed.as_secs_f64());
     println!("Iterations: {}", iters);
+    if let Some(method) = fallback {
+        println!("Solver: {} (fallback: cp-sat unavailable)", method);
+    }
+
+    // 多目的: 目的ごとの値
+    if model.objectives.len() > 1 {
+        let (_scalar, values, _violation) = model.evaluate_all(best);
+        println!("\nObjectives:");
+        for (o, v) in model.objectives.iter().zip(values) {
+            println!("  {} = {:.6e}", o.name, v);
+        }
+    }
 
     if !model.var_names.is_empty() {
         println!("\nVariables:");
         for (i, name) in model.var_names.iter().enumerate() {
             if best[i].abs() > DISPLAY_TOLERANCE {
-                println!("  {} = {:.6}", name, best[i]);
+                if model.var_type(i).is_integral() {
+                    println!("  {} = {}", name, best[i].round() as i64);
+                } else {
+                    println!("  {} = {:.6}", name, best[i]);
+                }
             }
         }
     }
 }
 
+/// CSVの1フィールド（`x[A,1]` のようにカンマや引用符を含む名前は引用符で囲む）
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+/// JSONを1行で出力してすぐ書き出す（`--stream-json` の読み手が逐次受け取れるように）
+fn print_json_line(value: &serde_json::Value) {
+    let mut out = io::stdout().lock();
+    let _ = writeln!(out, "{}", value);
+    let _ = out.flush();
+}
+
+/// 最適性ギャップ（既知の限界との差）
+///
+/// 戻り値は (絶対ギャップ, 相対ギャップ `差 / |bound|`)。差は最小化なら `obj - bound`、
+/// 最大化なら `bound - obj`。限界が0なら相対ギャップは定義しない。目的値が限界を
+/// 越えている（限界が正しくない）場合は `None`。
+fn optimality_gap(obj: f64, bound: f64, maximize: bool) -> Option<(f64, Option<f64>)> {
+    let diff = if maximize { bound - obj } else { obj - bound };
+    if diff < -DISPLAY_TOLERANCE * bound.abs().max(1.0) {
+        return None;
+    }
+    let diff = diff.max(0.0);
+    let rel = (bound.abs() >= TOLERANCE).then(|| diff / bound.abs());
+    Some((diff, rel))
+}
+
+fn print_gap(obj: f64, bound: f64, maximize: bool) {
+    match optimality_gap(obj, bound, maximize) {
+        Some((_, Some(rel))) => {
+            println!("Gap: {:.4}% (bound {:.6e})", rel * 100.0, bound)
+        }
+        Some((abs, None)) => println!("Gap: {:.6e} (absolute; bound is 0)", abs),
+        None => println!(
+            "Gap: n/a (objective {:.6e} is better than the bound {:.6e}; the bound is not valid)",
+            obj, bound
+        ),
+    }
+}
+
+/// `--solution-format flat` の1行（下流の数値ツール向け）
+///
+/// 表示閾値で省かず、全変数の値を `var_names` の順に空白区切りで並べる。
+fn flat_solution(best: &[f64]) -> String {
+    let values: Vec<String> = best.iter().map(|v| v.to_string()).collect();
+    values.join(" ")
+}
+
+/// 最良適合度の推移（最小化方向の累積最小値）から収束状況を判定する
+///
+/// 直近 `window` 反復の平均改善量（1反復あたり）を求め、改善が最終値の `PROGRESS_REL_TOL`
+/// 倍以上なら "slowly improving"。改善が無い場合、最良解が実行可能か許容誤差で早期終了して
+/// いれば "converged"、実行不能のまま止まっていれば "stalled"。戻り値は (状況, 平均改善量)。
+fn classify_progress(
+    fits: &[f64],
+    window: usize,
+    feasible: bool,
+    ended_early: bool,
+) -> (&'static str, f64) {
+    let Some(&last) = fits.last() else {
+        return ("converged", 0.0);
+    };
+    let w = window.min(fits.len() - 1);
+    let rate = if w == 0 {
+        0.0
+    } else {
+        (fits[fits.len() - 1 - w] - last) / w as f64
+    };
+    let status = if ended_early {
+        "converged"
+    } else if rate * w as f64 > last.abs().max(TOLERANCE) * PROGRESS_REL_TOL {
+        "slowly improving"
+    } else if feasible {
+        "converged"
+    } else {
+        "stalled"
+    };
+    (status, rate)
+}
+
+/// 最良解での目的関数の勾配（全変数）と、制約左辺の勾配（0でない成分のみ）を表示する
+fn print_gradient_report(model: &parser::Model, best: &[f64]) {
+    let width = model.var_names.iter().map(|n| n.len()).max().unwrap_or(0);
+    println!("\nGradient (objective):");
+    for (name, g) in model.var_names.iter().zip(model.gradient(best)) {
+        println!("  {:<width$}  {:.6e}", name, g, width = width);
+    }
+    if model.constraints.is_empty() {
+        return;
+    }
+    println!("\nGradient (constraint lhs):");
+    for (c, grad) in model
+        .constraints
+        .iter()
+        .zip(model.constraint_gradients(best))
+    {
+        let terms: Vec<String> = model
+            .var_names
+            .iter()
+            .zip(&grad)
+            .filter(|(_, g)| g.abs() > DISPLAY_TOLERANCE)
+            .map(|(name, g)| format!("{} = {:.6e}", name, g))
+            .collect();
+        println!("  {}: {}", c.name, terms.join(", "));
+    }
+}
+
+/// 制約ごとのペナルティ寄与（違反量 × 係数）を大きい順に表示
+fn print_penalty_report(model: &parser::Model, best: &[f64]) {
+    let rows = crate::solver::penalty_breakdown(model, best);
+    if rows.is_empty() {
+        println!("\nPenalty: no constraints");
+        return;
+    }
+    let total: f64 = rows.iter().map(|(_, p)| p).sum();
+    // `[penalty=W]` を付けた制約はその係数、残りは全体の係数
+    let custom = model.constraints.iter().any(|c| c.penalty.is_some());
+    println!(
+        "\nPenalty by constraint ({}coefficient {:.1e}):",
+        if custom { "default " } else { "" },
+        crate::solver::penalty_coeff()
+    );
+    let width = rows
+        .iter()
+        .map(|(n, _)| n.len())
+        .fold("total".len(), usize::max);
+    for (name, p) in &rows {
+        let share = if total > 0.0 { p / total * 100.0 } else { 0.0 };
+        println!(
+            "  {:<width$}  {:.6e}  {:>5.1}%",
+            name,
+            p,
+            share,
+            width = width
+        );
+    }
+    println!("  {:<width$}  {:.6e}", "total", total, width = width);
+}
+
+/// 不等式制約を binding（|左辺 - 右辺| < tol）・slack・violated に分けて表示
+///
+/// 等式制約は常に binding なので一覧には含めない。
+fn print_binding_report(model: &parser::Model, best: &[f64], tol: f64) {
+    let mut x = best.to_vec();
+    model.round_integers(&mut x);
+    // (名前, 左辺, 演算子, 右辺, 余裕)。余裕は満たす向きが正
+    let rows: Vec<(&str, f64, &str, f64, f64)> = model
+        .constraints
+        .iter()
+        .filter_map(|c| {
+            let lhs = model.constraint_lhs(c, &x);
+            let rhs = model.constraint_rhs(c, &x);
+            let (op, slack) = match c.op {
+                parser::ConstraintOp::Le => ("<=", rhs - lhs),
+                parser::ConstraintOp::Ge => (">=", lhs - rhs),
+                parser::ConstraintOp::Eq => return None,
+            };
+            Some((c.name.as_str(), lhs, op, rhs, slack))
+        })
+        .collect();
+    if rows.is_empty() {
+        println!("\nBinding constraints: no inequality constraints");
+        return;
+    }
+    let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let class = |slack: f64| {
+        if slack.abs() < tol {
+            "Binding"
+        } else if slack > 0.0 {
+            "Slack"
+        } else {
+            "Violated"
+        }
+    };
+    for title in ["Binding", "Slack", "Violated"] {
+        let group: Vec<_> = rows.iter().filter(|r| class(r.4) == title).collect();
+        if group.is_empty() {
+            continue;
+        }
+        println!("\n{} constraints (tol {:.1e}):", title, tol);
+        for (name, lhs, op, rhs, slack) in group {
+            println!(
+                "  {:<width$}  {:.6e} {} {:.6e}  (slack {:.3e})",
+                name,
+                lhs,
+                op,
+                rhs,
+                slack,
+                width = width
+            );
+        }
+    }
+}
+
 fn cmd_bench(dim: usize, threads: usize) {
     println!("Benchmark: dim={}, threads={}", dim, threads);
     println!("{}", "-".repeat(50));
 
-    let lb: Vec<f64> = vec![-5.0; dim];
-    let ub: Vec<f64> = vec![5.0; dim];
-    let mut model = parser::Model::new();
-    model.lb = lb.clone();
-    model.ub = ub.clone();
-    model.dim = dim;
-    model.maximize = false;
+    let model = bench_model(dim);
+
+    let single = crate::solver::Options::new(BENCH_ITER, 1);
+    let parallel = crate::solver::Options::new(BENCH_ITER, threads);
 
     // ウォームアップ
-    let _ = crate::solver::de(&model, 10, 1);
+    let _ = crate::solver::de(&model, &crate::solver::Options::new(10, 1));
 
     // DE
     let start = Instant::now();
-    let (_, f, _) = crate::solver::de(&model, 500, 1);
+    let (_, f, _) = crate::solver::de(&model, &single);
     let de_time = start.elapsed().as_secs_f64() * 1000.0;
     println!("DE:        {:>7.2}ms  f={:.2e}", de_time, f);
 
     // DE parallel
     let start = Instant::now();
-    let (_, f, _) = crate::solver::de(&model, 500, threads);
+    let (_, f, _) = crate::solver::de(&model, &parallel);
     let de_par_time = start.elapsed().as_secs_f64() * 1000.0;
     println!(
         "DE({}T):   {:>7.2}ms  f={:.2e}  {:.1}x",
@@ -215,22 +1224,431 @@ fn cmd_bench(dim: usize, threads: usize) {
 
     // PSO
     let start = Instant::now();
-    let (_, f, _) = crate::solver::pso(&model, 500);
+    let (_, f, _) = crate::solver::pso(&model, &single);
     let pso_time = start.elapsed().as_secs_f64() * 1000.0;
     println!("PSO:       {:>7.2}ms  f={:.2e}", pso_time, f);
 
     // Hybrid
     let start = Instant::now();
-    let (_, f, _) = crate::solver::hybrid(&model, 500, threads);
+    let (_, f, _) = crate::solver::hybrid(&model, &parallel);
     let hybrid_time = start.elapsed().as_secs_f64() * 1000.0;
     println!("Hybrid:    {:>7.2}ms  f={:.2e}", hybrid_time, f);
 
     println!("\nBest: DE({}T) = {:.2}ms", threads, de_par_time);
 }
 
+/// モデルを読み込んでプリソルブし、`solve:` ブロックを反映した引数と共に返す
+///
+/// 返り値は (元のモデル, プリソルブ後のモデル, 引数)。
+fn load_presolved(file: &str, args: &Args) -> (parser::Model, parser::Model, Args) {
+    let mut model = load_model(file, args.data.as_deref());
+    let original = model.clone();
+    presolve::presolve(&mut model);
+    match args.with_model_defaults(&model.hints.to_flags()) {
+        Ok(a) => (original, model, a),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 1回分の求解結果
+struct RunResult {
+    best: Vec<f64>, // 元の変数空間の解
+    fitness: f64,
+    time: f64, // 秒
+}
+
+/// `items` の各要素に `f` を適用する。`threads` 本のワーカーが順に要素を取って処理する
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let results: Vec<std::sync::Mutex<Option<R>>> =
+        items.iter().map(|_| std::sync::Mutex::new(None)).collect();
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let workers = threads.clamp(1, items.len().max(1));
+    std::thread::scope(|sc| {
+        for _ in 0..workers {
+            sc.spawn(|| loop {
+                let k = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(item) = items.get(k) else {
+                    break;
+                };
+                *results[k].lock().unwrap() = Some(f(item));
+            });
+        }
+    });
+    results
+        .into_iter()
+        .filter_map(|r| r.into_inner().unwrap())
+        .collect()
+}
+
+/// 単一スレッドで1回解き、整数を丸めて元の変数空間の解を返す
+fn run_once(
+    model: &parser::Model,
+    args: &Args,
+    method: &str,
+    seed: u64,
+    max_iter: usize,
+) -> RunResult {
+    let mut opts = solver_options(model, args);
+    opts.threads = 1;
+    opts.max_iter = max_iter;
+    opts.seed = seed;
+    let start = Instant::now();
+    let (mut best, fitness, _) = solve_heuristic(model, method, &opts);
+    let time = start.elapsed().as_secs_f64();
+    model.round_integers(&mut best);
+    RunResult {
+        best: model.expand_solution(&best).into_owned(),
+        fitness,
+        time,
+    }
+}
+
+/// (メソッド, 種, 反復数) の各組を単一スレッドで解く。組同士は `--threads` 本で並列に実行する
+fn run_jobs(model: &parser::Model, args: &Args, jobs: &[(&str, u64, usize)]) -> Vec<RunResult> {
+    parallel_map(jobs, args.threads, |&(method, seed, max_iter)| {
+        run_once(model, args, method, seed, max_iter)
+    })
+}
+
+/// モデルファイルを整形して標準出力に書く（`--write` ならファイルを書き換える）
+fn cmd_fmt(file: &str, args: &Args) {
+    let formatted = read_source(file)
+        .map_err(|e| format!("error: {}: {}", file, e))
+        .and_then(|source| formatter::format_source(&source));
+    let formatted = match formatted {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // 標準入力から読んだ場合は書き戻す先が無いので常に標準出力へ
+    if !args.write || file == STDIN_FILE {
+        print!("{}", formatted);
+        return;
+    }
+    if let Err(e) = fs::write(file, formatted) {
+        eprintln!("error: {}: {}", file, e);
+        std::process::exit(1);
+    }
+}
+
+/// モデルを一度だけパースし、`--scenario-dir` の各JSONでパラメータを上書きして解く
+///
+/// シナリオはファイル名順に `--threads` 本で並列に解き（各シナリオは単一スレッド）、
+/// ファイル名をキーにした結果のJSON配列を出力する。
+fn cmd_scenarios(file: &str, args: &Args) {
+    let Some(dir) = &args.scenario_dir else {
+        eprintln!("error: scenarios requires --scenario-dir <DIR>");
+        std::process::exit(1);
+    };
+    let mut paths: Vec<std::path::PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(e) => {
+            eprintln!("error: {}: {}", dir, e);
+            std::process::exit(1);
+        }
+    };
+    paths.sort();
+    if paths.is_empty() {
+        eprintln!("error: no scenario JSON files in {}", dir);
+        std::process::exit(1);
+    }
+
+    let base = load_model(file, args.data.as_deref());
+    let args = &match args.with_model_defaults(&base.hints.to_flags()) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // シナリオごとのモデル: (名前, 元のモデル, プリソルブ後のモデル)
+    let scenarios: Vec<(String, parser::Model, parser::Model)> = paths
+        .iter()
+        .map(|path| {
+            let mut original = base.clone();
+            if let Err(e) = parser::load_json_into(&mut original, path) {
+                eprintln!("error: scenario {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            let mut model = original.clone();
+            presolve::presolve(&mut model);
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            (name, original, model)
+        })
+        .collect();
+
+    let seed = resolve_seed(args.seed);
+    let results = parallel_map(&scenarios, args.threads, |(_, _, model)| {
+        let method = resolve_method(model, &args.method);
+        run_once(model, args, method, seed, args.max_iter)
+    });
+
+    let table: Vec<serde_json::Value> = scenarios
+        .iter()
+        .zip(&results)
+        .map(|((name, original, model), r)| {
+            let (feasible, _) = original.check_constraints(&r.best);
+            let (obj, _) = split_objective(original, &r.best);
+            let vars: serde_json::Map<String, serde_json::Value> = original
+                .var_names
+                .iter()
+                .zip(&r.best)
+                .map(|(n, v)| (n.clone(), serde_json::json!(v)))
+                .collect();
+            let mut row = serde_json::json!({
+                "scenario": name,
+                "objective": obj,
+                "feasible": feasible,
+                "time": r.time,
+                "variables": vars,
+            });
+            if let Some(bound) = args.objective_bound {
+                // 相対ギャップ（限界が0または正しくない場合は null）
+                row["gap"] = serde_json::json!(
+                    optimality_gap(obj, bound, model.maximize).and_then(|(_, rel)| rel)
+                );
+            }
+            row
+        })
+        .collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&table).unwrap_or_default()
+    );
+}
+
+/// experiment で解く (メソッド, 種, 反復数) の組。メソッド・種・反復数の順に入れ子で並べる
+///
+/// 未指定の軸は通常の `-m` / `--seed` / `-i` の値1つになる。`auto` はモデルに応じて解決する。
+fn experiment_grid<'a>(model: &parser::Model, args: &'a Args) -> Vec<(&'a str, u64, usize)> {
+    let methods: Vec<&str> = if args.methods.is_empty() {
+        vec![resolve_method(model, &args.method)]
+    } else {
+        args.methods
+            .iter()
+            .map(|m| resolve_method(model, m))
+            .collect()
+    };
+    let seeds = if args.seeds.is_empty() {
+        vec![resolve_seed(args.seed)]
+    } else {
+        args.seeds.clone()
+    };
+    let iters = if args.iters.is_empty() {
+        vec![args.max_iter]
+    } else {
+        args.iters.clone()
+    };
+    let mut grid = Vec::with_capacity(methods.len() * seeds.len() * iters.len());
+    for &m in &methods {
+        for &seed in &seeds {
+            for &it in &iters {
+                grid.push((m, seed, it));
+            }
+        }
+    }
+    grid
+}
+
+/// メソッド × 種 × 反復数 の全組み合わせでモデルを解き、結果表をJSON（`--csv` でCSV）で出力
+fn cmd_experiment(file: &str, args: &Args) {
+    let (original, model, args) = load_presolved(file, args);
+    let args = &args;
+
+    let grid = experiment_grid(&model, args);
+    let results = run_jobs(&model, args, &grid);
+    let rows = grid.iter().zip(&results).map(|(&(m, seed, it), r)| {
+        let (feasible, _) = original.check_constraints(&r.best);
+        let (obj, _) = split_objective(&original, &r.best);
+        (m, seed, it, obj, feasible, r.time)
+    });
+    if args.csv {
+        println!("method,seed,iter,objective,feasible,time");
+        for (m, seed, it, obj, feasible, time) in rows {
+            println!("{},{},{},{:e},{},{:.6}", m, seed, it, obj, feasible, time);
+        }
+    } else {
+        let table: Vec<serde_json::Value> = rows
+            .map(|(m, seed, it, obj, feasible, time)| {
+                serde_json::json!({
+                    "method": m,
+                    "seed": seed,
+                    "iter": it,
+                    "objective": obj,
+                    "feasible": feasible,
+                    "time": time,
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&table).unwrap_or_default()
+        );
+    }
+}
+
+/// 種を変えて `--stability N` 回解き、変数ごとの平均・標準偏差を報告する
+///
+/// 標準偏差が変数の範囲の `STABILITY_REL_STD` 倍を超える変数を unstable とする。
+fn cmd_stability(file: &str, args: &Args) {
+    let (original, model, args) = load_presolved(file, args);
+    let args = &args;
+    let runs = args.stability;
+    let base = resolve_seed(args.seed);
+    let method = resolve_method(&model, &args.method);
+    let jobs: Vec<(&str, u64, usize)> = (0..runs as u64)
+        .map(|k| (method, base.wrapping_add(k), args.max_iter))
+        .collect();
+    let results = run_jobs(&model, args, &jobs);
+
+    let mean_std = |vals: &mut dyn Iterator<Item = f64>| -> (f64, f64) {
+        let v: Vec<f64> = vals.collect();
+        let n = v.len().max(1) as f64;
+        let mean = v.iter().sum::<f64>() / n;
+        let var = v.iter().map(|a| (a - mean) * (a - mean)).sum::<f64>() / n;
+        (mean, var.sqrt())
+    };
+    let sign = if model.maximize { -1.0 } else { 1.0 };
+    let (obj_mean, obj_std) = mean_std(&mut results.iter().map(|r| sign * r.fitness));
+    let rows: Vec<(&str, f64, f64, bool)> = original
+        .var_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let (mean, std) = mean_std(&mut results.iter().map(|r| r.best[i]));
+            let range = (original.ub[i] - original.lb[i]).abs().max(1e-12);
+            (name.as_str(), mean, std, std > STABILITY_REL_STD * range)
+        })
+        .collect();
+    let unstable = rows.iter().filter(|r| r.3).count();
+
+    if args.format == OutputFormat::Json {
+        let vars: Vec<serde_json::Value> = rows
+            .iter()
+            .map(|&(name, mean, std, unstable)| {
+                serde_json::json!({"name": name, "mean": mean, "std": std, "unstable": unstable})
+            })
+            .collect();
+        let out = serde_json::json!({
+            "model": original.name,
+            "runs": runs,
+            "method": method,
+            "seeds": [base, base.wrapping_add(runs.saturating_sub(1) as u64)],
+            "objective": {"mean": obj_mean, "std": obj_std},
+            "unstable": unstable,
+            "variables": vars,
+        });
+        println!("{}", serde_json::to_string_pretty(&out).unwrap_or_default());
+        return;
+    }
+
+    println!(
+        "Stability: {} runs, method={}, seeds {}..{}",
+        runs,
+        method,
+        base,
+        base.wrapping_add(runs.saturating_sub(1) as u64)
+    );
+    println!("Objective: mean={:.6e} std={:.3e}", obj_mean, obj_std);
+    println!(
+        "Unstable: {} / {} (std > {}% of range)\n",
+        unstable,
+        rows.len(),
+        STABILITY_REL_STD * 100.0
+    );
+    let width = rows.iter().map(|r| r.0.len()).fold(8, usize::max);
+    println!(
+        "  {:<width$}  {:>12}  {:>10}",
+        "variable",
+        "mean",
+        "std",
+        width = width
+    );
+    for (name, mean, std, unstable) in rows {
+        println!(
+            "  {:<width$}  {:>12.6}  {:>10.3e}{}",
+            name,
+            mean,
+            std,
+            if unstable { "  unstable" } else { "" },
+            width = width
+        );
+    }
+}
+
+/// ベンチマーク用モデル（[-5, 5]^dim 上の sphere）
+fn bench_model(dim: usize) -> parser::Model {
+    let mut model = parser::Model::new();
+    model.lb = vec![-5.0; dim];
+    model.ub = vec![5.0; dim];
+    model.dim = dim;
+    model.maximize = false;
+    model
+}
+
+/// 次元を掃引して各ソルバーの時間と最終適合度を測る
+fn cmd_bench_scaling(args: &Args) {
+    let methods: Vec<&str> = match args.method.as_str() {
+        "auto" => vec!["de", "pso", "hybrid"],
+        m => vec![m],
+    };
+    let opts = crate::solver::Options::new(BENCH_ITER, args.threads);
+
+    if args.csv {
+        println!("dim,method,time_ms,fitness");
+    } else {
+        println!(
+            "Solver scaling: methods={}, threads={}, iter={}",
+            methods.join(","),
+            args.threads,
+            BENCH_ITER
+        );
+        println!("{}", "-".repeat(50));
+        println!(
+            "{:>6}  {:<8} {:>10}  {:>10}",
+            "dim", "method", "time_ms", "fitness"
+        );
+    }
+
+    // ウォームアップ
+    let _ = crate::solver::de(&bench_model(10), &crate::solver::Options::new(10, 1));
+
+    for &dim in SCALING_DIMS {
+        let model = bench_model(dim);
+        for method in &methods {
+            let start = Instant::now();
+            let (_, f, _) = solve_heuristic(&model, method, &opts);
+            let ms = start.elapsed().as_secs_f64() * 1000.0;
+            if args.csv {
+                println!("{},{},{:.3},{:e}", dim, method, ms, f);
+            } else {
+                println!("{:>6}  {:<8} {:>10.2}  {:>10.2e}", dim, method, ms, f);
+            }
+        }
+    }
+}
+
 fn cmd_repl() {
     println!("optica {} REPL", VERSION);
-    println!("Commands: solve, bench, quit");
+    println!("Commands: solve <file> (run <file>), plot, bench, quit");
+
+    // 直前に解いたモデルの収束推移（plot用）
+    let mut last_trace: Vec<f64> = Vec::new();
 
     let stdin = io::stdin();
     loop {
@@ -251,6 +1669,13 @@ fn cmd_repl() {
                     .map(|n| n.get())
                     .unwrap_or(1),
             ),
+            "plot" => {
+                if last_trace.is_empty() {
+                    println!("no trace available (solve a model first)");
+                } else {
+                    print!("{}", render_chart(&last_trace, CHART_WIDTH, CHART_HEIGHT));
+                }
+            }
             _ if line.starts_with("bench ") => {
                 if let Ok(dim) = line[6..].trim().parse() {
                     cmd_bench(
@@ -261,25 +1686,239 @@ fn cmd_repl() {
                     );
                 }
             }
-            _ if line.starts_with("solve ") => {
-                let file = line[6..].trim();
-                let args = Args {
-                    command: Command::Solve {
-                        file: file.to_string(),
-                    },
-                    method: "auto".to_string(),
-                    max_iter: DEFAULT_MAX_ITER,
-                    threads: std::thread::available_parallelism()
-                        .map(|n| n.get())
-                        .unwrap_or(1),
-                    verbose: false,
-                    quiet: false,
-                };
-                if let Command::Solve { file } = &args.command {
-                    cmd_solve(file, &args);
+            _ if line.starts_with("solve ") || line.starts_with("run ") => {
+                let file = line.split_once(' ').map(|(_, f)| f.trim()).unwrap_or("");
+                if file == STDIN_FILE {
+                    println!("cannot read a model from stdin in the REPL (give a file path)");
+                    continue;
+                }
+                match Args::parse(&["solve".to_string(), file.to_string()]) {
+                    Ok(args) => {
+                        if let Command::Solve { file } = &args.command {
+                            last_trace = cmd_solve(file, &args);
+                        }
+                    }
+                    Err(e) => eprintln!("{}", e),
                 }
             }
             _ => println!("Unknown command: {}", line),
         }
     }
 }
+
+/// 収束推移をASCIIの折れ線グラフにする（横: 反復, 縦: 最良目的値）
+fn render_chart(values: &[f64], width: usize, height: usize) -> String {
+    let values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return "no finite values to plot\n".to_string();
+    }
+    let n = values.len();
+    let cols = width.min(n).max(1);
+    // 各列はその列が受け持つ反復区間の最後の値
+    let col_vals: Vec<f64> = (0..cols)
+        .map(|c| values[((c + 1) * n).div_ceil(cols) - 1])
+        .collect();
+    let lo = col_vals.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = col_vals.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let level = |v: f64| -> usize {
+        if hi > lo {
+            (((v - lo) / (hi - lo)) * (height - 1) as f64).round() as usize
+        } else {
+            0
+        }
+    };
+
+    let mut out = String::new();
+    for row in (0..height).rev() {
+        let label = if row == height - 1 {
+            format!("{:>11.4e}", hi)
+        } else if row == 0 {
+            format!("{:>11.4e}", lo)
+        } else {
+            " ".repeat(11)
+        };
+        let line: String = col_vals
+            .iter()
+            .map(|&v| if level(v) == row { '*' } else { ' ' })
+            .collect();
+        out.push_str(&format!("{} |{}\n", label, line));
+    }
+    out.push_str(&format!("{} +{}\n", " ".repeat(11), "-".repeat(cols)));
+    let last = if n > 1 {
+        format!(
+            "{:>width$}",
+            n,
+            width = cols.saturating_sub(1).max(n.to_string().len() + 1)
+        )
+    } else {
+        String::new()
+    };
+    out.push_str(&format!("{}  1{}\n", " ".repeat(11), last));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 設定ファイル・環境変数を読まずにコマンドラインだけを解析する
+    fn args(flags: &[&str]) -> Args {
+        let flags: Vec<String> = flags.iter().map(|s| s.to_string()).collect();
+        Args::parse_layered(&flags, &[]).unwrap()
+    }
+
+    /// テスト用のモデルファイルを一時ディレクトリに書く
+    fn model_file(name: &str, source: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("optica-{}-{}.optica", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn solve_command_handles_eliminated_variables() {
+        let file = model_file(
+            "eliminated",
+            "var x >= 0, <= 10\nvar y >= -100, <= 100\n\nminimize: (x - 3)^2 + y^2\n\n\
+             subject to:\n    y - x - 1 == 0\n",
+        );
+        for method in ["pso", "de", "hybrid"] {
+            let a = args(&["solve", &file, "-m", method, "-i", "200", "-t", "1", "-q"]);
+            let history = cmd_solve(&file, &a);
+            assert!(!history.is_empty(), "{}", method);
+            let last = *history.last().unwrap();
+            assert!((last - 8.0).abs() < 1e-3, "{}: {}", method, last);
+        }
+    }
+
+    #[test]
+    fn experiment_grid_nests_methods_seeds_then_iters() {
+        let model =
+            parser::parse("var x[1..3] >= -5, <= 5\nminimize: sum(i in 1..3) x[i]^2\n").unwrap();
+        let a = args(&[
+            "experiment",
+            "m.optica",
+            "--methods",
+            "de,pso",
+            "--seeds",
+            "1:3",
+            "--iters",
+            "10,20",
+        ]);
+        let grid = experiment_grid(&model, &a);
+        assert_eq!(grid.len(), 2 * 3 * 2);
+        assert_eq!(grid[0], ("de", 1, 10));
+        assert_eq!(grid[1], ("de", 1, 20));
+        assert_eq!(grid[2], ("de", 2, 10));
+        assert_eq!(grid[5], ("de", 3, 20));
+        assert_eq!(grid[6], ("pso", 1, 10));
+        assert_eq!(grid[11], ("pso", 3, 20));
+
+        // 軸を省略すると通常の指定1つ、auto はモデルに応じて解決される
+        let a = args(&["experiment", "m.optica", "-i", "50", "--seed", "9"]);
+        let grid = experiment_grid(&model, &a);
+        assert_eq!(grid, vec![(resolve_method(&model, "auto"), 9, 50)]);
+    }
+
+    #[test]
+    fn progress_is_classified_from_synthetic_curves() {
+        // 序盤に下がりきって平坦
+        let flat: Vec<f64> = (0..200).map(|i| (100.0 - i as f64).max(1.0)).collect();
+        assert_eq!(
+            classify_progress(&flat, 50, true, false),
+            ("converged", 0.0)
+        );
+        assert_eq!(classify_progress(&flat, 50, false, false), ("stalled", 0.0));
+        // 許容誤差で早期終了していれば改善量によらず収束
+        assert_eq!(
+            classify_progress(&flat[..60], 50, false, true).0,
+            "converged"
+        );
+
+        // 最後まで一定の割合で下がり続ける
+        let slow: Vec<f64> = (0..200).map(|i| 100.0 - 0.01 * i as f64).collect();
+        let (status, rate) = classify_progress(&slow, 50, true, false);
+        assert_eq!(status, "slowly improving");
+        assert!((rate - 0.01).abs() < 1e-9);
+
+        // 窓が履歴より長ければ履歴全体で測る
+        let (status, rate) = classify_progress(&slow[..11], 50, false, false);
+        assert_eq!(status, "slowly improving");
+        assert!((rate - 0.01).abs() < 1e-9);
+        // 相対量 PROGRESS_REL_TOL 未満の改善は改善とみなさない
+        let tiny: Vec<f64> = (0..100).map(|i| 1e6 - 1e-9 * i as f64).collect();
+        assert_eq!(classify_progress(&tiny, 50, true, false).0, "converged");
+        assert_eq!(classify_progress(&[], 50, true, false), ("converged", 0.0));
+        assert_eq!(
+            classify_progress(&[3.0], 50, false, false),
+            ("stalled", 0.0)
+        );
+    }
+
+    #[test]
+    fn flat_solution_lists_every_variable_in_declaration_order() {
+        // `y` はプリソルブで消去され、`z` は表示閾値より小さい
+        let mut model = parser::parse(
+            "var x >= 0, <= 10\nvar y >= -100, <= 100\nvar n int >= 0, <= 10\nvar z >= 0, <= 1\n\n\
+             minimize: (x - 3)^2 + y^2 + (n - 2)^2 + z\n\nsubject to:\n    y - x - 1 == 0\n",
+        )
+        .unwrap();
+        let names = model.var_names.clone();
+        presolve::presolve(&mut model);
+        assert_eq!(model.var_names.len(), 3);
+
+        let mut reduced = vec![3.0, 2.4, 1e-9];
+        model.round_integers(&mut reduced);
+        let best = model.expand_solution(&reduced).into_owned();
+        let line = flat_solution(&best);
+        let values: Vec<f64> = line.split(' ').map(|v| v.parse().unwrap()).collect();
+        assert_eq!(values.len(), names.len());
+        assert_eq!(values, vec![3.0, 4.0, 2.0, 1e-9]);
+        assert_eq!(line, "3 4 2 0.000000001");
+    }
+
+    #[test]
+    fn require_objective_rejects_only_models_without_one() {
+        let none = parse("var x >= 0, <= 1\n\nsubject to:\n    x >= 0.5\n").unwrap();
+        let err = check_objective("m.optica", &none, true).unwrap_err();
+        assert!(err.starts_with("m.optica: no objective"), "{}", err);
+        // 指定しなければ従来どおり既定の目的で解く
+        assert!(check_objective("m.optica", &none, false).is_ok());
+        assert!(!args(&["solve", "m.optica"]).require_objective);
+        assert!(args(&["solve", "m.optica", "--require-objective"]).require_objective);
+
+        for src in [
+            "var x >= 0, <= 1\nminimize: 0\n",
+            "var x >= 0, <= 1\nmaximize: x\n",
+            "var x >= 0, <= 1\nminimize: x weight 1\nmaximize: x weight 2\n",
+        ] {
+            let m = parse(src).unwrap();
+            assert!(check_objective("m.optica", &m, true).is_ok(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn infeasible_objective_excludes_the_penalty() {
+        let model = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nmaximize: 2 * x + y\n\n\
+             subject to:\n    cap: x + y <= 4\n",
+        )
+        .unwrap();
+        // 実行不能: 目的値は向きのまま、違反は係数を掛けない量（x + y = 6 で 2 超過）を別に返す
+        assert_eq!(split_objective(&model, &[5.0, 1.0]), (11.0, 2.0));
+        assert_eq!(split_objective(&model, &[3.0, 1.0]), (7.0, 0.0));
+
+        // 矛盾した制約では最良解も実行不能で、適合度はペナルティを含む
+        let model =
+            parse("var x >= 0, <= 10\n\nminimize: x\n\nsubject to:\n    x >= 6\n    x <= 4\n")
+                .unwrap();
+        let mut opts = solver::Options::new(100, 1);
+        opts.seed = 1;
+        let (best, fitness, _) = solve_heuristic(&model, "de", &opts);
+        let (obj, violation) = split_objective(&model, &best);
+        assert_eq!(obj, best[0]);
+        // 4 <= x <= 6 なら違反はちょうど 2。適合度はそれに係数を掛けたペナルティを含む
+        assert!((violation - 2.0).abs() < 1e-6, "{}", violation);
+        assert!(fitness > obj + 1e3 * violation, "{} {}", obj, fitness);
+    }
+}
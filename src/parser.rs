@@ -1,35 +1,282 @@
 //! Optica言語パーサー（拡張版）
 
 use serde_json::Value;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, RwLock};
 
 /// パース済みモデル
 #[derive(Debug, Clone)]
 pub struct Model {
+    pub name: Option<String>, // `model <名前>` / `problem <名前>` の名前
     pub dim: usize,
     pub lb: Vec<f64>,
     pub ub: Vec<f64>,
     pub var_names: Vec<String>,
     pub var_map: HashMap<String, usize>, // 変数名 -> インデックス
+    pub var_types: Vec<VarType>,         // 宣言された変数の型
     pub maximize: bool,
     pub params: HashMap<String, HashMap<String, f64>>, // パラメータ値
+    pub consts: HashMap<String, f64>,                  // `const` で定義した定数
     pub sets: HashMap<String, Vec<String>>,            // 集合
-    pub objective_expr: Option<String>,                // 目的関数式
-    pub constraints: Vec<Constraint>,                  // 制約
-    pub objectives: Vec<Objective>,                    // 多目的
+    pub key_sets: Vec<(String, String)>, // `set S = keys(p);` の (集合, パラメータ)。データ読込後に引き直す
+    pub bound_refs: Vec<BoundRef>,       // パラメータを参照する変数の境界。データ読込後に引き直す
+    pub objective_expr: Option<String>,  // 目的関数式
+    pub constraints: Vec<Constraint>,    // 制約
+    pub objectives: Vec<Objective>,      // 多目的
     pub pareto: ParetoMethod,
     pub cp_globals: Vec<String>, // CPグローバル制約（no_overlap, disjunctive, cumulative）
+    pub hints: SolveHints,       // `solve:` ブロックのソルバー指定
+    pub reduction: Option<Reduction>, // プリソルブで消去した変数（あれば lb/ub 等は縮約後）
+    expansions: ExpansionCache,  // 集約ヘッダの展開キャッシュ
+    integer_vars: Vec<usize>,    // 整数型の変数のインデックス（`index_var_types` で作り直す）
+}
+
+/// パラメータ・定数を参照する変数の境界（`var stock[Items] >= 0 <= maxCap;`）
+///
+/// パラメータは変数より後（`data:` ブロックやサイドカーJSON）で与えてもよいので、
+/// パースの最後とJSONの読込後に `Model::resolve_bounds` で評価し直す。
+#[derive(Debug, Clone)]
+pub struct BoundRef {
+    pub vars: std::ops::Range<usize>, // 宣言で作った変数のインデックス
+    pub var_type: VarType,
+    pub lower: Option<String>, // 下界の式（数値なら None）
+    pub upper: Option<String>, // 上界の式（数値なら None）
+}
+
+/// プリソルブで等式制約から消去した変数の情報
+///
+/// 縮約後のモデルでは `dim`/`lb`/`ub`/`var_names`/`var_types` は残った変数だけを持ち、
+/// `var_map` と式は元の変数空間のまま。評価の前に `expand_solution` で元の空間に戻す。
+#[derive(Debug, Clone)]
+pub struct Reduction {
+    pub full_dim: usize,
+    pub free: Vec<usize>,         // 縮約後の座標 -> 元のインデックス
+    pub defined: Vec<DefinedVar>, // 消去した変数（この順に値を復元する）
+}
+
+/// `coef * 変数 + (残りの項) == rhs` で定まる変数
+#[derive(Debug, Clone)]
+pub struct DefinedVar {
+    pub index: usize, // 元のインデックス
+    pub expr: String, // 等式制約の左辺
+    pub coef: f64,
+    pub rhs: f64,
+}
+
+/// モデルファイル内の `solve:` ブロックで指定するソルバー既定値
+///
+/// ```text
+/// solve:
+///     method: pso
+///     iters: 5000
+///     w_init: 0.7
+/// ```
+///
+/// コマンドラインで指定した値が優先される。
+#[derive(Debug, Clone, Default)]
+pub struct SolveHints {
+    pub method: Option<String>,
+    pub max_iter: Option<usize>,
+    pub threads: Option<usize>,
+    pub islands: Option<usize>,
+    pub seed: Option<u64>,
+    pub pop_size: Option<usize>,
+    pub de_f: Option<f64>,
+    pub de_cr: Option<f64>,
+    pub w_init: Option<f64>,
+    pub w_min: Option<f64>,
+    pub w_decay: Option<f64>,
+    pub c1: Option<f64>,
+    pub c2: Option<f64>,
+}
+
+impl SolveHints {
+    /// CLIフラグ列に変換（フラグで表せる項目のみ）
+    pub fn to_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if let Some(m) = &self.method {
+            flags.extend(["--method".to_string(), m.clone()]);
+        }
+        if let Some(n) = self.max_iter {
+            flags.extend(["--iter".to_string(), n.to_string()]);
+        }
+        if let Some(n) = self.threads {
+            flags.extend(["--threads".to_string(), n.to_string()]);
+        }
+        if let Some(n) = self.islands {
+            flags.extend(["--islands".to_string(), n.to_string()]);
+        }
+        if let Some(n) = self.seed {
+            flags.extend(["--seed".to_string(), n.to_string()]);
+        }
+        flags
+    }
+}
+
+/// 集約ヘッダを展開したインデックス組
+#[derive(Debug)]
+struct Expansion {
+    vars: Vec<String>,             // ループ変数名
+    combos: Vec<Vec<String>>,      // 直積（varsと同順）
+    filter: Option<String>,        // `where` 条件
+    filter_keys: Vec<Vec<String>>, // 条件評価用の数値化したインデックス（combosと同順）
+}
+
+impl Expansion {
+    /// `n` 番目の組を条件評価用の環境に入れる
+    ///
+    /// 添字（`value[i]`）は要素そのもので引き、数値でない要素を単独で使う（`i < j`）と
+    /// 集合内の順位で比べられるよう、順位を `rank_key` の名前で併せて入れる。
+    fn bind_filter(&self, n: usize, env: &mut HashMap<String, String>) {
+        for ((var, v), k) in self
+            .vars
+            .iter()
+            .zip(&self.combos[n])
+            .zip(&self.filter_keys[n])
+        {
+            env.insert(var.clone(), v.clone());
+            env.insert(rank_key(var), k.clone());
+        }
+    }
+}
+
+/// 集約の条件で、数値でない要素の順位を入れる環境のキー（識別子には現れない `#` を含む）
+fn rank_key(var: &str) -> String {
+    format!("{}#rank", var)
+}
+
+/// 集約ヘッダ文字列 -> 展開結果のキャッシュ
+///
+/// 集合はパース後に変化しないので、同じヘッダの直積は評価のたびに再計算せず使い回す。
+/// 並列DEでモデルを共有するため `RwLock` で保護する。
+#[derive(Debug, Default)]
+struct ExpansionCache(RwLock<HashMap<String, Arc<Expansion>>>);
+
+impl Clone for ExpansionCache {
+    fn clone(&self) -> Self {
+        Self(RwLock::new(self.0.read().unwrap().clone()))
+    }
+}
+
+/// 集約の種類
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Prod,
+    Max,
+    Min,
+    Avg,
+}
+
+/// 式全体が集約（`sum(i in S) ...`, `prod{i in S} ...`, `maxover{j in T} ...` 等）ならその種類を返す
+///
+/// `max`/`min` はヘッダに ` in ` を含む場合のみ集約として扱う（`max(a, b)` と区別）。
+fn aggregate_kind(expr: &str) -> Option<Aggregate> {
+    let open = expr.find(['(', '{'])?;
+    let kind = match &expr[..open] {
+        "sum" => Aggregate::Sum,
+        "prod" => Aggregate::Prod,
+        "maxover" => Aggregate::Max,
+        "minover" => Aggregate::Min,
+        "avg" => Aggregate::Avg,
+        "max" | "min" => {
+            let close = find_matching_close(expr, open)?;
+            if !expr[open + 1..close].contains(" in ") {
+                return None;
+            }
+            if expr.starts_with("max") {
+                Aggregate::Max
+            } else {
+                Aggregate::Min
+            }
+        }
+        _ => return None,
+    };
+    Some(kind)
 }
 
 #[derive(Debug, Clone)]
 pub struct Constraint {
-    #[allow(dead_code)]
     pub name: String,
     pub expr: String,
     pub op: ConstraintOp,
+    /// 数値の右辺（`rhs_expr` があるときは使わない）
     pub rhs: f64,
+    /// 右辺が数値でなければその式（`capacity * 2`, `demand[A]` など）。評価のたびに引き直すので、
+    /// サイドカーJSONで後からパラメータが与えられても追従する
+    pub rhs_expr: Option<String>,
+    /// この制約のペナルティ係数（`name [penalty=100]: ...`）。None なら全体の係数（`OPTICA_PENALTY`）
+    pub penalty: Option<f64>,
+}
+
+/// 変数の型（宣言のキーワード `real`/`continuous`, `int`/`integer`, `binary` で決まる）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VarType {
+    #[default]
+    Continuous,
+    Integer,
+    Binary,
+}
+
+impl VarType {
+    /// 整数値を取る型か
+    pub fn is_integral(self) -> bool {
+        matches!(self, VarType::Integer | VarType::Binary)
+    }
+
+    /// 型のキーワードなら型を返す
+    fn from_keyword(word: &str) -> Option<Self> {
+        match word {
+            "real" | "Real" | "continuous" | "Continuous" => Some(VarType::Continuous),
+            "int" | "Int" | "integer" | "Integer" => Some(VarType::Integer),
+            "binary" | "Binary" | "bool" => Some(VarType::Binary),
+            _ => None,
+        }
+    }
+
+    /// 宣言行のキーワードから型を決める（複数指定はエラー）
+    fn from_decl(line: &str) -> Result<Self, String> {
+        let mut found: Option<(VarType, &str)> = None;
+        for word in line.split(|c: char| c.is_whitespace() || c == ';') {
+            let Some(t) = VarType::from_keyword(word) else {
+                continue;
+            };
+            if let Some((prev, prev_word)) = found {
+                if prev != t {
+                    return Err(format!(
+                        "conflicting variable types '{}' and '{}'",
+                        prev_word, word
+                    ));
+                }
+            }
+            found = Some((t, word));
+        }
+        Ok(found.map(|(t, _)| t).unwrap_or_default())
+    }
+
+    /// 型に合わせて境界を検査・調整する（整数型は内側の整数に丸める）
+    fn check_domain(self, lb: f64, ub: f64) -> Result<(f64, f64), String> {
+        let (lb, ub) = match self {
+            VarType::Continuous => (lb, ub),
+            VarType::Integer => (lb.ceil(), ub.floor()),
+            VarType::Binary => {
+                if lb < 0.0 || ub > 1.0 {
+                    return Err(format!(
+                        "binary variable bounds {}..{} outside 0..1",
+                        lb, ub
+                    ));
+                }
+                (lb.ceil(), ub.floor())
+            }
+        };
+        if lb > ub {
+            return Err(format!("empty domain {}..{}", lb, ub));
+        }
+        Ok((lb, ub))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -39,6 +286,18 @@ pub enum ConstraintOp {
     Eq, // ==
 }
 
+/// 線形式 (変数ごとの係数, 定数項)
+pub type LinearForm = (Vec<f64>, f64);
+
+/// 最小化する目的の区分線形な分解（`Model::piecewise_form`）: `線形式 + Σ 重み × max(線形式, ...)`
+#[derive(Debug, Clone, Default)]
+pub struct PiecewiseForm {
+    /// 線形部分
+    pub linear: LinearForm,
+    /// (重み（正）, max を取る線形式の列)
+    pub maxes: Vec<(f64, Vec<LinearForm>)>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Objective {
     pub name: String,
@@ -54,27 +313,243 @@ pub enum ParetoMethod {
         primary: String,
         eps: Vec<(String, ConstraintOp, f64)>,
     },
+    /// 目標計画法: 重み付きの目標値からの絶対偏差の和を最小化
+    Goal(Vec<(String, f64, f64)>), // (name, target, weight)
+    /// パレートフロント全体を求める（`pareto method: front`）。スカラー評価では先頭の目的を使う
+    Front,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Model {
     pub fn new() -> Self {
         Self {
+            name: None,
             dim: 0,
             lb: Vec::new(),
             ub: Vec::new(),
             var_names: Vec::new(),
             var_map: HashMap::new(),
+            var_types: Vec::new(),
             maximize: false,
             params: HashMap::new(),
+            consts: HashMap::new(),
             sets: HashMap::new(),
+            key_sets: Vec::new(),
+            bound_refs: Vec::new(),
             objective_expr: None,
             constraints: Vec::new(),
             objectives: Vec::new(),
             pareto: ParetoMethod::Single,
             cp_globals: Vec::new(),
+            hints: SolveHints::default(),
+            reduction: None,
+            expansions: ExpansionCache::default(),
+            integer_vars: Vec::new(),
+        }
+    }
+
+    /// 元の変数空間の点を縮約後の座標に射影する（消去した変数は捨てる）
+    pub fn reduce_point(&self, full: &[f64]) -> Vec<f64> {
+        match &self.reduction {
+            Some(red) => red.free.iter().map(|&i| full[i]).collect(),
+            None => full.to_vec(),
+        }
+    }
+
+    /// 縮約後の解を元の変数空間に戻す（消去した変数は定義式から計算）
+    pub fn expand_solution<'a>(&self, x: &'a [f64]) -> Cow<'a, [f64]> {
+        if self.reduction.is_none() {
+            return Cow::Borrowed(x);
+        }
+        let mut full = Vec::new();
+        self.expand_solution_into(x, &mut full);
+        Cow::Owned(full)
+    }
+
+    /// `expand_solution` の結果を `full` に書き込む（評価ごとに確保しないための版）。
+    /// 縮約していなければ `x` をそのまま写す
+    pub fn expand_solution_into(&self, x: &[f64], full: &mut Vec<f64>) {
+        full.clear();
+        let Some(red) = &self.reduction else {
+            full.extend_from_slice(x);
+            return;
+        };
+        full.resize(red.full_dim, 0.0);
+        for (&i, &v) in red.free.iter().zip(x) {
+            full[i] = v;
+        }
+        for d in &red.defined {
+            let rest = self.evaluate_expr(&d.expr, full, &HashMap::new());
+            full[d.index] = (d.rhs - rest) / d.coef;
+        }
+    }
+
+    /// `keys(p)` で定義した集合を、パラメータ `p` に現にあるキーで埋め直す
+    ///
+    /// キーがすべて数値なら数値順、そうでなければ文字列順に並べる。
+    pub fn resolve_key_sets(&mut self) {
+        for (set, param) in &self.key_sets {
+            let mut keys: Vec<String> = self
+                .params
+                .get(param)
+                .map(|m| m.keys().filter(|k| *k != "_").cloned().collect())
+                .unwrap_or_default();
+            let numeric: Option<Vec<f64>> = keys.iter().map(|k| k.parse().ok()).collect();
+            match numeric {
+                Some(_) => keys.sort_by(|a, b| {
+                    let (a, b): (f64, f64) = (a.parse().unwrap(), b.parse().unwrap());
+                    a.total_cmp(&b)
+                }),
+                None => keys.sort(),
+            }
+            self.sets.insert(set.clone(), keys);
+        }
+    }
+
+    /// 変数を1つ追加し、そのインデックスを返す（DSLを書かずにモデルを組み立てる用）
+    ///
+    /// `var_map` / `dim` も更新する。名前の重複や空の定義域はエラー。
+    pub fn add_variable(
+        &mut self,
+        name: &str,
+        lb: f64,
+        ub: f64,
+        kind: VarType,
+    ) -> Result<usize, String> {
+        if self.var_map.contains_key(name) {
+            return Err(format!("duplicate variable name '{}'", name));
+        }
+        let (lb, ub) = kind
+            .check_domain(lb, ub)
+            .map_err(|e| format!("var {}: {}", name, e))?;
+        let i = self.var_names.len();
+        self.lb.push(lb);
+        self.ub.push(ub);
+        self.var_types.push(kind);
+        self.var_names.push(name.to_string());
+        self.var_map.insert(name.to_string(), i);
+        self.dim = self.var_names.len();
+        self.index_var_types();
+        Ok(i)
+    }
+
+    /// 集合を定義する（`set S = {...};` 相当、同名なら置き換え）
+    pub fn add_set(&mut self, name: &str, elements: &[&str]) {
+        let elements = elements.iter().map(|e| e.to_string()).collect();
+        self.sets.insert(name.to_string(), elements);
+        self.expansions = ExpansionCache::default();
+    }
+
+    /// パラメータの値を1つ入れる。`index` が空ならスカラー、`"A"` や `"A,1"` なら表の要素
+    pub fn add_param(&mut self, name: &str, index: &str, value: f64) -> Result<(), String> {
+        if !value.is_finite() {
+            return Err(format!("param {}: value {} is not finite", name, value));
+        }
+        let key = if index.trim().is_empty() {
+            "_".to_string()
+        } else {
+            param_key(index)
+        };
+        self.params
+            .entry(name.to_string())
+            .or_default()
+            .insert(key, value);
+        // パラメータが変わると `where` 条件の展開結果も変わり得る
+        self.expansions = ExpansionCache::default();
+        Ok(())
+    }
+
+    /// 目的関数を設定する（`minimize: expr` / `maximize: expr` 相当）
+    pub fn set_objective(&mut self, expr: &str, maximize: bool) {
+        self.objective_expr = Some(expr.to_string());
+        self.maximize = maximize;
+    }
+
+    /// 制約 `expr op rhs` を追加し、その名前（`c0`, `c1`, ...）を返す
+    pub fn add_constraint(&mut self, expr: &str, op: ConstraintOp, rhs: f64) -> String {
+        let name = format!("c{}", self.constraints.len());
+        self.constraints.push(Constraint {
+            name: name.clone(),
+            expr: expr.to_string(),
+            op,
+            rhs,
+            rhs_expr: None,
+            penalty: None,
+        });
+        name
+    }
+
+    /// パラメータを参照する境界を評価して `lb`/`ub` に入れる
+    ///
+    /// まだ値の無いパラメータを参照する境界は既定値（0 / 1000）のまま。
+    pub fn resolve_bounds(&mut self) -> Result<(), String> {
+        for r in &self.bound_refs {
+            let eval = |e: &str| {
+                expand_card(e, &self.sets)
+                    .and_then(|e| eval_param_expr(&e, &self.params, &self.consts))
+                    .ok()
+            };
+            let lower = r.lower.as_deref().and_then(eval);
+            let upper = r.upper.as_deref().and_then(eval);
+            if lower.is_none() && upper.is_none() {
+                continue;
+            }
+            let first = r.vars.start;
+            let (lb, ub) = r
+                .var_type
+                .check_domain(
+                    lower.unwrap_or(self.lb[first]),
+                    upper.unwrap_or(self.ub[first]),
+                )
+                .map_err(|e| format!("var {}: {}", self.var_names[first], e))?;
+            for i in r.vars.clone() {
+                self.lb[i] = lb;
+                self.ub[i] = ub;
+            }
+        }
+        Ok(())
+    }
+
+    /// i番目の変数の型（宣言のない変数は連続）
+    pub fn var_type(&self, i: usize) -> VarType {
+        self.var_types.get(i).copied().unwrap_or_default()
+    }
+
+    /// 整数型の変数の一覧を `var_types` から作り直す
+    ///
+    /// 評価のたびに `var_types` を走査しないよう、パースの最後・`add_variable`・プリソルブで呼ぶ。
+    /// `var_types` を直接書き換えた場合も呼ぶこと。
+    pub fn index_var_types(&mut self) {
+        self.integer_vars = (0..self.var_types.len())
+            .filter(|&i| self.var_type(i).is_integral())
+            .collect();
+    }
+
+    /// 整数型の変数を含むか
+    pub fn has_integer_vars(&self) -> bool {
+        !self.integer_vars.is_empty()
+    }
+
+    /// 整数型の座標を最も近い整数に丸める
+    pub fn round_integers(&self, x: &mut [f64]) {
+        for &i in &self.integer_vars {
+            if let Some(v) = x.get_mut(i) {
+                *v = v.round();
+            }
         }
     }
 
+    /// 目的（`minimize` / `maximize` / `objectives:`）が書かれているか。
+    /// 無ければ `evaluate_objective` は既定の Sphere 関数になる
+    pub fn has_objective(&self) -> bool {
+        self.objective_expr.is_some() || !self.objectives.is_empty()
+    }
+
     /// 目的関数を評価
     pub fn evaluate_objective(&self, x: &[f64]) -> f64 {
         // 単一目的（従来互換）か、多目的の重み付け/epsilonを後段で処理する
@@ -90,27 +565,149 @@ impl Model {
         }
     }
 
+    /// 目的と制約を一度に評価する
+    ///
+    /// 戻り値は (スカラー化した目的値, 目的ごとの生の値, 制約違反の合計)。
+    /// スカラー値は最小化向き（maximize の目的は符号反転）で、重み付き和/epsilon を反映する。
+    /// 違反はペナルティ係数を掛けた重み付きの合計（`check_constraints`）で、epsilon制約の閾値超過にも
+    /// 全体の係数を掛けて含める。単一目的のモデルでは生の値は1要素。
+    pub fn evaluate_all(&self, x: &[f64]) -> (f64, Vec<f64>, f64) {
+        let env = HashMap::new();
+        let (_feasible, mut violation) = self.check_constraints(x);
+        if self.objectives.is_empty() {
+            let v = self.evaluate_objective(x);
+            let scalar = if self.maximize { -v } else { v };
+            return (scalar, vec![v], violation);
+        }
+
+        let raw: Vec<f64> = self
+            .objectives
+            .iter()
+            .map(|o| self.evaluate_expr(&o.expr, x, &env))
+            .collect();
+        let signed = |name: &str| self.signed_objective(&raw, name);
+        let scalar = match &self.pareto {
+            ParetoMethod::WeightedSum(weights) if !weights.is_empty() => weights
+                .iter()
+                .filter_map(|(name, w)| signed(name).map(|v| w * v))
+                .sum(),
+            ParetoMethod::Epsilon { primary, .. } => {
+                violation += crate::solver::penalty_coeff() * self.epsilon_excess(&raw);
+                signed(primary).unwrap_or(0.0)
+            }
+            ParetoMethod::Goal(goals) => goals
+                .iter()
+                .filter_map(|(name, target, w)| {
+                    let k = self.objectives.iter().position(|o| &o.name == name)?;
+                    Some(w * (raw[k] - target).abs())
+                })
+                .sum(),
+            // 既定: 先頭の目的を使用
+            _ => signed(&self.objectives[0].name).unwrap_or(0.0),
+        };
+        (scalar, raw, violation)
+    }
+
+    /// 名前で引いた目的の値（最小化向き、`raw` は目的ごとの生の値）
+    fn signed_objective(&self, raw: &[f64], name: &str) -> Option<f64> {
+        let k = self.objectives.iter().position(|o| o.name == name)?;
+        Some(if self.objectives[k].maximize {
+            -raw[k]
+        } else {
+            raw[k]
+        })
+    }
+
+    /// epsilon制約の閾値超過の合計（係数は掛けない。epsilon法でなければ0）
+    fn epsilon_excess(&self, raw: &[f64]) -> f64 {
+        let ParetoMethod::Epsilon { eps, .. } = &self.pareto else {
+            return 0.0;
+        };
+        eps.iter()
+            .filter_map(|(name, op, rhs)| {
+                let v = self.signed_objective(raw, name)?;
+                Some(match op {
+                    ConstraintOp::Le => (v - rhs).max(0.0),
+                    ConstraintOp::Ge => (rhs - v).max(0.0),
+                    ConstraintOp::Eq => (v - rhs).abs(),
+                })
+            })
+            .sum()
+    }
+
+    /// 表示用の制約違反の合計（`constraint_violations` の和とepsilon制約の閾値超過。係数は掛けない）
+    pub fn total_violation(&self, x: &[f64]) -> f64 {
+        let mut total: f64 = self.constraint_violations(x).iter().map(|(_, v)| v).sum();
+        if matches!(self.pareto, ParetoMethod::Epsilon { .. }) {
+            let raw: Vec<f64> = self
+                .objectives
+                .iter()
+                .map(|o| self.evaluate_expr(&o.expr, x, &HashMap::new()))
+                .collect();
+            total += self.epsilon_excess(&raw);
+        }
+        total
+    }
+
     /// 制約違反をチェック
+    ///
+    /// 戻り値は (実行可能か, 違反量 × 制約ごとのペナルティ係数 の合計)。
     pub fn check_constraints(&self, x: &[f64]) -> (bool, f64) {
         let mut feasible = true;
         let mut total_violation = 0.0;
 
         for constraint in &self.constraints {
-            let lhs = self.evaluate_expr(&constraint.expr, x, &HashMap::new());
-            let v = match constraint.op {
-                ConstraintOp::Le => (lhs - constraint.rhs).max(0.0),
-                ConstraintOp::Ge => (constraint.rhs - lhs).max(0.0),
-                ConstraintOp::Eq => (lhs - constraint.rhs).abs(),
-            };
+            let v = self.constraint_violation(constraint, x);
             if v > 1e-9 {
                 feasible = false;
-                total_violation += v;
+                total_violation += v * self.penalty_weight(constraint);
             }
         }
 
         (feasible, total_violation)
     }
 
+    /// 制約のペナルティ係数（個別の指定が無ければ全体の係数）
+    pub fn penalty_weight(&self, constraint: &Constraint) -> f64 {
+        constraint
+            .penalty
+            .unwrap_or_else(crate::solver::penalty_coeff)
+    }
+
+    /// 制約ごとの違反量（`check_constraints` と同じ基準、閾値未満は0。ペナルティ係数は掛けない）
+    pub fn constraint_violations(&self, x: &[f64]) -> Vec<(&str, f64)> {
+        self.constraints
+            .iter()
+            .map(|c| {
+                let v = self.constraint_violation(c, x);
+                (c.name.as_str(), if v > 1e-9 { v } else { 0.0 })
+            })
+            .collect()
+    }
+
+    /// 制約の左辺の値
+    pub fn constraint_lhs(&self, constraint: &Constraint, x: &[f64]) -> f64 {
+        self.evaluate_expr(&constraint.expr, x, &HashMap::new())
+    }
+
+    /// 制約の右辺の値（式なら評価する。右辺の式は変数を含まない）
+    pub fn constraint_rhs(&self, constraint: &Constraint, x: &[f64]) -> f64 {
+        match &constraint.rhs_expr {
+            Some(expr) => self.evaluate_expr(expr, x, &HashMap::new()),
+            None => constraint.rhs,
+        }
+    }
+
+    fn constraint_violation(&self, constraint: &Constraint, x: &[f64]) -> f64 {
+        let lhs = self.constraint_lhs(constraint, x);
+        let rhs = self.constraint_rhs(constraint, x);
+        match constraint.op {
+            ConstraintOp::Le => (lhs - rhs).max(0.0),
+            ConstraintOp::Ge => (rhs - lhs).max(0.0),
+            ConstraintOp::Eq => (lhs - rhs).abs(),
+        }
+    }
+
     /// 式を評価（簡易版）
     pub fn evaluate_expr(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> f64 {
         let expr = expr.trim();
@@ -120,9 +717,9 @@ impl Model {
             return val;
         }
 
-        // sum(...)
-        if expr.starts_with("sum(") || expr.starts_with("sum{") {
-            return self.evaluate_sum(expr, x, env);
+        // sum(...) / maxover(...) などの集約
+        if let Some(kind) = aggregate_kind(expr) {
+            return self.evaluate_aggregate(kind, expr, x, env);
         }
 
         // 比較（条件用）
@@ -135,29 +732,46 @@ impl Model {
     }
 
     fn eval_if(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> Option<f64> {
-        let lower = expr.to_lowercase();
-        if let (Some(t_pos), Some(e_pos)) = (lower.find(" then "), lower.find(" else ")) {
-            let cond_str = &expr[..t_pos];
-            let then_str = &expr[t_pos + 6..e_pos];
-            let else_str = &expr[e_pos + 6..];
-            let cond_val = self.eval_condition(cond_str.trim(), x, env);
-            if cond_val {
-                Some(self.evaluate_expr(then_str.trim(), x, env))
-            } else {
-                Some(self.evaluate_expr(else_str.trim(), x, env))
-            }
+        // if <cond> then <a> else <b>（括弧内の then/else は入れ子の式として扱う）
+        let lower = expr.to_ascii_lowercase();
+        if !lower.starts_with("if ") {
+            return None;
+        }
+        let t_pos = find_top_level(&lower, " then ")?;
+        let e_pos = t_pos + find_top_level(&lower[t_pos..], " else ")?;
+        let cond_str = &expr[3..t_pos];
+        let then_str = &expr[t_pos + 6..e_pos];
+        let else_str = &expr[e_pos + 6..];
+        if self.eval_condition(cond_str.trim(), x, env) {
+            Some(self.evaluate_expr(then_str.trim(), x, env))
         } else {
-            None
+            Some(self.evaluate_expr(else_str.trim(), x, env))
         }
     }
 
     fn eval_condition(&self, cond: &str, x: &[f64], env: &HashMap<String, String>) -> bool {
-        // サポート: <, <=, >, >=, ==, !=
+        // サポート: <, <=, >, >=, ==, !=（`and` / `or` でつなげる）
+        if let Some(pos) = find_top_level(cond, " or ") {
+            return self.eval_condition(cond[..pos].trim(), x, env)
+                || self.eval_condition(cond[pos + 4..].trim(), x, env);
+        }
+        if let Some(pos) = find_top_level(cond, " and ") {
+            return self.eval_condition(cond[..pos].trim(), x, env)
+                && self.eval_condition(cond[pos + 5..].trim(), x, env);
+        }
         let ops = ["<=", ">=", "==", "!=", "<", ">"];
         for op in ops {
-            if let Some(pos) = cond.find(op) {
+            if let Some(pos) = find_top_level(cond, op) {
                 let lhs = cond[..pos].trim();
                 let rhs = cond[pos + op.len()..].trim();
+                // 文字列リテラルとの等値比較は要素そのもので比べる（`i != "Tokyo"`）
+                if let (Some(a), Some(b), "==" | "!=") =
+                    (string_operand(lhs, env), string_operand(rhs, env), op)
+                {
+                    if is_quoted(lhs) || is_quoted(rhs) {
+                        return (a == b) == (op == "==");
+                    }
+                }
                 let a = self.evaluate_expr(lhs, x, env);
                 let b = self.evaluate_expr(rhs, x, env);
                 return match op {
@@ -177,7 +791,7 @@ impl Model {
     fn eval_comparison(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> Option<f64> {
         let ops = ["<=", ">=", "==", "!=", "<", ">"];
         for op in ops {
-            if let Some(pos) = expr.find(op) {
+            if let Some(pos) = find_top_level(expr, op) {
                 let lhs = expr[..pos].trim();
                 let rhs = expr[pos + op.len()..].trim();
                 let a = self.evaluate_expr(lhs, x, env);
@@ -198,148 +812,16 @@ impl Model {
     }
 
     fn eval_arith(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> f64 {
-        // 逆ポーランドへの簡易変換（+ - * / と括弧、単項-）
-        #[derive(Debug, Clone)]
-        enum Tok {
-            Num(f64),
-            Sym(String),
-            Op(char),
-            LPar,
-            RPar,
-            Comma,
-        }
-        fn prec(op: char) -> i32 {
-            match op {
-                '+' | '-' => 1,
-                '*' | '/' => 2,
-                _ => 0,
-            }
-        }
-        // トークナイズ
-        let mut toks: Vec<Tok> = Vec::new();
-        let mut i = 0;
-        let bytes = expr.as_bytes();
-        while i < bytes.len() {
-            let c = bytes[i] as char;
-            if c.is_whitespace() {
-                i += 1;
-                continue;
-            }
-            if c.is_ascii_digit() || c == '.' {
-                let start = i;
-                i += 1;
-                while i < bytes.len()
-                    && ((bytes[i] as char).is_ascii_digit() || bytes[i] as char == '.')
-                {
-                    i += 1;
-                }
-                let s = &expr[start..i];
-                if let Ok(v) = s.parse::<f64>() {
-                    toks.push(Tok::Num(v));
-                }
-                continue;
-            }
-            if c == '(' {
-                toks.push(Tok::LPar);
-                i += 1;
-                continue;
-            }
-            if c == ')' {
-                toks.push(Tok::RPar);
-                i += 1;
-                continue;
-            }
-            if c == ',' {
-                toks.push(Tok::Comma);
-                i += 1;
-                continue;
-            }
-            if "+-*/".contains(c) {
-                toks.push(Tok::Op(c));
-                i += 1;
-                continue;
-            }
-            // identifier or function or symbol with brackets
-            let start = i;
-            i += 1;
-            while i < bytes.len() {
-                let ch = bytes[i] as char;
-                if ch.is_ascii_alphanumeric() || ch == '_' || ch == '[' || ch == ']' || ch == '.' {
-                    i += 1;
-                } else {
-                    break;
-                }
-            }
-            toks.push(Tok::Sym(expr[start..i].to_string()));
-        }
-
-        // Shunting-yard to RPN
-        let mut output: Vec<Tok> = Vec::new();
-        let mut stack: Vec<Tok> = Vec::new();
-        let mut prev_was_op = true;
-        for t in toks {
-            match t {
-                Tok::Num(_) | Tok::Sym(_) => {
-                    output.push(t);
-                    prev_was_op = false;
-                }
-                Tok::Op(op) => {
-                    let mut op_char = op;
-                    // 単項マイナス対応: 前が演算子/左括弧の場合は0を挿入
-                    if op == '-' && prev_was_op {
-                        output.push(Tok::Num(0.0));
-                        op_char = '-';
-                    }
-                    while let Some(Tok::Op(top)) = stack.last() {
-                        if prec(*top) >= prec(op_char) {
-                            output.push(stack.pop().unwrap());
-                        } else {
-                            break;
-                        }
-                    }
-                    stack.push(Tok::Op(op_char));
-                    prev_was_op = true;
-                }
-                Tok::LPar => {
-                    stack.push(Tok::LPar);
-                    prev_was_op = true;
-                }
-                Tok::RPar => {
-                    while let Some(tok) = stack.pop() {
-                        if let Tok::LPar = tok {
-                            break;
-                        }
-                        output.push(tok);
-                    }
-                    prev_was_op = false;
-                }
-                Tok::Comma => {
-                    // treat as low-precedence separator
-                    while let Some(tok) = stack.last() {
-                        if let Tok::LPar = tok {
-                            break;
-                        }
-                        output.push(stack.pop().unwrap());
-                    }
-                    prev_was_op = true;
-                }
-            }
-        }
-        while let Some(tok) = stack.pop() {
-            output.push(tok);
-        }
-
-        // Evaluate RPN
         let mut st: Vec<f64> = Vec::new();
-        for tok in output {
+        for tok in arith_rpn(expr) {
             match tok {
-                Tok::Num(v) => st.push(v),
-                Tok::Sym(s) => {
-                    // max/min functions with one comma arg are handled by Op + special names; but here treat as symbol
-                    let v = self.eval_symbol(&s, x, env);
+                ArithTok::Num(v) => st.push(v),
+                ArithTok::Sub(sub) => st.push(self.evaluate_expr(sub, x, env)),
+                ArithTok::Sym(s) => {
+                    let v = self.eval_symbol(s, x, env);
                     st.push(v);
                 }
-                Tok::Op(op) => {
+                ArithTok::Op(op) => {
                     if st.len() < 2 {
                         return 0.0;
                     }
@@ -347,8 +829,9 @@ impl Model {
                     let a = st.pop().unwrap();
                     let v = match op {
                         '+' => a + b,
-                        '-' => a - b,
+                        '-' | 'n' => a - b,
                         '*' => a * b,
+                        '^' => pow(a, b),
                         '/' => {
                             if b.abs() < 1e-12 {
                                 0.0
@@ -367,36 +850,16 @@ impl Model {
     }
 
     fn eval_symbol(&self, sym: &str, x: &[f64], env: &HashMap<String, String>) -> f64 {
-        // max(...) / min(...)
-        if sym.starts_with("max(") && sym.ends_with(')') {
-            let inner = &sym[4..sym.len() - 1];
-            let parts: Vec<&str> = inner.split(',').collect();
-            if parts.len() == 2 {
-                let a = self.evaluate_expr(parts[0].trim(), x, env);
-                let b = self.evaluate_expr(parts[1].trim(), x, env);
-                return a.max(b);
-            }
-        }
-        if sym.starts_with("min(") && sym.ends_with(')') {
-            let inner = &sym[4..sym.len() - 1];
-            let parts: Vec<&str> = inner.split(',').collect();
-            if parts.len() == 2 {
-                let a = self.evaluate_expr(parts[0].trim(), x, env);
-                let b = self.evaluate_expr(parts[1].trim(), x, env);
-                return a.min(b);
-            }
+        // max(a, b) / pow(a, b) / sqrt(a) などの関数呼び出し
+        if let Some((name, args)) = call_args(sym) {
+            let args: Vec<f64> = args.iter().map(|a| self.evaluate_expr(a, x, env)).collect();
+            return apply_function(name, &args);
         }
 
         // identifier with optional [..]
         if let Some(b) = sym.find('[') {
             let name = &sym[..b];
-            let idx_part = sym[b + 1..].trim_end_matches(']');
-            let idx_tokens: Vec<String> = idx_part
-                .split(',')
-                .map(|t| t.trim())
-                .map(|t| env.get(t).cloned().unwrap_or_else(|| t.to_string()))
-                .collect();
-            let idx_key = idx_tokens.join(",");
+            let idx_key = index_key(&sym[b + 1..], env);
 
             // var
             let var_key = format!("{}[{}]", name, idx_key);
@@ -413,6 +876,13 @@ impl Model {
             return 0.0;
         }
 
+        // 定数
+        if !self.consts.is_empty() {
+            if let Some(&v) = self.consts.get(sym) {
+                return v;
+            }
+        }
+
         // スカラーパラメータ
         if let Some(param_map) = self.params.get(sym) {
             if let Some(v) = param_map.get("_") {
@@ -425,99 +895,857 @@ impl Model {
             return x[*idx];
         }
 
-        // 環境（インデックス値を数値化可能なら）
+        // 環境（インデックス値を数値化可能なら。集約の条件では数値でない要素は順位）
         if let Some(sv) = env.get(sym) {
             if let Ok(v) = sv.parse::<f64>() {
                 return v;
             }
+            if let Some(rank) = env.get(&rank_key(sym)).and_then(|r| r.parse::<f64>().ok()) {
+                return rank;
+            }
+        }
+
+        // 組み込み定数（同名の変数・パラメータ・インデックスがあればそちらが優先）
+        if let Some(v) = builtin_const(sym) {
+            return v;
         }
 
         0.0
     }
 
-    /// sum式を評価
-    fn evaluate_sum(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> f64 {
+    /// 集約式（sum等）を展開済みのインデックス組と本体に分解
+    fn expand_aggregate<'a>(&self, expr: &'a str) -> Option<(Arc<Expansion>, &'a str)> {
         // 形式: sum(i in SET, j in SET2) body
-        let (header, body) = if let Some(start) = expr.find('(') {
-            if let Some(end) = expr.find(')') {
-                (&expr[start + 1..end], expr[end + 1..].trim())
-            } else {
-                return 0.0;
-            }
-        } else if let Some(start) = expr.find('{') {
-            if let Some(end) = expr.find('}') {
-                (&expr[start + 1..end], expr[end + 1..].trim())
-            } else {
-                return 0.0;
-            }
-        } else {
-            return 0.0;
+        // ヘッダ内に集合リテラル `{a}` や括弧が入り得るので対応する閉じ括弧を探す
+        let open = expr.find(['(', '{'])?;
+        let close = find_matching_close(expr, open)?;
+        let (header, body) = (&expr[open + 1..close], expr[close + 1..].trim());
+
+        if let Some(exp) = self.expansions.0.read().unwrap().get(header) {
+            return Some((Arc::clone(exp), body));
+        }
+
+        // `where` / `:` 以降はインデックスの組に対する条件（`sum{i in S : value[i] > 0}`）
+        let sep = find_top_level(header, " where ")
+            .map(|pos| (pos, " where ".len()))
+            .or_else(|| find_top_level(header, ":").map(|pos| (pos, 1)));
+        let (bindings, filter) = match sep {
+            Some((pos, len)) => (&header[..pos], Some(header[pos + len..].trim().to_string())),
+            None => (header, None),
         };
 
-        let mut loops: Vec<(String, Vec<String>)> = Vec::new();
-        for part in header.split(',') {
+        let mut vars: Vec<String> = Vec::new();
+        let mut values: Vec<Vec<String>> = Vec::new();
+        for part in split_top_level(bindings, ',') {
             if let Some(pos) = part.find(" in ") {
-                let var = part[..pos].trim().to_string();
-                let set_name = part[pos + 4..].trim();
-                let vals = if let Some(set) = self.sets.get(set_name) {
-                    set.clone()
-                } else if let Some(dd) = set_name.find("..") {
-                    let a = set_name[..dd].trim().parse::<i32>().unwrap_or(0);
-                    let b = set_name[dd + 2..].trim().parse::<i32>().unwrap_or(0);
-                    (a..=b).map(|v| v.to_string()).collect()
-                } else {
-                    vec![set_name.to_string()]
-                };
-                loops.push((var, vals));
-            }
-        }
-
-        let mut total = 0.0;
-        fn dfs(
-            model: &Model,
-            loops: &[(String, Vec<String>)],
-            idx: usize,
-            env: &mut HashMap<String, String>,
-            body: &str,
-            x: &[f64],
-            acc: &mut f64,
-        ) {
-            if idx == loops.len() {
-                *acc += model.evaluate_expr(body, x, env);
-                return;
-            }
-            let (ref var, ref vals) = loops[idx];
-            for v in vals {
-                env.insert(var.clone(), v.clone());
-                dfs(model, loops, idx + 1, env, body, x, acc);
+                let set_expr = part[pos + 4..].trim();
+                vars.push(part[..pos].trim().to_string());
+                values.push(
+                    eval_set_expr(set_expr, &self.sets)
+                        .unwrap_or_else(|| vec![set_expr.to_string()]),
+                );
             }
-            env.remove(var);
         }
-        let mut env2 = env.clone();
-        dfs(self, &loops, 0, &mut env2, body, x, &mut total);
-        total
+        // 条件では数値の要素はその値、それ以外は集合内の順位（1始まり）で比較する
+        let filter_keys = if filter.is_some() {
+            let keys: Vec<Vec<String>> = values
+                .iter()
+                .map(|set| {
+                    set.iter()
+                        .enumerate()
+                        .map(|(k, e)| match e.parse::<f64>() {
+                            Ok(_) => e.clone(),
+                            Err(_) => (k + 1).to_string(),
+                        })
+                        .collect()
+                })
+                .collect();
+            cartesian(&keys)
+        } else {
+            Vec::new()
+        };
+        let exp = Arc::new(Expansion {
+            vars,
+            combos: cartesian(&values),
+            filter,
+            filter_keys,
+        });
+        self.expansions
+            .0
+            .write()
+            .unwrap()
+            .insert(header.to_string(), Arc::clone(&exp));
+        Some((exp, body))
     }
-}
 
-/// ソースコードをパース
-pub fn parse(source: &str) -> Result<Model, String> {
-    let mut model = Model::new();
-    let mut in_subject_to = false;
-    let mut in_data = false;
-    let mut in_objectives = false;
-    let mut weights: Vec<(String, f64)> = Vec::new();
-    let mut eps_constraints: Vec<(String, ConstraintOp, f64)> = Vec::new();
-    let mut primary_obj: Option<String> = None;
-    let mut pareto_mode: Option<String> = None;
+    /// 集約式を評価（各反復の本体の値を `kind` に従って畳み込む）
+    fn evaluate_aggregate(
+        &self,
+        kind: Aggregate,
+        expr: &str,
+        x: &[f64],
+        env: &HashMap<String, String>,
+    ) -> f64 {
+        let Some((exp, body)) = self.expand_aggregate(expr) else {
+            return 0.0;
+        };
 
-    for line in source.lines() {
-        let line = line.trim();
+        let mut acc = match kind {
+            Aggregate::Sum | Aggregate::Avg => 0.0,
+            Aggregate::Prod => 1.0,
+            Aggregate::Max => f64::NEG_INFINITY,
+            Aggregate::Min => f64::INFINITY,
+        };
+        let mut env2 = env.clone();
+        let mut filter_env = env.clone();
+        let mut count = 0usize;
+        for (n, combo) in exp.combos.iter().enumerate() {
+            if let Some(cond) = &exp.filter {
+                exp.bind_filter(n, &mut filter_env);
+                if !self.eval_condition(cond, x, &filter_env) {
+                    continue;
+                }
+            }
+            for (var, v) in exp.vars.iter().zip(combo) {
+                env2.insert(var.clone(), v.clone());
+            }
+            let v = self.evaluate_expr(body, x, &env2);
+            acc = match kind {
+                Aggregate::Sum | Aggregate::Avg => acc + v,
+                Aggregate::Prod => acc * v,
+                Aggregate::Max => acc.max(v),
+                Aggregate::Min => acc.min(v),
+            };
+            count += 1;
+        }
+
+        match kind {
+            // 空の集合上の集約は0とする（積は1）
+            Aggregate::Prod => acc,
+            _ if count == 0 => 0.0,
+            Aggregate::Avg => acc / count as f64,
+            _ => acc,
+        }
+    }
+
+    /// 目的関数が変数ごとの項の和に分解できるか（加法的分離可能性）
+    ///
+    /// 制約・CP・多目的を含むモデルは対象外。式を最上位の `+`/`-` で項に分け、
+    /// 各項（sumは展開して各反復の本体）が高々1変数にしか依存しなければ分離可能とみなす。
+    /// 構文木ではなく式の文字列で判定するので項の中の括弧は展開せず、`2 * (x[1] + x[2])`
+    /// のような分離可能な項も非分離とみなす（分離できない式を分離可能とすることはない）。
+    pub fn is_separable(&self) -> bool {
+        if !self.constraints.is_empty()
+            || !self.cp_globals.is_empty()
+            || self.objectives.len() > 1
+            || self.reduction.is_some()
+        {
+            return false;
+        }
+        match &self.objective_expr {
+            Some(expr) => self.expr_is_separable(expr, &HashMap::new()),
+            // デフォルトのSphere関数は分離可能
+            None => true,
+        }
+    }
+
+    fn expr_is_separable(&self, expr: &str, env: &HashMap<String, String>) -> bool {
+        let expr = expr.trim();
+        // if-then-else や比較は全体を1項として扱う
+        let lower = expr.to_lowercase();
+        let terms = if lower.contains(" then ") || expr.contains(['<', '>', '=']) {
+            vec![expr]
+        } else {
+            split_terms(expr)
+        };
+        terms.into_iter().all(|term| {
+            let term = term.trim();
+            if term.starts_with("sum(") || term.starts_with("sum{") {
+                let Some((exp, body)) = self.expand_aggregate(term) else {
+                    return false;
+                };
+                let mut env2 = env.clone();
+                exp.combos.iter().all(|combo| {
+                    for (var, v) in exp.vars.iter().zip(combo) {
+                        env2.insert(var.clone(), v.clone());
+                    }
+                    self.expr_is_separable(body, &env2)
+                })
+            } else if term.contains("sum(")
+                || term.contains("sum{")
+                || aggregate_kind(term).is_some()
+            {
+                // 係数付きsumなど入れ子や、prod・maxover 等の集約は保守的に非分離とする
+                false
+            } else {
+                self.referenced_vars(term, env).len() <= 1
+            }
+        })
+    }
+
+    /// 式中で参照される決定変数のインデックス集合
+    ///
+    /// 添字を `env` で解決できない参照（集約の本体の `x[i]` など）は、同じ名前の変数すべてを
+    /// 参照するものとみなす（依存を見落とさない側に倒す）。
+    pub(crate) fn referenced_vars(&self, expr: &str, env: &HashMap<String, String>) -> Vec<usize> {
+        let mut out: Vec<usize> = Vec::new();
+        let bytes = expr.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let c = bytes[i] as char;
+            if !(c.is_ascii_alphabetic() || c == '_') {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            i = symbol_end(expr, start);
+            let sym = &expr[start..i];
+            let key = if let Some(b) = sym.find('[') {
+                format!("{}[{}]", &sym[..b], index_key(&sym[b + 1..], env))
+            } else {
+                sym.to_string()
+            };
+            if let Some(&idx) = self.var_map.get(&key) {
+                if !out.contains(&idx) {
+                    out.push(idx);
+                }
+            } else if let Some(b) = sym.find('[') {
+                let base = &sym[..=b];
+                for (name, &idx) in &self.var_map {
+                    if name.starts_with(base) && !out.contains(&idx) {
+                        out.push(idx);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// 式の変数についての次数（線形化の判定用。大きいほど強い）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Degree {
+    Const,
+    Linear,
+    Nonlinear,
+}
+
+/// 値と勾配の組（勾配が空なら全成分0、すなわち定数）
+#[derive(Debug, Clone)]
+struct Dual {
+    v: f64,
+    g: Vec<f64>,
+}
+
+impl Dual {
+    fn constant(v: f64) -> Self {
+        Self { v, g: Vec::new() }
+    }
+
+    /// `a * self.g + b * other.g`
+    fn combine(&self, a: f64, other: &Dual, b: f64) -> Vec<f64> {
+        match (self.g.is_empty(), other.g.is_empty()) {
+            (true, true) => Vec::new(),
+            (false, true) => self.g.iter().map(|g| a * g).collect(),
+            (true, false) => other.g.iter().map(|g| b * g).collect(),
+            (false, false) => self
+                .g
+                .iter()
+                .zip(&other.g)
+                .map(|(p, q)| a * p + b * q)
+                .collect(),
+        }
+    }
+}
+
+/// 勾配
+///
+/// `evaluate_expr` と同じ順に式を解釈し、四則演算・集約（sum/avg、maxover/minover は選ばれた要素）・
+/// `max(a, b)` などの関数は解析的に微分する。`if` と比較は中心差分で近似する。
+impl Model {
+    /// 目的関数の勾配（`evaluate_objective` の値を各変数で微分したもの。符号は maximize でも反転しない）
+    pub fn gradient(&self, x: &[f64]) -> Vec<f64> {
+        let expr = self
+            .objective_expr
+            .as_deref()
+            .or(self.objectives.first().map(|o| o.expr.as_str()));
+        match expr {
+            Some(expr) => self.expr_gradient(expr, x),
+            // デフォルト: Sphere関数
+            None => x.iter().map(|v| 2.0 * v).collect(),
+        }
+    }
+
+    /// 制約ごとの左辺の勾配（制約の順）
+    pub fn constraint_gradients(&self, x: &[f64]) -> Vec<Vec<f64>> {
+        self.constraints
+            .iter()
+            .map(|c| self.expr_gradient(&c.expr, x))
+            .collect()
+    }
+
+    /// 式の勾配
+    pub fn expr_gradient(&self, expr: &str, x: &[f64]) -> Vec<f64> {
+        let d = self.eval_dual(expr, x, &HashMap::new());
+        if d.g.is_empty() {
+            vec![0.0; x.len()]
+        } else {
+            d.g
+        }
+    }
+
+    /// 線形式を係数と定数項に分解する（CP-SAT向け）
+    ///
+    /// 評価器と同じ解釈（括弧・引き算・係数・`sum` の展開・パラメータ）で、原点での値を定数項、
+    /// 勾配を係数とする。変数を含む `max` / `min` / `abs` などの関数・`if`・比較・変数同士の積や
+    /// 変数での割り算・べき乗は、式の構造をたどって非線形としてエラーにする。
+    #[cfg_attr(not(feature = "cp-sat"), allow(dead_code))]
+    pub fn linear_form(&self, expr: &str) -> Result<(Vec<f64>, f64), String> {
+        if self.expr_degree(expr, &HashMap::new()) == Degree::Nonlinear {
+            return Err(format!("'{}' is not linear", expr));
+        }
+        let origin = vec![0.0; self.var_map.len()];
+        let offset = self.evaluate_expr(expr, &origin, &HashMap::new());
+        if !offset.is_finite() {
+            return Err(format!("'{}' is not finite at the origin", expr));
+        }
+        Ok((self.expr_gradient(expr, &origin), offset))
+    }
+
+    /// 式の変数についての次数（`eval_dual` と同じ順に構造をたどる）
+    fn expr_degree(&self, expr: &str, env: &HashMap<String, String>) -> Degree {
+        let expr = expr.trim();
+        // if-then-else と比較は変数を含めば階段状
+        let is_comparison = ["<=", ">=", "==", "!=", "<", ">"]
+            .iter()
+            .any(|op| find_top_level(expr, op).is_some());
+        if expr.to_ascii_lowercase().starts_with("if ") || is_comparison {
+            return if self.referenced_vars(expr, env).is_empty() {
+                Degree::Const
+            } else {
+                Degree::Nonlinear
+            };
+        }
+        if let Some(kind) = aggregate_kind(expr) {
+            let Some((exp, body)) = self.expand_aggregate(expr) else {
+                return Degree::Const;
+            };
+            if exp
+                .filter
+                .as_ref()
+                .is_some_and(|cond| !self.referenced_vars(cond, env).is_empty())
+            {
+                return Degree::Nonlinear;
+            }
+            let mut env2 = env.clone();
+            let mut degree = Degree::Const;
+            for combo in &exp.combos {
+                for (var, v) in exp.vars.iter().zip(combo) {
+                    env2.insert(var.clone(), v.clone());
+                }
+                degree = degree.max(self.expr_degree(body, &env2));
+            }
+            // 和と平均だけが次数を保つ（積・最大・最小は変数を含めば非線形）
+            return match kind {
+                Aggregate::Sum | Aggregate::Avg => degree,
+                _ if degree == Degree::Const => Degree::Const,
+                _ => Degree::Nonlinear,
+            };
+        }
+
+        let mut st: Vec<Degree> = Vec::new();
+        for tok in arith_rpn(expr) {
+            match tok {
+                ArithTok::Num(_) => st.push(Degree::Const),
+                ArithTok::Sub(sub) => st.push(self.expr_degree(sub, env)),
+                ArithTok::Sym(s) => st.push(match call_args(s) {
+                    // 関数は引数がすべて定数のときだけ定数
+                    Some((_, args)) => {
+                        if args
+                            .iter()
+                            .all(|a| self.expr_degree(a, env) == Degree::Const)
+                        {
+                            Degree::Const
+                        } else {
+                            Degree::Nonlinear
+                        }
+                    }
+                    None if self.symbol_var(s, env).is_some() => Degree::Linear,
+                    None => Degree::Const,
+                }),
+                ArithTok::Op(op) => {
+                    let (Some(b), Some(a)) = (st.pop(), st.pop()) else {
+                        return Degree::Const;
+                    };
+                    st.push(match op {
+                        '+' | '-' | 'n' => a.max(b),
+                        '*' if a != Degree::Const && b != Degree::Const => Degree::Nonlinear,
+                        '*' => a.max(b),
+                        '/' if b == Degree::Const => a,
+                        '^' if a == Degree::Const && b == Degree::Const => Degree::Const,
+                        _ => Degree::Nonlinear,
+                    });
+                }
+                _ => {}
+            }
+        }
+        st.pop().unwrap_or(Degree::Const)
+    }
+
+    /// 最小化する目的 `sign * expr` を `線形式 + Σ 重み × max(線形式, ...)` に分解する（CP-SAT向け）
+    ///
+    /// 最上位の項（`sum` / `avg` の中は展開する）のうち、`[係数 *] abs(e)` は max(e, -e)、
+    /// `max(a, b)` と `maxover` はそのまま、`min(a, b)` と `minover` は重みが負なら符号を反転した
+    /// max として扱う。それ以外の項は `linear_form` で線形化する。最小化で凸にならない組み合わせ
+    /// （正の重みの min、負の重みの max / abs）や変数を掛けた max はエラー。
+    pub fn piecewise_form(&self, expr: &str, sign: f64) -> Result<PiecewiseForm, String> {
+        let mut form = PiecewiseForm {
+            linear: (vec![0.0; self.var_map.len()], 0.0),
+            maxes: Vec::new(),
+        };
+        self.add_piecewise(expr, sign, &mut form)?;
+        Ok(form)
+    }
+
+    fn add_piecewise(
+        &self,
+        expr: &str,
+        weight: f64,
+        form: &mut PiecewiseForm,
+    ) -> Result<(), String> {
+        let expr = expr.trim();
+        if !calls_piecewise(expr) {
+            let (coefs, offset) = self.linear_form(expr)?;
+            for (acc, c) in form.linear.0.iter_mut().zip(&coefs) {
+                *acc += weight * c;
+            }
+            form.linear.1 += weight * offset;
+            return Ok(());
+        }
+        // 式全体が集約なら本体は式の終わりまで続く（評価器と同じ）。和・平均は本体ごとに重みを配る
+        match aggregate_kind(expr) {
+            Some(Aggregate::Sum) => {
+                for body in self.instantiate_aggregate(expr)? {
+                    self.add_piecewise(&body, weight, form)?;
+                }
+                return Ok(());
+            }
+            Some(Aggregate::Avg) => {
+                let bodies = self.instantiate_aggregate(expr)?;
+                let n = bodies.len().max(1) as f64;
+                for body in bodies {
+                    self.add_piecewise(&body, weight / n, form)?;
+                }
+                return Ok(());
+            }
+            Some(Aggregate::Max | Aggregate::Min) => return self.add_max(expr, expr, weight, form),
+            Some(Aggregate::Prod) => {
+                return Err(format!("'{}' is not piecewise linear", expr));
+            }
+            None => {}
+        }
+        for (sign, term) in signed_terms(expr) {
+            if !calls_piecewise(term) {
+                self.add_piecewise(term, weight * sign, form)?;
+                continue;
+            }
+            let (factor, coef) = self.piecewise_factor(term)?;
+            let w = weight * sign * coef;
+            if w == 0.0 {
+                continue;
+            }
+            if factor.starts_with('(') && find_matching_close(factor, 0) == Some(factor.len() - 1) {
+                self.add_piecewise(&factor[1..factor.len() - 1], w, form)?;
+            } else {
+                self.add_max(term, factor, w, form)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `w × factor`（abs / max / min / maxover / minover）を max の項として加える
+    fn add_max(
+        &self,
+        term: &str,
+        factor: &str,
+        w: f64,
+        form: &mut PiecewiseForm,
+    ) -> Result<(), String> {
+        let is_max = factor.starts_with("abs(")
+            || matches!(call_args(factor), Some(("max", _)))
+            || matches!(aggregate_kind(factor), Some(Aggregate::Max));
+        let convex = if is_max { w > 0.0 } else { w < 0.0 };
+        if !convex {
+            return Err(format!(
+                "'{}' is not convex for minimization (max/abs need a positive weight, min a negative one)",
+                term
+            ));
+        }
+        let mut pieces = self.max_pieces(factor, is_max)?;
+        if !is_max {
+            // w * min(a, b) = -w * max(-a, -b)
+            for (coefs, offset) in &mut pieces {
+                coefs.iter_mut().for_each(|c| *c = -*c);
+                *offset = -*offset;
+            }
+        }
+        form.maxes.push((w.abs(), pieces));
+        Ok(())
+    }
+
+    /// 項を (max/min/abs の因子, 定数の係数) に分ける（`2 * abs(x)`, `w * max(a, b)`）
+    ///
+    /// 積の中の集約は評価器が扱わないので、線形化と同じくエラーにする。
+    fn piecewise_factor<'a>(&self, term: &'a str) -> Result<(&'a str, f64), String> {
+        let mut coef = 1.0;
+        let mut factor: Option<&str> = None;
+        for part in split_top_level(term, '*') {
+            let part = part.trim();
+            if aggregate_kind(part).is_some() {
+                return Err(format!("'{}' has an aggregate inside a product", term));
+            }
+            if calls_piecewise(part) {
+                if factor.replace(part).is_some() {
+                    return Err(format!("'{}' multiplies max/min/abs terms", term));
+                }
+                continue;
+            }
+            let (coefs, value) = self.linear_form(part)?;
+            if coefs.iter().any(|&c| c != 0.0) {
+                return Err(format!("'{}' multiplies max/min/abs by a variable", term));
+            }
+            coef *= value;
+        }
+        let factor = factor.ok_or_else(|| format!("'{}' is not linear", term))?;
+        Ok((factor, coef))
+    }
+
+    /// `abs` / `max` / `maxover`（`is_max` が偽なら `min` / `minover`）の中の線形式を集める
+    ///
+    /// 同じ種類の入れ子（`max(max(a, b), c)`）は平らにする。
+    fn max_pieces(&self, expr: &str, is_max: bool) -> Result<Vec<LinearForm>, String> {
+        let expr = expr.trim();
+        let same_aggregate = matches!(
+            (aggregate_kind(expr), is_max),
+            (Some(Aggregate::Max), true) | (Some(Aggregate::Min), false)
+        );
+        if same_aggregate {
+            let mut pieces = Vec::new();
+            for body in self.instantiate_aggregate(expr)? {
+                pieces.extend(self.max_pieces(&body, is_max)?);
+            }
+            return Ok(pieces);
+        }
+        match call_args(expr) {
+            Some(("abs", args)) if is_max => {
+                let (coefs, offset) = self.linear_form(args[0])?;
+                let negated = coefs.iter().map(|c| -c).collect();
+                Ok(vec![(coefs, offset), (negated, -offset)])
+            }
+            Some((name, args)) if name == if is_max { "max" } else { "min" } => {
+                let mut pieces = Vec::new();
+                for arg in args {
+                    pieces.extend(self.max_pieces(arg, is_max)?);
+                }
+                Ok(pieces)
+            }
+            _ if calls_piecewise(expr) => Err(format!("'{}' is not piecewise linear", expr)),
+            _ => Ok(vec![self.linear_form(expr)?]),
+        }
+    }
+
+    /// 集約を展開し、組ごとにループ変数を要素へ置き換えた本体を返す（`where` を満たす組だけ）
+    fn instantiate_aggregate(&self, expr: &str) -> Result<Vec<String>, String> {
+        let (exp, body) = self
+            .expand_aggregate(expr)
+            .ok_or_else(|| format!("cannot expand '{}'", expr))?;
+        let zeros = vec![0.0; self.var_map.len()];
+        let mut env = HashMap::new();
+        let mut bodies = Vec::new();
+        for (n, combo) in exp.combos.iter().enumerate() {
+            if let Some(cond) = &exp.filter {
+                exp.bind_filter(n, &mut env);
+                if !self.eval_condition(cond, &zeros, &env) {
+                    continue;
+                }
+            }
+            let binding: Vec<(String, String, String)> = exp
+                .vars
+                .iter()
+                .zip(combo)
+                .map(|(var, v)| (var.clone(), v.clone(), v.clone()))
+                .collect();
+            bodies.push(substitute_indices(body, &binding));
+        }
+        Ok(bodies)
+    }
+
+    fn eval_dual(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> Dual {
+        let expr = expr.trim();
+        if expr.to_ascii_lowercase().starts_with("if ") {
+            return self.finite_difference(expr, x, env);
+        }
+        if let Some(kind) = aggregate_kind(expr) {
+            return self.dual_aggregate(kind, expr, x, env);
+        }
+        if self.eval_comparison(expr, x, env).is_some() {
+            return self.finite_difference(expr, x, env);
+        }
+        self.dual_arith(expr, x, env)
+    }
+
+    /// 中心差分による値と勾配（微分できない部分式向け）
+    fn finite_difference(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> Dual {
+        let mut xs = x.to_vec();
+        let g = (0..x.len())
+            .map(|j| {
+                let h = 1e-6 * x[j].abs().max(1.0);
+                xs[j] = x[j] + h;
+                let up = self.evaluate_expr(expr, &xs, env);
+                xs[j] = x[j] - h;
+                let down = self.evaluate_expr(expr, &xs, env);
+                xs[j] = x[j];
+                (up - down) / (2.0 * h)
+            })
+            .collect();
+        Dual {
+            v: self.evaluate_expr(expr, x, env),
+            g,
+        }
+    }
+
+    fn dual_aggregate(
+        &self,
+        kind: Aggregate,
+        expr: &str,
+        x: &[f64],
+        env: &HashMap<String, String>,
+    ) -> Dual {
+        let Some((exp, body)) = self.expand_aggregate(expr) else {
+            return Dual::constant(0.0);
+        };
+
+        let mut acc: Option<Dual> = None;
+        let mut env2 = env.clone();
+        let mut filter_env = env.clone();
+        let mut count = 0usize;
+        for (n, combo) in exp.combos.iter().enumerate() {
+            if let Some(cond) = &exp.filter {
+                exp.bind_filter(n, &mut filter_env);
+                if !self.eval_condition(cond, x, &filter_env) {
+                    continue;
+                }
+            }
+            for (var, v) in exp.vars.iter().zip(combo) {
+                env2.insert(var.clone(), v.clone());
+            }
+            let d = self.eval_dual(body, x, &env2);
+            acc = Some(match (acc, kind) {
+                (None, _) => d,
+                (Some(a), Aggregate::Sum | Aggregate::Avg) => Dual {
+                    v: a.v + d.v,
+                    g: a.combine(1.0, &d, 1.0),
+                },
+                (Some(a), Aggregate::Prod) => Dual {
+                    v: a.v * d.v,
+                    g: a.combine(d.v, &d, a.v),
+                },
+                (Some(a), Aggregate::Max) if d.v > a.v => d,
+                (Some(a), Aggregate::Min) if d.v < a.v => d,
+                (Some(a), _) => a,
+            });
+            count += 1;
+        }
+
+        match (acc, kind) {
+            (Some(a), Aggregate::Avg) => {
+                let k = 1.0 / count as f64;
+                Dual {
+                    v: a.v * k,
+                    g: a.g.iter().map(|g| g * k).collect(),
+                }
+            }
+            (Some(a), _) => a,
+            // 空の集合上の集約は0とする（積は1）
+            (None, Aggregate::Prod) => Dual::constant(1.0),
+            (None, _) => Dual::constant(0.0),
+        }
+    }
+
+    fn dual_arith(&self, expr: &str, x: &[f64], env: &HashMap<String, String>) -> Dual {
+        let mut st: Vec<Dual> = Vec::new();
+        for tok in arith_rpn(expr) {
+            match tok {
+                ArithTok::Num(v) => st.push(Dual::constant(v)),
+                ArithTok::Sub(sub) => st.push(self.eval_dual(sub, x, env)),
+                ArithTok::Sym(s) => st.push(self.dual_symbol(s, x, env)),
+                ArithTok::Op(op) => {
+                    if st.len() < 2 {
+                        return Dual::constant(0.0);
+                    }
+                    let b = st.pop().unwrap();
+                    let a = st.pop().unwrap();
+                    let d = match op {
+                        '+' => Dual {
+                            v: a.v + b.v,
+                            g: a.combine(1.0, &b, 1.0),
+                        },
+                        '-' | 'n' => Dual {
+                            v: a.v - b.v,
+                            g: a.combine(1.0, &b, -1.0),
+                        },
+                        '^' => dual_pow(&a, &b),
+                        '*' => Dual {
+                            v: a.v * b.v,
+                            g: a.combine(b.v, &b, a.v),
+                        },
+                        // 評価と同じく 0 除算は 0
+                        '/' if b.v.abs() < 1e-12 => Dual::constant(0.0),
+                        '/' => Dual {
+                            v: a.v / b.v,
+                            g: a.combine(1.0 / b.v, &b, -a.v / (b.v * b.v)),
+                        },
+                        _ => Dual::constant(0.0),
+                    };
+                    st.push(d);
+                }
+                _ => {}
+            }
+        }
+        st.pop().unwrap_or(Dual::constant(0.0))
+    }
+
+    /// 記号の値と勾配（変数なら単位ベクトル、関数呼び出しは連鎖律）
+    fn dual_symbol(&self, sym: &str, x: &[f64], env: &HashMap<String, String>) -> Dual {
+        if let Some((name, args)) = call_args(sym) {
+            let args: Vec<Dual> = args.iter().map(|a| self.eval_dual(a, x, env)).collect();
+            return dual_function(name, &args);
+        }
+
+        let v = self.eval_symbol(sym, x, env);
+        match self.symbol_var(sym, env) {
+            Some(idx) => {
+                let mut g = vec![0.0; x.len()];
+                g[idx] = 1.0;
+                Dual { v, g }
+            }
+            None => Dual::constant(v),
+        }
+    }
+
+    /// 記号が変数を指していればそのインデックス（`eval_symbol` と同じ優先順位で引く）
+    fn symbol_var(&self, sym: &str, env: &HashMap<String, String>) -> Option<usize> {
+        if let Some(b) = sym.find('[') {
+            let idx_key = index_key(&sym[b + 1..], env);
+            return self
+                .var_map
+                .get(&format!("{}[{}]", &sym[..b], idx_key))
+                .copied();
+        }
+        let shadowed = self.consts.contains_key(sym)
+            || self.params.get(sym).is_some_and(|m| m.contains_key("_"));
+        if shadowed {
+            return None;
+        }
+        self.var_map.get(sym).copied()
+    }
+}
+
+/// パースエラー
+///
+/// `line` / `col` は1始まり。名前の重複などファイル全体に関わるエラーでは `line` は0。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    /// 行 `raw` で起きたエラー。列はメッセージ中の最初の `'...'` が行に現れる位置
+    /// （見つからなければ行の先頭の非空白文字）
+    fn at(line: usize, raw: &str, message: String) -> Self {
+        let quoted = message
+            .split('\'')
+            .nth(1)
+            .filter(|q| !q.is_empty())
+            .and_then(|q| raw.find(q));
+        let byte = quoted.unwrap_or(raw.len() - raw.trim_start().len());
+        Self {
+            line,
+            col: raw[..byte].chars().count() + 1,
+            message,
+        }
+    }
+
+    fn whole(message: String) -> Self {
+        Self {
+            line: 0,
+            col: 0,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.line == 0 {
+            write!(f, "error: {}", self.message)
+        } else {
+            write!(f, "error at line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+/// ソースコードをパース
+///
+/// 先頭の UTF-8 BOM は読み飛ばし、行末の `\r`（CRLF）は各行の trim で落とす。
+pub fn parse(source: &str) -> Result<Model, ParseError> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let mut model = Model::new();
+    let mut in_subject_to = false;
+    let mut in_data = false;
+    let mut in_objectives = false;
+    // `solve:` 見出しのインデント（ブロック内ならより深くインデントした行が続く）
+    let mut solve_indent: Option<usize> = None;
+    let mut weights: Vec<(String, f64)> = Vec::new();
+    let mut eps_constraints: Vec<(String, ConstraintOp, f64)> = Vec::new();
+    let mut primary_obj: Option<String> = None;
+    let mut pareto_mode: Option<String> = None;
+    let mut goals: Vec<(String, f64, f64)> = Vec::new();
+    // `label:` だけの行に続く（より深くインデントした）名前なし制約の
+    // (ラベル, ラベル行のインデント, 最初の制約の位置)
+    let mut group: Option<(String, usize, usize)> = None;
+    // 本体を次の行に書く `forall ...:` の (名前, ヘッダ, インデント, ペナルティ係数)
+    let mut forall: Option<(Option<String>, String, usize, Option<f64>)> = None;
+    // 各目的・制約を定義した行番号（名前の重複を行番号付きで報告する）
+    let mut objective_lines: Vec<usize> = Vec::new();
+    let mut constraint_lines: Vec<usize> = Vec::new();
+
+    for (lineno, raw_line) in source.lines().enumerate() {
+        // 前の行までに増えた目的・制約はその行（1始まりで `lineno`）で定義したもの
+        objective_lines.resize(model.objectives.len(), lineno);
+        constraint_lines.resize(model.constraints.len(), lineno);
+        let at = |e: String| ParseError::at(lineno + 1, raw_line, e);
+        let line = raw_line.trim();
+        let indent = raw_line.len() - raw_line.trim_start().len();
+
+        // モデル名: `model "knapsack"` / `model knapsack;`（`problem` は `model` が無いときだけ）
+        if let Some(rest) = line.strip_prefix("model ").or_else(|| {
+            line.strip_prefix("problem ")
+                .filter(|_| model.name.is_none())
+        }) {
+            let name = rest.trim().trim_end_matches(';').trim().trim_matches('"');
+            if !name.is_empty() {
+                model.name = Some(name.to_string());
+            }
+            continue;
+        }
 
         // 空行・コメントをスキップ
         if line.is_empty()
             || line.starts_with('#')
             || line.starts_with("//")
-            || line.starts_with("model ")
             || line.starts_with("problem ")
             || line.starts_with("transition:")
             || line.starts_with("def ")
@@ -530,6 +1758,25 @@ pub fn parse(source: &str) -> Result<Model, String> {
             continue;
         }
 
+        if line.starts_with("solve:") {
+            solve_indent = Some(indent);
+            in_subject_to = false;
+            continue;
+        }
+
+        // solveブロック: 見出しより深くインデントした行を `key: value` として読み、
+        // 見出し以下のインデントの行で終了（その行は通常どおり処理する）
+        if let Some(base) = solve_indent {
+            if indent > base {
+                let (key, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| at(format!("expected `key: value` in solve block: {}", line)))?;
+                parse_solve_hint(&mut model.hints, key.trim(), value).map_err(at)?;
+                continue;
+            }
+            solve_indent = None;
+        }
+
         if line.starts_with("objectives:") {
             in_objectives = true;
             in_subject_to = false;
@@ -546,6 +1793,7 @@ pub fn parse(source: &str) -> Result<Model, String> {
             // dataブロック終了条件: 空行や次のセクション開始
             if line.is_empty()
                 || line.starts_with("param ")
+                || line.starts_with("const ")
                 || line.starts_with("var ")
                 || line.starts_with("set ")
                 || line.starts_with("subject to")
@@ -555,7 +1803,7 @@ pub fn parse(source: &str) -> Result<Model, String> {
                 in_data = false;
                 // この行を再処理するためにfall-through
             } else {
-                parse_data_assignment(line, &mut model.params)?;
+                parse_data_assignment(line, &mut model.params).map_err(at)?;
                 continue;
             }
         }
@@ -575,6 +1823,14 @@ pub fn parse(source: &str) -> Result<Model, String> {
                                 eps: eps_constraints.clone(),
                             };
                         }
+                    } else if mode == "goal" && !goals.is_empty() {
+                        if let Some((name, _, _)) = goals
+                            .iter()
+                            .find(|(name, _, _)| model.objectives.iter().all(|o| &o.name != name))
+                        {
+                            return Err(at(format!("goal for unknown objective '{}'", name)));
+                        }
+                        model.pareto = ParetoMethod::Goal(goals.clone());
                     }
                 }
                 continue;
@@ -585,9 +1841,29 @@ pub fn parse(source: &str) -> Result<Model, String> {
                     pareto_mode = Some("weighted_sum".to_string());
                 } else if line.contains("epsilon_constraint") {
                     pareto_mode = Some("epsilon_constraint".to_string());
+                } else if line.contains("goal") {
+                    pareto_mode = Some("goal".to_string());
+                } else if line.contains("front") || line.contains("nsga") {
+                    pareto_mode = Some("front".to_string());
+                }
+                continue;
+            }
+            // goal: 目的名 = 目標値 [重み]（`goal:` だけの行の後に続けて書いてもよい）
+            if let Some(rest) = line.strip_prefix("goal:") {
+                pareto_mode = Some("goal".to_string());
+                if !rest.trim().is_empty() {
+                    goals.push(parse_goal(rest).map_err(at)?);
                 }
                 continue;
             }
+            if pareto_mode.as_deref() == Some("goal")
+                && !line.starts_with("maximize")
+                && !line.starts_with("minimize")
+                && line.contains('=')
+            {
+                goals.push(parse_goal(line).map_err(at)?);
+                continue;
+            }
             if pareto_mode.as_deref() == Some("weighted_sum") && line.starts_with("weight ") {
                 if let Some(colon) = line.find(':') {
                     let name = line[7..colon].trim().to_string();
@@ -640,6 +1916,8 @@ pub fn parse(source: &str) -> Result<Model, String> {
                 pareto_mode = Some("weighted_sum".to_string());
             } else if line.contains("epsilon_constraint") {
                 pareto_mode = Some("epsilon_constraint".to_string());
+            } else if line.contains("front") || line.contains("nsga") {
+                pareto_mode = Some("front".to_string());
             }
             continue;
         }
@@ -675,45 +1953,226 @@ pub fn parse(source: &str) -> Result<Model, String> {
         }
 
         if line.starts_with("set ") {
-            parse_set(line, &mut model.sets)?;
+            if let Some(decl) = parse_keys_set(line) {
+                model.key_sets.push(decl);
+                model.resolve_key_sets();
+            } else {
+                parse_set(line, &mut model.sets).map_err(at)?;
+            }
         } else if line.starts_with("stage ") {
-            parse_stage(line, &mut model.sets)?;
+            parse_stage(line, &mut model.sets).map_err(at)?;
         } else if line.starts_with("state ") {
             let sets = model.sets.clone();
-            parse_state_or_decision(line, &mut model, &sets, true)?;
+            parse_state_or_decision(line, &mut model, &sets, true).map_err(at)?;
         } else if line.starts_with("decision ") {
             let sets = model.sets.clone();
-            parse_state_or_decision(line, &mut model, &sets, false)?;
+            parse_state_or_decision(line, &mut model, &sets, false).map_err(at)?;
         } else if line.starts_with("param ") {
             let sets = model.sets.clone();
-            parse_param(line, &mut model.params, &sets)?;
+            parse_param(line, &mut model.params, &model.consts, &sets).map_err(at)?;
+        } else if line.starts_with("const ") {
+            parse_const(line, &mut model).map_err(at)?;
         } else if line.starts_with("var ") {
+            model.resolve_key_sets();
             let sets = model.sets.clone();
-            parse_var(line, &mut model, &sets)?;
+            parse_var(line, &mut model, &sets).map_err(at)?;
         } else if line.starts_with("maximize") {
             model.maximize = true;
-            parse_objective(line, &mut model)?;
+            parse_objective(line, &mut model).map_err(at)?;
         } else if line.starts_with("minimize") {
             model.maximize = false;
-            parse_objective(line, &mut model)?;
-        } else if line.starts_with("subject to") {
+            parse_objective(line, &mut model).map_err(at)?;
+        } else if let Some(rest) = line.strip_prefix("subject to") {
             in_subject_to = true;
+            // 1行の形 `subject to capacity: ... <= 10;`
+            let rest = rest.trim().trim_start_matches(':').trim();
+            if !rest.is_empty() {
+                parse_constraint(rest, &mut model).map_err(at)?;
+            }
         } else if in_subject_to && !line.is_empty() {
-            parse_constraint(line, &mut model)?;
+            if let Some(label) = constraint_label(line) {
+                group = Some((label.to_string(), indent, model.constraints.len()));
+                continue;
+            }
+            if group.as_ref().is_some_and(|g| indent <= g.1) {
+                group = None;
+            }
+            if forall.as_ref().is_some_and(|f| indent <= f.2) {
+                forall = None;
+            }
+            // `forall ...:` だけの行は、次の（より深くインデントした）行を本体にする
+            let (header_line, penalty) = split_penalty(line).map_err(at)?;
+            if let Some((name, header, _)) = split_forall(&header_line).filter(|f| f.2.is_empty()) {
+                forall = Some((name, header, indent, penalty));
+                continue;
+            }
+            let before = model.constraints.len();
+            if let Some((name, header, _, penalty)) = forall.take() {
+                let base = name
+                    .or_else(|| group.as_ref().map(|g| g.0.clone()))
+                    .unwrap_or_else(|| format!("c{}", before));
+                expand_forall(&header, line.trim_end_matches(';'), &base, &mut model)
+                    .map_err(at)?;
+                for c in &mut model.constraints[before..] {
+                    c.penalty = c.penalty.or(penalty);
+                }
+                continue;
+            }
+            // 1行の forall で名前が無ければラベルを名前にする
+            let line = match (&group, split_forall(line)) {
+                (Some((label, _, _)), Some((None, header, body))) if !body.is_empty() => {
+                    format!("{}: forall {}: {}", label, header, body)
+                }
+                _ => line.to_string(),
+            };
+            parse_constraint(&line, &mut model).map_err(at)?;
+            if let Some((label, _, first)) = &group {
+                name_grouped_constraint(&mut model.constraints, label, *first, before);
+            }
         }
     }
 
+    let last_line = source.lines().count();
+    objective_lines.resize(model.objectives.len(), last_line);
+    constraint_lines.resize(model.constraints.len(), last_line);
+
+    // 名前の重複は、重みや epsilon が別の目的に効いたり制約の報告が紛れたりするのでエラー
+    unique_names(
+        source,
+        "objective",
+        model
+            .objectives
+            .iter()
+            .map(|o| o.name.as_str())
+            .zip(objective_lines),
+    )?;
+    unique_names(
+        source,
+        "constraint",
+        model
+            .constraints
+            .iter()
+            .map(|c| c.name.as_str())
+            .zip(constraint_lines),
+    )?;
+
+    // 省略記法の多目的（`minimize [a, b];`）は `weight` 行があればその重みを使う
+    if matches!(model.pareto, ParetoMethod::WeightedSum(_))
+        && pareto_mode.as_deref() == Some("weighted_sum")
+        && !weights.is_empty()
+    {
+        model.pareto = ParetoMethod::WeightedSum(weights);
+    }
+    if pareto_mode.as_deref() == Some("front") {
+        model.pareto = ParetoMethod::Front;
+    }
+
+    // `keys(p)` の集合と、パラメータを参照する変数の境界を dataブロック等で与えた値で引き直す
+    model.resolve_key_sets();
+    model.resolve_bounds().map_err(ParseError::whole)?;
+
     // 変数マップを構築
     for (i, name) in model.var_names.iter().enumerate() {
         model.var_map.insert(name.clone(), i);
     }
 
     model.dim = model.lb.len();
+    model.index_var_types();
     Ok(model)
 }
 
-fn expand_indices(idx_list: Vec<&str>, sets: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
-    let mut values: Vec<Vec<String>> = Vec::new();
+/// 名前 (と定義した行) の重複を2つ目の行のエラーにする（名前を省いた目的の `obj` はその旨も添える）
+fn unique_names<'a>(
+    source: &str,
+    kind: &str,
+    names: impl Iterator<Item = (&'a str, usize)>,
+) -> Result<(), ParseError> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (name, line) in names {
+        let Some(&first) = seen.get(name) else {
+            seen.insert(name, line);
+            continue;
+        };
+        let hint = if kind == "objective" && name == "obj" {
+            " (unnamed objectives are called 'obj'; name them `minimize <name>: ...`)"
+        } else {
+            ""
+        };
+        let message = format!(
+            "duplicate {} name '{}' (first defined at line {}){}",
+            kind, name, first, hint
+        );
+        return Err(match source.lines().nth(line.wrapping_sub(1)) {
+            Some(raw) => ParseError::at(line, raw, message),
+            None => ParseError::whole(message),
+        });
+    }
+    Ok(())
+}
+
+/// 目標行 `目的名 = 目標値 [重み]` をパース（重みの省略時は1）
+fn parse_goal(line: &str) -> Result<(String, f64, f64), String> {
+    let invalid = || {
+        format!(
+            "invalid goal '{}': expected `name = target [weight]`",
+            line.trim()
+        )
+    };
+    let (name, rest) = line.split_once('=').ok_or_else(invalid)?;
+    let rest = rest.trim_start_matches('=');
+    let mut nums = rest.split_whitespace().map(|t| t.parse::<f64>());
+    let target = match nums.next() {
+        Some(Ok(v)) if v.is_finite() => v,
+        _ => return Err(invalid()),
+    };
+    let weight = match nums.next() {
+        None => 1.0,
+        Some(Ok(w)) if w.is_finite() && w >= 0.0 => w,
+        _ => return Err(invalid()),
+    };
+    let name = name.trim();
+    if name.is_empty() || nums.next().is_some() {
+        return Err(invalid());
+    }
+    Ok((name.to_string(), target, weight))
+}
+
+/// `solve:` ブロックの1行 `key: value` を解釈する（値の後ろの `#` コメントは無視）
+fn parse_solve_hint(hints: &mut SolveHints, key: &str, value: &str) -> Result<(), String> {
+    let value = value.split('#').next().unwrap_or("").trim();
+    let value = value.trim_end_matches(';').trim().trim_matches('"');
+    fn num<T: std::str::FromStr>(key: &str, value: &str) -> Result<Option<T>, String> {
+        value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid value for solve option '{}': {}", key, value))
+    }
+    match key {
+        "method" => hints.method = Some(value.to_string()),
+        "iters" | "iter" | "max_iter" => hints.max_iter = num(key, value)?,
+        "threads" => hints.threads = num(key, value)?,
+        "islands" => hints.islands = num(key, value)?,
+        "seed" => hints.seed = num(key, value)?,
+        "pop_size" => {
+            hints.pop_size = num(key, value)?;
+            if hints.pop_size.is_some_and(|n| n < 4) {
+                return Err(format!("solve option 'pop_size' must be >= 4: {}", value));
+            }
+        }
+        "de_f" => hints.de_f = num(key, value)?,
+        "de_cr" => hints.de_cr = num(key, value)?,
+        "w_init" => hints.w_init = num(key, value)?,
+        "w_min" => hints.w_min = num(key, value)?,
+        "w_decay" => hints.w_decay = num(key, value)?,
+        "c1" => hints.c1 = num(key, value)?,
+        "c2" => hints.c2 = num(key, value)?,
+        _ => return Err(format!("unknown solve option '{}'", key)),
+    }
+    Ok(())
+}
+
+fn expand_indices(idx_list: Vec<&str>, sets: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    let mut values: Vec<Vec<String>> = Vec::new();
     for idx in idx_list {
         if let Some(set) = sets.get(idx) {
             values.push(set.clone());
@@ -747,12 +2206,652 @@ fn cartesian(lists: &[Vec<String>]) -> Vec<Vec<String>> {
     res
 }
 
+/// 四則演算のトークン
+#[derive(Debug, Clone)]
+enum ArithTok<'a> {
+    Num(f64),
+    /// 変数・パラメータの参照、または `max(a, b)` / `min(a, b)` / `pow(a, b)` の呼び出し全体
+    Sym(&'a str),
+    /// 括弧付きの `(if ... then ... else ...)`（部分式として丸ごと評価する）
+    Sub(&'a str),
+    /// `+ - * / ^`。単項マイナスは左辺に0を補った `'n'`
+    Op(char),
+    LPar,
+    RPar,
+    Comma,
+}
+
+/// `start` を含む括弧グループの終わり（対応する閉じ括弧の位置か式の末尾）
+fn group_end(expr: &str, start: usize) -> usize {
+    let mut depth = 0usize;
+    for (i, c) in expr[start..].char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                if depth == 0 {
+                    return start + i;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    expr.len()
+}
+
+/// 式を逆ポーランド記法のトークン列に変換（+ - * / ^ と括弧、単項-）
+///
+/// `^` は右結合で `*` `/` より強い。単項マイナスは `*` `/` より強く `^` より弱い
+/// （`-x^2` は `-(x^2)`、`x^-1` は `x^(-1)`、`2*-x` は `2*(-x)`）。
+fn arith_rpn(expr: &str) -> Vec<ArithTok<'_>> {
+    fn prec(op: char) -> i32 {
+        match op {
+            '+' | '-' => 1,
+            '*' | '/' => 2,
+            'n' => 3,
+            '^' => 4,
+            _ => 0,
+        }
+    }
+    // トークナイズ
+    let mut toks: Vec<ArithTok> = Vec::new();
+    let mut i = 0;
+    let bytes = expr.as_bytes();
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            i += 1;
+            while i < bytes.len()
+                && ((bytes[i] as char).is_ascii_digit() || bytes[i] as char == '.')
+            {
+                i += 1;
+            }
+            // 指数部（`1e-6`, `2.5E3`）。`e` の直後が数字か符号 + 数字のときだけ
+            if matches!(bytes.get(i), Some(b'e' | b'E')) {
+                let digits = match bytes.get(i + 1) {
+                    Some(b'+' | b'-') => i + 2,
+                    _ => i + 1,
+                };
+                if bytes.get(digits).is_some_and(u8::is_ascii_digit) {
+                    i = digits;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let s = &expr[start..i];
+            if let Ok(v) = s.parse::<f64>() {
+                toks.push(ArithTok::Num(v));
+            }
+            continue;
+        }
+        if c == '(' {
+            // `(if ... then ... else ...)` は部分式として丸ごと評価する
+            if expr[i + 1..]
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("if ")
+            {
+                if let Some(close) = find_matching_close(expr, i) {
+                    toks.push(ArithTok::Sub(&expr[i + 1..close]));
+                    i = close + 1;
+                    continue;
+                }
+            }
+            toks.push(ArithTok::LPar);
+            i += 1;
+            continue;
+        }
+        if c == ')' {
+            toks.push(ArithTok::RPar);
+            i += 1;
+            continue;
+        }
+        if c == ',' {
+            toks.push(ArithTok::Comma);
+            i += 1;
+            continue;
+        }
+        if "+-*/^".contains(c) {
+            toks.push(ArithTok::Op(c));
+            i += 1;
+            continue;
+        }
+        // identifier or function or symbol with brackets
+        let start = i;
+        // 途中に現れる集約（`1 + sum(i in I) x[i]`）は、本体が式（または囲む括弧）の
+        // 終わりまで続くので、そこまでを部分式にする
+        if aggregate_kind(&expr[start..]).is_some() {
+            let end = group_end(expr, start);
+            toks.push(ArithTok::Sub(&expr[start..end]));
+            i = end;
+            continue;
+        }
+        i = symbol_end(expr, start + 1);
+        // 関数呼び出しは引数ごと1つの記号にする（集約の `max(i in S) ...` は除く）
+        if FUNCTIONS.contains(&&expr[start..i]) && bytes.get(i) == Some(&b'(') {
+            if let Some(close) = find_matching_close(expr, i) {
+                if call_args(&expr[start..=close]).is_some() {
+                    toks.push(ArithTok::Sym(&expr[start..=close]));
+                    i = close + 1;
+                    continue;
+                }
+            }
+        }
+        toks.push(ArithTok::Sym(&expr[start..i]));
+    }
+
+    // Shunting-yard to RPN
+    let mut output: Vec<ArithTok> = Vec::new();
+    let mut stack: Vec<ArithTok> = Vec::new();
+    let mut prev_was_op = true;
+    for t in toks {
+        match t {
+            ArithTok::Num(_) | ArithTok::Sym(_) | ArithTok::Sub(_) => {
+                output.push(t);
+                prev_was_op = false;
+            }
+            ArithTok::Op(op) => {
+                // 単項マイナス対応: 前が演算子/左括弧の場合は0を挿入し、左辺を持たないので何も取り出さない
+                if op == '-' && prev_was_op {
+                    output.push(ArithTok::Num(0.0));
+                    stack.push(ArithTok::Op('n'));
+                    continue;
+                }
+                // 単項プラス（`+3`, `x * +2`）は読み飛ばす
+                if op == '+' && prev_was_op {
+                    continue;
+                }
+                while let Some(ArithTok::Op(top)) = stack.last() {
+                    // `^` は右結合なので同じ優先順位では取り出さない
+                    let pops = if op == '^' {
+                        prec(*top) > prec(op)
+                    } else {
+                        prec(*top) >= prec(op)
+                    };
+                    if pops {
+                        output.push(stack.pop().unwrap());
+                    } else {
+                        break;
+                    }
+                }
+                stack.push(ArithTok::Op(op));
+                prev_was_op = true;
+            }
+            ArithTok::LPar => {
+                stack.push(ArithTok::LPar);
+                prev_was_op = true;
+            }
+            ArithTok::RPar => {
+                while let Some(tok) = stack.pop() {
+                    if let ArithTok::LPar = tok {
+                        break;
+                    }
+                    output.push(tok);
+                }
+                prev_was_op = false;
+            }
+            ArithTok::Comma => {
+                // treat as low-precedence separator
+                while let Some(tok) = stack.last() {
+                    if let ArithTok::LPar = tok {
+                        break;
+                    }
+                    output.push(stack.pop().unwrap());
+                }
+                prev_was_op = true;
+            }
+        }
+    }
+    while let Some(tok) = stack.pop() {
+        output.push(tok);
+    }
+    output
+}
+
+/// `open` 位置の開き括弧に対応する閉じ括弧の位置を返す（()[]{}の入れ子を考慮）
+fn find_matching_close(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0i32;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `start` から始まる記号（`name` / `name[...]`）の終わりの位置
+///
+/// 添字の中は対応する `]` まで読むので、`flow[A->B]` のような記号を含む要素も1つの記号になる。
+fn symbol_end(expr: &str, start: usize) -> usize {
+    let bytes = expr.as_bytes();
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'[' => match find_matching_close(expr, i) {
+                Some(close) => i = close + 1,
+                None => i += 1,
+            },
+            b if b.is_ascii_alphanumeric() || b == b'_' || b == b']' || b == b'.' => i += 1,
+            _ => break,
+        }
+    }
+    i
+}
+
+/// 引用符で囲んだ文字列リテラルか
+fn is_quoted(s: &str) -> bool {
+    s.len() >= 2
+        && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\'')))
+}
+
+/// 条件の被演算子を文字列として読む（引用符付きのリテラル、またはループ変数に束縛された要素）
+fn string_operand<'a>(s: &'a str, env: &'a HashMap<String, String>) -> Option<&'a str> {
+    if is_quoted(s) {
+        Some(&s[1..s.len() - 1])
+    } else {
+        env.get(s).map(|v| v.as_str())
+    }
+}
+
+/// 添字部分（`name[` より後ろ）を変数・パラメータのキーにする
+///
+/// 添字ごとに引用符を外し、ループ変数なら束縛された要素に置き換えて `,` でつなぐ。
+fn index_key(idx_part: &str, env: &HashMap<String, String>) -> String {
+    idx_part
+        .trim_end_matches(']')
+        .split(',')
+        .map(|t| t.trim().trim_matches('"').trim_matches('\''))
+        .map(|t| env.get(t).cloned().unwrap_or_else(|| t.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 式の中で使える関数（`log` は `log(x)` と底付きの `log(x, base)`）
+const FUNCTIONS: &[&str] = &[
+    "max", "min", "pow", "exp", "log", "ln", "sin", "cos", "tan", "sqrt", "abs",
+];
+
+/// 関数呼び出しを (関数名, 引数) に分ける（引数の個数が合わなければ `None`）
+fn call_args(sym: &str) -> Option<(&str, Vec<&str>)> {
+    let open = sym.find('(')?;
+    let name = &sym[..open];
+    if !FUNCTIONS.contains(&name) || !sym.ends_with(')') {
+        return None;
+    }
+    let args: Vec<&str> = split_top_level(&sym[open + 1..sym.len() - 1], ',')
+        .into_iter()
+        .map(str::trim)
+        .collect();
+    let arity_ok = match name {
+        "max" | "min" | "pow" => args.len() == 2,
+        "log" => matches!(args.len(), 1 | 2),
+        _ => args.len() == 1,
+    };
+    arity_ok.then_some((name, args))
+}
+
+/// 関数の値。0除算と同じく、実数にならない結果（負の数の平方根・対数、負の底の非整数乗など）は0
+fn apply_function(name: &str, args: &[f64]) -> f64 {
+    let a = args[0];
+    let v = match name {
+        "max" => a.max(args[1]),
+        "min" => a.min(args[1]),
+        "pow" => a.powf(args[1]),
+        "exp" => a.exp(),
+        "log" if args.len() == 2 => a.log(args[1]),
+        "log" | "ln" => a.ln(),
+        "sin" => a.sin(),
+        "cos" => a.cos(),
+        "tan" => a.tan(),
+        "sqrt" => a.sqrt(),
+        "abs" => a.abs(),
+        _ => 0.0,
+    };
+    real_or_zero(v)
+}
+
+/// べき乗（`a ^ b` / `pow(a, b)`）
+fn pow(a: f64, b: f64) -> f64 {
+    real_or_zero(a.powf(b))
+}
+
+fn real_or_zero(v: f64) -> f64 {
+    if v.is_finite() {
+        v
+    } else {
+        0.0
+    }
+}
+
+/// 関数の値と勾配（`max` / `min` は選ばれた側、それ以外は連鎖律。定義域の外では勾配も0）
+fn dual_function(name: &str, args: &[Dual]) -> Dual {
+    let a = &args[0];
+    let unary = |d: f64| {
+        let v = apply_function(name, &[a.v]);
+        let d = real_or_zero(d);
+        Dual {
+            v,
+            g: a.g.iter().map(|g| g * d).collect(),
+        }
+    };
+    match name {
+        "max" | "min" => {
+            let b = &args[1];
+            // 評価（f64::max / min）と同じく、同値なら先の引数を採る
+            if (b.v > a.v) == (name == "max") && b.v != a.v {
+                b.clone()
+            } else {
+                a.clone()
+            }
+        }
+        "pow" => dual_pow(a, &args[1]),
+        "log" if args.len() == 2 => {
+            // log_b(a) = ln(a) / ln(b)
+            let b = &args[1];
+            let v = apply_function(name, &[a.v, b.v]);
+            if !(a.v > 0.0 && b.v > 0.0 && b.v != 1.0) {
+                return Dual::constant(v);
+            }
+            let lb = b.v.ln();
+            let da = real_or_zero(1.0 / (a.v * lb));
+            let db = real_or_zero(-a.v.ln() / (b.v * lb * lb));
+            Dual {
+                v,
+                g: a.combine(da, b, db),
+            }
+        }
+        _ if a.g.is_empty() => Dual::constant(apply_function(name, &[a.v])),
+        "exp" => unary(a.v.exp()),
+        "log" | "ln" => unary(if a.v > 0.0 { 1.0 / a.v } else { 0.0 }),
+        "sin" => unary(a.v.cos()),
+        "cos" => unary(-a.v.sin()),
+        "tan" => unary(1.0 / (a.v.cos() * a.v.cos())),
+        "sqrt" => unary(if a.v > 0.0 { 0.5 / a.v.sqrt() } else { 0.0 }),
+        "abs" => unary(if a.v == 0.0 { 0.0 } else { a.v.signum() }),
+        _ => Dual::constant(0.0),
+    }
+}
+
+/// べき乗の値と勾配（d(a^b) = b a^(b-1) da + a^b ln(a) db。指数側は底が正のときだけ）
+fn dual_pow(a: &Dual, b: &Dual) -> Dual {
+    let v = pow(a.v, b.v);
+    if v == 0.0 && !(a.v == 0.0 && b.v > 0.0) {
+        return Dual::constant(v);
+    }
+    let da = pow(a.v, b.v - 1.0) * b.v;
+    let db = if a.v > 0.0 { v * a.v.ln() } else { 0.0 };
+    Dual {
+        v,
+        g: a.combine(da, b, db),
+    }
+}
+
+/// 括弧の外側で最初に現れる `pat` の位置
+fn find_top_level(s: &str, pat: &str) -> Option<usize> {
+    let mut depth = 0i32;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ if depth == 0 && s[i..].starts_with(pat) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 括弧の外側にある区切り文字でのみ分割
+fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// 最上位の `+`/`-` で式を符号付きの項に分ける
+pub(crate) fn signed_terms(expr: &str) -> Vec<(f64, &str)> {
+    let mut terms = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    let mut sign = 1.0;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '+' | '-' if depth == 0 && !is_exponent_sign(expr, i) => {
+                if !expr[start..i].trim().is_empty() {
+                    terms.push((sign, expr[start..i].trim()));
+                }
+                sign = if c == '-' { -1.0 } else { 1.0 };
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !expr[start..].trim().is_empty() {
+        terms.push((sign, expr[start..].trim()));
+    }
+    terms
+}
+
+/// 式が `abs` / `max` / `min`（集約の `maxover` / `minover` を含む）を呼んでいるか
+fn calls_piecewise(expr: &str) -> bool {
+    let bytes = expr.as_bytes();
+    ["abs", "max", "min"].iter().any(|f| {
+        expr.match_indices(f).any(|(i, _)| {
+            let rest = &expr[i + f.len()..];
+            let starts_word =
+                i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+            starts_word && (rest.starts_with(['(', '{']) || rest.starts_with("over"))
+        })
+    })
+}
+
+/// 括弧の外側にある `+`/`-` で式を項に分割（空の項は除く）
+fn split_terms(expr: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '+' | '-' if depth == 0 && !is_exponent_sign(expr, i) => {
+                parts.push(&expr[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&expr[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
+
+/// 位置 `i` の `+`/`-` が数値リテラルの指数部の符号（`1e-6` の `-`）か
+///
+/// `e`/`E` の前が数字（と小数点）だけの並びで、その前が識別子の文字でないときに限る
+/// （`x2e-1` は `x2e` から1を引く式）。
+fn is_exponent_sign(expr: &str, i: usize) -> bool {
+    let bytes = expr.as_bytes();
+    if i < 2 || !matches!(bytes[i - 1], b'e' | b'E') {
+        return false;
+    }
+    let mut j = i - 1;
+    while j > 0 && (bytes[j - 1].is_ascii_digit() || bytes[j - 1] == b'.') {
+        j -= 1;
+    }
+    j < i - 1
+        && bytes[j..i - 1].iter().any(u8::is_ascii_digit)
+        && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+        && (j == 0 || !(bytes[j - 1].is_ascii_alphanumeric() || bytes[j - 1] == b'_'))
+}
+
+/// 集合式を評価
+///
+/// サポート: 集合名、`{a, b}`、`1..5`、括弧、`union` / `inter` / `diff`（`\`）。
+/// 演算子は左結合・同順位。未知の集合名を含む場合は `None`。
+pub fn eval_set_expr(expr: &str, sets: &HashMap<String, Vec<String>>) -> Option<Vec<String>> {
+    // トークン分割: 演算子と被演算子を交互に並べる
+    let expr = expr.trim();
+    let mut operands: Vec<Vec<String>> = Vec::new();
+    let mut ops: Vec<&str> = Vec::new();
+    let mut i = 0;
+    while i < expr.len() {
+        let rest = &expr[i..];
+        let c = rest.chars().next()?;
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        if operands.len() > ops.len() {
+            // 演算子の位置
+            let (op, len) = if c == '\\' {
+                ("diff", 1)
+            } else {
+                let word: String = rest
+                    .chars()
+                    .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '_')
+                    .collect();
+                match word.as_str() {
+                    "union" | "inter" | "diff" => (&rest[..word.len()], word.len()),
+                    _ => return None,
+                }
+            };
+            ops.push(op);
+            i += len;
+            continue;
+        }
+        // 被演算子の位置
+        if c == '{' || c == '(' {
+            let close = find_matching_close(rest, 0)?;
+            let inner = &rest[1..close];
+            if c == '{' {
+                operands.push(parse_set_literal(inner));
+            } else {
+                operands.push(eval_set_expr(inner, sets)?);
+            }
+            i += close + 1;
+            continue;
+        }
+        let len = rest
+            .find(|ch: char| ch.is_whitespace() || ch == '\\' || ch == '(' || ch == '{')
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        if let Some(set) = sets.get(word) {
+            operands.push(set.clone());
+        } else if let Some(dd) = word.find("..") {
+            let a = word[..dd].parse::<i32>().ok()?;
+            let b = word[dd + 2..].parse::<i32>().ok()?;
+            operands.push((a..=b).map(|v| v.to_string()).collect());
+        } else {
+            return None;
+        }
+        i += len;
+    }
+    if operands.is_empty() || operands.len() != ops.len() + 1 {
+        return None;
+    }
+
+    let mut iter = operands.into_iter();
+    let mut acc = iter.next()?;
+    for (op, rhs) in ops.into_iter().zip(iter) {
+        match op {
+            "union" => {
+                for v in rhs {
+                    if !acc.contains(&v) {
+                        acc.push(v);
+                    }
+                }
+            }
+            "inter" => acc.retain(|v| rhs.contains(v)),
+            _ => acc.retain(|v| !rhs.contains(v)),
+        }
+    }
+    Some(acc)
+}
+
+/// 集合リテラルの中身（`1, 2, "a"`）を要素列に変換
+fn parse_set_literal(inner: &str) -> Vec<String> {
+    let mut elems = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            ',' if quote.is_none() => {
+                elems.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    elems.push(&inner[start..]);
+    elems
+        .into_iter()
+        .map(|s| s.trim().trim_matches('"').trim_matches('\'').to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// `set NAME = keys(param);` なら (集合名, パラメータ名) を返す
+fn parse_keys_set(line: &str) -> Option<(String, String)> {
+    let (name, value) = line[4..].split_once('=')?;
+    let value = value.trim().trim_end_matches(';').trim();
+    let param = value.strip_prefix("keys(")?.strip_suffix(')')?.trim();
+    Some((name.trim().to_string(), param.to_string()))
+}
+
 fn parse_set(line: &str, sets: &mut HashMap<String, Vec<String>>) -> Result<(), String> {
     // set Items = {1, 2, 3};
     // set CUSTOMERS = 1..5;
+    // set Active = Domestic union Foreign;
     if let Some(eq) = line.find('=') {
         let name = line[4..eq].trim().to_string();
-        let value = line[eq + 1..].trim().trim_end_matches(';');
+        let value = line[eq + 1..].split('#').next().unwrap_or("");
+        let value = value.trim().trim_end_matches(';').trim();
+
+        // 集合演算: 定義済みの集合・リテラル・範囲を左から順に union / inter / diff（`\`）
+        if value.contains('\\')
+            || value
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .any(|w| matches!(w, "union" | "inter" | "diff"))
+        {
+            let elems = eval_set_expr(value, sets).ok_or_else(|| {
+                format!(
+                    "set {}: cannot evaluate '{}' (operands must be sets defined earlier, \
+                     literals or ranges)",
+                    name, value
+                )
+            })?;
+            sets.insert(name, elems);
+            return Ok(());
+        }
 
         // 範囲表記: 1..5
         if let Some(dotdot) = value.find("..") {
@@ -765,13 +2864,16 @@ fn parse_set(line: &str, sets: &mut HashMap<String, Vec<String>>) -> Result<(),
             }
         }
 
-        // 集合表記: {1, 2, 3}
-        let elems_str = value.trim_matches(|c| c == '{' || c == '}');
-        let elems: Vec<String> = elems_str
-            .split(',')
-            .map(|s| s.trim().trim_matches('"').trim_matches('\'').to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
+        // 集合表記: {1, 2, 3}（引用符の中の `,` は要素の一部）
+        let elems = parse_set_literal(value.trim_matches(|c| c == '{' || c == '}'));
+        // 要素は `x[A,B]` のキーにそのまま入るので、キーの区切りと紛れる文字は使えない
+        if let Some(bad) = elems.iter().find(|e| e.contains([',', '[', ']'])) {
+            return Err(format!(
+                "set {}: element \"{}\" contains ',', '[' or ']', which cannot be used in \
+                 variable and parameter keys",
+                name, bad
+            ));
+        }
 
         sets.insert(name, elems);
     }
@@ -781,7 +2883,8 @@ fn parse_set(line: &str, sets: &mut HashMap<String, Vec<String>>) -> Result<(),
 fn parse_param(
     line: &str,
     params: &mut HashMap<String, HashMap<String, f64>>,
-    _sets: &HashMap<String, Vec<String>>,
+    consts: &HashMap<String, f64>,
+    sets: &HashMap<String, Vec<String>>,
 ) -> Result<(), String> {
     // param value[Items] = {1: 10, 2: 20};
     // param capacity = 10;
@@ -793,12 +2896,24 @@ fn parse_param(
         let value_str = line[eq + 1..].trim();
 
         if !name_part.contains('[') {
-            // スカラー: param capacity = 10;
-            if let Ok(val) = value_str.parse::<f64>() {
-                let mut map = HashMap::new();
-                map.insert("_".to_string(), val);
-                params.insert(name_part.to_string(), map);
-            }
+            // スカラー: param capacity = 10; / param total = base * 1.2; / param n = card(S);
+            // 行末コメントを落としてから `;` を外す（`param a = b * 2;  # ...`）
+            let value_str = value_str
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_end_matches(';')
+                .trim();
+            let val = match value_str.parse::<f64>() {
+                Ok(v) => v,
+                Err(_) => expand_card(value_str, sets)
+                    .and_then(|e| eval_param_expr(&e, params, consts))
+                    .map_err(|e| format!("param {}: {}", name_part, e))?,
+            };
+            let mut map = HashMap::new();
+            map.insert("_".to_string(), finite_param(name_part, None, val)?);
+            params.insert(name_part.to_string(), map);
             return Ok(());
         }
 
@@ -807,23 +2922,7 @@ fn parse_param(
             let name = name_part[..bracket].trim().to_string();
             let _idx_name = name_part[bracket + 1..].trim_end_matches(']').trim();
 
-            let mut map = HashMap::new();
-            let value_str = value_str.trim_matches(|c| c == '{' || c == '}');
-
-            for pair in value_str.split(',') {
-                let pair = pair.trim();
-                if let Some(colon) = pair.find(':') {
-                    let key = pair[..colon]
-                        .trim()
-                        .trim_matches('"')
-                        .trim_matches('\'')
-                        .to_string();
-                    if let Ok(val) = pair[colon + 1..].trim().parse::<f64>() {
-                        map.insert(key, val);
-                    }
-                }
-            }
-
+            let map = parse_param_table(&name, value_str)?;
             params.insert(name, map);
         }
     } else {
@@ -834,64 +2933,424 @@ fn parse_param(
             params.insert(name, HashMap::new());
         }
     }
-
-    Ok(())
+
+    Ok(())
+}
+
+fn parse_data_assignment(
+    line: &str,
+    params: &mut HashMap<String, HashMap<String, f64>>,
+) -> Result<(), String> {
+    // 例: capacity = 100, cost[A] = 10
+    if let Some(eq) = line.find('=') {
+        let name_part = line[..eq].trim();
+        let value_str = line[eq + 1..].trim();
+
+        if !name_part.contains('[') {
+            // 表: cost = {A,1: 5, A,2: 7}
+            if value_str.starts_with('{') {
+                let map = parse_param_table(name_part, value_str)?;
+                params.entry(name_part.to_string()).or_default().extend(map);
+            } else if let Ok(val) = value_str.parse::<f64>() {
+                let mut map = HashMap::new();
+                map.insert("_".to_string(), finite_param(name_part, None, val)?);
+                params.insert(name_part.to_string(), map);
+            }
+            return Ok(());
+        }
+
+        if let Some(b) = name_part.find('[') {
+            let name = name_part[..b].trim().to_string();
+            let idx = param_key(name_part[b + 1..].trim_end_matches(']'));
+            let val = value_str.parse::<f64>().unwrap_or(0.0);
+            let val = finite_param(&name, Some(&idx), val)?;
+            let entry = params.entry(name).or_default();
+            entry.insert(idx, val);
+        }
+    }
+    Ok(())
+}
+
+/// 添字付きパラメータの値の表 `{A: 10, B: 20}` / `{A,1: 5, "New York",2: 7}` を読む
+///
+/// 複数の添字はキーを `,` でつなぎ（`cost[A,1]` の参照と同じ `"A,1"`）、添字ごとに
+/// 前後の空白と引用符を除く。数値でない値は読み飛ばす。
+fn parse_param_table(name: &str, value_str: &str) -> Result<HashMap<String, f64>, String> {
+    let inner = value_str
+        .trim()
+        .trim_start_matches('{')
+        .trim_end_matches('}');
+    // `:` で区切ると、先頭は最初のキー、途中は「値, 次のキー」、末尾は最後の値になる
+    let parts = split_unquoted(inner, ':');
+    let mut map = HashMap::new();
+    let mut key = parts[0];
+    for (k, part) in parts.iter().enumerate().skip(1) {
+        let (value, next) = if k + 1 < parts.len() {
+            match part.find(',') {
+                Some(comma) => (&part[..comma], &part[comma + 1..]),
+                None => {
+                    return Err(format!(
+                        "param {}: expected ',' before '{}'",
+                        name,
+                        part.trim()
+                    ))
+                }
+            }
+        } else {
+            (*part, "")
+        };
+        let key_str = param_key(key);
+        if let Ok(val) = value.trim().parse::<f64>() {
+            let val = finite_param(name, Some(&key_str), val)?;
+            map.insert(key_str, val);
+        }
+        key = next;
+    }
+    Ok(map)
+}
+
+/// 引用符の外にある区切り文字でのみ分割
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if c == sep && quote.is_none() => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// パラメータのキーを参照時と同じ形にする（添字ごとに空白と引用符を除き `,` でつなぐ）
+fn param_key(raw: &str) -> String {
+    split_unquoted(raw, ',')
+        .iter()
+        .map(|t| t.trim().trim_matches('"').trim_matches('\''))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// JSONファイルからパラメータを読み込む（サイドカー）
+///
+/// 有限でない値（NaN / ±inf）はエラーにする。
+pub fn load_json_into(model: &mut Model, path: &Path) -> Result<(), String> {
+    let data = read_text(path)?;
+    let v: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    if let Some(obj) = v.as_object() {
+        for (pname, val) in obj {
+            let entry = model.params.entry(pname.clone()).or_default();
+            match val {
+                Value::Number(_) | Value::String(_) => {
+                    if let Some(fv) = json_number(val) {
+                        entry.insert("_".to_string(), finite_param(pname, None, fv)?);
+                    }
+                }
+                Value::Object(map) => {
+                    for (k, v2) in map {
+                        if let Some(fv) = json_number(v2) {
+                            // `"A, 1"` も `cost[A,1]` で引けるようにする
+                            let k = param_key(k);
+                            let fv = finite_param(pname, Some(&k), fv)?;
+                            entry.insert(k, fv);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    model.resolve_key_sets();
+    model.resolve_bounds()?;
+    // パラメータが変わると `where` 条件の展開結果も変わり得る
+    model.expansions = ExpansionCache::default();
+    Ok(())
+}
+
+/// スカラーパラメータの右辺式を、定義済みのスカラーパラメータと定数だけで評価する
+///
+/// 未定義（前方参照を含む）の名前や添字付きの参照はエラー。
+fn eval_param_expr(
+    expr: &str,
+    params: &HashMap<String, HashMap<String, f64>>,
+    consts: &HashMap<String, f64>,
+) -> Result<f64, String> {
+    eval_scalar_expr(expr, "scalar param", |name| {
+        consts
+            .get(name)
+            .or_else(|| params.get(name).and_then(|m| m.get("_")))
+            .copied()
+            .or_else(|| builtin_const(name))
+    })
+}
+
+/// `const name = expr;` をパースする（右辺は数値と定義済みの定数だけの式）
+fn parse_const(line: &str, model: &mut Model) -> Result<(), String> {
+    let body = line["const ".len()..].trim().trim_end_matches(';');
+    let (name, expr) = body
+        .split_once('=')
+        .ok_or_else(|| format!("const: expected `const name = expr`: {}", line))?;
+    let (name, expr) = (name.trim(), expr.trim());
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("const: invalid name '{}'", name));
+    }
+    if builtin_const(name).is_some() {
+        return Err(format!("const {}: redefines a built-in constant", name));
+    }
+    let var_clash = model
+        .var_names
+        .iter()
+        .any(|v| v == name || v.strip_prefix(name).is_some_and(|r| r.starts_with('[')));
+    if model.consts.contains_key(name) || model.params.contains_key(name) || var_clash {
+        return Err(format!("const {}: name already defined", name));
+    }
+    let consts = &model.consts;
+    let value = eval_scalar_expr(expr, "constant", |n| {
+        consts.get(n).copied().or_else(|| builtin_const(n))
+    })
+    .map_err(|e| format!("const {}: {}", name, e))?;
+    if !value.is_finite() {
+        return Err(format!("const {}: non-finite value {}", name, value));
+    }
+    model.consts.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// 組み込みの数学定数
+fn builtin_const(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
+
+/// 式中の `card(S)` を集合 `S` の要素数に置き換える
+fn expand_card(expr: &str, sets: &HashMap<String, Vec<String>>) -> Result<String, String> {
+    let mut out = String::with_capacity(expr.len());
+    let mut rest = expr;
+    while let Some(pos) = rest.find("card(") {
+        let ident_before = rest[..pos]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+        let close = rest[pos..]
+            .find(')')
+            .ok_or_else(|| "card: missing ')'".to_string())?;
+        out.push_str(&rest[..pos]);
+        if ident_before {
+            out.push_str(&rest[pos..pos + close + 1]);
+        } else {
+            let set = rest[pos + "card(".len()..pos + close].trim();
+            let n = sets
+                .get(set)
+                .ok_or_else(|| format!("card: unknown set '{}'", set))?
+                .len();
+            out.push_str(&n.to_string());
+        }
+        rest = &rest[pos + close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// 名前を `lookup` で解決できる値だけからなる式を評価する（`kind` はエラー表示用）
+fn eval_scalar_expr(
+    expr: &str,
+    kind: &str,
+    lookup: impl Fn(&str) -> Option<f64>,
+) -> Result<f64, String> {
+    let mut scope = Model::new();
+    let bytes = expr.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if c.is_ascii_digit() || c == '.' {
+            // 数値リテラル
+            while i < bytes.len()
+                && ((bytes[i] as char).is_ascii_alphanumeric() || bytes[i] == b'.')
+            {
+                i += 1;
+            }
+            continue;
+        }
+        if !(c.is_ascii_alphabetic() || c == '_') {
+            if c == '[' {
+                return Err(format!("indexed references are not allowed in a {}", kind));
+            }
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && ((bytes[i] as char).is_ascii_alphanumeric() || bytes[i] == b'_') {
+            i += 1;
+        }
+        let name = &expr[start..i];
+        if FUNCTIONS.contains(&name) && bytes.get(i) == Some(&b'(') {
+            continue;
+        }
+        match lookup(name) {
+            Some(v) => {
+                scope
+                    .params
+                    .insert(name.to_string(), HashMap::from([("_".to_string(), v)]));
+            }
+            None => return Err(format!("unknown {} '{}'", kind, name)),
+        }
+    }
+    Ok(scope.eval_arith(expr, &[], &HashMap::new()))
+}
+
+/// 初期解（ウォームスタート）をJSONから読み込む
+///
+/// `{name: value}` 形式、または `{"variables": {name: value}}`（結果のJSON出力）形式。
+/// 返り値はモデルの変数順のベクトルで、ファイルに無い変数は NaN（ソルバー側で乱数初期化）。
+pub fn load_warm_start(model: &Model, path: &Path) -> Result<Vec<f64>, String> {
+    let data = read_text(path)?;
+    let v: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let obj = v
+        .get("variables")
+        .unwrap_or(&v)
+        .as_object()
+        .ok_or("expected a JSON object of {name: value}")?;
+    let mut x = vec![f64::NAN; model.dim];
+    for (name, val) in obj {
+        match (model.var_map.get(name), json_number(val)) {
+            (Some(&i), Some(fv)) if fv.is_finite() => x[i] = fv,
+            (Some(_), _) => return Err(format!("variable {}: invalid value {}", name, val)),
+            (None, _) => eprintln!("warning: warm start: unknown variable '{}'", name),
+        }
+    }
+    Ok(x)
+}
+
+/// 初期解の集団をCSVから読み込む（1行 = 1候補解）
+///
+/// 先頭行に数値でないセルがあれば変数名のヘッダとして扱い、無ければ列を `var_names` の順に
+/// 対応させる。未知の列・余分な列は警告して無視し、足りない列と空のセルは NaN
+/// （ソルバー側で乱数初期化）。`#` で始まる行と空行は読み飛ばす。
+pub fn load_warm_csv(model: &Model, path: &Path) -> Result<Vec<Vec<f64>>, String> {
+    let data = read_text(path)?;
+    let mut lines = data
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (n + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .peekable();
+
+    // 列 → 変数インデックス
+    let header = lines.peek().map(|(_, l)| split_csv_row(l)).filter(|cells| {
+        cells
+            .iter()
+            .any(|c| !c.is_empty() && c.parse::<f64>().is_err())
+    });
+    let columns: Vec<Option<usize>> = match header {
+        Some(cells) => {
+            lines.next();
+            cells
+                .iter()
+                .map(|name| {
+                    let idx = model.var_map.get(name.as_str()).copied();
+                    if idx.is_none() {
+                        eprintln!("warning: warm csv: unknown column '{}'", name);
+                    }
+                    idx
+                })
+                .collect()
+        }
+        None => (0..model.dim).map(Some).collect(),
+    };
+
+    let mut rows = Vec::new();
+    let mut warned = false;
+    for (n, line) in lines {
+        let cells = split_csv_row(line);
+        if cells.len() != columns.len() && !warned {
+            eprintln!(
+                "warning: warm csv: line {} has {} column(s), expected {}",
+                n,
+                cells.len(),
+                columns.len()
+            );
+            warned = true;
+        }
+        let mut x = vec![f64::NAN; model.dim];
+        for (cell, col) in cells.iter().zip(&columns) {
+            let Some(&i) = col.as_ref() else {
+                continue;
+            };
+            if cell.is_empty() {
+                continue;
+            }
+            match cell.parse::<f64>() {
+                Ok(v) if v.is_finite() => x[i] = v,
+                _ => return Err(format!("line {}: invalid value '{}'", n, cell)),
+            }
+        }
+        rows.push(x);
+    }
+    Ok(rows)
 }
 
-fn parse_data_assignment(
-    line: &str,
-    params: &mut HashMap<String, HashMap<String, f64>>,
-) -> Result<(), String> {
-    // 例: capacity = 100, cost[A] = 10
-    if let Some(eq) = line.find('=') {
-        let name_part = line[..eq].trim();
-        let value_str = line[eq + 1..].trim();
-
-        if !name_part.contains('[') {
-            if let Ok(val) = value_str.parse::<f64>() {
-                let mut map = HashMap::new();
-                map.insert("_".to_string(), val);
-                params.insert(name_part.to_string(), map);
+/// CSVの1行をセルに分ける（`"..."` と `[...]` の中のカンマでは分けない）
+fn split_csv_row(line: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cur = String::new();
+    let mut quoted = false;
+    let mut depth = 0i32;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' if !quoted => {
+                depth += 1;
+                cur.push(c);
             }
-            return Ok(());
-        }
-
-        if let Some(b) = name_part.find('[') {
-            let name = name_part[..b].trim().to_string();
-            let idx = name_part[b + 1..].trim_end_matches(']').trim().to_string();
-            let val = value_str.parse::<f64>().unwrap_or(0.0);
-            let entry = params.entry(name).or_default();
-            entry.insert(idx, val);
+            ']' if !quoted => {
+                depth -= 1;
+                cur.push(c);
+            }
+            ',' if !quoted && depth == 0 => cells.push(std::mem::take(&mut cur).trim().to_string()),
+            _ => cur.push(c),
         }
     }
-    Ok(())
+    cells.push(cur.trim().to_string());
+    cells
 }
 
-/// JSONファイルからパラメータを読み込む（サイドカー）
-pub fn load_json_into(model: &mut Model, path: &Path) -> Result<(), String> {
+/// テキストファイルを読む（Windows のエディタが付ける先頭の UTF-8 BOM は取り除く）
+fn read_text(path: &Path) -> Result<String, String> {
     let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let v: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-    if let Some(obj) = v.as_object() {
-        for (pname, val) in obj {
-            let entry = model.params.entry(pname.clone()).or_default();
-            match val {
-                Value::Number(n) => {
-                    if let Some(fv) = n.as_f64() {
-                        entry.insert("_".to_string(), fv);
-                    }
-                }
-                Value::Object(map) => {
-                    for (k, v2) in map {
-                        if let Some(fv) = v2.as_f64() {
-                            entry.insert(k.clone(), fv);
-                        }
-                    }
-                }
-                _ => {}
-            }
-        }
+    Ok(match data.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => data,
+    })
+}
+
+/// JSONの数値（`"NaN"` のような数値文字列も検査のため数値として読む）
+fn json_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
     }
-    Ok(())
+}
+
+/// パラメータ値が有限であることを確かめる
+fn finite_param(name: &str, key: Option<&str>, v: f64) -> Result<f64, String> {
+    if v.is_finite() {
+        return Ok(v);
+    }
+    Err(match key {
+        Some(k) => format!("param {}[{}]: non-finite value {}", name, k, v),
+        None => format!("param {}: non-finite value {}", name, v),
+    })
 }
 
 fn parse_var(
@@ -915,8 +3374,34 @@ fn parse_var(
         (line.split_whitespace().next().unwrap_or(""), None)
     };
 
-    // 境界値の解析
-    let (lb, ub) = parse_bounds(line)?;
+    // 型と境界値の解析
+    let var_type = VarType::from_decl(line).map_err(|e| format!("var {}: {}", name, e))?;
+    let ((lb, lower), (ub, upper)) = parse_bounds(line, var_type)?;
+    let (lb, ub) = var_type
+        .check_domain(lb, ub)
+        .map_err(|e| format!("var {}: {}", name, e))?;
+
+    // `keys(p)` の集合はパース時点の p のキーで展開するので、空なら宣言順の誤りとみなす
+    for idx in indices.iter().flatten() {
+        if let Some((set, param)) = model.key_sets.iter().find(|(s, _)| s == idx) {
+            if sets.get(set).is_none_or(|v| v.is_empty()) {
+                return Err(format!(
+                    "var {}: set {} = keys({}) is empty; define {} in the model before the var \
+                     (variables are created at parse time)",
+                    name, set, param, param
+                ));
+            }
+        }
+    }
+
+    // 宣言のインデックスは集合か範囲（綴りの誤りを1要素の変数にしない）
+    if let Some(bad) = indices
+        .iter()
+        .flatten()
+        .find(|idx| !sets.contains_key(**idx) && !idx.contains(".."))
+    {
+        return Err(format!("unknown set '{}' in var {}", bad, name));
+    }
 
     // インデックスの展開
     let mut combos: Vec<String> = Vec::new();
@@ -929,11 +3414,21 @@ fn parse_var(
         combos.push(name.to_string());
     }
 
+    let first = model.var_names.len();
     for var_name in combos {
         model.lb.push(lb);
         model.ub.push(ub);
+        model.var_types.push(var_type);
         model.var_names.push(var_name);
     }
+    if lower.is_some() || upper.is_some() {
+        model.bound_refs.push(BoundRef {
+            vars: first..model.var_names.len(),
+            var_type,
+            lower,
+            upper,
+        });
+    }
 
     Ok(())
 }
@@ -967,44 +3462,66 @@ fn parse_state_or_decision(
     is_state: bool,
 ) -> Result<(), String> {
     // state S[t] in 0..100 int;
-    // decision order[t] in 0..50 int;
+    // state S[t] in 0.0..500.0 real;       （連続値の状態）
+    // decision order[t] in 0..max_order;  （範囲の端はスカラーパラメータ・定数の式でもよい）
     let line = line.trim_end_matches(';');
     let prefix = if is_state { "state " } else { "decision " };
     let line = &line[prefix.len()..];
 
-    // 変数名とインデックスを抽出
-    let (name, indices) = if let Some(b) = line.find('[') {
+    // 変数名とインデックスを抽出（`in` の範囲はインデックスの後ろから探す）
+    let (name, indices, rest) = if let Some(b) = line.find('[') {
         let e = line.find(']').unwrap_or(line.len());
         let indices_str = &line[b + 1..e];
         let indices: Vec<&str> = indices_str.split(',').map(|s| s.trim()).collect();
-        (line[..b].trim(), Some(indices))
+        (
+            line[..b].trim(),
+            Some(indices),
+            &line[(e + 1).min(line.len())..],
+        )
     } else {
-        (line.split_whitespace().next().unwrap_or(""), None)
+        let name = line.split_whitespace().next().unwrap_or("");
+        (name, None, &line.trim_start()[name.len()..])
     };
 
-    // in キーワードの後の範囲を抽出
+    // in キーワードの後の範囲を抽出（省略時は 0..1000）
     let mut lb = 0.0f64;
     let mut ub = 1000.0f64;
 
-    if let Some(in_pos) = line.find(" in ") {
-        let range_str = &line[in_pos + 4..];
+    if let Some(in_pos) = format!(" {}", rest).find(" in ") {
+        let range_str = &rest[in_pos + 3..];
 
-        // 範囲表記: 0..100
+        // 範囲表記: 0..100 / 0.0..500.0
         if let Some(dotdot) = range_str.find("..") {
             let start_str = range_str[..dotdot].trim();
+            // 終端は型のキーワード・コメントの手前まで（`0..max_order / 2 int`）
             let end_str = range_str[dotdot + 2..]
-                .split_whitespace()
+                .split('#')
                 .next()
-                .unwrap_or("");
-            if let (Ok(start), Ok(end)) = (start_str.parse::<f64>(), end_str.parse::<f64>()) {
-                lb = start;
-                ub = end;
+                .unwrap_or("")
+                .split_whitespace()
+                .take_while(|w| VarType::from_keyword(w).is_none())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let bound = |s: &str| eval_param_expr(s, &model.params, &model.consts).ok();
+            match (bound(start_str), bound(&end_str)) {
+                (Some(start), Some(end)) => {
+                    lb = start;
+                    ub = end;
+                }
+                _ => {
+                    return Err(format!(
+                        "{}{}: invalid range '{}..{}'",
+                        prefix, name, start_str, end_str
+                    ))
+                }
             }
         }
     }
 
-    // intキーワードの確認（将来の拡張用）
-    let _is_int = line.contains(" int") || line.contains(" Integer");
+    let var_type = VarType::from_decl(line).map_err(|e| format!("{}{}: {}", prefix, name, e))?;
+    let (lb, ub) = var_type
+        .check_domain(lb, ub)
+        .map_err(|e| format!("{}{}: {}", prefix, name, e))?;
 
     let mut combos: Vec<String> = Vec::new();
     if let Some(idx_list) = indices {
@@ -1019,6 +3536,7 @@ fn parse_state_or_decision(
     for var_name in combos {
         model.lb.push(lb);
         model.ub.push(ub);
+        model.var_types.push(var_type);
         model.var_names.push(var_name);
     }
 
@@ -1027,27 +3545,59 @@ fn parse_state_or_decision(
 
 fn parse_objective(line: &str, model: &mut Model) -> Result<(), String> {
     // maximize profit: sum{i in Items} value[i] * x[i];
-    if let Some(colon) = line.find(':') {
-        let expr = line[colon + 1..].trim().trim_end_matches(';');
-        model.objective_expr = Some(expr.to_string());
+    let expr = if let Some(colon) = find_top_level(line, ":") {
+        line[colon + 1..].trim().trim_end_matches(';')
     } else {
         // コロンなしの場合
-        if let Some(rest) = line.strip_prefix("maximize ") {
-            model
-                .objective_expr
-                .replace(rest.trim().trim_end_matches(';').to_string());
-        } else if let Some(rest) = line.strip_prefix("minimize ") {
-            model
-                .objective_expr
-                .replace(rest.trim().trim_end_matches(';').to_string());
+        let rest = line
+            .strip_prefix("maximize ")
+            .or_else(|| line.strip_prefix("minimize "));
+        let Some(rest) = rest else {
+            return Ok(());
+        };
+        rest.trim().trim_end_matches(';')
+    };
+
+    // minimize [cost, co2]; は多目的の省略記法（目的名は obj1, obj2, ...）
+    if let Some(list) = expr
+        .trim()
+        .strip_prefix('[')
+        .and_then(|e| e.strip_suffix(']'))
+    {
+        let items: Vec<&str> = split_top_level(list, ',')
+            .into_iter()
+            .map(str::trim)
+            .collect();
+        if items.iter().any(|e| e.is_empty()) {
+            return Err(format!("empty objective in list '{}'", expr.trim()));
         }
+        for (k, e) in items.iter().enumerate() {
+            model.objectives.push(Objective {
+                name: format!("obj{}", k + 1),
+                expr: e.to_string(),
+                maximize: model.maximize,
+            });
+        }
+        // 既定は等しい重みの重み付き和（`weight objK: w` で上書きできる）
+        let w = 1.0 / items.len() as f64;
+        model.pareto = ParetoMethod::WeightedSum(
+            model
+                .objectives
+                .iter()
+                .map(|o| (o.name.clone(), w))
+                .collect(),
+        );
+        model.objective_expr = Some(items[0].to_string());
+        return Ok(());
     }
+
+    model.objective_expr = Some(expr.to_string());
     Ok(())
 }
 
 fn parse_objective_named(line: &str) -> (String, String) {
     // minimize total_cost: expr
-    if let Some(colon) = line.find(':') {
+    if let Some(colon) = find_top_level(line, ":") {
         let head = line[..colon].trim();
         let expr = line[colon + 1..].trim().trim_end_matches(';').to_string();
         let mut parts = head.split_whitespace();
@@ -1059,17 +3609,111 @@ fn parse_objective_named(line: &str) -> (String, String) {
     }
 }
 
+/// 制約ブロックの `label:` だけの行ならラベルを返す
+fn constraint_label(line: &str) -> Option<&str> {
+    let label = line.strip_suffix(':')?.trim();
+    let ident = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    ident.then_some(label)
+}
+
+/// ラベル行に続く名前なし制約にラベルを付ける
+///
+/// 1つだけなら `label`、2つ以上なら `label[1]`, `label[2]`, ... と番号を振る。
+/// 自前の名前（`name: ...`）を持つ制約はそのまま。
+fn name_grouped_constraint(constraints: &mut [Constraint], label: &str, first: usize, at: usize) {
+    let Some(c) = constraints.get(at) else {
+        return;
+    };
+    if c.name != format!("c{}", at) {
+        return;
+    }
+    // このラベルで自動命名した制約（先頭から連続している前提）
+    let named = constraints[first..at]
+        .iter()
+        .filter(|c| c.name == label || c.name.starts_with(&format!("{}[", label)))
+        .count();
+    constraints[at].name = if named == 0 {
+        label.to_string()
+    } else {
+        if named == 1 {
+            if let Some(prev) = constraints[first..at].iter_mut().find(|c| c.name == label) {
+                prev.name = format!("{}[1]", label);
+            }
+        }
+        format!("{}[{}]", label, named + 1)
+    };
+}
+
+/// 制約名の後ろの `[penalty=W]` を取り除き、(残りの行, 係数) を返す
+///
+/// `weight_limit [penalty=100]: ...` → (`weight_limit: ...`, Some(100))。名前が無ければ `:` ごと落とす。
+fn split_penalty(line: &str) -> Result<(String, Option<f64>), String> {
+    let Some(colon) = find_top_level(line, ":") else {
+        return Ok((line.to_string(), None));
+    };
+    let head = line[..colon].trim_end();
+    let attr = head
+        .strip_suffix(']')
+        .and_then(|h| h.rfind('[').map(|open| (open, &h[open + 1..])))
+        .filter(|(_, inner)| inner.trim_start().starts_with("penalty"));
+    let Some((open, inner)) = attr else {
+        return Ok((line.to_string(), None));
+    };
+    let value = inner
+        .split_once('=')
+        .filter(|(key, _)| key.trim() == "penalty")
+        .map(|(_, v)| v.trim())
+        .ok_or_else(|| {
+            format!(
+                "invalid constraint attribute '[{}]' (expected [penalty=W])",
+                inner
+            )
+        })?;
+    let weight = value
+        .parse::<f64>()
+        .ok()
+        .filter(|w| w.is_finite() && *w > 0.0)
+        .ok_or_else(|| format!("invalid penalty '{}' (expected a positive number)", value))?;
+    let name = head[..open].trim();
+    let rest = line[colon + 1..].trim();
+    let line = if name.is_empty() {
+        rest.to_string()
+    } else {
+        format!("{}: {}", name, rest)
+    };
+    Ok((line, Some(weight)))
+}
+
 fn parse_constraint(line: &str, model: &mut Model) -> Result<(), String> {
     // weight_limit: sum{i in Items} weight[i] * x[i] <= capacity;
     let line = line.trim_end_matches(';');
 
+    // 個別のペナルティ係数: `weight_limit [penalty=100]: ...`（forall なら展開した全制約に付ける）
+    if let (line, Some(weight)) = split_penalty(line)? {
+        let before = model.constraints.len();
+        parse_constraint(&line, model)?;
+        for c in &mut model.constraints[before..] {
+            c.penalty = Some(weight);
+        }
+        return Ok(());
+    }
+
+    // 1行の制約族: `cap: forall i in Items: x[i] <= cap[i]`
+    if let Some((name, header, body)) = split_forall(line).filter(|f| !f.2.is_empty()) {
+        let base = name.unwrap_or_else(|| format!("c{}", model.constraints.len()));
+        return expand_forall(&header, &body, &base, model);
+    }
+
     // CPグローバル制約は記録のみ（簡易ペナルティ用）
     if line.contains("no_overlap") || line.contains("disjunctive") || line.contains("cumulative") {
         model.cp_globals.push(line.to_string());
         return Ok(());
     }
 
-    let (name, expr_part) = if let Some(colon) = line.find(':') {
+    let (name, expr_part) = if let Some(colon) = find_top_level(line, ":") {
         (line[..colon].trim().to_string(), &line[colon + 1..])
     } else {
         ("".to_string(), line)
@@ -1096,16 +3740,14 @@ fn parse_constraint(line: &str, model: &mut Model) -> Result<(), String> {
     let lhs = parts[0].trim();
     let rhs_str = parts[1].trim();
 
-    // RHSを数値に変換
-    let rhs = if let Ok(val) = rhs_str.parse::<f64>() {
-        val
+    // 右辺: 数値はそのまま（高速な経路）。変数を含む式（`end[a] <= start[b]` など）は
+    // 左辺へ移項して `lhs - (rhs) op 0` にし、それ以外の式（パラメータの式）は評価時に計算する
+    let (lhs, rhs, rhs_expr) = if let Ok(val) = rhs_str.parse::<f64>() {
+        (lhs.to_string(), val, None)
+    } else if references_var(model, rhs_str) {
+        (format!("{} - ({})", lhs, rhs_str), 0.0, None)
     } else {
-        // パラメータ参照の可能性
-        if let Some(param_map) = model.params.get(rhs_str) {
-            param_map.get("_").copied().unwrap_or(0.0)
-        } else {
-            0.0
-        }
+        (lhs.to_string(), 0.0, Some(rhs_str.to_string()))
     };
 
     model.constraints.push(Constraint {
@@ -1114,48 +3756,1038 @@ fn parse_constraint(line: &str, model: &mut Model) -> Result<(), String> {
         } else {
             name
         },
-        expr: lhs.to_string(),
+        expr: lhs,
         op,
         rhs,
+        rhs_expr,
+        penalty: None,
     });
 
     Ok(())
 }
 
-fn parse_bounds(line: &str) -> Result<(f64, f64), String> {
-    let mut lb = 0.0f64;
-    let mut ub = 1000.0f64;
+/// `forall i in S, j in T where cond: body` を (名前, ヘッダ, 本体) に分ける
+///
+/// `name: forall ...: body` の形なら名前も返す。本体が空なら次の行が本体（複数行の形）。
+fn split_forall(line: &str) -> Option<(Option<String>, String, String)> {
+    let line = line.trim().trim_end_matches(';');
+    let (name, rest) = if line.starts_with("forall ") {
+        (None, line)
+    } else {
+        let colon = find_top_level(line, ":")?;
+        let rest = line[colon + 1..].trim();
+        if !rest.starts_with("forall ") {
+            return None;
+        }
+        (Some(line[..colon].trim().to_string()), rest)
+    };
+    let rest = &rest["forall ".len()..];
+    let colon = find_top_level(rest, ":")?;
+    Some((
+        name,
+        rest[..colon].trim().to_string(),
+        rest[colon + 1..].trim().to_string(),
+    ))
+}
+
+/// `forall` の制約族を展開し、インデックスの組ごとに1つの制約を追加する
+///
+/// ヘッダのループ変数は左から順に束縛するので、後ろの集合・範囲は前の変数を参照できる
+/// （`forall i in 1..N, j in i+1..N: ...`）。`where` 条件を満たす組だけを展開し、本体の
+/// ループ変数は要素に置き換える（条件は `sum` の `where` と同じく束縛して評価する）。
+/// 制約名は `base[要素,...]`。
+fn expand_forall(header: &str, body: &str, base: &str, model: &mut Model) -> Result<(), String> {
+    let (bindings, filter) = match find_top_level(header, " where ") {
+        Some(pos) => (&header[..pos], Some(header[pos + " where ".len()..].trim())),
+        None => (header, None),
+    };
+
+    // 組ごとの (ループ変数, 要素, 条件で比べる値)
+    let mut combos: Vec<Vec<(String, String, String)>> = vec![Vec::new()];
+    for part in split_top_level(bindings, ',') {
+        let (var, set_expr) = part
+            .split_once(" in ")
+            .map(|(v, s)| (v.trim(), s.trim()))
+            .ok_or_else(|| format!("forall: expected `i in SET`, got '{}'", part.trim()))?;
+        let mut next = Vec::new();
+        for combo in combos {
+            let set_expr = substitute_indices(set_expr, &combo);
+            let values = forall_set(&set_expr, model)
+                .ok_or_else(|| format!("forall: unknown set '{}'", set_expr))?;
+            for (k, v) in values.into_iter().enumerate() {
+                // 条件では `sum` の `where` と同じく、数値でない要素は集合内の順位で比べる
+                let key = match v.parse::<f64>() {
+                    Ok(_) => v.clone(),
+                    Err(_) => (k + 1).to_string(),
+                };
+                let mut c = combo.clone();
+                c.push((var.to_string(), v, key));
+                next.push(c);
+            }
+        }
+        combos = next;
+    }
+
+    let mut env = HashMap::new();
+    for combo in combos {
+        if let Some(cond) = filter {
+            for (var, v, key) in &combo {
+                env.insert(var.clone(), v.clone());
+                env.insert(rank_key(var), key.clone());
+            }
+            if !model.eval_condition(cond, &[], &env) {
+                continue;
+            }
+        }
+        let before = model.constraints.len();
+        parse_constraint(&substitute_indices(body, &combo), model)?;
+        if let Some(c) = model.constraints.get_mut(before) {
+            let key: Vec<&str> = combo.iter().map(|(_, v, _)| v.as_str()).collect();
+            c.name = format!("{}[{}]", base, key.join(","));
+        }
+    }
+    Ok(())
+}
+
+/// `forall` の集合式: 集合名・集合演算・整数範囲。範囲の端は式でもよい（`1..N`, `i+1..N`）
+fn forall_set(expr: &str, model: &Model) -> Option<Vec<String>> {
+    if let Some(set) = eval_set_expr(expr, &model.sets) {
+        return Some(set);
+    }
+    let dd = find_top_level(expr, "..")?;
+    let env = HashMap::new();
+    let lo = model.evaluate_expr(&expr[..dd], &[], &env).round() as i64;
+    let hi = model.evaluate_expr(&expr[dd + 2..], &[], &env).round() as i64;
+    Some((lo..=hi).map(|v| v.to_string()).collect())
+}
+
+/// 式中のループ変数（識別子として現れるもの）を束縛された要素に置き換える
+///
+/// 添字の中（`x[i]`）はそのまま、それ以外で数値でない要素は `"A"` と引用符で囲む
+/// （条件 `j != i` が文字列の比較になる）。引用符の中は置き換えない。
+fn substitute_indices(expr: &str, combo: &[(String, String, String)]) -> String {
+    let mut out = String::with_capacity(expr.len());
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut chars = expr.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            out.push(c);
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+        if !(c.is_ascii_alphabetic() || c == '_') {
+            out.push(c);
+            continue;
+        }
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, d)) = chars.peek() {
+            if !(d.is_ascii_alphanumeric() || d == '_') {
+                break;
+            }
+            end = j + d.len_utf8();
+            chars.next();
+        }
+        let word = &expr[i..end];
+        match combo.iter().find(|(var, _, _)| var == word) {
+            Some((_, v, _)) if depth == 0 && v.parse::<f64>().is_err() => {
+                out.push_str(&format!("\"{}\"", v))
+            }
+            Some((_, v, _)) => out.push_str(v),
+            None => out.push_str(word),
+        }
+    }
+    out
+}
+
+/// 式が決定変数を参照しているか（`x` / `x[...]` の名前で判定する）
+fn references_var(model: &Model, expr: &str) -> bool {
+    expr.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|t| t.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+        .any(|t| {
+            model
+                .var_names
+                .iter()
+                .any(|v| v == t || v.strip_prefix(t).is_some_and(|rest| rest.starts_with('[')))
+        })
+}
+
+/// 境界1つ分の (数値, 式)
+type BoundSpec = (f64, Option<String>);
+
+/// 変数宣言の `>=` / `<=` を読む。戻り値は (下界, 上界)
+///
+/// 数値でない境界（`maxCap`, `2*cap`）は式として返し、数値は既定値（0 / 1000、binary は 0..1）
+/// のまま。式は `Model::resolve_bounds` で評価する。
+fn parse_bounds(line: &str, var_type: VarType) -> Result<(BoundSpec, BoundSpec), String> {
+    // Binary変数は明示がなければ 0..1
+    let default_ub = if var_type == VarType::Binary {
+        1.0
+    } else {
+        1000.0
+    };
+    let bound = |op: &str, default: f64| -> BoundSpec {
+        let Some(token) = line.find(op).and_then(|p| {
+            line[p + 2..]
+                .split_whitespace()
+                .next()
+                .map(|s| s.trim_end_matches(';'))
+        }) else {
+            return (default, None);
+        };
+        match token.parse::<f64>() {
+            Ok(v) => (v, None),
+            Err(_) => (default, Some(token.to_string())),
+        }
+    };
+
+    Ok((bound(">=", 0.0), bound("<=", default_ub)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solve_block_reads_indented_options() {
+        let m = parse(
+            "var x >= 0, <= 10\n\
+             solve:\n    method: pso\n    iters: 5000\n    seed: 42        # 再現用\n    pop_size: 80;\n    de_f: 0.6\n\
+             minimize: x^2\n",
+        )
+        .unwrap();
+        let h = &m.hints;
+        assert_eq!(h.method.as_deref(), Some("pso"));
+        assert_eq!(h.max_iter, Some(5000));
+        assert_eq!(h.seed, Some(42));
+        assert_eq!(h.pop_size, Some(80));
+        assert_eq!(h.de_f, Some(0.6));
+        assert!(m.objective_expr.is_some());
+    }
+
+    #[test]
+    fn solve_block_ends_at_the_next_unindented_line() {
+        // `minimize:` は solve オプションではなく次のセクション
+        let m = parse("var a >= -3, <= 3\nsolve:\n    method: pso\nminimize: a^2\n").unwrap();
+        assert_eq!(m.hints.method.as_deref(), Some("pso"));
+        assert_eq!(m.objective_expr.as_deref().map(str::trim), Some("a^2"));
+
+        let m = parse(
+            "var a >= -3, <= 3\nsolve:\n    iters: 10\nsubject to:\n    a >= 1\nminimize: a^2\n",
+        )
+        .unwrap();
+        assert_eq!(m.hints.max_iter, Some(10));
+        assert_eq!(m.constraints.len(), 1);
+    }
+
+    #[test]
+    fn solve_block_rejects_unknown_or_malformed_options() {
+        let err = parse("var a >= 0, <= 1\nsolve:\n    methd: pso\nminimize: a\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(
+            err.message.contains("unknown solve option 'methd'"),
+            "{}",
+            err.message
+        );
+
+        let err = parse("var a >= 0, <= 1\nsolve:\n    iters: many\nminimize: a\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(
+            err.message
+                .contains("invalid value for solve option 'iters'"),
+            "{}",
+            err.message
+        );
+
+        let err = parse("var a >= 0, <= 1\nsolve:\n    pop_size: 2\nminimize: a\n").unwrap_err();
+        assert!(err.message.contains("pop_size"), "{}", err.message);
+
+        let err = parse("var a >= 0, <= 1\nsolve:\n    method pso\nminimize: a\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(
+            err.message.contains("expected `key: value`"),
+            "{}",
+            err.message
+        );
+    }
+
+    fn scalar(m: &Model, name: &str) -> f64 {
+        m.params[name]["_"]
+    }
+
+    #[test]
+    fn scalar_params_evaluate_expressions_over_earlier_params() {
+        let m = parse(
+            "set S = {\"a\", \"b\", \"c\"}\n\
+             param base_cap = 10;\n\
+             param total_cap = base_cap * 1.2 + 2;   # 派生パラメータ\n\
+             param n = card(S) * 2;\n\
+             param share = total_cap / n;\n\
+             var x >= 0, <= total_cap\n\
+             minimize: x\n",
+        )
+        .unwrap();
+        assert_eq!(scalar(&m, "total_cap"), 14.0);
+        assert_eq!(scalar(&m, "n"), 6.0);
+        assert!((scalar(&m, "share") - 14.0 / 6.0).abs() < 1e-12);
+        assert_eq!(m.ub[0], 14.0);
+    }
+
+    #[test]
+    fn scalar_param_expressions_reject_unknown_or_later_names() {
+        for source in [
+            "param a = b + 1;\nparam b = 2;\n",
+            "param a = missing * 2;\n",
+            "param a = card(T);\n",
+        ] {
+            let err = parse(source).unwrap_err().to_string();
+            assert!(err.contains("param a"), "{:?}: {}", source, err);
+        }
+    }
+
+    #[test]
+    fn non_finite_params_are_rejected_inline_and_in_data_blocks() {
+        for (source, message) in [
+            ("param cap = inf;\n", "param cap: non-finite value inf"),
+            ("param cap = NaN;\n", "param cap: non-finite value NaN"),
+            (
+                "set I = {\"a\", \"b\"}\nparam cost[I] = {\"a\": 1, \"b\": -inf};\n",
+                "param cost[b]: non-finite value -inf",
+            ),
+            (
+                "data:\n    capacity = NaN\n",
+                "param capacity: non-finite value NaN",
+            ),
+            (
+                "data:\n    cost = {a: 1, b: inf}\n",
+                "param cost[b]: non-finite value inf",
+            ),
+            (
+                "data:\n    cost[a] = -inf\n",
+                "param cost[a]: non-finite value -inf",
+            ),
+        ] {
+            let err = parse(source).unwrap_err().to_string();
+            assert!(err.contains(message), "{:?}: {}", source, err);
+        }
+        let m = parse("param cap = 1e3;\ndata:\n    cost = {a: 2.5}\n").unwrap();
+        assert_eq!(m.params["cap"]["_"], 1000.0);
+        assert_eq!(m.params["cost"]["a"], 2.5);
+    }
+
+    #[test]
+    fn non_finite_params_are_rejected_in_sidecar_json() {
+        let path = std::env::temp_dir().join(format!("optica-{}-params.json", std::process::id()));
+        let load = |json: &str| {
+            std::fs::write(&path, json).unwrap();
+            let mut m = parse("var x >= 0, <= 1\nminimize: x\n").unwrap();
+            load_json_into(&mut m, &path).map(|_| m)
+        };
+        assert_eq!(
+            load(r#"{"cap": "NaN"}"#).unwrap_err(),
+            "param cap: non-finite value NaN"
+        );
+        assert_eq!(
+            load(r#"{"cost": {"a": 1, "b": "-inf"}}"#).unwrap_err(),
+            "param cost[b]: non-finite value -inf"
+        );
+        let m = load(r#"{"cap": "12.5", "cost": {"a, 1": 3}}"#).unwrap();
+        assert_eq!(m.params["cap"]["_"], 12.5);
+        assert_eq!(m.params["cost"]["a,1"], 3.0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn exponent_signs_are_only_inside_numeric_literals() {
+        assert!(is_exponent_sign("1e-6", 2));
+        assert!(is_exponent_sign("x + 2.5E+3", 8));
+        // 識別子の一部の `e` や定数 `e` の後ろの符号は演算子
+        assert!(!is_exponent_sign("x2e-1", 3));
+        assert!(!is_exponent_sign("e - 1", 2));
+        assert!(!is_exponent_sign("3*e-1", 3));
+        assert!(!is_exponent_sign("2e-x", 2));
+    }
+
+    #[test]
+    fn arithmetic_reads_exponents_signs_and_the_constant_e() {
+        let m = parse("var x >= 0, <= 10\nvar x2e >= 0, <= 10\nminimize: x\n").unwrap();
+        let x = [3.0, 5.0];
+        let eval = |expr: &str| m.evaluate_expr(expr, &x, &HashMap::new());
+        assert_eq!(eval("1e-6"), 1e-6);
+        assert_eq!(eval("2.5E3"), 2500.0);
+        assert_eq!(eval("1e-6 * 2e+6"), 2.0);
+        assert_eq!(eval("x * +2"), 6.0);
+        assert_eq!(eval("x * -2"), -6.0);
+        assert_eq!(eval("x2e-1"), 4.0);
+        assert_eq!(eval("e - 1"), std::f64::consts::E - 1.0);
+        assert_eq!(eval("e-1"), std::f64::consts::E - 1.0);
+    }
+
+    #[test]
+    fn duplicate_constraint_names_report_both_lines() {
+        let err = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nminimize: x + y\n\n\
+             subject to:\n    cap: x + y <= 8\n    floor: x >= 1\n    cap: x - y <= 2\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 9);
+        assert_eq!(err.col, 5);
+        assert_eq!(
+            err.to_string(),
+            "error at line 9: duplicate constraint name 'cap' (first defined at line 7)"
+        );
+    }
+
+    #[test]
+    fn duplicate_objective_names_report_both_lines() {
+        let err = parse(
+            "var x >= 0, <= 10\n\nobjectives:\n    minimize cost: x\n    maximize cost: x^2\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 5);
+        assert!(
+            err.message
+                .starts_with("duplicate objective name 'cost' (first defined at line 4)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn multi_dimensional_param_tables_inline_and_in_data_blocks() {
+        let m = parse(
+            "set S = {\"A\", \"New York\"}\nset T = {1, 2}\n\
+             param cost[S, T] = {A,1: 5, A, 2: 7, \"New York\",2: 3};\n\
+             var x[S, T] >= 0, <= 10\n\
+             minimize: sum(s in S, t in T) cost[s, t] * x[s, t]\n\
+             data:\n    dist = {A,1: 1.5, 'New York', 1: 2}\n    dist[A, 2] = 4\n",
+        )
+        .unwrap();
+        let cost = &m.params["cost"];
+        assert_eq!(cost["A,1"], 5.0);
+        assert_eq!(cost["A,2"], 7.0);
+        assert_eq!(cost["New York,2"], 3.0);
+        assert_eq!(cost.len(), 3);
+        let dist = &m.params["dist"];
+        assert_eq!(dist["A,1"], 1.5);
+        assert_eq!(dist["New York,1"], 2.0);
+        assert_eq!(dist["A,2"], 4.0);
+
+        // 参照 `cost[s, t]` は同じキーで引ける（値の無い組は0）
+        let x = [1.0, 1.0, 1.0, 1.0];
+        assert_eq!(m.evaluate_objective(&x), 15.0);
+        assert_eq!(
+            m.evaluate_expr("cost[A, 2] + cost[\"New York\",2]", &x, &HashMap::new()),
+            10.0
+        );
+    }
+
+    #[test]
+    fn multi_dimensional_param_tables_need_a_key_before_each_value() {
+        let err = parse("param cost[S, T] = {A,1: 5: 7};\n").unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("param cost: expected ','"), "{}", err);
+    }
+
+    #[test]
+    fn model_with_bom_and_crlf_parses_like_lf() {
+        let lf = "set I = {\"a\", \"b\"}\nparam cap = 4;\nvar x[I] >= 0, <= 10\n\
+                  solve:\n    method: pso\n\
+                  minimize: sum(i in I) x[i]\n\
+                  subject to:\n    total: sum(i in I) x[i] >= cap\n\
+                  data:\n    cost[a] = 2\n";
+        let crlf = format!("\u{feff}{}", lf.replace('\n', "\r\n"));
+        let (a, b) = (parse(lf).unwrap(), parse(&crlf).unwrap());
+        assert_eq!(b.var_names, a.var_names);
+        assert_eq!(b.var_names, vec!["x[a]".to_string(), "x[b]".to_string()]);
+        assert_eq!(b.hints.method.as_deref(), Some("pso"));
+        assert_eq!(b.constraints[0].name, "total");
+        assert_eq!(b.params["cost"]["a"], 2.0);
+        let x = [1.0, 2.0];
+        assert_eq!(b.evaluate_all(&x), a.evaluate_all(&x));
+    }
+
+    #[test]
+    fn data_files_with_bom_and_crlf_are_read() {
+        let m = parse("var x >= 0, <= 10\nvar y >= 0, <= 10\nminimize: x + y\n").unwrap();
+        let dir = std::env::temp_dir();
+        let path = |ext: &str| dir.join(format!("optica-{}-bom.{}", std::process::id(), ext));
 
-    // Binary変数
-    if line.contains("binary") || line.contains("Binary") {
-        return Ok((0.0, 1.0));
+        let json = path("json");
+        std::fs::write(&json, "\u{feff}{\r\n  \"cap\": 3,\r\n  \"x\": 1.5\r\n}\r\n").unwrap();
+        let mut with_params = m.clone();
+        load_json_into(&mut with_params, &json).unwrap();
+        assert_eq!(with_params.params["cap"]["_"], 3.0);
+        let warm = load_warm_start(&m, &json).unwrap();
+        assert_eq!(warm[0], 1.5);
+        assert!(warm[1].is_nan());
+
+        let csv = path("csv");
+        std::fs::write(&csv, "\u{feff}y,x\r\n1,2\r\n3,4\r\n").unwrap();
+        assert_eq!(
+            load_warm_csv(&m, &csv).unwrap(),
+            vec![vec![2.0, 1.0], vec![4.0, 3.0]]
+        );
+
+        std::fs::remove_file(json).unwrap();
+        std::fs::remove_file(csv).unwrap();
+    }
+
+    #[test]
+    fn sum_headers_iterate_over_inline_set_expressions() {
+        let m = parse(
+            "set ITEMS = {\"a\", \"b\", \"c\", \"d\"}\nset EXTRA = {\"e\"}\n\
+             param v[ITEMS] = {a: 1, b: 10, c: 100, d: 1000};\n\
+             data:\n    v[e] = 10000\n",
+        )
+        .unwrap();
+        let eval = |expr: &str| m.evaluate_expr(expr, &[], &HashMap::new());
+        assert_eq!(eval("sum(i in ITEMS \\ {b}) v[i]"), 1101.0);
+        assert_eq!(eval("sum(i in ITEMS diff {a, d}) v[i]"), 110.0);
+        assert_eq!(eval("sum(i in ITEMS union EXTRA) v[i]"), 11111.0);
+        assert_eq!(eval("sum(i in ITEMS inter {c, e}) v[i]"), 100.0);
+        assert_eq!(eval("sum(i in (ITEMS union EXTRA) \\ {a}) v[i]"), 11110.0);
+        assert_eq!(
+            eval_set_expr("ITEMS \\ {b} union {b}", &m.sets).unwrap(),
+            vec!["a", "c", "d", "b"]
+        );
+    }
+
+    #[test]
+    fn aggregate_only_objectives_are_evaluated() {
+        let objective = |header: &str| {
+            let m = parse(&format!(
+                "set TASKS = {{1, 2, 3}}\nvar end[TASKS] >= 0, <= 100\n\nminimize makespan: {}\n",
+                header
+            ))
+            .unwrap();
+            m.evaluate_objective(&[4.0, 9.0, 2.0])
+        };
+        assert_eq!(objective("maxover(j in TASKS) end[j]"), 9.0);
+        assert_eq!(objective("max(j in TASKS) end[j]"), 9.0);
+        assert_eq!(objective("minover{j in TASKS} end[j]"), 2.0);
+        assert_eq!(objective("avg(j in TASKS) end[j]"), 5.0);
+        assert_eq!(objective("prod(j in TASKS) end[j]"), 72.0);
+        // 集約の後ろに続く項も集約の本体に含まれる
+        assert_eq!(objective("maxover(j in TASKS) end[j] + 1"), 10.0);
+    }
+
+    #[test]
+    fn aggregates_after_other_terms_are_evaluated() {
+        let m = parse(
+            "set I = {1, 2}\nparam c[I] = {1: 2, 2: 3};\nvar x[I] >= 0, <= 10\nminimize: x[1]\n",
+        )
+        .unwrap();
+        let x = [1.0, 4.0];
+        let eval = |e: &str| m.evaluate_expr(e, &x, &HashMap::new());
+        assert_eq!(eval("1 + sum(i in I) c[i] * x[i]"), 15.0);
+        assert_eq!(eval("2 * sum{i in I} x[i]"), 10.0);
+        assert_eq!(eval("(sum(i in I) x[i]) + 1"), 6.0);
+        assert_eq!(eval("x[1] - maxover(i in I) x[i]"), -3.0);
+        // 本体は囲む括弧の終わりまで
+        assert_eq!(eval("(1 + sum(i in I) x[i]) * 2"), 12.0);
+        assert_eq!(
+            m.expr_gradient("3 + sum(i in I) c[i] * x[i]", &x),
+            vec![2.0, 3.0]
+        );
+        assert_eq!(
+            m.linear_form("3 + sum(i in I) c[i] * x[i]").unwrap(),
+            (vec![2.0, 3.0], 3.0)
+        );
+    }
+
+    #[test]
+    fn where_filters_compare_index_bindings_numerically() {
+        // 11要素なので "10" < "9" のような文字列比較では組が変わる
+        let n = 11;
+        let elems: Vec<String> = (1..=n).map(|i| i.to_string()).collect();
+        let pos: Vec<String> = (1..=n).map(|i| format!("{}: {}", i, i * i)).collect();
+        let m = parse(&format!(
+            "set N = {{{}}}\nparam p[N] = {{{}}};\nvar x >= 0, <= 1\n\
+             minimize dist: sum(i in N, j in N where i < j) abs(p[i] - p[j])\n",
+            elems.join(", "),
+            pos.join(", ")
+        ))
+        .unwrap();
+        let mut expected = 0.0;
+        for i in 1..=n {
+            for j in i + 1..=n {
+                expected += ((j * j - i * i) as f64).abs();
+            }
+        }
+        assert_eq!(m.evaluate_objective(&[0.0]), expected);
+        let eval = |e: &str| m.evaluate_expr(e, &[0.0], &HashMap::new());
+        assert_eq!(eval("sum(i in N, j in N where i < j) 1"), 55.0);
+        assert_eq!(eval("sum(i in N, j in N where i > j) 1"), 55.0);
+        assert_eq!(eval("sum{i in N, j in N : i == j} 1"), 11.0);
+        assert_eq!(eval("sum(i in N, j in N where i < j and j <= 3) 1"), 3.0);
+    }
+
+    #[test]
+    fn if_penalties_inside_sums_bind_the_loop_variable() {
+        let m = parse(
+            "set S = {a, b, c}\nparam cap[S] = {a: 1, b: 5, c: 2};\nvar x[S] >= 0, <= 10\n\
+             minimize over: sum(i in S) (if x[i] > cap[i] then (x[i] - cap[i]) else 0)\n",
+        )
+        .unwrap();
+        // a, c だけが容量を超える
+        let x = [3.0, 4.0, 2.5];
+        assert_eq!(m.evaluate_objective(&x), 2.5);
+        assert_eq!(m.evaluate_objective(&[0.0, 0.0, 0.0]), 0.0);
+        let grad = m.expr_gradient(m.objective_expr.as_deref().unwrap(), &x);
+        for (g, want) in grad.iter().zip([1.0, 0.0, 1.0]) {
+            assert!((g - want).abs() < 1e-6, "{:?}", grad);
+        }
+
+        let eval = |e: &str| m.evaluate_expr(e, &x, &HashMap::new());
+        assert_eq!(
+            eval("sum(i in S) 2 * (if x[i] > cap[i] then (x[i] - cap[i]) else 0)"),
+            5.0
+        );
+        assert_eq!(
+            eval("sum(i in S) if x[i] > cap[i] then x[i] - cap[i] else 0"),
+            2.5
+        );
+        assert_eq!(
+            eval("sum(i in S) (if x[i] <= cap[i] then (cap[i] - x[i]) else 0) + 1"),
+            4.0
+        );
+    }
+
+    #[test]
+    fn goal_block_minimizes_weighted_absolute_deviations() {
+        let src = |goals: &str| {
+            format!(
+                "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nobjectives:\n\
+                 \x20   minimize total: x + y\n    maximize spread: x - y\n{}\n\
+                 subject to:\n    x <= 10\n",
+                goals
+            )
+        };
+        let m = parse(&src("    goal:\n        total = 6\n        spread = 2 3")).unwrap();
+        let ParetoMethod::Goal(goals) = &m.pareto else {
+            panic!("expected goal programming, got {:?}", m.pareto);
+        };
+        assert_eq!(
+            goals,
+            &vec![
+                ("total".to_string(), 6.0, 1.0),
+                ("spread".to_string(), 2.0, 3.0)
+            ]
+        );
+        // |5 - 6| + 3 * |1 - 2|（maximize でも偏差は生の値で測る）
+        assert_eq!(m.evaluate_all(&[3.0, 2.0]).0, 4.0);
+        assert_eq!(m.evaluate_all(&[4.0, 2.0]).0, 0.0);
+
+        let (x, f, _) = crate::solver::de(&m, &crate::solver::Options::new(300, 1));
+        assert!(f < 1e-3, "{} at {:?}", f, x);
+        assert!(
+            (x[0] - 4.0).abs() < 1e-2 && (x[1] - 2.0).abs() < 1e-2,
+            "{:?}",
+            x
+        );
+
+        // 1行形式
+        let m = parse(&src("    goal: total = 6")).unwrap();
+        assert_eq!(m.evaluate_all(&[3.0, 2.0]).0, 1.0);
+
+        let err = parse(&src("    goal: cost = 6")).unwrap_err();
+        assert!(
+            err.message.contains("goal for unknown objective 'cost'"),
+            "{}",
+            err
+        );
+        let err = parse(&src("    goal: total = 6 -1")).unwrap_err();
+        assert!(err.message.contains("invalid goal"), "{}", err);
+    }
+
+    #[test]
+    fn warm_csv_rows_become_candidate_points() {
+        let m = parse("var x >= 0, <= 10\nvar y >= 0, <= 10\nvar z >= 0, <= 10\nminimize: x\n")
+            .unwrap();
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("optica-{}-{}.csv", std::process::id(), name));
+        let same = |a: &[f64], b: &[f64]| {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|(p, q)| p == q || (p.is_nan() && q.is_nan()))
+        };
+
+        // ヘッダあり: 列順は自由、未知の列は無視、空のセルは NaN
+        let with_header = path("warm-header");
+        std::fs::write(&with_header, "z,x,note\n# comment\n3,1,a\n6,,b\n\n9,7,c\n").unwrap();
+        let rows = load_warm_csv(&m, &with_header).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(same(&rows[0], &[1.0, f64::NAN, 3.0]));
+        assert!(same(&rows[1], &[f64::NAN, f64::NAN, 6.0]));
+        assert!(same(&rows[2], &[7.0, f64::NAN, 9.0]));
+
+        // ヘッダなし: `var_names` の順。余分な列は無視し、足りない列は NaN
+        let plain = path("warm-plain");
+        std::fs::write(&plain, "1,2,3\n4,5,6,99\n7,8\n").unwrap();
+        let rows = load_warm_csv(&m, &plain).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(same(&rows[0], &[1.0, 2.0, 3.0]));
+        assert!(same(&rows[1], &[4.0, 5.0, 6.0]));
+        assert!(same(&rows[2], &[7.0, 8.0, f64::NAN]));
+
+        let bad = path("warm-bad");
+        std::fs::write(&bad, "x,y\n1,2\n3,inf\n").unwrap();
+        assert_eq!(
+            load_warm_csv(&m, &bad).unwrap_err(),
+            "line 3: invalid value 'inf'"
+        );
+
+        for p in [with_header, plain, bad] {
+            std::fs::remove_file(p).unwrap();
+        }
     }
 
-    // Integer変数（境界はそのまま）
-    let is_int = line.contains("int") || line.contains("Integer");
+    #[test]
+    fn consts_are_usable_in_objectives_and_params() {
+        let m = parse(
+            "const tau = 2 * pi;\nconst half_tau = tau / 2;\nparam r = half_tau + 1;\n\
+             var x >= 0, <= 10\nminimize: tau * x + half_tau + e\n",
+        )
+        .unwrap();
+        let pi = std::f64::consts::PI;
+        assert_eq!(m.consts["tau"], 2.0 * pi);
+        assert_eq!(m.consts["half_tau"], pi);
+        assert_eq!(m.params["r"]["_"], pi + 1.0);
+        assert!((m.evaluate_objective(&[2.0]) - (5.0 * pi + std::f64::consts::E)).abs() < 1e-12);
 
-    // >= パターン
-    if let Some(p) = line.find(">=") {
-        lb = line[p + 2..]
-            .split_whitespace()
-            .next()
-            .and_then(|s| s.trim_end_matches(';').parse().ok())
-            .unwrap_or(0.0);
+        let err = |src: &str| parse(src).unwrap_err().message;
+        assert!(err("const pi = 3;\n").contains("redefines a built-in constant"));
+        assert!(err("const a = 1;\nconst a = 2;\n").contains("const a: name already defined"));
+        assert!(err("param a = 1;\nconst a = 2;\n").contains("const a: name already defined"));
+        assert!(err("var x >= 0, <= 1\nconst x = 2;\n").contains("name already defined"));
+        assert!(err("const a = b + 1;\nconst b = 2;\n").contains("const a:"));
+        assert!(err("const a = 1e308 * 10;\n").contains("non-finite"));
     }
 
-    // <= パターン
-    if let Some(p) = line.find("<=") {
-        ub = line[p + 2..]
-            .split_whitespace()
-            .next()
-            .and_then(|s| s.trim_end_matches(';').parse().ok())
-            .unwrap_or(1000.0);
+    #[test]
+    fn keys_sets_follow_the_loaded_parameter_keys() {
+        // 集合を先に宣言し、キーは後の dataブロックで与える
+        let mut m = parse(
+            "set ARCS = keys(demand);\nvar x >= 0, <= 10\n\
+             minimize: sum(a in ARCS) demand[a] * x\n\
+             data:\n    demand = {10: 1, 2: 4, 7: 0.5}\n",
+        )
+        .unwrap();
+        assert_eq!(m.sets["ARCS"], vec!["2", "7", "10"]);
+        assert_eq!(m.evaluate_objective(&[2.0]), 11.0);
+
+        // サイドカーJSONで足したキーも集合に入る（数値でないキーが混ざると文字列順）
+        let json = std::env::temp_dir().join(format!("optica-{}-keys.json", std::process::id()));
+        std::fs::write(&json, r#"{"demand": {"b": 2, "a": 3}}"#).unwrap();
+        load_json_into(&mut m, &json).unwrap();
+        std::fs::remove_file(&json).unwrap();
+        assert_eq!(m.sets["ARCS"], vec!["10", "2", "7", "a", "b"]);
+        assert_eq!(m.evaluate_objective(&[1.0]), 10.5);
+
+        // 変数の添字に使うならパラメータを先に定義する
+        let m = parse(
+            "param cap[P] = {p: 1, q: 2};\nset P = keys(cap);\nvar x[P] >= 0, <= 10\n\
+             minimize: sum(i in P) cap[i] * x[i]\n",
+        )
+        .unwrap();
+        assert_eq!(m.var_names, vec!["x[p]", "x[q]"]);
+        let err = parse("set P = keys(cap);\nvar x[P] >= 0, <= 10\nparam cap[P] = {p: 1};\n")
+            .unwrap_err();
+        assert!(
+            err.message.contains("set P = keys(cap) is empty"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn inline_objective_lists_declare_weighted_objectives() {
+        let m = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nminimize [x^2 + pow(y, 2), max(x, y)];\n",
+        )
+        .unwrap();
+        let exprs: Vec<(&str, &str)> = m
+            .objectives
+            .iter()
+            .map(|o| (o.name.as_str(), o.expr.as_str()))
+            .collect();
+        assert_eq!(exprs, [("obj1", "x^2 + pow(y, 2)"), ("obj2", "max(x, y)")]);
+        assert!(matches!(&m.pareto, ParetoMethod::WeightedSum(w)
+            if w == &[("obj1".to_string(), 0.5), ("obj2".to_string(), 0.5)]));
+        let (scalar, raw, _) = m.evaluate_all(&[1.0, 2.0]);
+        assert_eq!(raw, vec![5.0, 2.0]);
+        assert_eq!(scalar, 3.5);
+
+        // maximize のリストは各目的を最大化する。重みは `weight` 行で上書きできる
+        let m = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nmaximize: [x, y]\n\nobjectives:\n\
+             pareto method: \"weighted_sum\"\n    weight obj1: 0.75\n    weight obj2: 0.25\n\n\
+             subject to:\n    x + y <= 10\n",
+        )
+        .unwrap();
+        assert!(m.objectives.iter().all(|o| o.maximize));
+        assert_eq!(m.evaluate_all(&[4.0, 8.0]).0, -(0.75 * 4.0 + 0.25 * 8.0));
+
+        let err = parse("var x >= 0, <= 1\nminimize [x, ];\n").unwrap_err();
+        assert!(err.message.contains("empty objective in list"), "{}", err);
+    }
+
+    #[test]
+    fn continuous_state_variables_keep_real_bounds() {
+        let mut m = parse(
+            "set T = {1, 2}\nparam max_order = 40;\n\
+             state S[T] in 0.0..500.5 real;\ndecision order[T] in 0..max_order / 2 int;\n\
+             minimize: (sum(t in T) (S[t] - 2.25)^2) + sum(t in T) order[t]\n",
+        )
+        .unwrap();
+        assert_eq!(m.var_names, vec!["S[1]", "S[2]", "order[1]", "order[2]"]);
+        assert_eq!(m.lb, vec![0.0; 4]);
+        assert_eq!(m.ub, vec![500.5, 500.5, 20.0, 20.0]);
+        assert_eq!(m.var_types[..2], [VarType::Continuous, VarType::Continuous]);
+        assert_eq!(m.var_types[2..], [VarType::Integer, VarType::Integer]);
+
+        // 整数の決定変数だけを丸め、連続の状態は端数を残す
+        let mut x = vec![2.25, 7.5, 1.4, 2.6];
+        m.round_integers(&mut x);
+        assert_eq!(x, vec![2.25, 7.5, 1.0, 3.0]);
+        assert_eq!(m.evaluate_objective(&x), 5.25f64.powi(2) + 4.0);
+
+        m = parse("state S in 0..1000\n").unwrap();
+        assert_eq!((m.lb[0], m.ub[0]), (0.0, 1000.0));
+        let err = parse("state S in 0..cap real;\n").unwrap_err();
+        assert!(
+            err.message.contains("state S: invalid range '0..cap'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn analytic_gradients_match_finite_differences() {
+        let m = parse(
+            "set I = {1, 2, 3}\nparam w[I] = {1: 0.5, 2: -1.5, 3: 2};\n\
+             var x[I] >= -3, <= 3\nvar y >= 0.1, <= 5\n\nminimize: y\n\n\
+             subject to:\n    sum(i in I) w[i] * x[i]^2 <= 4\n    exp(x[1]) * log(y) >= 0\n",
+        )
+        .unwrap();
+        let exprs = [
+            "sum(i in I) w[i] * x[i]^2 + y",
+            "prod(i in I) (x[i] + 4)",
+            "pow(y, 3) / (1 + x[1]^2) - sqrt(y) * x[2]",
+            "exp(0.3 * x[1]) + log(y) + ln(y + 1) - sin(x[2]) * cos(x[3]) + tan(0.2 * x[3])",
+            "avg(i in I) (x[i] - y)^2",
+            "maxover(i in I) w[i] * x[i]",
+            "minover(i in I) x[i] * y",
+            "max(x[1], x[2] * y) - min(y, x[3]) + abs(x[1] - 2)",
+            "2 * sum(i in I) x[i] * y - (x[1] - x[2]) / y",
+            "(if x[1] > 0 then x[1]^2 else -x[1]) + y",
+        ];
+        let points = [
+            vec![0.7, -1.2, 2.1, 1.3],
+            vec![-2.2, 0.4, -0.9, 3.7],
+            vec![1.9, 2.5, 0.3, 0.6],
+        ];
+        let fd = |expr: &str, x: &[f64]| -> Vec<f64> {
+            (0..x.len())
+                .map(|j| {
+                    let h = 1e-6;
+                    let (mut p, mut q) = (x.to_vec(), x.to_vec());
+                    p[j] += h;
+                    q[j] -= h;
+                    let env = HashMap::new();
+                    (m.evaluate_expr(expr, &p, &env) - m.evaluate_expr(expr, &q, &env)) / (2.0 * h)
+                })
+                .collect()
+        };
+        for expr in exprs {
+            for x in &points {
+                let analytic = m.expr_gradient(expr, x);
+                let numeric = fd(expr, x);
+                for (a, n) in analytic.iter().zip(&numeric) {
+                    assert!(
+                        (a - n).abs() <= 1e-5 * (1.0 + n.abs()),
+                        "{} at {:?}: {:?} vs {:?}",
+                        expr,
+                        x,
+                        analytic,
+                        numeric
+                    );
+                }
+            }
+        }
+
+        // 目的・制約の勾配も同じ式の勾配
+        let x = &points[0];
+        assert_eq!(m.gradient(x), vec![0.0, 0.0, 0.0, 1.0]);
+        let cons = m.constraint_gradients(x);
+        assert_eq!(cons.len(), 2);
+        // 2 * w[i] * x[i]
+        for (a, want) in cons[0].iter().zip([0.7, 3.6, 8.4, 0.0]) {
+            assert!((a - want).abs() < 1e-12, "{:?}", cons[0]);
+        }
+        for (a, n) in cons[1].iter().zip(fd(&m.constraints[1].expr, x)) {
+            assert!((a - n).abs() < 1e-6, "{:?}", cons[1]);
+        }
+    }
+
+    #[test]
+    fn model_header_names_round_trip() {
+        let name = |src: &str| parse(src).unwrap().name;
+        assert_eq!(
+            name("model knapsack;\nvar x >= 0, <= 1\n").as_deref(),
+            Some("knapsack")
+        );
+        assert_eq!(
+            name("model \"inventory management\"\nvar x >= 0, <= 1\n").as_deref(),
+            Some("inventory management")
+        );
+        assert_eq!(
+            name("problem tsp\nvar x >= 0, <= 1\n").as_deref(),
+            Some("tsp")
+        );
+        // `problem dp` のような種別は `model` の名前を上書きしない
+        assert_eq!(
+            name("model \"inventory\"\nproblem dp\nvar x >= 0, <= 1\n").as_deref(),
+            Some("inventory")
+        );
+        assert_eq!(name("var x >= 0, <= 1\n"), None);
+
+        // 整形しても名前は残る
+        let src = "model  knapsack ;\nvar x >= 0,<= 1\nminimize: x\n";
+        let formatted = crate::formatter::format_source(src).unwrap();
+        assert_eq!(parse(&formatted).unwrap().name.as_deref(), Some("knapsack"));
+    }
+
+    #[test]
+    fn arrow_named_elements_resolve_to_the_same_keys() {
+        let m = parse(
+            "set ARCS = {\"A->B\", \"B->C\", \"C->A\"}\n\
+             param cost[ARCS] = {\"A->B\": 2, \"B->C\": 3, \"C->A\": 5};\n\
+             var flow[ARCS] >= 0, <= 10\n\n\
+             minimize: sum(a in ARCS) cost[a] * flow[a]\n\nsubject to:\n\
+             \x20   out: flow[A->B] + flow[\"A->B\"] >= 2\n\
+             \x20   cycle: flow[B->C] - flow[C->A] == 0\n",
+        )
+        .unwrap();
+        assert_eq!(m.var_names, vec!["flow[A->B]", "flow[B->C]", "flow[C->A]"]);
+        assert_eq!(m.params["cost"]["B->C"], 3.0);
+        let x = [1.0, 2.0, 4.0];
+        assert_eq!(m.evaluate_objective(&x), 2.0 + 6.0 + 20.0);
+        let eval = |e: &str| m.evaluate_expr(e, &x, &HashMap::new());
+        assert_eq!(eval("flow[C->A] + cost[\"C->A\"]"), 9.0);
+
+        // 制約の `->` は比較演算子として扱わない
+        let (names, ops): (Vec<&str>, Vec<ConstraintOp>) = m
+            .constraints
+            .iter()
+            .map(|c| (c.name.as_str(), c.op))
+            .unzip();
+        assert_eq!(names, ["out", "cycle"]);
+        assert!(matches!(ops[..], [ConstraintOp::Ge, ConstraintOp::Eq]));
+        assert_eq!(
+            m.constraint_violations(&x),
+            vec![("out", 0.0), ("cycle", 2.0)]
+        );
+        assert_eq!(
+            m.linear_form(&m.constraints[0].expr).unwrap(),
+            (vec![2.0, 0.0, 0.0], 0.0)
+        );
+
+        for bad in ["{\"A,B\", C}", "{\"A[1]\", C}"] {
+            let err = parse(&format!("set S = {}\nvar x[S] >= 0, <= 1\n", bad)).unwrap_err();
+            assert!(err.message.contains("set S"), "{}", err);
+        }
     }
 
-    // real変数で境界がない場合はデフォルト
-    if !is_int && lb == 0.0 && ub == 1000.0 {
-        // デフォルトのまま
+    #[test]
+    fn linear_forms_follow_the_evaluator() {
+        let m = parse(
+            "const K = 4\nset I = {1, 2, 3}\nparam w[I] = {1: 1, 2: 2, 3: 3};\n\
+             var x[I] >= 0, <= 10 integer\nvar y >= 0, <= 10 integer\n\n\
+             minimize: y\n\nsubject to:\n\
+             \x20   moved: x[1] + 2 <= y - (x[2] - x[3])\n",
+        )
+        .unwrap();
+        let lf = |e: &str| m.linear_form(e).unwrap();
+        // 引き算と入れ子の括弧は符号を反転して展開する
+        assert_eq!(
+            lf("x[1] - (x[2] - (x[3] - 1))"),
+            (vec![1.0, -1.0, 1.0, 0.0], -1.0)
+        );
+        // 係数は定数・パラメータ・括弧の積でもよい
+        assert_eq!(
+            lf("K * x[1] + (2 + 1) * (y - 2) / 2"),
+            (vec![4.0, 0.0, 0.0, 1.5], -3.0)
+        );
+        assert_eq!(
+            lf("(sum(i in I) w[i] * (x[i] - 1)) + K"),
+            (vec![1.0, 2.0, 3.0, 0.0], -2.0)
+        );
+        // 右辺に変数を含む制約は左辺へ移項した形になる
+        let c = &m.constraints[0];
+        let (coefs, offset) = lf(&c.expr);
+        assert_eq!(coefs, vec![1.0, 1.0, -1.0, -1.0]);
+        assert_eq!(offset - m.constraint_rhs(c, &[0.0; 4]), 2.0);
+
+        for e in [
+            "x[1] * x[2]",
+            "abs(x[1] - 1)",
+            "exp(y)",
+            "max(x[1], y)",
+            "y ^ 2",
+        ] {
+            assert!(m.linear_form(e).is_err(), "{}", e);
+        }
     }
 
-    Ok((lb, ub))
+    #[test]
+    fn penalty_weights_scale_fitness_but_not_the_reported_violation() {
+        let m = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nminimize: x + y\n\nsubject to:\n\
+             \x20   cap [penalty=100]: x + y <= 4\n\
+             \x20   low: x >= 1\n",
+        )
+        .unwrap();
+        let x = [0.0, 5.0];
+        let coeff = crate::solver::penalty_coeff();
+        // 適合度のペナルティは制約ごとの係数を掛ける
+        assert_eq!(m.check_constraints(&x), (false, 100.0 + coeff));
+        assert_eq!(m.evaluate_all(&x).2, 100.0 + coeff);
+        // 表示する違反量は係数を掛けない
+        assert_eq!(
+            m.constraint_violations(&x),
+            vec![("cap", 1.0), ("low", 1.0)]
+        );
+        assert_eq!(m.total_violation(&x), 2.0);
+        assert_eq!(m.total_violation(&[1.0, 2.0]), 0.0);
+
+        // epsilon制約の閾値超過も係数を掛けずに含める
+        let m = parse(
+            "var x >= 0, <= 10\n\nobjectives:\n\
+             \x20   minimize cost: x\n\
+             \x20   maximize gain: 10 - x\n\n\
+             pareto method: \"epsilon_constraint\"\n\
+             \x20   primary: cost\n\
+             \x20   epsilon:\n\
+             \x20       gain <= -8\n\n\
+             subject to:\n\
+             \x20   x >= 4\n",
+        )
+        .unwrap();
+        // gain は最小化向きで -(10 - x)。x = 3 では制約を1、閾値を1超える
+        assert_eq!(m.total_violation(&[3.0]), 2.0);
+        assert_eq!(m.evaluate_all(&[3.0]).2, 2.0 * coeff);
+        assert_eq!(m.total_violation(&[2.0]), 2.0);
+        assert_eq!(m.total_violation(&[5.0]), 3.0);
+    }
 }
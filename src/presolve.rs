@@ -0,0 +1,291 @@
+//! プリソルブ（求解前のモデル簡約）
+//!
+//! - 単一変数の定数境界制約（`x[i] >= 0`, `2 * y <= 10` など）を `lb`/`ub` に畳み込む
+//! - 1変数を他の変数で定める線形の等式（`y - x[1] - x[2] == 0` など）から変数を消去する
+
+use crate::parser::{
+    signed_terms, Constraint, ConstraintOp, DefinedVar, Model, Reduction, VarType,
+};
+use std::collections::HashMap;
+
+/// プリソルブの結果
+#[derive(Debug, Default)]
+pub struct Report {
+    /// 変数境界に畳み込んだ制約の数
+    pub folded_bounds: usize,
+    /// 等式制約から消去した変数名
+    pub eliminated: Vec<String>,
+}
+
+/// モデルを簡約する
+pub fn presolve(model: &mut Model) -> Report {
+    let folded_bounds = fold_bound_constraints(model);
+    let eliminated = eliminate_defined_vars(model);
+    Report {
+        folded_bounds,
+        eliminated,
+    }
+}
+
+/// 単一変数の定数境界制約を変数の境界に畳み込み、制約から取り除く
+///
+/// 対象は `[係数 *] 変数 [* 係数] (<=|>=|==) 定数` の形（係数は数値かスカラーパラメータ）。
+/// 畳み込むと定義域が空になる制約と、`[penalty=W]` を付けた制約（破ってよい柔らかい制約）は
+/// 残してペナルティで扱う。
+fn fold_bound_constraints(model: &mut Model) -> usize {
+    let constraints = std::mem::take(&mut model.constraints);
+    let mut kept = Vec::with_capacity(constraints.len());
+    let mut folded = 0;
+    // 右辺の式は変数を含まないので、評価点はどこでもよい
+    let zeros = vec![0.0; model.var_map.len()];
+
+    for c in constraints {
+        let term = single_var_term(model, &c.expr).filter(|_| c.penalty.is_none());
+        let Some((idx, coef)) = term else {
+            kept.push(c);
+            continue;
+        };
+        let bound = model.constraint_rhs(&c, &zeros) / coef;
+        // 負の係数では不等号の向きが反転する
+        let (lower, upper) = match (c.op, coef > 0.0) {
+            (ConstraintOp::Eq, _) => (Some(bound), Some(bound)),
+            (ConstraintOp::Ge, true) | (ConstraintOp::Le, false) => (Some(bound), None),
+            (ConstraintOp::Le, true) | (ConstraintOp::Ge, false) => (None, Some(bound)),
+        };
+        let integral = model.var_type(idx).is_integral();
+        let mut lb = model.lb[idx];
+        let mut ub = model.ub[idx];
+        if let Some(l) = lower {
+            lb = lb.max(if integral { l.ceil() } else { l });
+        }
+        if let Some(u) = upper {
+            ub = ub.min(if integral { u.floor() } else { u });
+        }
+        if lb > ub {
+            kept.push(c);
+            continue;
+        }
+        model.lb[idx] = lb;
+        model.ub[idx] = ub;
+        folded += 1;
+    }
+
+    model.constraints = kept;
+    folded
+}
+
+/// 式が「定数係数 × 単一変数」ならその変数インデックスと係数を返す
+fn single_var_term(model: &Model, expr: &str) -> Option<(usize, f64)> {
+    let compact: String = expr.chars().filter(|c| !c.is_whitespace()).collect();
+    let (sign, body) = match compact.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, compact.as_str()),
+    };
+
+    let mut var: Option<usize> = None;
+    let mut coef = sign;
+    for factor in body.split('*') {
+        if let Some(&idx) = model.var_map.get(factor) {
+            if var.replace(idx).is_some() {
+                return None;
+            }
+        } else if let Ok(v) = factor.parse::<f64>() {
+            coef *= v;
+        } else if let Some(v) = model.consts.get(factor) {
+            coef *= v;
+        } else if let Some(v) = model.params.get(factor).and_then(|m| m.get("_")) {
+            coef *= v;
+        } else {
+            return None;
+        }
+    }
+
+    match var {
+        Some(idx) if coef != 0.0 && coef.is_finite() => Some((idx, coef)),
+        _ => None,
+    }
+}
+
+/// 1変数を他の変数で定める等式制約を取り除き、その変数を探索空間から消去する
+///
+/// 対象は左辺の最上位の項のうち1つだけが `[係数 *] 変数` で、他の項がその変数に依存しない
+/// 等式。消去した変数の境界は制約として残す。CP制約を含むモデル（CP-SATで解く）、
+/// 連続でない変数、`[penalty=W]` を付けた制約は対象外。
+fn eliminate_defined_vars(model: &mut Model) -> Vec<String> {
+    if !model.cp_globals.is_empty() || model.reduction.is_some() {
+        return Vec::new();
+    }
+
+    let constraints = std::mem::take(&mut model.constraints);
+    let mut kept: Vec<Constraint> = Vec::with_capacity(constraints.len());
+    let mut defined: Vec<DefinedVar> = Vec::new();
+    let zeros = vec![0.0; model.var_map.len()];
+    for c in constraints {
+        let candidate = match c.op {
+            ConstraintOp::Eq if c.penalty.is_none() => defining_term(model, &c.expr),
+            _ => None,
+        };
+        let Some((idx, coef)) = candidate else {
+            kept.push(c);
+            continue;
+        };
+        // 既に消去した変数の定義式に現れる変数は、復元順が崩れるので消去しない
+        let clash = defined
+            .iter()
+            .any(|d| d.index == idx || depends_on(model, &d.expr, idx));
+        if clash || model.var_type(idx) != VarType::Continuous {
+            kept.push(c);
+            continue;
+        }
+        let rhs = model.constraint_rhs(&c, &zeros);
+        defined.push(DefinedVar {
+            index: idx,
+            expr: c.expr,
+            coef,
+            rhs,
+        });
+    }
+    model.constraints = kept;
+    if defined.is_empty() {
+        return Vec::new();
+    }
+
+    // 消去した変数の境界はペナルティ制約として残す
+    let mut names = Vec::with_capacity(defined.len());
+    for d in &defined {
+        let name = model.var_names[d.index].clone();
+        for (suffix, op, rhs) in [
+            ("lb", ConstraintOp::Ge, model.lb[d.index]),
+            ("ub", ConstraintOp::Le, model.ub[d.index]),
+        ] {
+            model.constraints.push(Constraint {
+                name: format!("{}.{}", name, suffix),
+                expr: name.clone(),
+                op,
+                rhs,
+                rhs_expr: None,
+                penalty: None,
+            });
+        }
+        names.push(name);
+    }
+
+    let full_dim = model.dim;
+    let free: Vec<usize> = (0..full_dim)
+        .filter(|i| defined.iter().all(|d| d.index != *i))
+        .collect();
+    model.lb = free.iter().map(|&i| model.lb[i]).collect();
+    model.ub = free.iter().map(|&i| model.ub[i]).collect();
+    model.var_names = free.iter().map(|&i| model.var_names[i].clone()).collect();
+    model.var_types = free.iter().map(|&i| model.var_type(i)).collect();
+    model.index_var_types();
+    model.dim = free.len();
+    model.reduction = Some(Reduction {
+        full_dim,
+        free,
+        defined,
+    });
+    names
+}
+
+/// 左辺の最上位の項のうち、ちょうど1つが `[係数 *] 変数` でその変数が他の項に現れなければ
+/// (変数インデックス, 係数) を返す
+fn defining_term(model: &Model, expr: &str) -> Option<(usize, f64)> {
+    let terms = signed_terms(expr);
+    let mut found: Option<(usize, usize, f64)> = None; // (項番号, 変数, 係数)
+    for (k, (sign, term)) in terms.iter().enumerate() {
+        if let Some((idx, coef)) = single_var_term(model, term) {
+            if found.is_some() {
+                // 変数単独の項が複数ある場合は最初のものを使い、残りは「他の項」とする
+                continue;
+            }
+            found = Some((k, idx, sign * coef));
+        }
+    }
+    let (k, idx, coef) = found?;
+    let others_depend = terms
+        .iter()
+        .enumerate()
+        .any(|(j, (_, t))| j != k && depends_on(model, t, idx));
+    (!others_depend).then_some((idx, coef))
+}
+
+/// 式が変数 `idx` を参照するか（式の字面から判定する。集約の本体の `x[i]` は `x` のどれでも参照しうる）
+///
+/// 値を動かして確かめる方法は、打ち消し合う項（`x - x`）や平坦な区間で依存を見落とす。
+fn depends_on(model: &Model, expr: &str, idx: usize) -> bool {
+    model.referenced_vars(expr, &HashMap::new()).contains(&idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn presolved(source: &str) -> (Model, Report) {
+        let mut model = parser::parse(source).unwrap();
+        let report = presolve(&mut model);
+        (model, report)
+    }
+
+    #[test]
+    fn eliminates_linearly_defined_variable_and_restores_it() {
+        let (model, report) = presolved(
+            "var a >= 0, <= 10\nvar b >= 0, <= 10\nvar y >= 0, <= 100\n\n\
+             minimize: (a - 1)^2 + (b - 2)^2 + y\n\nsubject to:\n    def: 2 * y - a - 3 * b == 4\n",
+        );
+        assert_eq!(report.eliminated, vec!["y".to_string()]);
+        assert_eq!(model.dim, 2);
+        assert_eq!(model.var_names, vec!["a".to_string(), "b".to_string()]);
+
+        // 縮約後の点を元の空間に戻すと y = (4 + a + 3b) / 2
+        let full = model.expand_solution(&[1.0, 2.0]);
+        assert_eq!(full.as_ref(), &[1.0, 2.0, 5.5]);
+        assert_eq!(model.reduce_point(&full), vec![1.0, 2.0]);
+        // 消去した変数の境界は制約として残る
+        let names: Vec<&str> = model.constraints.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["y.lb", "y.ub"]);
+    }
+
+    #[test]
+    fn keeps_variables_that_other_terms_reference() {
+        for expr in [
+            // 値を動かしても変わらない区間（max の平坦部）でも字面上は y に依存する
+            "y - a - max(y, 100) == 0",
+            "y - a + 0.5 * y^2 == 0",
+            // 集約の本体の x[i] は x[1] を含みうる
+            "x[1] - sum(i in S) x[i] == 0",
+        ] {
+            let (model, report) = presolved(&format!(
+                "set S = {{1, 2}}\nvar x[S] >= 0, <= 10\nvar a >= 0, <= 10\nvar y >= 0, <= 10\n\n\
+                 minimize: a + y\n\nsubject to:\n    {}\n",
+                expr
+            ));
+            assert!(
+                report.eliminated.is_empty(),
+                "{}: {:?}",
+                expr,
+                report.eliminated
+            );
+            assert!(model.reduction.is_none());
+            assert_eq!(model.constraints.len(), 1);
+        }
+    }
+
+    #[test]
+    fn keeps_integer_variables_and_chained_definitions() {
+        let (_, report) = presolved(
+            "var a >= 0, <= 10\nvar n int >= 0, <= 10\n\nminimize: a + n\n\nsubject to:\n    n - a == 0\n",
+        );
+        assert!(report.eliminated.is_empty());
+
+        // y を消去した定義式に z が現れるので、z は消去しない
+        let (model, report) = presolved(
+            "var a >= 0, <= 10\nvar y >= 0, <= 10\nvar z >= 0, <= 10\n\nminimize: a + y + z\n\n\
+             subject to:\n    y - a - z == 0\n    z - 2 * a == 0\n",
+        );
+        assert_eq!(report.eliminated, vec!["y".to_string()]);
+        let full = model.expand_solution(&[1.0, 3.0]);
+        assert_eq!(full.as_ref(), &[1.0, 4.0, 3.0]);
+    }
+}
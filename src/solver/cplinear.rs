@@ -0,0 +1,384 @@
+//! CP-SAT に渡す整数線形モデルの組み立て（ソルバーに依存しない部分）
+//!
+//! 変数は `VAR_SCALE` 倍、線形化した係数は `COEF_SCALE` 倍して整数にする。制約の右辺と
+//! 補助変数の行の定数は両方の倍率をかけた単位で扱う。
+
+use crate::parser::{ConstraintOp, Model};
+
+/// 実数の変数を整数にするための倍率
+pub const VAR_SCALE: f64 = 1000.0;
+/// 線形化した係数を整数にするための倍率
+pub const COEF_SCALE: f64 = 1000.0;
+
+/// (整数係数, 変数インデックス) の列
+pub type Terms = Vec<(i64, usize)>;
+
+/// 線形制約 `Σ terms (op) rhs`
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub name: String,
+    pub terms: Terms,
+    pub op: ConstraintOp,
+    pub rhs: i64,
+}
+
+/// 目的の `weight × max(piece, ...)`
+///
+/// 補助変数 z（範囲 `z_bounds`、変数と同じ `VAR_SCALE` 倍の単位）を置き、各 piece について
+/// `Σ terms - COEF_SCALE × z <= -offset` を課して目的に `weight × z` を足す。
+#[derive(Debug, Clone)]
+pub struct MaxTerm {
+    pub weight: i64,
+    pub z_bounds: (i64, i64),
+    /// (整数化した piece の線形部分, 定数項)
+    pub pieces: Vec<(Terms, i64)>,
+}
+
+/// 線形化した（最小化する）目的。`offset` はスケール前の定数項でソルバーには渡さず、
+/// 解いた後の目的値に足し戻す
+#[derive(Debug, Clone, Default)]
+pub struct Objective {
+    pub terms: Terms,
+    pub offset: f64,
+    pub maxes: Vec<MaxTerm>,
+}
+
+/// 変数 `i` の整数化した範囲
+pub fn var_bounds(model: &Model, i: usize) -> (i64, i64) {
+    (
+        (model.lb[i] * VAR_SCALE) as i64,
+        (model.ub[i] * VAR_SCALE) as i64,
+    )
+}
+
+/// 先頭の目的（`objectives:` の先頭、無ければ `minimize` / `maximize`）を線形化する
+///
+/// CP-SATは最小化のみなので、maximize は符号を反転して max / abs の凸性を判定する。
+/// 目的が無ければ `None`。
+pub fn objective(model: &Model) -> Result<Option<Objective>, String> {
+    let expr = match model.objectives.first() {
+        Some(obj) => obj.expr.as_str(),
+        None => match model.objective_expr.as_deref() {
+            Some(e) => e,
+            None => return Ok(None),
+        },
+    };
+    let sign = if model.maximize { -1.0 } else { 1.0 };
+    let form = model.piecewise_form(expr, sign)?;
+    let mut objective = Objective {
+        terms: scaled_terms(model, expr, &form.linear.0)?,
+        offset: form.linear.1,
+        maxes: Vec::with_capacity(form.maxes.len()),
+    };
+    for (w, pieces) in &form.maxes {
+        let weight = (w * COEF_SCALE).round() as i64;
+        if weight == 0 {
+            return Err(format!(
+                "weight {} in '{}' is below the resolution 1/{}",
+                w, expr, COEF_SCALE
+            ));
+        }
+        // z はどの piece よりも小さくなれない。上端は piece の最大値で足りる
+        let mut z_range = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        let mut rows = Vec::with_capacity(pieces.len());
+        for (coefs, offset) in pieces {
+            let terms = scaled_terms(model, expr, coefs)?;
+            let offset = (offset * VAR_SCALE * COEF_SCALE).round() as i64;
+            let (lo, hi) = scaled_range(model, expr, coefs)?;
+            z_range = (
+                z_range.0.max(lo + offset as f64),
+                z_range.1.max(hi + offset as f64),
+            );
+            rows.push((terms, offset));
+        }
+        objective.maxes.push(MaxTerm {
+            weight,
+            z_bounds: (
+                (z_range.0 / COEF_SCALE).floor() as i64,
+                (z_range.1 / COEF_SCALE).ceil() as i64,
+            ),
+            pieces: rows,
+        });
+    }
+    Ok(Some(objective))
+}
+
+/// 制約をすべて線形化する（非線形な制約があればエラー）
+pub fn constraint_rows(model: &Model) -> Result<Vec<Row>, String> {
+    // 右辺の式は変数を含まないので、評価点はどこでもよい
+    let zeros = vec![0.0; model.var_map.len()];
+    model
+        .constraints
+        .iter()
+        .map(|c| {
+            let (coefs, offset) = model
+                .linear_form(&c.expr)
+                .and_then(|(coefs, offset)| Ok((scaled_terms(model, &c.expr, &coefs)?, offset)))
+                .map_err(|e| format!("constraint {}: {}", c.name, e))?;
+            Ok(Row {
+                name: c.name.clone(),
+                terms: coefs,
+                op: c.op,
+                rhs: scaled_rhs(model.constraint_rhs(c, &zeros), offset),
+            })
+        })
+        .collect()
+}
+
+/// 左辺の定数項 `offset` を移項した右辺を、係数と同じ倍率で整数にする
+///
+/// 切り捨てずに丸める（`0.7 * 1e6 = 699999.99...` を 699999 にすると `>=` が緩み `<=` が締まる）。
+pub fn scaled_rhs(rhs: f64, offset: f64) -> i64 {
+    ((rhs - offset) * VAR_SCALE * COEF_SCALE).round() as i64
+}
+
+/// 係数を `COEF_SCALE` 倍して整数に丸めた (整数係数, 変数) の列
+///
+/// 丸めると0になる係数（`0.0004 * x`）は項を黙って落とさずにエラーにする（呼び出し側は
+/// ヒューリスティックへ戻る）。プリソルブで消去した変数を参照する式もエラー。
+pub fn scaled_terms(model: &Model, expr: &str, coefs: &[f64]) -> Result<Terms, String> {
+    let mut terms = Vec::new();
+    for (j, &c) in coefs.iter().enumerate() {
+        let k = (c * COEF_SCALE).round() as i64;
+        if k == 0 {
+            if c != 0.0 {
+                let name = model.var_names.get(j).map_or("?", |s| s.as_str());
+                return Err(format!(
+                    "coefficient {} of {} in '{}' is below the resolution 1/{}",
+                    c, name, expr, COEF_SCALE
+                ));
+            }
+            continue;
+        }
+        if j >= model.dim {
+            return Err(format!("'{}' refers to an eliminated variable", expr));
+        }
+        terms.push((k, j));
+    }
+    Ok(terms)
+}
+
+/// 線形式の整数部分（定数項を除く）が変数の範囲でとり得る値の範囲（`scaled_terms` と同じ単位）
+fn scaled_range(model: &Model, expr: &str, coefs: &[f64]) -> Result<(f64, f64), String> {
+    let (mut lo, mut hi) = (0.0, 0.0);
+    for (j, &c) in coefs.iter().enumerate() {
+        let k = (c * COEF_SCALE).round();
+        if k == 0.0 {
+            continue;
+        }
+        if j >= model.dim {
+            return Err(format!("'{}' refers to an eliminated variable", expr));
+        }
+        let (a, b) = var_bounds(model, j);
+        let (a, b) = (k * a as f64, k * b as f64);
+        lo += a.min(b);
+        hi += a.max(b);
+    }
+    Ok((lo, hi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn shifted_rhs_is_rounded_not_truncated() {
+        // 2.9996 × 1000 = 2999.6 は 3000 に丸める（切り捨てると 2999）
+        assert_eq!(scaled_rhs(2.9996 / 1000.0, 0.0), 3000);
+        // 0.7 × 1e6 は浮動小数点で 699999.99... になる
+        assert_eq!(scaled_rhs(0.7, 0.0), 700_000);
+        assert_eq!(scaled_rhs(1.0, 0.3), 700_000);
+        assert_eq!(scaled_rhs(-0.7, 0.0), -700_000);
+    }
+
+    #[test]
+    fn constraint_rows_move_the_constant_to_the_rhs() {
+        let m = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\nminimize: x + y\n\
+             subject to:\n    cap: 2 * x - y + 0.3 >= 1\n    x + 0.1 <= 0.8\n",
+        )
+        .unwrap();
+        let rows = constraint_rows(&m).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name, "cap");
+        assert_eq!(rows[0].terms, vec![(2000, 0), (-1000, 1)]);
+        assert!(matches!(rows[0].op, ConstraintOp::Ge));
+        assert_eq!(rows[0].rhs, 700_000);
+        assert_eq!(rows[1].terms, vec![(1000, 0)]);
+        assert_eq!(rows[1].rhs, 700_000);
+    }
+
+    #[test]
+    fn coefficients_below_the_resolution_are_rejected() {
+        let m = parse("var x >= 0, <= 10\nvar y >= 0, <= 10\nminimize: x\n").unwrap();
+        let err = scaled_terms(&m, "0.0001 * x + y", &[1e-4, 1.0]).unwrap_err();
+        assert!(err.contains("coefficient 0.0001 of x"), "{}", err);
+        // 0.0005 は 1/1000 の分解能で 1 に丸まるので残る。0 の係数は項ごと省く
+        assert_eq!(scaled_terms(&m, "", &[5e-4, 0.0]).unwrap(), vec![(1, 0)]);
+
+        let m = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\nminimize: x\nsubject to:\n    0.0001 * x + y <= 5\n",
+        )
+        .unwrap();
+        let err = constraint_rows(&m).unwrap_err();
+        assert!(err.contains("below the resolution"), "{}", err);
+    }
+
+    /// 整数化した目的を点 `x`（スケール前）で評価する（z は最小の値 = 各 piece の最大値）
+    fn eval_scaled(obj: &Objective, x: &[f64]) -> f64 {
+        let dot = |terms: &Terms| -> f64 {
+            terms
+                .iter()
+                .map(|&(k, j)| k as f64 * x[j] * VAR_SCALE)
+                .sum()
+        };
+        let mut v = dot(&obj.terms);
+        for m in &obj.maxes {
+            let z = m
+                .pieces
+                .iter()
+                .map(|(terms, offset)| (dot(terms) + *offset as f64) / COEF_SCALE)
+                .fold(f64::NEG_INFINITY, f64::max);
+            assert!(
+                z >= m.z_bounds.0 as f64 && z <= m.z_bounds.1 as f64,
+                "z {} outside {:?}",
+                z,
+                m.z_bounds
+            );
+            v += m.weight as f64 * z;
+        }
+        v / (VAR_SCALE * COEF_SCALE) + obj.offset
+    }
+
+    #[test]
+    fn abs_and_max_objectives_become_auxiliary_rows() {
+        let m = parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\nminimize: abs(x - 2) + 3 * max(x, y) + 1\n",
+        )
+        .unwrap();
+        let obj = objective(&m).unwrap().unwrap();
+        assert!(obj.terms.is_empty());
+        assert_eq!(obj.offset, 1.0);
+        assert_eq!(obj.maxes.len(), 2);
+
+        // |x - 2| = max(x - 2, 2 - x)
+        let abs = &obj.maxes[0];
+        assert_eq!(abs.weight, 1000);
+        assert_eq!(
+            abs.pieces,
+            vec![(vec![(1000, 0)], -2_000_000), (vec![(-1000, 0)], 2_000_000)]
+        );
+        assert_eq!(abs.z_bounds, (-2000, 8000));
+
+        let max = &obj.maxes[1];
+        assert_eq!(max.weight, 3000);
+        assert_eq!(max.pieces, vec![(vec![(1000, 0)], 0), (vec![(1000, 1)], 0)]);
+        assert_eq!(max.z_bounds, (0, 10_000));
+
+        for x in [[0.0, 0.0], [2.0, 7.5], [9.25, 3.0], [1.5, 1.5]] {
+            let expected = m.evaluate_objective(&x);
+            assert!((eval_scaled(&obj, &x) - expected).abs() < 1e-9, "{:?}", x);
+        }
+    }
+
+    #[test]
+    fn maximized_min_becomes_a_max_of_negated_pieces() {
+        let m =
+            parse("var x >= 0, <= 4\nvar y >= 1, <= 5\nmaximize: min(x, y) - 0.5 * x\n").unwrap();
+        let obj = objective(&m).unwrap().unwrap();
+        // 最小化する -min(x, y) + 0.5x = max(-x, -y) + 0.5x
+        assert_eq!(obj.terms, vec![(500, 0)]);
+        assert_eq!(obj.maxes.len(), 1);
+        assert_eq!(obj.maxes[0].weight, 1000);
+        assert_eq!(
+            obj.maxes[0].pieces,
+            vec![(vec![(-1000, 0)], 0), (vec![(-1000, 1)], 0)]
+        );
+        for x in [[0.0, 1.0], [4.0, 2.5], [3.0, 5.0]] {
+            let minimized = -m.evaluate_objective(&x);
+            assert!((eval_scaled(&obj, &x) - minimized).abs() < 1e-9, "{:?}", x);
+        }
+    }
+
+    #[test]
+    fn non_convex_piecewise_objectives_are_rejected() {
+        for src in [
+            "var x >= -5, <= 5\nminimize: -abs(x)\n",
+            "var x >= -5, <= 5\nvar y >= -5, <= 5\nmaximize: max(x, y)\n",
+            "var x >= -5, <= 5\nvar y >= -5, <= 5\nminimize: min(x, y)\n",
+            "var x >= -5, <= 5\nvar y >= -5, <= 5\nminimize: x * max(x, y)\n",
+        ] {
+            let m = parse(src).unwrap();
+            assert!(objective(&m).is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn constant_objective_terms_become_the_offset() {
+        let m = parse(
+            "set I = {1, 2}\nparam c[I] = {1: 2, 2: 3};\nparam fixed_cost = 12.5;\n\
+             var x[I] >= 0, <= 10\nminimize: fixed_cost + sum(i in I) c[i] * x[i]\n",
+        )
+        .unwrap();
+        let obj = objective(&m).unwrap().unwrap();
+        assert_eq!(obj.terms, vec![(2000, 0), (3000, 1)]);
+        assert_eq!(obj.offset, 12.5);
+        for x in [[0.0, 0.0], [1.5, 4.0]] {
+            assert!((eval_scaled(&obj, &x) - m.evaluate_objective(&x)).abs() < 1e-9);
+        }
+
+        // 最大化は符号を反転した目的を最小化するので、定数項も反転する
+        let m = parse("var x >= 0, <= 10\nmaximize: 2 * x + 3 - 0.5\n").unwrap();
+        let obj = objective(&m).unwrap().unwrap();
+        assert_eq!(obj.terms, vec![(-2000, 0)]);
+        assert_eq!(obj.offset, -2.5);
+        assert!((eval_scaled(&obj, &[4.0]) + m.evaluate_objective(&[4.0])).abs() < 1e-9);
+    }
+
+    #[test]
+    fn precedence_rows_share_the_interval_endpoints() {
+        let m = parse(include_str!("../../examples/precedence_no_overlap.optica")).unwrap();
+        assert_eq!(m.cp_globals.len(), 1);
+        let var = |name: &str| m.var_map[name];
+        let rows = constraint_rows(&m).unwrap();
+        let row = |name: &str| rows.iter().find(|r| r.name == name).unwrap();
+
+        // `end[a] <= start[b]` は右辺の変数を左辺へ移して `end[a] - start[b] <= 0`
+        let after1 = row("after1");
+        // 項は変数の順
+        assert_eq!(
+            after1.terms,
+            vec![(-1000, var("start[3]")), (1000, var("end[1]"))]
+        );
+        assert!(matches!(after1.op, ConstraintOp::Le));
+        assert_eq!(after1.rhs, 0);
+        let len3 = row("len3");
+        assert_eq!(
+            len3.terms,
+            vec![(-1000, var("start[3]")), (1000, var("end[3]"))]
+        );
+        assert_eq!(len3.rhs, 4_000_000);
+
+        // 短い順（2, 1, 3）は重ならないが先行制約を破る。最適解 1, 3, 2 はすべて満たす
+        let schedule = |starts: [f64; 3]| {
+            let mut x = vec![0.0; m.dim];
+            for (j, (s, d)) in starts.iter().zip([3.0, 2.0, 4.0]).enumerate() {
+                x[var(&format!("start[{}]", j + 1))] = *s;
+                x[var(&format!("end[{}]", j + 1))] = s + d;
+            }
+            x
+        };
+        let violated = |x: &[f64]| -> Vec<String> {
+            m.constraint_violations(x)
+                .into_iter()
+                .filter(|(_, v)| *v > 0.0)
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        assert_eq!(violated(&schedule([2.0, 0.0, 5.0])), ["after3"]);
+        let best = schedule([0.0, 7.0, 3.0]);
+        assert!(violated(&best).is_empty());
+        assert_eq!(m.evaluate_objective(&best), 19.0);
+    }
+}
@@ -1,120 +1,145 @@
 //! CP-SAT (OR-Tools) で CP 制約を厳密に解く
+//!
+//! 線形化は `cplinear`（ソルバー非依存）で行い、ここでは `cp_sat` のモデルに写すだけにする。
+//! 区間変数・no_overlap・cumulative はビルダーに無いので、組み立てたモデルのprotoに直接足す。
 #![cfg(feature = "cp-sat")]
 
-use crate::parser::{ConstraintOp, Model};
-use or_tools::sat::*;
+use super::cplinear::{self, Terms, COEF_SCALE, VAR_SCALE};
+use super::{CpOutcome, Deadline};
+use crate::parser::{ConstraintOp, Model, VarType};
+use cp_sat::builder::{CpModelBuilder, IntVar, LinearExpr};
+use cp_sat::proto::{
+    constraint_proto, ConstraintProto, CpModelProto, CpSolverStatus, CumulativeConstraintProto,
+    IntervalConstraintProto, NoOverlapConstraintProto, SatParameters,
+};
 
 pub fn solve_cp(
     model: &Model,
-    _max_iter: usize,
-    _threads: usize,
-) -> Result<(Vec<f64>, f64, usize), String> {
-    let mut solver = CpModelBuilder::new();
-    // 実数をスケールして整数化
-    let scale = 1000.0;
+    threads: usize,
+    hint: Option<&[f64]>,
+    deadline: Option<&Deadline>,
+) -> Result<CpOutcome, String> {
+    let mut solver = CpModelBuilder::default();
 
-    // 変数: すべて整数化
+    // 変数: 連続変数はスケールして整数化、整数・バイナリ変数はスケール後も整数値の倍数に固定
     let mut vars: Vec<IntVar> = Vec::with_capacity(model.dim);
     for i in 0..model.dim {
-        let lb = (model.lb[i] * scale) as i64;
-        let ub = (model.ub[i] * scale) as i64;
-        vars.push(solver.new_int_var(lb, ub, format!("v{}", i)));
+        let v = solver.new_int_var_with_name([cplinear::var_bounds(model, i)], format!("v{}", i));
+        let units = match model.var_type(i) {
+            VarType::Continuous => None,
+            VarType::Integer => Some((model.lb[i] as i64, model.ub[i] as i64)),
+            VarType::Binary => Some((0, 1)),
+        };
+        if let Some(domain) = units {
+            let k = solver.new_int_var_with_name([domain], format!("k{}", i));
+            solver.add_eq(LinearExpr::from(k) * VAR_SCALE as i64, v);
+        }
+        vars.push(v);
     }
 
-    // 目的（先頭目的 or weighted/epsilon は簡易に先頭のみ）
-    let mut objective_terms = Vec::new();
-    if !model.objectives.is_empty() {
-        let obj = &model.objectives[0];
-        let lin = linearize_expr(model, obj.expr.as_str(), &vars, scale);
-        objective_terms.extend(lin);
-    } else if let Some(expr) = &model.objective_expr {
-        let lin = linearize_expr(model, expr.as_str(), &vars, scale);
-        objective_terms.extend(lin);
-    }
-    if model.maximize {
-        // OR-Toolsは最小化のみ。符号反転
-        for (c, v) in objective_terms.iter_mut() {
-            *c = -*c;
+    // ヒューリスティック解をヒントとして与える（スケール後の整数値）
+    if let Some(h) = hint {
+        for (v, &val) in vars.iter().zip(h) {
+            // NaN はウォームスタートに含まれない変数
+            if val.is_finite() {
+                solver.add_hint(*v, (val * VAR_SCALE).round() as i64);
+            }
         }
     }
-    let obj = objective_terms
-        .iter()
-        .map(|(c, v)| LinearExpr::from(*v) * *c)
-        .fold(LinearExpr::from(0), |acc, e| acc + e);
-    solver.minimize(obj);
 
-    // 線形制約
-    for c in &model.constraints {
-        let lin = linearize_expr(model, c.expr.as_str(), &vars, scale);
-        let lhs = lin
-            .iter()
-            .map(|(coef, var)| LinearExpr::from(*var) * *coef)
-            .fold(LinearExpr::from(0), |acc, e| acc + e);
-        let rhs = (c.rhs * scale) as i64;
-        match c.op {
-            ConstraintOp::Le => {
-                solver.add_linear_constraint(lhs <= rhs);
+    // 目的（先頭目的 or weighted/epsilon は簡易に先頭のみ）
+    // 定数項はソルバーに渡さず、解いた後の目的値に足し戻す
+    let mut objective_offset = 0.0;
+    if let Some(objective) = cplinear::objective(model)? {
+        let mut obj = linear_sum(&objective.terms, &vars);
+        objective_offset = objective.offset;
+        // w × max(e1, e2, ...) は補助変数 z >= ek（スケール後の単位）で表し、目的に w × z を足す
+        for (k, m) in objective.maxes.iter().enumerate() {
+            let z = solver.new_int_var_with_name([m.z_bounds], format!("max{}", k));
+            for (terms, offset) in &m.pieces {
+                let lhs = linear_sum(terms, &vars) + LinearExpr::from(z) * -(COEF_SCALE as i64);
+                solver.add_le(lhs, -offset);
             }
-            ConstraintOp::Ge => {
-                solver.add_linear_constraint(lhs >= rhs);
+            obj += LinearExpr::from(z) * m.weight;
+        }
+        solver.minimize(obj);
+    }
+
+    // 線形制約（左辺の定数項は右辺へ移項済み）
+    for row in cplinear::constraint_rows(model)? {
+        let lhs = linear_sum(&row.terms, &vars);
+        let c = match row.op {
+            ConstraintOp::Le => solver.add_le(lhs, row.rhs),
+            ConstraintOp::Ge => solver.add_ge(lhs, row.rhs),
+            ConstraintOp::Eq => solver.add_eq(lhs, row.rhs),
+        };
+        solver.set_constraint_name(c, &row.name);
+    }
+
+    // リリース時刻・締切: start[j] >= release[j], end[j] (= start[j] + duration[j]) <= deadline[j]
+    if !model.cp_globals.is_empty() {
+        let release = model.params.get("release");
+        let deadline = model.params.get("deadline");
+        for (name, &idx) in &model.var_map {
+            let Some(idx_suffix) = name.strip_prefix("start[") else {
+                continue;
+            };
+            let start = vars[idx];
+            let key = idx_suffix.trim_end_matches(']');
+            if let Some(&r) = release.and_then(|m| m.get(key)) {
+                solver.add_ge(start, (r * VAR_SCALE).ceil() as i64);
             }
-            ConstraintOp::Eq => {
-                solver.add_linear_constraint(lhs == rhs);
+            if let Some(&d) = deadline.and_then(|m| m.get(key)) {
+                let d = (d * VAR_SCALE).floor() as i64;
+                if let Some(&eidx) = model.var_map.get(&format!("end[{}", idx_suffix)) {
+                    solver.add_le(vars[eidx], d);
+                } else if let Some(&didx) = model.var_map.get(&format!("duration[{}", idx_suffix)) {
+                    solver.add_le(LinearExpr::from(start) + vars[didx], d);
+                }
             }
         }
     }
 
     // CPグローバル: disjunctive/no_overlap/cumulative (簡易)
     // 期待する変数名: start[...], end[...], duration[...]
+    // 区間は上の線形制約と同じ `vars` を使うので、`end[a] <= start[b]` のような
+    // 先行制約は区間の端点そのものに効く
+    let mut globals: Vec<Global> = Vec::new();
     for g in &model.cp_globals {
         if g.contains("disjunctive") || g.contains("no_overlap") {
             let mut intervals = Vec::new();
             for (name, &idx) in &model.var_map {
-                if name.starts_with("start[") {
-                    let start = vars[idx];
-                    let idx_str = name["start[".len()..].to_string();
-                    // duration or end
-                    let dur_key = format!("duration[{}", idx_str);
-                    let end_key = format!("end[{}", idx_str);
-                    let duration = if let Some(&didx) = model.var_map.get(&dur_key) {
-                        Some(vars[didx])
-                    } else {
-                        None
-                    };
-                    let end = if let Some(&eidx) = model.var_map.get(&end_key) {
-                        Some(vars[eidx])
-                    } else {
-                        None
-                    };
-                    let dur = if let Some(d) = duration {
-                        d
-                    } else if let Some(e) = end {
-                        // end - start
-                        let d =
-                            solver.new_int_var(0, i64::MAX, format!("dur_tmp_{}", intervals.len()));
-                        solver.add_linear_constraint(e - start == d);
-                        d
-                    } else {
-                        // fallback duration=1
-                        solver.new_constant(1)
-                    };
-                    let interval = solver.new_interval_var(
-                        start,
-                        dur,
-                        LinearExpr::from(start) + dur,
-                        format!("iv_{}", intervals.len()),
-                    );
-                    intervals.push(interval);
-                }
-            }
-            if !intervals.is_empty() {
-                solver.add_no_overlap(intervals);
+                let Some(idx_str) = name.strip_prefix("start[") else {
+                    continue;
+                };
+                let start = LinearExpr::from(vars[idx]);
+                let duration = model.var_map.get(&format!("duration[{}", idx_str));
+                let end = model.var_map.get(&format!("end[{}", idx_str));
+                // 区間の終端は end 変数があればそれ自体にする（区間が start + dur == end を課す）。
+                // start + dur の式にすると end 変数が区間から切り離され、end を使う先行制約が効かない
+                let (size, end) = match (duration, end) {
+                    (Some(&d), Some(&e)) => (LinearExpr::from(vars[d]), LinearExpr::from(vars[e])),
+                    (Some(&d), None) => (LinearExpr::from(vars[d]), start.clone() + vars[d]),
+                    (None, Some(&e)) => {
+                        // 長さは end - start（区間の定義で非負になる）
+                        let span = (model.ub[e] - model.lb[idx]).max(0.0);
+                        let d = solver.new_int_var_with_name(
+                            [(0, (span * VAR_SCALE).ceil() as i64)],
+                            format!("dur_tmp_{}", intervals.len()),
+                        );
+                        (LinearExpr::from(d), LinearExpr::from(vars[e]))
+                    }
+                    // fallback duration=1
+                    (None, None) => {
+                        let unit = LinearExpr::from(VAR_SCALE as i64);
+                        (unit.clone(), start.clone() + unit)
+                    }
+                };
+                intervals.push((start, size, end));
             }
+            globals.push(Global::NoOverlap(intervals));
         } else if g.contains("cumulative") {
             // 期待: cumulative(start[j], duration[j], demand, capacity)
-            let mut starts = Vec::new();
-            let mut durations = Vec::new();
-            let mut demands = Vec::new();
             // capacityは行から抽出（既存ペナルティと同様）: 最初の2つの数値
             let nums: Vec<i64> = g
                 .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
@@ -122,64 +147,126 @@ pub fn solve_cp(
                     if s.is_empty() {
                         None
                     } else {
-                        s.parse::<f64>().ok().map(|v| (v * scale) as i64)
+                        s.parse::<f64>().ok().map(|v| (v * VAR_SCALE) as i64)
                     }
                 })
                 .collect();
-            let demand_val = nums.get(0).copied().unwrap_or(scale as i64);
-            let capacity_val = nums.get(1).copied().unwrap_or(scale as i64);
+            let demand = nums.first().copied().unwrap_or(VAR_SCALE as i64);
+            let capacity = nums.get(1).copied().unwrap_or(VAR_SCALE as i64);
 
+            let mut intervals = Vec::new();
             for (name, &idx) in &model.var_map {
-                if name.starts_with("start[") {
-                    let start = vars[idx];
-                    let idx_str = name["start[".len()..].to_string();
-                    let dkey = format!("duration[{}", idx_str);
-                    if let Some(&didx) = model.var_map.get(&dkey) {
-                        let dur = vars[didx];
-                        starts.push(start);
-                        durations.push(dur);
-                        demands.push(demand_val);
-                    }
+                let Some(idx_str) = name.strip_prefix("start[") else {
+                    continue;
+                };
+                if let Some(&didx) = model.var_map.get(&format!("duration[{}", idx_str)) {
+                    let start = LinearExpr::from(vars[idx]);
+                    let end = start.clone() + vars[didx];
+                    intervals.push((start, LinearExpr::from(vars[didx]), end));
                 }
             }
-            if !starts.is_empty() {
-                solver.add_cumulative(starts, durations, demands, capacity_val);
+            globals.push(Global::Cumulative(intervals, demand, capacity));
+        }
+    }
+    let mut proto = solver.proto().clone();
+    for global in globals {
+        match global {
+            Global::NoOverlap(intervals) if !intervals.is_empty() => {
+                let intervals = add_intervals(&mut proto, intervals);
+                add_constraint(
+                    &mut proto,
+                    constraint_proto::Constraint::NoOverlap(NoOverlapConstraintProto { intervals }),
+                );
+            }
+            Global::Cumulative(intervals, demand, capacity) if !intervals.is_empty() => {
+                let demands = vec![LinearExpr::from(demand).into(); intervals.len()];
+                let intervals = add_intervals(&mut proto, intervals);
+                add_constraint(
+                    &mut proto,
+                    constraint_proto::Constraint::Cumulative(CumulativeConstraintProto {
+                        capacity: Some(LinearExpr::from(capacity).into()),
+                        intervals,
+                        demands,
+                    }),
+                );
             }
+            _ => {}
         }
     }
 
     // solve
-    let mut opt = CpSolver::new();
-    opt.set_num_search_workers(4);
-    let result = opt.solve(&solver.build());
-    if result != CpSolverStatus::Optimal && result != CpSolverStatus::Feasible {
-        return Err(format!("cp-sat status: {:?}", result));
+    let mut params = SatParameters::default();
+    // 0（ライブラリから直接呼んだ場合など）は使えるコア数。1なら探索が決定的になる
+    let workers = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    params.num_search_workers = Some(workers as i32);
+    if let Some(d) = deadline {
+        params.max_time_in_seconds = Some(d.remaining().as_secs_f64());
     }
-    let mut best = vec![0.0; model.dim];
-    for i in 0..model.dim {
-        best[i] = opt.value(vars[i]) as f64 / scale;
+    let response = cp_sat::ffi::solve_with_parameters(&proto, &params);
+    let status = response.status();
+    // 最適性を証明する前に時間上限で止まった場合は `Stopped: time limit` の表示に反映する
+    if let Some(d) = deadline {
+        if matches!(status, CpSolverStatus::Feasible | CpSolverStatus::Unknown)
+            && d.remaining().is_zero()
+        {
+            d.mark_reached();
+        }
     }
-    let fitness = opt.objective_value() as f64 / scale;
-    Ok((best, fitness, 0))
+    match status {
+        CpSolverStatus::Optimal | CpSolverStatus::Feasible => {}
+        CpSolverStatus::Infeasible => return Ok(CpOutcome::Infeasible),
+        _ => return Err(format!("cp-sat status: {:?}", status)),
+    }
+    let best: Vec<f64> = vars
+        .iter()
+        .map(|v| v.solution_value(&response) as f64 / VAR_SCALE)
+        .collect();
+    let fitness = response.objective_value / (VAR_SCALE * COEF_SCALE) + objective_offset;
+    Ok(if status == CpSolverStatus::Optimal {
+        CpOutcome::Optimal(best, fitness, 0)
+    } else {
+        CpOutcome::Feasible(best, fitness, 0)
+    })
 }
 
-// 線形化（非常に限定的：x[i], 定数、単純な足し算のみを想定）
-fn linearize_expr(model: &Model, expr: &str, vars: &[IntVar], scale: f64) -> Vec<(i64, IntVar)> {
-    let mut terms: Vec<(i64, IntVar)> = Vec::new();
-    for token in expr.split('+') {
-        let t = token.trim();
-        if let Some(idx) = model.var_map.get(t) {
-            terms.push((1, vars[*idx]));
-        } else if let Some(v) = model.params.get(t).and_then(|m| m.get("_")) {
-            let c = (v * scale) as i64;
-            let const_var = vars.get(0).cloned().unwrap_or_else(|| {
-                // もし変数がない場合のダミー
-                // ここでは0~0の定数を返す
-                // ただし通常dim>0の前提
-                unimplemented!()
-            });
-            terms.push((c, const_var)); // const_var * c（後で符号反転含む）
-        }
-    }
-    terms
+/// 区間 (start, size, end) の列
+type Intervals = Vec<(LinearExpr, LinearExpr, LinearExpr)>;
+
+/// ビルダーに無いCPグローバル制約（protoに直接足す）
+enum Global {
+    NoOverlap(Intervals),
+    /// (区間, 各区間の需要, 容量)
+    Cumulative(Intervals, i64, i64),
+}
+
+fn linear_sum(terms: &Terms, vars: &[IntVar]) -> LinearExpr {
+    terms.iter().map(|&(c, j)| (c, vars[j])).collect()
+}
+
+/// 区間制約を足し、その制約番号の列を返す
+fn add_intervals(proto: &mut CpModelProto, intervals: Intervals) -> Vec<i32> {
+    intervals
+        .into_iter()
+        .map(|(start, size, end)| {
+            add_constraint(
+                proto,
+                constraint_proto::Constraint::Interval(IntervalConstraintProto {
+                    start: Some(start.into()),
+                    end: Some(end.into()),
+                    size: Some(size.into()),
+                }),
+            )
+        })
+        .collect()
+}
+
+fn add_constraint(proto: &mut CpModelProto, constraint: constraint_proto::Constraint) -> i32 {
+    proto.constraints.push(ConstraintProto {
+        constraint: Some(constraint),
+        ..Default::default()
+    });
+    proto.constraints.len() as i32 - 1
 }
@@ -0,0 +1,109 @@
+//! 小さな密行列（CMA-ESの共分散行列用）
+
+/// n×n の正方行列（行優先）
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    n: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn identity(n: usize) -> Self {
+        let mut m = Self {
+            n,
+            data: vec![0.0; n * n],
+        };
+        for i in 0..n {
+            m.data[i * n + i] = 1.0;
+        }
+        m
+    }
+
+    #[inline(always)]
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        self.data[i * self.n + j]
+    }
+
+    #[inline(always)]
+    pub fn set(&mut self, i: usize, j: usize, v: f64) {
+        self.data[i * self.n + j] = v;
+    }
+
+    /// `out = self * v`
+    pub fn mul_vec(&self, v: &[f64], out: &mut [f64]) {
+        for (i, o) in out.iter_mut().enumerate() {
+            let row = &self.data[i * self.n..(i + 1) * self.n];
+            *o = row.iter().zip(v).map(|(a, b)| a * b).sum();
+        }
+    }
+
+    /// `out = selfᵀ * v`
+    pub fn mul_vec_t(&self, v: &[f64], out: &mut [f64]) {
+        out.fill(0.0);
+        for (i, &vi) in v.iter().enumerate() {
+            let row = &self.data[i * self.n..(i + 1) * self.n];
+            for (o, a) in out.iter_mut().zip(row) {
+                *o += a * vi;
+            }
+        }
+    }
+
+    /// 対称行列の固有値分解（巡回Jacobi法）。(固有値, 固有ベクトルを列に並べた行列) を返す
+    ///
+    /// 下三角は使わず上三角から対称に扱う。非対角成分の二乗和が対角の 1e-24 倍を
+    /// 下回るか、掃引回数の上限で打ち切る。
+    pub fn symmetric_eigen(&self) -> (Vec<f64>, Matrix) {
+        let n = self.n;
+        let mut a = self.clone();
+        for i in 0..n {
+            for j in 0..i {
+                a.set(i, j, a.get(j, i));
+            }
+        }
+        let mut v = Matrix::identity(n);
+
+        for _ in 0..50 {
+            let mut off = 0.0;
+            let mut diag = 0.0;
+            for i in 0..n {
+                diag += a.get(i, i) * a.get(i, i);
+                for j in i + 1..n {
+                    off += a.get(i, j) * a.get(i, j);
+                }
+            }
+            if off <= 1e-24 * diag.max(f64::MIN_POSITIVE) {
+                break;
+            }
+            for p in 0..n {
+                for q in p + 1..n {
+                    let apq = a.get(p, q);
+                    if apq == 0.0 {
+                        continue;
+                    }
+                    // a'[p][q] = 0 となる回転角（小さい方の根）
+                    let theta = (a.get(q, q) - a.get(p, p)) / (2.0 * apq);
+                    let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                    let c = 1.0 / (t * t + 1.0).sqrt();
+                    let s = t * c;
+                    for k in 0..n {
+                        let (akp, akq) = (a.get(k, p), a.get(k, q));
+                        a.set(k, p, c * akp - s * akq);
+                        a.set(k, q, s * akp + c * akq);
+                    }
+                    for k in 0..n {
+                        let (apk, aqk) = (a.get(p, k), a.get(q, k));
+                        a.set(p, k, c * apk - s * aqk);
+                        a.set(q, k, s * apk + c * aqk);
+                    }
+                    for k in 0..n {
+                        let (vkp, vkq) = (v.get(k, p), v.get(k, q));
+                        v.set(k, p, c * vkp - s * vkq);
+                        v.set(k, q, s * vkp + c * vkq);
+                    }
+                }
+            }
+        }
+
+        ((0..n).map(|i| a.get(i, i)).collect(), v)
+    }
+}
@@ -7,28 +7,69 @@
 //! - 効率的な並列処理
 //! - 分岐予測最適化
 
+mod matrix;
 mod objective;
 mod rng;
 
 use crate::config::*;
-use crate::parser::{ConstraintOp, Model, ParetoMethod};
-use std::sync::{Arc, OnceLock};
+use crate::parser::Model;
+use matrix::Matrix;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
+use std::time::{Duration, Instant};
+// CP-SAT向けの線形化はソルバー無しでも組み立てて試せるよう、feature に関係なくコンパイルする
+#[cfg_attr(not(feature = "cp-sat"), allow(dead_code))]
+mod cplinear;
 pub mod cpsat;
+/// CP-SATの結果
+#[derive(Debug, Clone)]
+pub enum CpOutcome {
+    /// 最適性を証明した解 (解, 適合度, 反復数)
+    Optimal(Vec<f64>, f64, usize),
+    /// 実行可能解（最適性は未証明）
+    Feasible(Vec<f64>, f64, usize),
+    /// 実行可能解が存在しないことを証明した
+    Infeasible,
+}
+
 #[cfg(feature = "cp-sat")]
-use crate::solver::cpsat::solve_cp;
+fn solve_cp(
+    model: &Model,
+    threads: usize,
+    hint: Option<&[f64]>,
+    deadline: Option<&Deadline>,
+) -> Option<CpOutcome> {
+    // 線形化できない式などで解けない場合は理由を出して呼び出し側のフォールバックに任せる
+    cpsat::solve_cp(model, threads, hint, deadline)
+        .inspect_err(|e| eprintln!("warning: cp-sat: {}", e))
+        .ok()
+}
 #[cfg(not(feature = "cp-sat"))]
-fn solve_cp(_model: &Model) -> Option<(Vec<f64>, f64, usize)> {
+fn solve_cp(
+    _model: &Model,
+    _threads: usize,
+    _hint: Option<&[f64]>,
+    _deadline: Option<&Deadline>,
+) -> Option<CpOutcome> {
     None
 }
 
+/// CP-SATで解く（使えなければNone）
+///
+/// `hint` を渡すと初期解のヒントとしてCP-SATに与える（ヒューリスティック解の引き継ぎ用）。
+/// 実行不能を証明した場合は `CpOutcome::Infeasible`（ヒューリスティックへは戻さない）。
+/// 探索ワーカー数は `threads`（0なら使えるコア数）、`deadline` は残り時間をCP-SATの時間上限にする。
+/// CP-SATには反復の概念が無いので反復数の上限は受け取らない（結果の反復数は0）。
 pub fn solve_cp_entry(
     model: &Model,
-    max_iter: usize,
     threads: usize,
-) -> Option<(Vec<f64>, f64, usize)> {
-    let _ = (max_iter, threads);
-    solve_cp(model)
+    hint: Option<&[f64]>,
+    deadline: Option<&Deadline>,
+) -> Option<CpOutcome> {
+    solve_cp(model, threads, hint, deadline)
 }
 
 pub use rng::Rng;
@@ -36,49 +77,764 @@ pub use rng::Rng;
 const PENALTY_COEFF: f64 = 1e6;
 static PENALTY_ENV: OnceLock<f64> = OnceLock::new();
 
-pub mod cpsat;
+/// ソルバー共通の実行オプション
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub max_iter: usize,
+    pub threads: usize,
+    /// DEの島（部分集団）数。0ならスレッド数に合わせる
+    pub islands: usize,
+    pub de: DeParams,
+    pub pso: PsoParams,
+    /// 乱数の種（DEの島 `k` は `seed + k * 7919`、PSOは `seed + PSO_SEED_OFFSET`、焼きなましは `seed`）
+    pub seed: u64,
+    /// 初期集団に入れる点（ウォームスタート）。NaN の座標は乱数初期化のまま
+    pub warm: Vec<Vec<f64>>,
+    /// 反復ごとの最良適合度を記録する場合に指定
+    pub trace: Option<Trace>,
+    /// 求解中に最良値を定期的に通知する場合に指定
+    pub progress: Option<Progress>,
+    /// DEの多様性リスタート（未指定なら行わない）
+    pub restart: Option<Restart>,
+    /// 島モデルDEの移住（未指定なら島は最後まで独立）
+    pub migration: Option<Migration>,
+    /// DE: 島数をスレッド数から決めず（`islands` か `DETERMINISTIC_ISLANDS`）、移住もしない。
+    /// 結果がスレッド数に依存しなくなる
+    pub deterministic: bool,
+    /// DE/PSOの選択で使う評価の平均回数（ノイズのある目的向け。1なら1回だけ評価）
+    pub resample: usize,
+    /// 実行可能な個体が現れないまま所定の世代数が過ぎたら打ち切る（未指定なら行わない）
+    pub feasible_watch: Option<FeasibleWatch>,
+    /// 決定的で重い目的向けの適合度キャッシュ（未指定なら毎回評価）
+    pub fitness_cache: Option<FitnessCache>,
+    /// hybrid: 前段（DE）に割り当てる反復の割合
+    pub hybrid_split: f64,
+    /// hybrid: 最後に最良解から Nelder-Mead で仕上げる
+    pub polish: bool,
+    /// 経過時間の上限（未指定なら反復数だけで止める）
+    pub deadline: Option<Deadline>,
+    /// DE/PSO: 最良値がこの世代数続けて改善しなければ打ち切る（未指定なら行わない）
+    pub patience: Option<usize>,
+    /// DEの置き換えでの制約の扱い
+    pub constraint_mode: ConstraintMode,
+    /// 目的・制約違反が NaN/inf になった評価の回数（複製したオプションとも共有する）
+    non_finite: Arc<AtomicUsize>,
+}
+
+/// 制約の扱い（DEの置き換え・最良解の更新）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConstraintMode {
+    /// 違反量 × ペナルティ係数を目的に足した値で比べる
+    #[default]
+    Penalty,
+    /// Debの規則: 実行可能解は実行不能解に常に勝ち、実行可能どうしは目的、
+    /// 実行不能どうしは違反量の合計で比べる（ペナルティ係数に依存しない）
+    Deb,
+}
+
+impl Options {
+    pub fn new(max_iter: usize, threads: usize) -> Self {
+        Self {
+            max_iter,
+            threads,
+            islands: 0,
+            de: DeParams::default(),
+            pso: PsoParams::default(),
+            seed: DEFAULT_SEED,
+            warm: Vec::new(),
+            trace: None,
+            progress: None,
+            restart: None,
+            migration: None,
+            deterministic: false,
+            resample: 1,
+            feasible_watch: None,
+            fitness_cache: None,
+            hybrid_split: HYBRID_SPLIT,
+            polish: false,
+            deadline: None,
+            patience: None,
+            constraint_mode: ConstraintMode::Penalty,
+            non_finite: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// このオプションで解いた間に NaN/inf を最悪値に置き換えた評価の回数（`-v` で報告する）
+    pub fn non_finite_evaluations(&self) -> usize {
+        self.non_finite.load(Ordering::Relaxed)
+    }
+}
+
+/// DEの多様性リスタート
+///
+/// 世代の終わりに集団の多様性（各次元の標準偏差を変数の範囲で割った値の平均）が
+/// `threshold` を下回ったら、最良個体を除く下位 `fraction` の個体を一様乱数で置き換える。
+/// 発動した反復番号を記録する（島モデルでは全島の分をまとめる）。
+#[derive(Debug, Clone)]
+pub struct Restart {
+    pub fraction: f64,
+    pub threshold: f64,
+    /// 多様性を調べる間隔（世代数。1なら毎世代）
+    pub interval: usize,
+    log: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Restart {
+    pub fn new(fraction: f64, threshold: f64) -> Self {
+        Self {
+            fraction,
+            threshold,
+            interval: 1,
+            log: Arc::default(),
+        }
+    }
+
+    fn record(&self, iter: usize) {
+        self.log.lock().unwrap().push(iter);
+    }
+
+    /// リスタートした反復番号（昇順）
+    pub fn iterations(&self) -> Vec<usize> {
+        let mut v = self.log.lock().unwrap().clone();
+        v.sort_unstable();
+        v
+    }
+
+    /// 多様性が閾値を下回っていれば集団の一部を再初期化する
+    fn apply<F>(
+        &self,
+        iter: usize,
+        pop: &mut Population,
+        rng: &mut Rng,
+        lb: &[f64],
+        ub: &[f64],
+        fitness: F,
+    ) where
+        F: FnMut(&[f64]) -> (f64, f64),
+    {
+        if !(iter + 1).is_multiple_of(self.interval.max(1))
+            || pop.diversity(lb, ub) >= self.threshold
+        {
+            return;
+        }
+        let count = ((pop.size as f64 * self.fraction).ceil() as usize).min(pop.size - 1);
+        if count > 0 {
+            pop.reinitialize_worst(count, rng, lb, ub, fitness);
+            self.record(iter);
+        }
+    }
+}
+
+/// 解と (目的, 違反量)
+type ScoredPoint = (Vec<f64>, (f64, f64));
+
+/// 島モデルDEの移住
+///
+/// 各島は `interval` 世代ごとに島の最良解を全島共通の最良解と比べ、良ければ共通の最良解を
+/// 更新し、悪ければ共通の最良解を最悪の個体と入れ替えて取り込む。同じスレッドの島は順に
+/// 処理されるので、先に回った島の最良解も後の島へ渡る。
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub interval: usize,
+    best: Arc<Mutex<Option<ScoredPoint>>>,
+    adopted: Arc<AtomicUsize>,
+}
+
+impl Migration {
+    pub fn new(interval: usize) -> Self {
+        Self {
+            interval,
+            best: Arc::default(),
+            adopted: Arc::default(),
+        }
+    }
+
+    /// 共通の最良解を取り込んだ回数（全島の合計）
+    pub fn adoptions(&self) -> usize {
+        self.adopted.load(Ordering::Relaxed)
+    }
+
+    /// 島の最良解を出し、共通の最良解の方が良ければそれを返す
+    fn exchange(
+        &self,
+        iter: usize,
+        best: &[f64],
+        score: (f64, f64),
+    ) -> Option<(Vec<f64>, (f64, f64))> {
+        if self.interval == 0 || !(iter + 1).is_multiple_of(self.interval) {
+            return None;
+        }
+        let mut shared = self.best.lock().unwrap();
+        match &*shared {
+            Some((x, s)) if deb_cmp(*s, score).is_lt() => {
+                self.adopted.fetch_add(1, Ordering::Relaxed);
+                Some((x.clone(), *s))
+            }
+            _ => {
+                *shared = Some((best.to_vec(), score));
+                None
+            }
+        }
+    }
+}
+
+/// 実行可能解の出現監視
+///
+/// 各世代の終わりに集団を調べ、一度も実行可能な個体（制約違反なし）が現れないまま
+/// `generations` 世代が過ぎたら打ち切りを指示する。島モデルでは全島で状態を共有する。
+#[derive(Debug, Clone)]
+pub struct FeasibleWatch {
+    pub generations: usize,
+    seen: Arc<AtomicBool>,
+    aborted: Arc<AtomicBool>,
+    offset: usize,
+}
+
+impl FeasibleWatch {
+    pub fn new(generations: usize) -> Self {
+        Self {
+            generations,
+            seen: Arc::default(),
+            aborted: Arc::default(),
+            offset: 0,
+        }
+    }
+
+    /// 実行可能解が見つからず打ち切ったか
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// 状態を共有したまま世代番号を `by` だけずらすハンドル（ハイブリッドの後段用）
+    fn shifted(&self, by: usize) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self.clone()
+        }
+    }
+
+    /// 世代 `iter` の終わりに呼び、打ち切るべきなら true を返す
+    fn check<'a>(
+        &self,
+        iter: usize,
+        model: &Model,
+        mut rows: impl Iterator<Item = &'a [f64]>,
+    ) -> bool {
+        if self.seen.load(Ordering::Relaxed) {
+            return false;
+        }
+        if self.aborted() {
+            return true;
+        }
+        if rows.any(|x| is_feasible(model, x)) {
+            self.seen.store(true, Ordering::Relaxed);
+            return false;
+        }
+        if iter + self.offset + 1 >= self.generations {
+            self.aborted.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
+
+/// 経過時間の上限（`--timeout`）
+///
+/// 各世代の始めに確かめ、過ぎていればその時点の最良解を返す。島モデルでは全島が同じ期限を使う。
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    pub limit: Duration,
+    at: Instant,
+    hit: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    /// 今から `limit` 後を期限にする
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            limit,
+            at: Instant::now() + limit,
+            hit: Arc::default(),
+        }
+    }
+
+    /// 期限までの残り時間（過ぎていれば0）
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// 期限で打ち切ったか
+    pub fn reached(&self) -> bool {
+        self.hit.load(Ordering::Relaxed)
+    }
+
+    /// 外部のソルバー（CP-SAT）が期限で止まったことを記録する
+    #[cfg_attr(not(feature = "cp-sat"), allow(dead_code))]
+    fn mark_reached(&self) {
+        self.hit.store(true, Ordering::Relaxed);
+    }
+
+    fn expired(&self) -> bool {
+        if self.reached() {
+            return true;
+        }
+        if Instant::now() >= self.at {
+            self.hit.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
+
+/// 停滞による打ち切り（`--patience`）
+///
+/// 最良値が直前の基準から `PATIENCE_REL_TOL` の相対量以上改善しない世代を数える。
+/// 島モデルでは島ごとに数える。
+struct Stagnation {
+    patience: usize,
+    reference: f64,
+    stalled: usize,
+}
+
+impl Stagnation {
+    fn new(patience: usize, fit: f64) -> Self {
+        Self {
+            patience,
+            reference: fit,
+            stalled: 0,
+        }
+    }
+
+    /// 世代の終わりの最良値を渡し、打ち切るべきなら true を返す
+    fn stalled(&mut self, fit: f64) -> bool {
+        if fit < self.reference - PATIENCE_REL_TOL * self.reference.abs().max(1.0) {
+            self.reference = fit;
+            self.stalled = 0;
+            return false;
+        }
+        self.stalled += 1;
+        self.stalled >= self.patience
+    }
+}
+
+/// 期限が過ぎたか（期限の指定が無ければ false）
+fn out_of_time(opts: &Options) -> bool {
+    opts.deadline.as_ref().is_some_and(|d| d.expired())
+}
+
+/// 適合度のLRUキャッシュ
+///
+/// 座標を `quantum` 刻みに量子化した点（整数型の座標は丸めた値）をキーにする。
+/// DEで交叉が親の座標をすべて残した場合など、同じ点を何度も評価するときに効く。
+/// 全スレッドで共有する。
+#[derive(Debug, Clone)]
+pub struct FitnessCache {
+    capacity: usize,
+    quantum: f64,
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    /// キー -> (適合度, 最終参照時刻)
+    map: HashMap<Vec<i64>, (f64, u64)>,
+    /// 参照順（古い順）。参照し直した分は古い時刻のまま残り、追い出し時に読み飛ばす
+    order: VecDeque<(Vec<i64>, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl FitnessCache {
+    pub fn new(capacity: usize, quantum: f64) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            quantum,
+            inner: Arc::default(),
+        }
+    }
+
+    /// (ヒット数, 参照数)
+    pub fn stats(&self) -> (u64, u64) {
+        let c = self.inner.lock().unwrap();
+        (c.hits, c.hits + c.misses)
+    }
+
+    fn get_or_eval<F>(&self, model: &Model, x: &[f64], eval: F) -> f64
+    where
+        F: FnOnce() -> f64,
+    {
+        let key: Vec<i64> = x
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let v = if model.var_type(i).is_integral() {
+                    v.round()
+                } else {
+                    v
+                };
+                (v / self.quantum).round() as i64
+            })
+            .collect();
+        {
+            let mut c = self.inner.lock().unwrap();
+            c.tick += 1;
+            let tick = c.tick;
+            if let Some(entry) = c.map.get_mut(&key) {
+                entry.1 = tick;
+                let f = entry.0;
+                c.hits += 1;
+                c.order.push_back((key, tick));
+                return f;
+            }
+            c.misses += 1;
+        }
+        // 評価はロックの外で行う（同じ点を並行して評価することはあり得るが結果は同じ）
+        let f = eval();
+        self.inner.lock().unwrap().insert(key, f, self.capacity);
+        f
+    }
+}
+
+impl CacheInner {
+    fn insert(&mut self, key: Vec<i64>, f: f64, capacity: usize) {
+        self.tick += 1;
+        let tick = self.tick;
+        self.map.insert(key.clone(), (f, tick));
+        self.order.push_back((key, tick));
+        while self.map.len() > capacity {
+            let Some((old, t)) = self.order.pop_front() else {
+                break;
+            };
+            if self.map.get(&old).is_some_and(|e| e.1 == t) {
+                self.map.remove(&old);
+            }
+        }
+        // 読み飛ばす古い参照が溜まりすぎたら詰め直す
+        if self.order.len() > 2 * capacity {
+            let map = &self.map;
+            self.order
+                .retain(|(k, t)| map.get(k).is_some_and(|e| e.1 == *t));
+        }
+    }
+}
+
+/// 反復ごとの最良適合度（最小化方向）の記録
+///
+/// 複数の島が同じ反復番号を記録した場合は小さい方を残す。
+/// `offset` はハイブリッドの後段など、反復番号をずらして記録するために使う。
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    values: Arc<Mutex<Vec<f64>>>,
+    offset: usize,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, iter: usize, fit: f64) {
+        let idx = iter + self.offset;
+        let mut v = self.values.lock().unwrap();
+        if v.len() <= idx {
+            v.resize(idx + 1, f64::INFINITY);
+        }
+        v[idx] = v[idx].min(fit);
+    }
+
+    /// 同じ記録先に反復番号を `by` だけずらして書き込むハンドル
+    fn shifted(&self, by: usize) -> Self {
+        Self {
+            values: Arc::clone(&self.values),
+            offset: self.offset + by,
+        }
+    }
+
+    /// これまでの最良値の推移（各反復までの累積最小値）
+    pub fn values(&self) -> Vec<f64> {
+        let mut best = f64::INFINITY;
+        self.values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|&f| {
+                best = best.min(f);
+                best
+            })
+            .collect()
+    }
+}
+
+/// 求解中の進捗通知（`--stream-json`）
+///
+/// 各反復の終わりに最良点を受け取り、`every` 反復ごとに全島を通じた最良の
+/// (反復番号（1始まり）, 適合度, 実行可能か) をコールバックへ渡す。
+/// コールバックはロックの中で呼ぶので、通知は反復番号の順に並ぶ。
+#[derive(Clone)]
+pub struct Progress {
+    every: usize,
+    offset: usize,
+    state: Arc<Mutex<ProgressState>>,
+    callback: Arc<dyn Fn(usize, f64, bool) + Send + Sync>,
+}
+
+struct ProgressState {
+    best_fit: f64,
+    feasible: bool,
+    /// 次に通知する反復番号（1始まり）
+    next: usize,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("every", &self.every)
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Progress {
+    pub fn new<F>(every: usize, callback: F) -> Self
+    where
+        F: Fn(usize, f64, bool) + Send + Sync + 'static,
+    {
+        let every = every.max(1);
+        Self {
+            every,
+            offset: 0,
+            state: Arc::new(Mutex::new(ProgressState {
+                best_fit: f64::INFINITY,
+                feasible: false,
+                next: every,
+            })),
+            callback: Arc::new(callback),
+        }
+    }
+
+    fn record(&self, iter: usize, model: &Model, fit: f64, x: &[f64]) {
+        let done = iter + self.offset + 1;
+        let mut s = self.state.lock().unwrap();
+        if fit < s.best_fit {
+            s.best_fit = fit;
+            s.feasible = is_feasible(model, x);
+        }
+        if done >= s.next {
+            s.next = (done / self.every + 1) * self.every;
+            (self.callback)(done, s.best_fit, s.feasible);
+        }
+    }
+
+    /// 同じ通知先に反復番号を `by` だけずらして通知するハンドル
+    fn shifted(&self, by: usize) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self.clone()
+        }
+    }
+}
+
+/// DEの集団サイズ・差分の重み `f`・交叉率 `cr`（島モデルでは集団を島数で分ける）
+#[derive(Debug, Clone, Copy)]
+pub struct DeParams {
+    pub pop_size: usize,
+    pub f: f64,
+    pub cr: f64,
+    pub strategy: DeStrategy,
+}
+
+impl Default for DeParams {
+    fn default() -> Self {
+        Self {
+            pop_size: POP_SIZE,
+            f: DE_F,
+            cr: DE_CR,
+            strategy: DeStrategy::Classic,
+        }
+    }
+}
+
+/// DEの `f` / `cr` の決め方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeStrategy {
+    /// 全個体で固定の `f` / `cr`
+    #[default]
+    Classic,
+    /// jDE: 個体ごとの `f` / `cr` を確率 `JDE_TAU` で引き直し、試行が置き換えに成功したら引き継ぐ
+    /// （初期値は `f` / `cr`）
+    Jde,
+    /// SHADE: 成功した `f` / `cr` の履歴から試行ごとに引き、current-to-pbest/1 で変異する
+    /// （差分の片方は置き換えられた個体のアーカイブからも選ぶ。履歴の初期値は `f` / `cr`）
+    Shade,
+}
+
+/// PSOの粒子数と慣性・加速係数（慣性は毎反復 `w_decay` 倍し `w_min` で下げ止まる）
+#[derive(Debug, Clone, Copy)]
+pub struct PsoParams {
+    pub particles: usize,
+    pub w_init: f64,
+    pub w_min: f64,
+    pub w_decay: f64,
+    pub c1: f64,
+    pub c2: f64,
+}
+
+impl Default for PsoParams {
+    fn default() -> Self {
+        Self {
+            particles: N_PARTICLES,
+            w_init: PSO_W_INIT,
+            w_min: PSO_W_MIN,
+            w_decay: PSO_W_DECAY,
+            c1: PSO_C1,
+            c2: PSO_C2,
+        }
+    }
+}
 
 // =============================================================================
 // 差分進化（DE）
 // =============================================================================
 
 /// DE最適化（モデルを考慮）
-pub fn de(model: &Model, max_iter: usize, threads: usize) -> (Vec<f64>, f64, usize) {
-    if !model.cp_globals.is_empty() {
-        if let Some(res) = solve_cp(model) {
-            return res;
-        }
-    }
+pub fn de(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
     let dim = model.dim;
+    let max_iter = opts.max_iter;
+    let threads = opts.threads.max(1);
+
+    // 島数の明示指定がなければスレッド数に合わせ（決定的モードでは固定）、小さな問題は単一集団で解く
+    let islands = if opts.islands > 0 {
+        opts.islands
+    } else if dim < PARALLEL_MIN_DIM || max_iter < PARALLEL_MIN_ITER {
+        1
+    } else if opts.deterministic {
+        DETERMINISTIC_ISLANDS
+    } else if threads <= 1 {
+        1
+    } else {
+        threads
+    };
+
+    // 移住の順序はスレッドの進み具合で変わるので、決定的モードでは行わない
+    let unshared;
+    let opts = if opts.deterministic && opts.migration.is_some() {
+        unshared = Options {
+            migration: None,
+            ..opts.clone()
+        };
+        &unshared
+    } else {
+        opts
+    };
 
-    if threads <= 1 || dim < PARALLEL_MIN_DIM || max_iter < PARALLEL_MIN_ITER {
-        de_single(model, max_iter)
+    if islands <= 1 {
+        de_island(model, opts, opts.de.pop_size, opts.seed, &opts.warm)
     } else {
-        de_parallel(model, max_iter, threads)
+        de_parallel(model, opts, islands, threads.min(islands))
     }
 }
 
-fn de_single(model: &Model, max_iter: usize) -> (Vec<f64>, f64, usize) {
+/// jDE（個体ごとに `f` / `cr` を自己適応するDE）。島モデル・リスタート等は `de` と同じ
+pub fn jde(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let mut opts = opts.clone();
+    opts.de.strategy = DeStrategy::Jde;
+    de(model, &opts)
+}
+
+/// SHADE（成功履歴に基づくパラメータ適応DE）。島モデル・リスタート等は `de` と同じ
+pub fn shade(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let mut opts = opts.clone();
+    opts.de.strategy = DeStrategy::Shade;
+    de(model, &opts)
+}
+
+/// 島モデルDE: `islands` 個の部分集団を `threads` 本のスレッドに割り当てて解く
+///
+/// 島 `k` はスレッド `k % threads` が順に処理する。乱数の種は島番号から決まるので、
+/// 各島の結果はスレッド数に依存しない。
+fn de_parallel(
+    model: &Model,
+    opts: &Options,
+    islands: usize,
+    threads: usize,
+) -> (Vec<f64>, f64, usize) {
+    let model = Arc::new(model.clone());
+    let opts = Arc::new(opts.clone());
+    let sub_pop = (opts.de.pop_size / islands).max(10);
+
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let model = Arc::clone(&model);
+            let opts = Arc::clone(&opts);
+            thread::spawn(move || {
+                (t..islands)
+                    .step_by(threads)
+                    .map(|k| {
+                        let seed = opts.seed.wrapping_add(k as u64 * 7919);
+                        // 初期点は島に順番に配る
+                        let mine: Vec<Vec<f64>> =
+                            opts.warm.iter().skip(k).step_by(islands).cloned().collect();
+                        (k, de_island(&model, &opts, sub_pop, seed, &mine))
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    // 結果集約（反復数は最も進んだ島のもの。期限や停滞で打ち切った島は途中で止まっている）。
+    // 同じ適合度の島が複数あれば番号の小さい島を選ぶよう、島の順に並べてから比べる
+    let mut results: Vec<_> = handles
+        .into_iter()
+        .flat_map(|h| h.join().unwrap())
+        .collect();
+    results.sort_by_key(|r| r.0);
+    let results: Vec<_> = results.into_iter().map(|(_, r)| r).collect();
+    let iters = results.iter().map(|r| r.2).max().unwrap_or(0);
+    results
+        .into_iter()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(best, fit, _)| (best, fit, iters))
+        .unwrap()
+}
+
+/// 1つの集団（単一集団のDE、または島モデルの1島）でDEを回す
+fn de_island(
+    model: &Model,
+    opts: &Options,
+    sub_pop: usize,
+    seed: u64,
+    warm: &[Vec<f64>],
+) -> (Vec<f64>, f64, usize) {
     let dim = model.dim;
-    let lb = &model.lb;
-    let ub = &model.ub;
-    let mut rng = Rng::new(12345);
+    let (lb, ub) = (&model.lb, &model.ub);
+    let mut rng = Rng::new(seed);
 
     // 集団初期化
-    let mut pop = Population::new(dim, POP_SIZE);
-    pop.initialize(&mut rng, lb, ub, |cand| compute_fitness(model, cand));
+    let mut pop = Population::new(dim, sub_pop, &opts.de);
+    pop.initialize(&mut rng, lb, ub, |cand| de_score(model, opts, cand));
+    pop.inject(warm, lb, ub, |cand| de_score(model, opts, cand));
 
-    // 最良解
+    // 最良解（best_fit は報告用のペナルティ込みの値）
     let mut best = pop.find_best();
-    let mut best_fit = compute_fitness(model, &best);
+    let mut best_score = de_score(model, opts, &best);
+    let mut best_fit = score_fitness(best_score);
+    let mut stagnation = opts.patience.map(|p| Stagnation::new(p, best_fit));
 
     // 作業用バッファ
     let mut trial = vec![0.0; dim];
     let mut rnd_cr = vec![0.0; dim];
+    let mut shade = (opts.de.strategy == DeStrategy::Shade).then(|| Shade::new(&opts.de, sub_pop));
 
     // メインループ
-    for iter in 0..max_iter {
-        for i in 0..POP_SIZE {
+    for iter in 0..opts.max_iter {
+        if out_of_time(opts) {
+            return (best, best_fit, iter);
+        }
+        if let Some(sh) = &mut shade {
+            sh.rank(&pop);
+        }
+        for i in 0..sub_pop {
             // 親選択
             let (r1, r2) = pop.select_parents(&mut rng, i);
             let j_rand = rng.usize(dim);
@@ -86,94 +842,103 @@ fn de_single(model: &Model, max_iter: usize) -> (Vec<f64>, f64, usize) {
             // 一括乱数生成
             rng.fill_f64(&mut rnd_cr);
 
+            // この試行で使う f / cr（jDE は置き換えに成功した場合だけ個体に残す）
+            let (f, cr) = match (&shade, opts.de.strategy) {
+                (Some(sh), _) => sh.params(&mut rng),
+                (None, DeStrategy::Jde) => jde_params(&mut rng, pop.f[i], pop.cr[i]),
+                _ => (pop.f[i], pop.cr[i]),
+            };
+
             // 変異 + 交叉
-            de_crossover(&pop, i, r1, r2, j_rand, &best, &rnd_cr, lb, ub, &mut trial);
+            if let Some(sh) = &shade {
+                sh.crossover(
+                    &pop, &mut rng, f, cr, i, r1, j_rand, &rnd_cr, lb, ub, &mut trial,
+                );
+            } else {
+                de_crossover(
+                    &pop, f, cr, i, r1, r2, j_rand, &best, &rnd_cr, lb, ub, &mut trial,
+                );
+            }
 
             // 評価 + 選択
-            let trial_fit = compute_fitness(model, &trial);
-            if trial_fit <= pop.fit[i] {
-                pop.update(i, &trial, trial_fit);
+            let trial_score = de_score(model, opts, &trial);
+            if deb_cmp(trial_score, pop.fit[i]).is_le() {
+                if let Some(sh) = &mut shade {
+                    sh.record(&pop, &mut rng, i, f, cr, trial_score);
+                }
+                pop.update(i, &trial, trial_score);
+                pop.f[i] = f;
+                pop.cr[i] = cr;
 
-                if trial_fit < best_fit {
-                    best_fit = trial_fit;
+                if deb_cmp(trial_score, best_score).is_lt() {
+                    best_score = trial_score;
+                    best_fit = score_fitness(trial_score);
                     best.copy_from_slice(&trial);
 
-                    if best_fit < TOLERANCE {
+                    if best_fit.abs() < TOLERANCE {
+                        if let Some(t) = &opts.trace {
+                            t.record(iter, best_fit);
+                        }
                         return (best, best_fit, iter + 1);
                     }
                 }
             }
         }
+        if let Some(sh) = &mut shade {
+            sh.update_memory();
+        }
+        if let Some(t) = &opts.trace {
+            t.record(iter, best_fit);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(iter, model, best_fit, &best);
+        }
+        if let Some(w) = &opts.feasible_watch {
+            if w.check(iter, model, pop.rows()) {
+                return (best, best_fit, iter + 1);
+            }
+        }
+        if stagnation.as_mut().is_some_and(|s| s.stalled(best_fit)) {
+            return (best, best_fit, iter + 1);
+        }
+        if let Some(r) = &opts.restart {
+            r.apply(iter, &mut pop, &mut rng, lb, ub, |cand| {
+                de_score(model, opts, cand)
+            });
+        }
+        if let Some((x, score)) = opts
+            .migration
+            .as_ref()
+            .and_then(|m| m.exchange(iter, &best, best_score))
+        {
+            pop.replace_worst(&x, score);
+            best.copy_from_slice(&x);
+            best_score = score;
+            best_fit = score_fitness(score);
+        }
     }
 
-    (best, best_fit, max_iter)
+    (best, best_fit, opts.max_iter)
 }
 
-fn de_parallel(model: &Model, max_iter: usize, threads: usize) -> (Vec<f64>, f64, usize) {
-    let dim = model.dim;
-    let lb = model.lb.clone();
-    let ub = model.ub.clone();
-    let model = Arc::new(model.clone());
-
-    let handles: Vec<_> = (0..threads)
-        .map(|t| {
-            let lb = lb.clone();
-            let ub = ub.clone();
-            let model = Arc::clone(&model);
-            thread::spawn(move || {
-                let mut rng = Rng::new(12345 + t as u64 * 7919);
-                let sub_pop = (POP_SIZE / threads).max(10);
-
-                let mut pop = Population::new(dim, sub_pop);
-                pop.initialize(&mut rng, &lb, &ub, |cand| compute_fitness(&model, cand));
-
-                let mut best = pop.find_best();
-                let mut best_fit = compute_fitness(&model, &best);
-                let mut trial = vec![0.0; dim];
-                let mut rnd_cr = vec![0.0; dim];
-
-                for _iter in 0..max_iter {
-                    for i in 0..sub_pop {
-                        let (r1, r2) = pop.select_parents(&mut rng, i);
-                        let j_rand = rng.usize(dim);
-                        rng.fill_f64(&mut rnd_cr);
-
-                        de_crossover(
-                            &pop, i, r1, r2, j_rand, &best, &rnd_cr, &lb, &ub, &mut trial,
-                        );
-
-                        let trial_fit = compute_fitness(&model, &trial);
-                        if trial_fit <= pop.fit[i] {
-                            pop.update(i, &trial, trial_fit);
-                            if trial_fit < best_fit {
-                                best_fit = trial_fit;
-                                best.copy_from_slice(&trial);
-                                if best_fit < TOLERANCE {
-                                    return (best, best_fit);
-                                }
-                            }
-                        }
-                    }
-                }
-
-                (best, best_fit)
-            })
-        })
-        .collect();
-
-    // 結果集約
-    handles
-        .into_iter()
-        .map(|h| h.join().unwrap())
-        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-        .map(|(best, fit)| (best, fit, max_iter))
-        .unwrap()
+/// jDEの `f` / `cr` の引き直し: それぞれ確率 `JDE_TAU` で
+/// `f` は [`JDE_F_MIN`, `JDE_F_MAX`)、`cr` は [0, 1) の一様乱数にする
+fn jde_params(rng: &mut Rng, f: f64, cr: f64) -> (f64, f64) {
+    let f = if rng.f64() < JDE_TAU {
+        JDE_F_MIN + rng.f64() * (JDE_F_MAX - JDE_F_MIN)
+    } else {
+        f
+    };
+    let cr = if rng.f64() < JDE_TAU { rng.f64() } else { cr };
+    (f, cr)
 }
 
 #[inline(always)]
 #[allow(clippy::too_many_arguments)]
 fn de_crossover(
     pop: &Population,
+    f: f64,
+    cr: f64,
     i: usize,
     r1: usize,
     r2: usize,
@@ -191,15 +956,15 @@ fn de_crossover(
 
     // j_randを先に処理（分岐予測最適化）
     if j_rand < dim {
-        let v = best[j_rand] + DE_F * (pop.data[pop_r1 + j_rand] - pop.data[pop_r2 + j_rand]);
+        let v = best[j_rand] + f * (pop.data[pop_r1 + j_rand] - pop.data[pop_r2 + j_rand]);
         trial[j_rand] = v.clamp(lb[j_rand], ub[j_rand]);
     }
 
     // 残りを一括処理
     for j in 0..dim {
         if j != j_rand {
-            trial[j] = if rnd_cr[j] < DE_CR {
-                let v = best[j] + DE_F * (pop.data[pop_r1 + j] - pop.data[pop_r2 + j]);
+            trial[j] = if rnd_cr[j] < cr {
+                let v = best[j] + f * (pop.data[pop_r1 + j] - pop.data[pop_r2 + j]);
                 v.clamp(lb[j], ub[j])
             } else {
                 pop.data[pop_i + j]
@@ -213,30 +978,30 @@ fn de_crossover(
 // =============================================================================
 
 /// PSO最適化
-pub fn pso(model: &Model, max_iter: usize) -> (Vec<f64>, f64, usize) {
-    if !model.cp_globals.is_empty() {
-        if let Some(res) = solve_cp(model) {
-            return res;
-        }
-    }
+pub fn pso(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let max_iter = opts.max_iter;
     let dim = model.dim;
     let lb = &model.lb;
     let ub = &model.ub;
-    let mut rng = Rng::new(67890);
+    let mut rng = Rng::new(opts.seed.wrapping_add(PSO_SEED_OFFSET));
 
     // v_max
     let v_max: Vec<f64> = lb.iter().zip(ub).map(|(l, u)| (u - l) * 0.5).collect();
 
     // 初期化
-    let mut swarm = Swarm::new(dim, N_PARTICLES);
+    let n_particles = opts.pso.particles;
+    let mut swarm = Swarm::new(dim, n_particles);
     swarm.initialize(&mut rng, lb, ub);
-    swarm.pbest_fit = (0..N_PARTICLES)
-        .map(|i| compute_fitness(model, &swarm.pos[i * dim..(i + 1) * dim]))
+    swarm.inject(&opts.warm, lb, ub);
+    swarm.pbest_fit = (0..n_particles)
+        .map(|i| sampled_fitness(model, opts, &swarm.pos[i * dim..(i + 1) * dim]))
         .collect();
 
-    let mut gbest = swarm.find_global_best(|cand| compute_fitness(model, cand));
-    let mut gbest_fit = compute_fitness(model, &gbest);
-    let mut w = PSO_W_INIT;
+    let mut gbest = swarm.find_global_best(|cand| sampled_fitness(model, opts, cand));
+    let mut gbest_fit = sampled_fitness(model, opts, &gbest);
+    let mut stagnation = opts.patience.map(|p| Stagnation::new(p, gbest_fit));
+    let pp = &opts.pso;
+    let mut w = pp.w_init;
 
     // 作業用バッファ
     let mut r1_buf = vec![0.0; dim];
@@ -244,7 +1009,10 @@ pub fn pso(model: &Model, max_iter: usize) -> (Vec<f64>, f64, usize) {
 
     // メインループ
     for iter in 0..max_iter {
-        for i in 0..N_PARTICLES {
+        if out_of_time(opts) {
+            return (gbest, gbest_fit, iter);
+        }
+        for i in 0..n_particles {
             let offset = i * dim;
 
             // 一括乱数生成
@@ -253,69 +1021,1015 @@ pub fn pso(model: &Model, max_iter: usize) -> (Vec<f64>, f64, usize) {
 
             // 速度・位置更新
             pso_update_velocity_position(
-                &mut swarm, i, offset, &gbest, &v_max, lb, ub, w, &r1_buf, &r2_buf,
+                &mut swarm, pp, offset, &gbest, &v_max, lb, ub, w, &r1_buf, &r2_buf,
             );
 
-            // 評価
-            let fit = compute_fitness(model, &swarm.pos[offset..offset + dim]);
+            // 評価
+            let fit = sampled_fitness(model, opts, &swarm.pos[offset..offset + dim]);
+
+            // pbest更新
+            if fit < swarm.pbest_fit[i] {
+                swarm.pbest[offset..offset + dim].copy_from_slice(&swarm.pos[offset..offset + dim]);
+                swarm.pbest_fit[i] = fit;
+
+                // gbest更新
+                if fit < gbest_fit {
+                    gbest_fit = fit;
+                    gbest.copy_from_slice(&swarm.pos[offset..offset + dim]);
+
+                    if gbest_fit.abs() < TOLERANCE {
+                        if let Some(t) = &opts.trace {
+                            t.record(iter, gbest_fit);
+                        }
+                        return (gbest, gbest_fit, iter + 1);
+                    }
+                }
+            }
+        }
+        if let Some(t) = &opts.trace {
+            t.record(iter, gbest_fit);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(iter, model, gbest_fit, &gbest);
+        }
+        if let Some(fw) = &opts.feasible_watch {
+            if fw.check(iter, model, swarm.pbest.chunks(dim)) {
+                return (gbest, gbest_fit, iter + 1);
+            }
+        }
+        if stagnation.as_mut().is_some_and(|s| s.stalled(gbest_fit)) {
+            return (gbest, gbest_fit, iter + 1);
+        }
+
+        w = (w * pp.w_decay).max(pp.w_min);
+    }
+
+    (gbest, gbest_fit, max_iter)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+fn pso_update_velocity_position(
+    swarm: &mut Swarm,
+    pp: &PsoParams,
+    offset: usize,
+    gbest: &[f64],
+    v_max: &[f64],
+    lb: &[f64],
+    ub: &[f64],
+    w: f64,
+    r1: &[f64],
+    r2: &[f64],
+) {
+    let dim = lb.len();
+
+    for j in 0..dim {
+        let mut v = w * swarm.vel[offset + j]
+            + pp.c1 * r1[j] * (swarm.pbest[offset + j] - swarm.pos[offset + j])
+            + pp.c2 * r2[j] * (gbest[j] - swarm.pos[offset + j]);
+
+        v = v.clamp(-v_max[j], v_max[j]);
+        swarm.vel[offset + j] = v;
+
+        let mut p = swarm.pos[offset + j] + v;
+        if p < lb[j] {
+            p = lb[j];
+            swarm.vel[offset + j] = 0.0;
+        }
+        if p > ub[j] {
+            p = ub[j];
+            swarm.vel[offset + j] = 0.0;
+        }
+        swarm.pos[offset + j] = p;
+    }
+}
+
+// =============================================================================
+// 座標降下（分離可能な目的関数向け）
+// =============================================================================
+
+/// 座標降下: 1変数ずつ黄金分割探索で最適化する
+///
+/// 目的関数が変数ごとの項の和に分解できる場合（`Model::is_separable`）に有効。
+/// 各座標は格子走査で最良点を挟む区間を決めてから黄金分割で絞り込む。
+/// 1スイープで改善がなくなった時点で終了する。
+pub fn coordinate_descent(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let max_iter = opts.max_iter;
+    let dim = model.dim;
+    let mut x: Vec<f64> = (0..dim)
+        .map(|j| 0.5 * (model.lb[j] + model.ub[j]))
+        .collect();
+    if let Some(w) = opts.warm.first() {
+        overwrite_point(&mut x, w, &model.lb, &model.ub);
+    }
+    let mut best_fit = cached_fitness(model, opts, &x);
+
+    for sweep in 0..max_iter.max(1) {
+        if out_of_time(opts) {
+            return (x, best_fit, sweep);
+        }
+        let prev_fit = best_fit;
+        for j in 0..dim {
+            best_fit = coord_line_search(model, opts, &mut x, j, best_fit);
+            // 1周が重い高次元の問題では座標ごとにも期限を確かめる
+            if out_of_time(opts) {
+                break;
+            }
+        }
+        if let Some(t) = &opts.trace {
+            t.record(sweep, best_fit);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(sweep, model, best_fit, &x);
+        }
+        if best_fit.abs() < TOLERANCE || prev_fit - best_fit <= TOLERANCE * prev_fit.abs().max(1.0)
+        {
+            return (x, best_fit, sweep + 1);
+        }
+    }
+
+    (x, best_fit, max_iter)
+}
+
+/// 座標 `j` について直線探索し、改善した場合は `x[j]` を更新して新しい適合度を返す
+fn coord_line_search(model: &Model, opts: &Options, x: &mut [f64], j: usize, cur_fit: f64) -> f64 {
+    let (lb, ub) = (model.lb[j], model.ub[j]);
+    if ub <= lb {
+        return cur_fit;
+    }
+    let orig = x[j];
+    let eval = |v: f64, x: &mut [f64]| {
+        x[j] = v;
+        cached_fitness(model, opts, x)
+    };
+
+    // 格子走査で最良点を探し、その両隣を初期区間にする
+    let step = (ub - lb) / COORD_GRID as f64;
+    let mut best_k = 0;
+    let mut best_grid = f64::MAX;
+    for k in 0..=COORD_GRID {
+        let f = eval(lb + step * k as f64, x);
+        if f < best_grid {
+            best_grid = f;
+            best_k = k;
+        }
+    }
+    let mut a = lb + step * best_k.saturating_sub(1) as f64;
+    let mut b = (lb + step * (best_k + 1) as f64).min(ub);
+
+    // 黄金分割
+    let inv_phi = 0.5 * (5f64.sqrt() - 1.0);
+    let mut c = b - inv_phi * (b - a);
+    let mut d = a + inv_phi * (b - a);
+    let mut fc = eval(c, x);
+    let mut fd = eval(d, x);
+    for _ in 0..COORD_GS_ITERS {
+        if fc < fd {
+            b = d;
+            d = c;
+            fd = fc;
+            c = b - inv_phi * (b - a);
+            fc = eval(c, x);
+        } else {
+            a = c;
+            c = d;
+            fc = fd;
+            d = a + inv_phi * (b - a);
+            fd = eval(d, x);
+        }
+    }
+
+    // 候補の中で最良のものを採用（元の値より悪ければ戻す）
+    let mut cand = [
+        (lb + step * best_k as f64, best_grid),
+        (c, fc),
+        (d, fd),
+        (orig, cur_fit),
+    ];
+    cand.sort_by(|p, q| p.1.partial_cmp(&q.1).unwrap_or(std::cmp::Ordering::Equal));
+    x[j] = cand[0].0;
+    cand[0].1
+}
+
+// =============================================================================
+// 焼きなまし法（SA）
+// =============================================================================
+
+/// 焼きなまし法: 1点を正規分布の近傍へ動かし、Metropolis基準で受理する
+///
+/// 1反復 = 1温度で `SA_MOVES` 回提案し、温度は幾何的に下げて `max_iter` 反復で
+/// 初期温度の `SA_T_FINAL` 倍にする。初期温度は始点からの試行移動での適合度の
+/// 変化の平均。近傍の幅は反復が進むほど狭める。
+pub fn sa(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let dim = model.dim;
+    let (lb, ub) = (&model.lb, &model.ub);
+    let max_iter = opts.max_iter.max(1);
+    let mut rng = Rng::new(opts.seed);
+
+    let mut x: Vec<f64> = (0..dim)
+        .map(|j| lb[j] + rng.f64() * (ub[j] - lb[j]))
+        .collect();
+    if let Some(w) = opts.warm.first() {
+        overwrite_point(&mut x, w, lb, ub);
+    }
+    let mut fit = sampled_fitness(model, opts, &x);
+    let mut best = x.clone();
+    let mut best_fit = fit;
+    let mut stagnation = opts.patience.map(|p| Stagnation::new(p, best_fit));
+
+    // 全座標を同時に動かすので、幅は次元の平方根で割る
+    let width: Vec<f64> = (0..dim)
+        .map(|j| SA_STEP * (ub[j] - lb[j]) / (dim.max(1) as f64).sqrt())
+        .collect();
+    let mut cand = vec![0.0; dim];
+    let propose = |rng: &mut Rng, x: &[f64], cand: &mut [f64], scale: f64| {
+        for j in 0..dim {
+            cand[j] = (x[j] + scale * width[j] * rng.normal()).clamp(lb[j], ub[j]);
+        }
+    };
+
+    let mut t0 = 0.0;
+    for _ in 0..SA_INIT_SAMPLES {
+        propose(&mut rng, &x, &mut cand, 1.0);
+        t0 += (sampled_fitness(model, opts, &cand) - fit).abs();
+    }
+    t0 /= SA_INIT_SAMPLES as f64;
+    if !t0.is_finite() || t0 <= 0.0 {
+        t0 = 1.0;
+    }
+    let cooling = SA_T_FINAL.powf(1.0 / max_iter as f64);
+    let mut t = t0;
+
+    for iter in 0..max_iter {
+        if out_of_time(opts) {
+            return (best, best_fit, iter);
+        }
+        let scale = (1.0 - iter as f64 / max_iter as f64).max(0.01);
+        for _ in 0..SA_MOVES {
+            propose(&mut rng, &x, &mut cand, scale);
+            let cand_fit = sampled_fitness(model, opts, &cand);
+            let delta = cand_fit - fit;
+            if delta <= 0.0 || rng.f64() < (-delta / t).exp() {
+                x.copy_from_slice(&cand);
+                fit = cand_fit;
+                if fit < best_fit {
+                    best_fit = fit;
+                    best.copy_from_slice(&x);
+                }
+            }
+        }
+        t *= cooling;
+
+        if let Some(tr) = &opts.trace {
+            tr.record(iter, best_fit);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(iter, model, best_fit, &best);
+        }
+        if best_fit.abs() < TOLERANCE {
+            return (best, best_fit, iter + 1);
+        }
+        if stagnation.as_mut().is_some_and(|s| s.stalled(best_fit)) {
+            return (best, best_fit, iter + 1);
+        }
+    }
+
+    (best, best_fit, max_iter)
+}
+
+// =============================================================================
+// CMA-ES（滑らかな低次元問題向け）
+// =============================================================================
+
+/// CMA-ES: 多変量正規分布から標本を引き、平均・共分散（rank-1 + rank-μ 更新）・
+/// ステップ幅（累積経路長制御）を適応させる
+///
+/// 変数の範囲を [0, 1] に正規化した空間で動かし、標本は範囲にクランプしてから評価する。
+/// 1反復 = 1世代（λ = 4 + ⌊3 ln n⌋ 個体）。`dim > CMAES_MAX_DIM` ではDEで解く。
+pub fn cmaes(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let n = model.dim;
+    if n == 0 || n > CMAES_MAX_DIM {
+        return de(model, opts);
+    }
+    let (lb, ub) = (&model.lb, &model.ub);
+    let to_x = |y: &[f64], x: &mut [f64]| {
+        for j in 0..n {
+            x[j] = lb[j] + y[j].clamp(0.0, 1.0) * (ub[j] - lb[j]);
+        }
+    };
+    let mut rng = Rng::new(opts.seed);
+
+    // 戦略パラメータ（Hansen の既定値）
+    let nf = n as f64;
+    let lambda = 4 + (3.0 * nf.ln()) as usize;
+    let mu = lambda / 2;
+    let raw: Vec<f64> = (0..mu)
+        .map(|i| (mu as f64 + 0.5).ln() - ((i + 1) as f64).ln())
+        .collect();
+    let wsum: f64 = raw.iter().sum();
+    let weights: Vec<f64> = raw.iter().map(|w| w / wsum).collect();
+    let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+    let cc = (4.0 + mueff / nf) / (nf + 4.0 + 2.0 * mueff / nf);
+    let cs = (mueff + 2.0) / (nf + mueff + 5.0);
+    let c1 = 2.0 / ((nf + 1.3).powi(2) + mueff);
+    let cmu = (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) / ((nf + 2.0).powi(2) + mueff));
+    let damps = 1.0 + 2.0 * (((mueff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+    let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+    let eigen_every = ((lambda as f64 / (c1 + cmu) / nf / 10.0) as usize).max(1);
+
+    // 平均は範囲の中央（初期点があればそこ）から始める
+    let mut mean = vec![0.5; n];
+    if let Some(w) = opts.warm.first() {
+        let mut x = vec![0.0; n];
+        to_x(&mean, &mut x);
+        overwrite_point(&mut x, w, lb, ub);
+        for j in 0..n {
+            let width = ub[j] - lb[j];
+            mean[j] = if width > 0.0 {
+                (x[j] - lb[j]) / width
+            } else {
+                0.5
+            };
+        }
+    }
+    let mut sigma = CMAES_SIGMA0;
+    let mut cov = Matrix::identity(n);
+    let mut basis = Matrix::identity(n);
+    let mut scale = vec![1.0; n]; // 共分散の固有値の平方根
+    let mut ps = vec![0.0; n];
+    let mut pc = vec![0.0; n];
+
+    let mut best = vec![0.0; n];
+    to_x(&mean, &mut best);
+    let mut best_fit = sampled_fitness(model, opts, &best);
+    let mut stagnation = opts.patience.map(|p| Stagnation::new(p, best_fit));
+
+    // 作業用バッファ
+    let mut ys = vec![0.0; lambda * n];
+    let mut fits = vec![(0.0, 0); lambda];
+    let mut z = vec![0.0; n];
+    let mut dz = vec![0.0; n];
+    let mut x = vec![0.0; n];
+    let mut step = vec![0.0; n];
+    let mut tmp = vec![0.0; n];
+
+    for gen in 0..opts.max_iter {
+        if out_of_time(opts) {
+            return (best, best_fit, gen);
+        }
+
+        // 標本: y = mean + σ B D z
+        for k in 0..lambda {
+            for j in 0..n {
+                dz[j] = scale[j] * rng.normal();
+            }
+            basis.mul_vec(&dz, &mut z);
+            let y = &mut ys[k * n..(k + 1) * n];
+            for j in 0..n {
+                y[j] = (mean[j] + sigma * z[j]).clamp(0.0, 1.0);
+            }
+            to_x(y, &mut x);
+            let f = sampled_fitness(model, opts, &x);
+            fits[k] = (f, k);
+            if f < best_fit {
+                best_fit = f;
+                best.copy_from_slice(&x);
+            }
+        }
+        fits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        // 平均の更新
+        let old_mean = mean.clone();
+        mean.fill(0.0);
+        for (w, &(_, k)) in weights.iter().zip(&fits) {
+            for j in 0..n {
+                mean[j] += w * ys[k * n + j];
+            }
+        }
+        for j in 0..n {
+            step[j] = (mean[j] - old_mean[j]) / sigma;
+        }
+
+        // 進化経路: ps は C^{-1/2} = B D⁻¹ Bᵀ で白色化した移動量
+        basis.mul_vec_t(&step, &mut tmp);
+        for j in 0..n {
+            tmp[j] /= scale[j];
+        }
+        basis.mul_vec(&tmp, &mut z);
+        let k_s = (cs * (2.0 - cs) * mueff).sqrt();
+        for j in 0..n {
+            ps[j] = (1.0 - cs) * ps[j] + k_s * z[j];
+        }
+        let ps_norm = ps.iter().map(|v| v * v).sum::<f64>().sqrt();
+        let hsig = ps_norm / (1.0 - (1.0 - cs).powi(2 * (gen as i32 + 1))).sqrt() / chi_n
+            < 1.4 + 2.0 / (nf + 1.0);
+        let k_c = if hsig {
+            (cc * (2.0 - cc) * mueff).sqrt()
+        } else {
+            0.0
+        };
+        for j in 0..n {
+            pc[j] = (1.0 - cc) * pc[j] + k_c * step[j];
+        }
+
+        // 共分散の更新（上三角だけ持つ）
+        let keep = 1.0 - c1 - cmu + if hsig { 0.0 } else { c1 * cc * (2.0 - cc) };
+        for i in 0..n {
+            for j in i..n {
+                let mut rank_mu = 0.0;
+                for (w, &(_, k)) in weights.iter().zip(&fits) {
+                    let yi = (ys[k * n + i] - old_mean[i]) / sigma;
+                    let yj = (ys[k * n + j] - old_mean[j]) / sigma;
+                    rank_mu += w * yi * yj;
+                }
+                let c = keep * cov.get(i, j) + c1 * pc[i] * pc[j] + cmu * rank_mu;
+                cov.set(i, j, c);
+            }
+        }
+
+        // ステップ幅の更新（正規化空間の幅を超えて広がらないようにする）
+        sigma *= ((cs / damps) * (ps_norm / chi_n - 1.0)).exp();
+        sigma = sigma.min(1.0);
+
+        if gen % eigen_every == 0 {
+            let (eig, vecs) = cov.symmetric_eigen();
+            basis = vecs;
+            for j in 0..n {
+                scale[j] = eig[j].max(1e-20).sqrt();
+            }
+        }
+
+        if let Some(t) = &opts.trace {
+            t.record(gen, best_fit);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(gen, model, best_fit, &best);
+        }
+        if best_fit.abs() < TOLERANCE || sigma < 1e-16 {
+            return (best, best_fit, gen + 1);
+        }
+        if stagnation.as_mut().is_some_and(|s| s.stalled(best_fit)) {
+            return (best, best_fit, gen + 1);
+        }
+    }
+
+    (best, best_fit, opts.max_iter)
+}
+
+// =============================================================================
+// Nelder-Mead（微分を使わない局所探索）
+// =============================================================================
+
+/// Nelder-Mead の多点スタート: 収束したら最良点の周りに単体を張り直して続ける
+///
+/// 初期点（無ければ範囲の中央）から始め、張り直しても改善しなくなるか
+/// `max_iter`（単体の更新回数の合計）に達したら止める。
+pub fn nm(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let mut x: Vec<f64> = (0..model.dim)
+        .map(|j| 0.5 * (model.lb[j] + model.ub[j]))
+        .collect();
+    if let Some(w) = opts.warm.first() {
+        overwrite_point(&mut x, w, &model.lb, &model.ub);
+    }
+    let mut fit = sampled_fitness(model, opts, &x);
+    let mut total = 0;
+    while total < opts.max_iter && !out_of_time(opts) {
+        let run = Options {
+            trace: opts.trace.as_ref().map(|t| t.shifted(total)),
+            progress: opts.progress.as_ref().map(|p| p.shifted(total)),
+            ..opts.clone()
+        };
+        let (x2, f2, it) = nelder_mead(model, &run, &x, opts.max_iter - total);
+        total += it;
+        let improved = f2 < fit - TOLERANCE * fit.abs().max(1.0);
+        if f2 < fit {
+            x = x2;
+            fit = f2;
+        }
+        if !improved || fit.abs() < TOLERANCE {
+            break;
+        }
+    }
+    (x, fit, total)
+}
+
+/// `x0` の周りに単体を張り、反射・拡大・収縮・縮小で最大 `max_iter` 回更新する
+///
+/// 評価点は範囲にクランプする。単体の広がり（範囲で正規化）と適合度の差が
+/// どちらも十分小さくなったら収束とみなして止める。
+fn nelder_mead(
+    model: &Model,
+    opts: &Options,
+    x0: &[f64],
+    max_iter: usize,
+) -> (Vec<f64>, f64, usize) {
+    let n = model.dim;
+    let (lb, ub) = (&model.lb, &model.ub);
+    let eval = |p: &mut Vec<f64>| {
+        for j in 0..n {
+            p[j] = p[j].clamp(lb[j], ub[j]);
+        }
+        sampled_fitness(model, opts, p)
+    };
+
+    // 初期単体: x0 と、各座標を範囲の NM_STEP だけ（上端を越えるなら逆向きに）ずらした n 点
+    let mut pts: Vec<Vec<f64>> = vec![x0.to_vec()];
+    for j in 0..n {
+        let mut p = x0.to_vec();
+        let step = NM_STEP * (ub[j] - lb[j]);
+        p[j] = if p[j] + step <= ub[j] {
+            p[j] + step
+        } else {
+            p[j] - step
+        };
+        pts.push(p);
+    }
+    let mut fits: Vec<f64> = pts.iter_mut().map(eval).collect();
+
+    let mut centroid = vec![0.0; n];
+    for iter in 0..max_iter {
+        if out_of_time(opts) {
+            return best_vertex(pts, &fits, iter);
+        }
+        // 良い順に並べる
+        let mut order: Vec<usize> = (0..=n).collect();
+        order.sort_by(|&a, &b| {
+            fits[a]
+                .partial_cmp(&fits[b])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        pts = order.iter().map(|&i| pts[i].clone()).collect();
+        fits = order.iter().map(|&i| fits[i]).collect();
+
+        if let Some(t) = &opts.trace {
+            t.record(iter, fits[0]);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(iter, model, fits[0], &pts[0]);
+        }
+        let spread = (1..=n)
+            .flat_map(|i| (0..n).map(move |j| (i, j)))
+            .map(|(i, j)| (pts[i][j] - pts[0][j]).abs() / (ub[j] - lb[j]).max(f64::MIN_POSITIVE))
+            .fold(0.0, f64::max);
+        if fits[0].abs() < TOLERANCE
+            || (spread < 1e-10 && fits[n] - fits[0] <= TOLERANCE * fits[0].abs().max(1.0))
+        {
+            return best_vertex(pts, &fits, iter + 1);
+        }
+
+        // 最悪点以外の重心
+        centroid.fill(0.0);
+        for p in &pts[..n] {
+            for j in 0..n {
+                centroid[j] += p[j] / n as f64;
+            }
+        }
+        let toward = |t: f64| -> Vec<f64> {
+            (0..n)
+                .map(|j| centroid[j] + t * (pts[n][j] - centroid[j]))
+                .collect()
+        };
+
+        let mut xr = toward(-1.0);
+        let fr = eval(&mut xr);
+        if fr < fits[0] {
+            let mut xe = toward(-2.0);
+            let fe = eval(&mut xe);
+            (pts[n], fits[n]) = if fe < fr { (xe, fe) } else { (xr, fr) };
+        } else if fr < fits[n - 1] {
+            (pts[n], fits[n]) = (xr, fr);
+        } else {
+            // 反射点が最悪点より良ければ外側、そうでなければ内側へ収縮
+            let mut xc = if fr < fits[n] {
+                toward(-0.5)
+            } else {
+                toward(0.5)
+            };
+            let fc = eval(&mut xc);
+            if fc < fr.min(fits[n]) {
+                (pts[n], fits[n]) = (xc, fc);
+            } else {
+                // 最良点に向けて縮小
+                let (head, tail) = pts.split_at_mut(1);
+                for (i, p) in tail.iter_mut().enumerate() {
+                    for (v, b) in p.iter_mut().zip(&head[0]) {
+                        *v = b + 0.5 * (*v - b);
+                    }
+                    fits[i + 1] = eval(p);
+                }
+            }
+        }
+    }
+
+    best_vertex(pts, &fits, max_iter)
+}
+
+fn best_vertex(pts: Vec<Vec<f64>>, fits: &[f64], iters: usize) -> (Vec<f64>, f64, usize) {
+    let (i, f) = fits
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, f)| (i, *f))
+        .unwrap_or((0, f64::MAX));
+    (pts.into_iter().nth(i).unwrap_or_default(), f, iters)
+}
+
+// =============================================================================
+// L-BFGS（差分勾配による準ニュートン法、境界へ射影）
+// =============================================================================
+
+/// L-BFGS（滑らかな連続問題の局所探索）
+///
+/// 初期点は範囲の中央（ウォームスタートがあればその点）。
+pub fn lbfgs(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let mut x: Vec<f64> = (0..model.dim)
+        .map(|j| 0.5 * (model.lb[j] + model.ub[j]))
+        .collect();
+    if let Some(w) = opts.warm.first() {
+        overwrite_point(&mut x, w, &model.lb, &model.ub);
+    }
+    lbfgs_from(model, opts, &x, opts.max_iter)
+}
+
+/// 適合度の差分勾配（刻みは各変数の範囲 × `h`。内側は中心差分、境界では内向きの片側差分）
+fn grad(model: &Model, opts: &Options, x: &[f64], h: f64) -> Vec<f64> {
+    let (lb, ub) = (&model.lb, &model.ub);
+    let mut p = x.to_vec();
+    (0..x.len())
+        .map(|j| {
+            let step = h * (ub[j] - lb[j]).max(1.0);
+            let (lo, hi) = ((x[j] - step).max(lb[j]), (x[j] + step).min(ub[j]));
+            if hi <= lo {
+                return 0.0;
+            }
+            p[j] = hi;
+            let f_hi = sampled_fitness(model, opts, &p);
+            p[j] = lo;
+            let f_lo = sampled_fitness(model, opts, &p);
+            p[j] = x[j];
+            (f_hi - f_lo) / (hi - lo)
+        })
+        .collect()
+}
+
+/// 境界付きL-BFGS: 境界に張り付いて外向きの勾配を持つ変数を固定し、残りの変数で
+/// 2ループ再帰の探索方向を求め、境界へ射影しながらバックトラックする（Armijo条件）
+fn lbfgs_from(
+    model: &Model,
+    opts: &Options,
+    x0: &[f64],
+    max_iter: usize,
+) -> (Vec<f64>, f64, usize) {
+    let n = model.dim;
+    let (lb, ub) = (&model.lb, &model.ub);
+    let mut x: Vec<f64> = (0..n).map(|j| x0[j].clamp(lb[j], ub[j])).collect();
+    let mut fx = sampled_fitness(model, opts, &x);
+    let mut g = grad(model, opts, &x, LBFGS_H);
+    // (s, y, 1 / s·y) の履歴（古い順）
+    let mut memory: VecDeque<(Vec<f64>, Vec<f64>, f64)> = VecDeque::new();
+
+    for iter in 0..max_iter {
+        if out_of_time(opts) {
+            return (x, fx, iter);
+        }
+        if let Some(t) = &opts.trace {
+            t.record(iter, fx);
+        }
+        if let Some(p) = &opts.progress {
+            p.record(iter, model, fx, &x);
+        }
+        // 境界で外向きの勾配を持つ変数は動かさない
+        let free: Vec<bool> = (0..n)
+            .map(|j| !((x[j] <= lb[j] && g[j] > 0.0) || (x[j] >= ub[j] && g[j] < 0.0)))
+            .collect();
+        let pg_norm = (0..n)
+            .filter(|&j| free[j])
+            .map(|j| g[j].abs())
+            .fold(0.0, f64::max);
+        if fx.abs() < TOLERANCE || pg_norm < LBFGS_GTOL {
+            return (x, fx, iter + 1);
+        }
+
+        // 2ループ再帰で d = -H g（固定した変数の成分は 0）
+        let mut q: Vec<f64> = (0..n).map(|j| if free[j] { g[j] } else { 0.0 }).collect();
+        let mut alpha = vec![0.0; memory.len()];
+        for (k, (s, y, rho)) in memory.iter().enumerate().rev() {
+            alpha[k] = rho * dot(s, &q);
+            q.iter_mut()
+                .zip(y)
+                .for_each(|(qj, yj)| *qj -= alpha[k] * yj);
+        }
+        if let Some((s, y, _)) = memory.back() {
+            let gamma = dot(s, y) / dot(y, y).max(f64::MIN_POSITIVE);
+            q.iter_mut().for_each(|qj| *qj *= gamma);
+        }
+        for (k, (s, y, rho)) in memory.iter().enumerate() {
+            let beta = rho * dot(y, &q);
+            q.iter_mut()
+                .zip(s)
+                .for_each(|(qj, sj)| *qj += (alpha[k] - beta) * sj);
+        }
+        let mut d: Vec<f64> = (0..n).map(|j| if free[j] { -q[j] } else { 0.0 }).collect();
+        if dot(&d, &g) >= 0.0 {
+            // 降下方向でなければ履歴を捨てて最急降下に戻す
+            memory.clear();
+            d = (0..n).map(|j| if free[j] { -g[j] } else { 0.0 }).collect();
+        }
+        // 履歴が無いうちは最初の一歩を範囲の1割程度に抑える
+        let mut t = if memory.is_empty() {
+            let longest = (0..n)
+                .map(|j| d[j].abs() / (ub[j] - lb[j]).max(f64::MIN_POSITIVE))
+                .fold(0.0, f64::max);
+            (0.1 / longest.max(f64::MIN_POSITIVE)).min(1.0)
+        } else {
+            1.0
+        };
+
+        // 射影つきバックトラック
+        let mut accepted = None;
+        for _ in 0..LBFGS_BACKTRACKS {
+            let xt: Vec<f64> = (0..n)
+                .map(|j| (x[j] + t * d[j]).clamp(lb[j], ub[j]))
+                .collect();
+            let ft = sampled_fitness(model, opts, &xt);
+            let decrease: f64 = (0..n).map(|j| g[j] * (xt[j] - x[j])).sum();
+            if ft <= fx + LBFGS_ARMIJO * decrease && ft < fx {
+                accepted = Some((xt, ft));
+                break;
+            }
+            t *= 0.5;
+        }
+        let Some((x_new, f_new)) = accepted else {
+            // これ以上下がらない（差分勾配の精度の限界）
+            return (x, fx, iter + 1);
+        };
+        let g_new = grad(model, opts, &x_new, LBFGS_H);
+        let s: Vec<f64> = x_new.iter().zip(&x).map(|(a, b)| a - b).collect();
+        let y: Vec<f64> = g_new.iter().zip(&g).map(|(a, b)| a - b).collect();
+        let sy = dot(&s, &y);
+        if sy > f64::EPSILON * dot(&y, &y).sqrt() * dot(&s, &s).sqrt() {
+            if memory.len() == LBFGS_MEMORY {
+                memory.pop_front();
+            }
+            memory.push_back((s, y, 1.0 / sy));
+        }
+        x = x_new;
+        fx = f_new;
+        g = g_new;
+    }
+    (x, fx, max_iter)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
 
-            // pbest更新
-            if fit < swarm.pbest_fit[i] {
-                swarm.pbest[offset..offset + dim].copy_from_slice(&swarm.pos[offset..offset + dim]);
-                swarm.pbest_fit[i] = fit;
+// =============================================================================
+// 多目的: パレートフロント（NSGA-II）
+// =============================================================================
 
-                // gbest更新
-                if fit < gbest_fit {
-                    gbest_fit = fit;
-                    gbest.copy_from_slice(&swarm.pos[offset..offset + dim]);
+/// NSGA-II でパレートフロントを求め、非劣解の (探索空間の点, 目的ごとの値) を返す
+///
+/// 目的は `maximize` の向きを反映して比較し、返す値は各目的の元の向きのまま。
+/// 制約は Deb の規則で扱う（実行可能解は実行不能解に優越し、実行不能どうしは違反量で比べる）。
+/// 集団サイズは `opts.de.pop_size`、1反復 = 1世代。子の評価は `opts.threads` 本で分担する。
+/// 戻り値は先頭の目的の昇順。
+pub fn pareto_front(model: &Model, opts: &Options) -> Vec<(Vec<f64>, Vec<f64>)> {
+    let dim = model.dim;
+    let (lb, ub) = (&model.lb, &model.ub);
+    let size = opts.de.pop_size.max(4) & !1;
+    let mut rng = Rng::new(opts.seed);
+
+    let mut pop: Vec<Vec<f64>> = opts
+        .warm
+        .iter()
+        .take(size)
+        .map(|w| {
+            let mut x: Vec<f64> = (0..dim).map(|j| 0.5 * (lb[j] + ub[j])).collect();
+            overwrite_point(&mut x, w, lb, ub);
+            x
+        })
+        .collect();
+    while pop.len() < size {
+        pop.push(
+            (0..dim)
+                .map(|j| lb[j] + rng.f64() * (ub[j] - lb[j]))
+                .collect(),
+        );
+    }
+    let mut evals = evaluate_batch(model, &pop, opts.threads);
+    let (mut rank, mut crowd) = rank_and_crowd(&evals);
 
-                    if gbest_fit < TOLERANCE {
-                        return (gbest, gbest_fit, iter + 1);
-                    }
+    for _ in 0..opts.max_iter {
+        if out_of_time(opts) {
+            break;
+        }
+        // 2項トーナメントで親を選び、SBX交叉 + 多項式突然変異で子を作る
+        let mut children: Vec<Vec<f64>> = Vec::with_capacity(size);
+        while children.len() < size {
+            let mut pick = || {
+                let (a, b) = (rng.usize(size), rng.usize(size));
+                if (rank[a], -crowd[a]) < (rank[b], -crowd[b]) {
+                    a
+                } else {
+                    b
                 }
+            };
+            let (p1, p2) = (pick(), pick());
+            let (mut c1, mut c2) = (pop[p1].clone(), pop[p2].clone());
+            if rng.f64() < NSGA_CROSSOVER {
+                sbx_crossover(&mut rng, &mut c1, &mut c2, lb, ub);
             }
+            polynomial_mutation(&mut rng, &mut c1, lb, ub);
+            polynomial_mutation(&mut rng, &mut c2, lb, ub);
+            children.push(c1);
+            children.push(c2);
         }
-
-        w = (w * PSO_W_DECAY).max(PSO_W_MIN);
+        let child_evals = evaluate_batch(model, &children, opts.threads);
+
+        // 親と子を合わせ、順位の良いフロントから詰め、入りきらないフロントは混雑距離で選ぶ
+        pop.extend(children);
+        evals.extend(child_evals);
+        let (all_rank, all_crowd) = rank_and_crowd(&evals);
+        let mut order: Vec<usize> = (0..pop.len()).collect();
+        order.sort_by(|&a, &b| {
+            all_rank[a].cmp(&all_rank[b]).then(
+                all_crowd[b]
+                    .partial_cmp(&all_crowd[a])
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+        });
+        order.truncate(size);
+        pop = order.iter().map(|&i| pop[i].clone()).collect();
+        evals = order.iter().map(|&i| evals[i].clone()).collect();
+        (rank, crowd) = rank_and_crowd(&evals);
     }
 
-    (gbest, gbest_fit, max_iter)
+    // 第1フロント（重複は除く）
+    let mut front: Vec<(Vec<f64>, Vec<f64>)> = Vec::new();
+    for (i, (x, (_, raw, _))) in pop.into_iter().zip(evals).enumerate() {
+        if rank[i] == 0 && !front.iter().any(|(_, r)| *r == raw) {
+            front.push((x, raw));
+        }
+    }
+    front.sort_by(|a, b| {
+        a.1[0]
+            .partial_cmp(&b.1[0])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    front
 }
 
-#[allow(clippy::too_many_arguments)]
-#[inline(always)]
-fn pso_update_velocity_position(
-    swarm: &mut Swarm,
-    _i: usize,
-    offset: usize,
-    gbest: &[f64],
-    v_max: &[f64],
-    lb: &[f64],
-    ub: &[f64],
-    w: f64,
-    r1: &[f64],
-    r2: &[f64],
-) {
-    let dim = lb.len();
+/// (最小化向きの目的ベクトル, 元の向きの目的値, 制約違反) を点ごとに評価する
+fn evaluate_batch(
+    model: &Model,
+    xs: &[Vec<f64>],
+    threads: usize,
+) -> Vec<(Vec<f64>, Vec<f64>, f64)> {
+    let eval = |x: &Vec<f64>| {
+        let mut x = x.clone();
+        model.round_integers(&mut x);
+        let full = model.expand_solution(&x);
+        let (_, raw, violation) = model.evaluate_all(&full);
+        let signed = model
+            .objectives
+            .iter()
+            .zip(&raw)
+            .map(|(o, &v)| {
+                let v = if o.maximize { -v } else { v };
+                if v.is_finite() {
+                    v
+                } else {
+                    f64::MAX
+                }
+            })
+            .collect();
+        let violation = if violation.is_finite() {
+            violation
+        } else {
+            f64::MAX
+        };
+        (signed, raw, violation)
+    };
+    let threads = threads.clamp(1, xs.len().max(1));
+    if threads == 1 {
+        return xs.iter().map(eval).collect();
+    }
+    let chunk = xs.len().div_ceil(threads);
+    thread::scope(|s| {
+        let handles: Vec<_> = xs
+            .chunks(chunk)
+            .map(|part| s.spawn(move || part.iter().map(eval).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    })
+}
 
-    for j in 0..dim {
-        let mut v = w * swarm.vel[offset + j]
-            + PSO_C1 * r1[j] * (swarm.pbest[offset + j] - swarm.pos[offset + j])
-            + PSO_C2 * r2[j] * (gbest[j] - swarm.pos[offset + j]);
+/// 制約付きの優越（Deb の規則）: `a` が `b` に優越するか
+fn dominates(a: &(Vec<f64>, Vec<f64>, f64), b: &(Vec<f64>, Vec<f64>, f64)) -> bool {
+    let (fa, fb) = (a.2 <= 1e-9, b.2 <= 1e-9);
+    match (fa, fb) {
+        (true, false) => true,
+        (false, true) => false,
+        (false, false) => a.2 < b.2,
+        (true, true) => {
+            a.0.iter().zip(&b.0).all(|(x, y)| x <= y) && a.0.iter().zip(&b.0).any(|(x, y)| x < y)
+        }
+    }
+}
 
-        v = v.clamp(-v_max[j], v_max[j]);
-        swarm.vel[offset + j] = v;
+/// 非優越ソートの順位（0 が第1フロント）と、フロント内の混雑距離
+fn rank_and_crowd(evals: &[(Vec<f64>, Vec<f64>, f64)]) -> (Vec<usize>, Vec<f64>) {
+    let n = evals.len();
+    let mut dominated_by = vec![0usize; n];
+    let mut dominates_list: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for i in 0..n {
+        for j in i + 1..n {
+            if dominates(&evals[i], &evals[j]) {
+                dominates_list[i].push(j);
+                dominated_by[j] += 1;
+            } else if dominates(&evals[j], &evals[i]) {
+                dominates_list[j].push(i);
+                dominated_by[i] += 1;
+            }
+        }
+    }
+    let mut rank = vec![0usize; n];
+    let mut crowd = vec![0.0; n];
+    let mut front: Vec<usize> = (0..n).filter(|&i| dominated_by[i] == 0).collect();
+    let mut r = 0;
+    while !front.is_empty() {
+        for &i in &front {
+            rank[i] = r;
+        }
+        // 混雑距離: 目的ごとに並べ、両隣の差（範囲で正規化）を足す。端は無限大
+        let m = evals[front[0]].0.len();
+        for k in 0..m {
+            let mut sorted = front.clone();
+            sorted.sort_by(|&a, &b| {
+                evals[a].0[k]
+                    .partial_cmp(&evals[b].0[k])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            let (lo, hi) = (evals[sorted[0]].0[k], evals[sorted[sorted.len() - 1]].0[k]);
+            crowd[sorted[0]] = f64::INFINITY;
+            crowd[sorted[sorted.len() - 1]] = f64::INFINITY;
+            if hi > lo {
+                for w in sorted.windows(3) {
+                    crowd[w[1]] += (evals[w[2]].0[k] - evals[w[0]].0[k]) / (hi - lo);
+                }
+            }
+        }
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominates_list[i] {
+                dominated_by[j] -= 1;
+                if dominated_by[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        front = next;
+        r += 1;
+    }
+    (rank, crowd)
+}
 
-        let mut p = swarm.pos[offset + j] + v;
-        if p < lb[j] {
-            p = lb[j];
-            swarm.vel[offset + j] = 0.0;
+/// SBX（模擬2進交叉）。座標ごとに確率 1/2 で交叉し、範囲にクランプする
+fn sbx_crossover(rng: &mut Rng, c1: &mut [f64], c2: &mut [f64], lb: &[f64], ub: &[f64]) {
+    for j in 0..c1.len() {
+        if rng.f64() >= 0.5 || (c1[j] - c2[j]).abs() < 1e-14 {
+            continue;
         }
-        if p > ub[j] {
-            p = ub[j];
-            swarm.vel[offset + j] = 0.0;
+        let u = rng.f64();
+        let beta = if u <= 0.5 {
+            (2.0 * u).powf(1.0 / (NSGA_ETA_C + 1.0))
+        } else {
+            (1.0 / (2.0 * (1.0 - u))).powf(1.0 / (NSGA_ETA_C + 1.0))
+        };
+        let (p1, p2) = (c1[j], c2[j]);
+        c1[j] = (0.5 * ((1.0 + beta) * p1 + (1.0 - beta) * p2)).clamp(lb[j], ub[j]);
+        c2[j] = (0.5 * ((1.0 - beta) * p1 + (1.0 + beta) * p2)).clamp(lb[j], ub[j]);
+    }
+}
+
+/// 多項式突然変異。座標ごとに確率 1/次元 で動かし、範囲にクランプする
+fn polynomial_mutation(rng: &mut Rng, x: &mut [f64], lb: &[f64], ub: &[f64]) {
+    let pm = 1.0 / x.len().max(1) as f64;
+    for j in 0..x.len() {
+        if rng.f64() >= pm {
+            continue;
         }
-        swarm.pos[offset + j] = p;
+        let u = rng.f64();
+        let delta = if u < 0.5 {
+            (2.0 * u).powf(1.0 / (NSGA_ETA_M + 1.0)) - 1.0
+        } else {
+            1.0 - (2.0 * (1.0 - u)).powf(1.0 / (NSGA_ETA_M + 1.0))
+        };
+        x[j] = (x[j] + delta * (ub[j] - lb[j])).clamp(lb[j], ub[j]);
     }
 }
 
@@ -323,78 +2037,158 @@ fn pso_update_velocity_position(
 // 評価関数（目的 + 制約ペナルティ）
 // =============================================================================
 
-fn compute_fitness(model: &Model, x: &[f64]) -> f64 {
-    // 多目的対応
-    if !model.objectives.is_empty() {
-        match &model.pareto {
-            ParetoMethod::WeightedSum(weights) if !weights.is_empty() => {
-                // 重み付き和
-                let mut total = 0.0;
-                for (name, w) in weights {
-                    if let Some(obj) = model.objectives.iter().find(|o| &o.name == name) {
-                        let mut v =
-                            model.evaluate_expr(&obj.expr, x, &std::collections::HashMap::new());
-                        if obj.maximize {
-                            v = -v;
-                        }
-                        total += w * v;
-                    }
-                }
-                let (_f, vio) = model.check_constraints(x);
-                return total + vio * penalty_coeff();
-            }
-            ParetoMethod::Epsilon { primary, eps } => {
-                // epsilon制約: primaryを最適化、他は閾値超過にペナルティ
-                let mut v_primary = 0.0;
-                if let Some(obj) = model.objectives.iter().find(|o| &o.name == primary) {
-                    v_primary =
-                        model.evaluate_expr(&obj.expr, x, &std::collections::HashMap::new());
-                    if obj.maximize {
-                        v_primary = -v_primary;
-                    }
-                }
-                let mut vio_eps = 0.0;
-                for (name, op, rhs) in eps {
-                    if let Some(obj) = model.objectives.iter().find(|o| &o.name == name) {
-                        let mut v =
-                            model.evaluate_expr(&obj.expr, x, &std::collections::HashMap::new());
-                        if obj.maximize {
-                            v = -v;
-                        }
-                        let viol = match op {
-                            ConstraintOp::Le => (v - rhs).max(0.0),
-                            ConstraintOp::Ge => (rhs - v).max(0.0),
-                            ConstraintOp::Eq => (v - rhs).abs(),
-                        };
-                        vio_eps += viol;
-                    }
-                }
-                let (_f, vio) = model.check_constraints(x);
-                return v_primary + (vio + vio_eps) * penalty_coeff();
-            }
-            _ => {
-                // デフォルト: 先頭の目的を使用
-                let obj = &model.objectives[0];
-                let mut v = model.evaluate_expr(&obj.expr, x, &std::collections::HashMap::new());
-                if obj.maximize {
-                    v = -v;
-                }
-                let (_f, vio) = model.check_constraints(x);
-                return v + vio * penalty_coeff();
+/// 評価（キャッシュがあれば先に引く）
+fn cached_fitness(model: &Model, opts: &Options, x: &[f64]) -> f64 {
+    match &opts.fitness_cache {
+        Some(c) => c.get_or_eval(model, x, || compute_fitness(model, opts, x)),
+        None => compute_fitness(model, opts, x),
+    }
+}
+
+fn compute_fitness(model: &Model, opts: &Options, x: &[f64]) -> f64 {
+    let (objective, violation) = split_fitness(model, opts, x);
+    let f = objective + violation;
+    // NaN/inf は比較を壊すので最悪値として扱う
+    if f.is_finite() {
+        f
+    } else {
+        f64::MAX
+    }
+}
+
+thread_local! {
+    /// `split_fitness` の作業領域（丸めた点, 元の空間に戻した点）。評価ごとの確保を避ける
+    static SCRATCH: RefCell<(Vec<f64>, Vec<f64>)> = const { RefCell::new((Vec::new(), Vec::new())) };
+}
+
+/// 評価を (最小化向きの目的, ペナルティ係数を掛けた制約違反の合計) に分けて返す
+///
+/// NaN/inf は比較を壊す（Debの規則では NaN の違反量が実行可能に見える）ので、
+/// 目的・違反量とも最悪値 `f64::MAX` に置き換えて `opts` に回数を数える。
+fn split_fitness(model: &Model, opts: &Options, x: &[f64]) -> (f64, f64) {
+    let (objective, violation) = SCRATCH.with(|scratch| {
+        let (rounded, full) = &mut *scratch.borrow_mut();
+        // 整数・バイナリ変数は丸めた点で評価する
+        let mut point = x;
+        if model.has_integer_vars() {
+            rounded.clear();
+            rounded.extend_from_slice(x);
+            model.round_integers(rounded);
+            point = rounded;
+        }
+        // プリソルブで消去した変数を復元してから評価する
+        if model.reduction.is_some() {
+            model.expand_solution_into(point, full);
+            point = full;
+        }
+        objective_and_violation(model, point)
+    });
+    if objective.is_finite() && violation.is_finite() {
+        return (objective, violation);
+    }
+    opts.non_finite.fetch_add(1, Ordering::Relaxed);
+    let worst = |v: f64| if v.is_finite() { v } else { f64::MAX };
+    (worst(objective), worst(violation))
+}
+
+/// DEの個体の評価（ペナルティ方式では (適合度, 0)、Debの規則では (目的, 違反量)）
+fn de_score(model: &Model, opts: &Options, x: &[f64]) -> (f64, f64) {
+    match opts.constraint_mode {
+        ConstraintMode::Penalty => (sampled_fitness(model, opts, x), 0.0),
+        ConstraintMode::Deb if opts.resample <= 1 => split_fitness(model, opts, x),
+        ConstraintMode::Deb => {
+            let (mut o, mut v) = (0.0, 0.0);
+            for _ in 0..opts.resample {
+                let (a, b) = split_fitness(model, opts, x);
+                o += a / opts.resample as f64;
+                v += b / opts.resample as f64;
             }
+            (o, v)
         }
     }
-    // 単一目的（従来）
-    let mut obj = model.evaluate_objective(x);
-    if model.maximize {
-        obj = -obj;
+}
+
+/// (目的, 重み付き違反量) を報告用のペナルティ込みの適合度に戻す
+fn score_fitness((objective, violation): (f64, f64)) -> f64 {
+    let f = objective + violation;
+    if f.is_finite() {
+        f
+    } else {
+        f64::MAX
+    }
+}
+
+/// Debの規則による比較: 実行可能（違反0）が優先、実行可能どうしは目的、実行不能どうしは違反量
+///
+/// ペナルティ方式の評価（違反量が常に0）では目的の比較と同じになる。
+fn deb_cmp(a: (f64, f64), b: (f64, f64)) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    match (a.1 > 0.0, b.1 > 0.0) {
+        (false, false) => a.0.total_cmp(&b.0),
+        (false, true) => Ordering::Less,
+        (true, false) => Ordering::Greater,
+        (true, true) => a.1.total_cmp(&b.1),
+    }
+}
+
+/// `opts.resample` 回評価した平均（ノイズのある目的で選択を安定させる。1以下なら1回だけ評価）
+///
+/// 1回だけ評価する場合は `opts.fitness_cache` を引く（複数回の平均はノイズがある前提なので引かない）。
+fn sampled_fitness(model: &Model, opts: &Options, x: &[f64]) -> f64 {
+    let resample = opts.resample;
+    if resample <= 1 {
+        return cached_fitness(model, opts, x);
+    }
+    (0..resample)
+        .map(|_| compute_fitness(model, opts, x))
+        .sum::<f64>()
+        / resample as f64
+}
+
+/// 探索空間の点 `x` が制約をすべて満たすか（整数丸めと消去変数の復元をしてから判定）
+fn is_feasible(model: &Model, x: &[f64]) -> bool {
+    let mut x = x.to_vec();
+    model.round_integers(&mut x);
+    let full = model.expand_solution(&x);
+    let (ok, _) = model.check_constraints(&full);
+    ok && (!model.objectives.is_empty() || compute_cp_penalty(model, &full) <= 0.0)
+}
+
+fn objective_and_violation(model: &Model, x: &[f64]) -> (f64, f64) {
+    let (scalar, _raw, violation) = model.evaluate_all(x);
+    // CPグローバル制約は単一目的のときのみ評価する（多目的は従来どおり対象外）
+    let cp_penalty = if model.objectives.is_empty() {
+        compute_cp_penalty(model, x) * penalty_coeff()
+    } else {
+        0.0
+    };
+    (scalar, violation + cp_penalty)
+}
+
+/// 解 `x` におけるペナルティの内訳（制約名, 違反量 × 係数）を大きい順に返す
+///
+/// 係数は制約ごとの `[penalty=W]`（無ければ全体の係数）。CPグローバル制約の分は `cp-globals` としてまとめる。
+pub fn penalty_breakdown(model: &Model, x: &[f64]) -> Vec<(String, f64)> {
+    let mut x = x.to_vec();
+    model.round_integers(&mut x);
+    let coeff = penalty_coeff();
+    let mut rows: Vec<(String, f64)> = model
+        .constraint_violations(&x)
+        .into_iter()
+        .zip(&model.constraints)
+        .map(|((name, v), c)| (name.to_string(), v * model.penalty_weight(c)))
+        .collect();
+    if !model.cp_globals.is_empty() {
+        rows.push((
+            "cp-globals".to_string(),
+            compute_cp_penalty(model, &x) * coeff,
+        ));
     }
-    let (_feasible, violation) = model.check_constraints(x);
-    let cp_penalty = compute_cp_penalty(model, x);
-    obj + (violation + cp_penalty) * penalty_coeff()
+    rows.sort_by(|a, b| b.1.total_cmp(&a.1));
+    rows
 }
 
-fn penalty_coeff() -> f64 {
+pub fn penalty_coeff() -> f64 {
     *PENALTY_ENV.get_or_init(|| {
         std::env::var("OPTICA_PENALTY")
             .ok()
@@ -418,9 +2212,45 @@ fn compute_cp_penalty(model: &Model, x: &[f64]) -> f64 {
             pen += penalty_cumulative(model, x, g, "start[", "duration[");
         }
     }
+    if !model.cp_globals.is_empty() {
+        pen += penalty_time_windows(model, x);
+    }
     pen
 }
 
+/// リリース時刻・締切の違反量
+///
+/// `start[j] < release[j]` と `end[j] > deadline[j]` を罰する。終了時刻は `end[j]`、
+/// 無ければ `start[j] + duration[j]`。`release` / `deadline` はタスクと同じ添字の
+/// パラメータで、定義されていないタスクは制限なしとする。
+fn penalty_time_windows(model: &Model, x: &[f64]) -> f64 {
+    let release = model.params.get("release");
+    let deadline = model.params.get("deadline");
+    if release.is_none() && deadline.is_none() {
+        return 0.0;
+    }
+    let mut vio = 0.0;
+    for (name, &idx) in &model.var_map {
+        let Some(idx_suffix) = name.strip_prefix("start[") else {
+            continue;
+        };
+        let start = x[idx];
+        let key = idx_suffix.trim_end_matches(']');
+        if let Some(&r) = release.and_then(|m| m.get(key)) {
+            vio += (r - start).max(0.0);
+        }
+        if let Some(&d) = deadline.and_then(|m| m.get(key)) {
+            let end = get_var_val(model, x, &format!("end[{}", idx_suffix)).or_else(|| {
+                get_var_val(model, x, &format!("duration[{}", idx_suffix)).map(|dur| start + dur)
+            });
+            if let Some(e) = end {
+                vio += (e - d).max(0.0);
+            }
+        }
+    }
+    vio
+}
+
 fn penalty_no_overlap(
     model: &Model,
     x: &[f64],
@@ -502,7 +2332,7 @@ fn penalty_cumulative(
     }
     // collect time points
     let mut pts: Vec<f64> = intervals.iter().flat_map(|(s, e)| vec![*s, *e]).collect();
-    pts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    pts.sort_by(|a, b| a.total_cmp(b));
     pts.dedup();
     let mut vio = 0.0;
     for w in pts.windows(2) {
@@ -531,11 +2361,46 @@ fn get_var_val(model: &Model, x: &[f64], key: &str) -> Option<f64> {
 // =============================================================================
 
 /// ハイブリッド最適化（DE + PSO）
-pub fn hybrid(model: &Model, max_iter: usize, threads: usize) -> (Vec<f64>, f64, usize) {
+/// hybridの反復数を (前段DE, 後段PSO) に分ける
+///
+/// 前段は `max_iter * split` を丸めた値。2段の合計は常に `max_iter` に一致し、
+/// `max_iter >= 2` ならどちらの段にも最低1反復を残す。
+pub fn hybrid_phases(max_iter: usize, split: f64) -> (usize, usize) {
+    let mut explore = (max_iter as f64 * split).round() as usize;
+    if max_iter >= 2 {
+        explore = explore.clamp(1, max_iter - 1);
+    }
+    let explore = explore.min(max_iter);
+    (explore, max_iter - explore)
+}
+
+pub fn hybrid(model: &Model, opts: &Options) -> (Vec<f64>, f64, usize) {
+    let max_iter = opts.max_iter;
+    let (explore, refine) = hybrid_phases(max_iter, opts.hybrid_split);
+    let first = Options {
+        max_iter: explore,
+        ..opts.clone()
+    };
+
     // Phase 1: DE for exploration
-    let (x1, f1, _) = de(model, max_iter / 2, threads);
+    let (x1, f1, it1) = de(model, &first);
+    if opts.feasible_watch.as_ref().is_some_and(|w| w.aborted()) || out_of_time(opts) {
+        return (x1, f1, it1);
+    }
 
-    // Phase 2: PSO for refinement
+    // Phase 2: 連続変数だけでCP制約も無ければ L-BFGS、それ以外は最良解の周辺を PSO で詰める
+    let smooth =
+        model.cp_globals.is_empty() && (0..model.dim).all(|j| !model.var_type(j).is_integral());
+    if smooth {
+        let second = Options {
+            trace: opts.trace.as_ref().map(|t| t.shifted(explore)),
+            progress: opts.progress.as_ref().map(|p| p.shifted(explore)),
+            ..first.clone()
+        };
+        let (x2, f2, it2) = lbfgs_from(model, &second, &x1, refine);
+        let best = if f2 < f1 { (x2, f2) } else { (x1, f1) };
+        return polish(model, opts, best, it1 + it2);
+    }
     let dim = model.dim;
     let scale = 0.1;
 
@@ -554,13 +2419,38 @@ pub fn hybrid(model: &Model, max_iter: usize, threads: usize) -> (Vec<f64>, f64,
     sub_model.ub = ub2;
     sub_model.dim = dim;
 
-    let (x2, f2, _) = pso(&sub_model, max_iter / 2);
+    // 後段の反復は前段の後ろに続けて記録する
+    let second = Options {
+        max_iter: refine,
+        trace: opts.trace.as_ref().map(|t| t.shifted(explore)),
+        progress: opts.progress.as_ref().map(|p| p.shifted(explore)),
+        feasible_watch: opts.feasible_watch.as_ref().map(|w| w.shifted(explore)),
+        ..first.clone()
+    };
+    let (x2, f2, it2) = pso(&sub_model, &second);
+    let best = if f2 < f1 { (x2, f2) } else { (x1, f1) };
+    polish(model, opts, best, it1 + it2)
+}
 
-    if f2 < f1 {
-        (x2, f2, max_iter)
-    } else {
-        (x1, f1, max_iter)
+/// hybrid の Phase 3: `--polish` なら Nelder-Mead で局所的に仕上げる（反復数・トレースには含めない）
+fn polish(
+    model: &Model,
+    opts: &Options,
+    (best, best_fit): (Vec<f64>, f64),
+    iters: usize,
+) -> (Vec<f64>, f64, usize) {
+    if opts.polish && !out_of_time(opts) {
+        let third = Options {
+            trace: None,
+            progress: None,
+            ..opts.clone()
+        };
+        let (x3, f3, _) = nelder_mead(model, &third, &best, NM_POLISH_ITERS);
+        if f3 < best_fit {
+            return (x3, f3, iters);
+        }
     }
+    (best, best_fit, iters)
 }
 
 // =============================================================================
@@ -570,16 +2460,22 @@ pub fn hybrid(model: &Model, max_iter: usize, threads: usize) -> (Vec<f64>, f64,
 /// DE集団
 struct Population {
     data: Vec<f64>,
-    fit: Vec<f64>,
+    /// (目的, 違反量)。ペナルティ方式では (ペナルティ込みの適合度, 0)
+    fit: Vec<(f64, f64)>,
+    /// 個体ごとの差分の重みと交叉率（jDE以外は全個体で `DeParams` の値のまま）
+    f: Vec<f64>,
+    cr: Vec<f64>,
     dim: usize,
     size: usize,
 }
 
 impl Population {
-    fn new(dim: usize, size: usize) -> Self {
+    fn new(dim: usize, size: usize, dp: &DeParams) -> Self {
         Self {
             data: Vec::with_capacity(size * dim),
             fit: Vec::with_capacity(size),
+            f: vec![dp.f; size],
+            cr: vec![dp.cr; size],
             dim,
             size,
         }
@@ -587,7 +2483,7 @@ impl Population {
 
     fn initialize<F>(&mut self, rng: &mut Rng, lb: &[f64], ub: &[f64], mut fitness: F)
     where
-        F: FnMut(&[f64]) -> f64,
+        F: FnMut(&[f64]) -> (f64, f64),
     {
         let mut rnd_buf = vec![0.0; self.dim];
 
@@ -604,12 +2500,29 @@ impl Population {
         }
     }
 
+    /// 先頭の個体を初期点で置き換える
+    fn inject<F>(&mut self, warm: &[Vec<f64>], lb: &[f64], ub: &[f64], mut fitness: F)
+    where
+        F: FnMut(&[f64]) -> (f64, f64),
+    {
+        for (i, w) in warm.iter().take(self.size).enumerate() {
+            let row = &mut self.data[i * self.dim..(i + 1) * self.dim];
+            overwrite_point(row, w, lb, ub);
+            self.fit[i] = fitness(row);
+        }
+    }
+
+    /// 個体を1つずつ返す
+    fn rows(&self) -> std::slice::Chunks<'_, f64> {
+        self.data.chunks(self.dim)
+    }
+
     fn find_best(&self) -> Vec<f64> {
         let best_idx = self
             .fit
             .iter()
             .enumerate()
-            .min_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .min_by(|a, b| deb_cmp(*a.1, *b.1))
             .unwrap()
             .0;
         self.data[best_idx * self.dim..(best_idx + 1) * self.dim].to_vec()
@@ -627,11 +2540,226 @@ impl Population {
         (r1, r2)
     }
 
-    fn update(&mut self, i: usize, trial: &[f64], trial_fit: f64) {
+    fn update(&mut self, i: usize, trial: &[f64], trial_fit: (f64, f64)) {
         let offset = i * self.dim;
         self.data[offset..offset + self.dim].copy_from_slice(trial);
         self.fit[i] = trial_fit;
     }
+
+    /// 最悪の個体を `x` で置き換える（島モデルの移住）
+    fn replace_worst(&mut self, x: &[f64], fit: (f64, f64)) {
+        let worst = (0..self.size)
+            .max_by(|&a, &b| deb_cmp(self.fit[a], self.fit[b]))
+            .unwrap_or(0);
+        self.update(worst, x, fit);
+    }
+
+    /// 各次元の標準偏差を変数の範囲で割った値の平均（範囲0の次元は除く）
+    fn diversity(&self, lb: &[f64], ub: &[f64]) -> f64 {
+        let n = self.size as f64;
+        let mut total = 0.0;
+        let mut dims = 0;
+        for j in 0..self.dim {
+            let range = ub[j] - lb[j];
+            if range <= 0.0 {
+                continue;
+            }
+            let col = (0..self.size).map(|i| self.data[i * self.dim + j]);
+            let mean = col.clone().sum::<f64>() / n;
+            let var = col.map(|v| (v - mean) * (v - mean)).sum::<f64>() / n;
+            total += var.sqrt() / range;
+            dims += 1;
+        }
+        if dims == 0 {
+            f64::INFINITY
+        } else {
+            total / dims as f64
+        }
+    }
+
+    /// 適合度の悪い順に `count` 個体を一様乱数で置き換える
+    fn reinitialize_worst<F>(
+        &mut self,
+        count: usize,
+        rng: &mut Rng,
+        lb: &[f64],
+        ub: &[f64],
+        mut fitness: F,
+    ) where
+        F: FnMut(&[f64]) -> (f64, f64),
+    {
+        let mut order: Vec<usize> = (0..self.size).collect();
+        order.sort_by(|&a, &b| deb_cmp(self.fit[b], self.fit[a]));
+        let mut rnd_buf = vec![0.0; self.dim];
+        for &i in order.iter().take(count) {
+            rng.fill_f64(&mut rnd_buf);
+            let row = &mut self.data[i * self.dim..(i + 1) * self.dim];
+            for j in 0..self.dim {
+                row[j] = lb[j] + rnd_buf[j] * (ub[j] - lb[j]);
+            }
+            self.fit[i] = fitness(row);
+        }
+    }
+}
+
+/// SHADEの状態: 成功した `f` / `cr` の履歴、置き換えられた個体のアーカイブ、世代頭の順位
+struct Shade {
+    memory_f: Vec<f64>,
+    memory_cr: Vec<f64>,
+    /// 次に書き換える履歴の位置
+    slot: usize,
+    /// 置き換えられた個体（最大で集団と同じ数、あふれたら無作為に入れ替える）
+    archive: Vec<Vec<f64>>,
+    capacity: usize,
+    /// この世代で成功した (f, cr, 改善量)
+    success: Vec<(f64, f64, f64)>,
+    /// 世代頭の集団を良い順に並べた個体番号（pbest の候補）
+    order: Vec<usize>,
+}
+
+impl Shade {
+    fn new(dp: &DeParams, size: usize) -> Self {
+        Self {
+            memory_f: vec![dp.f; SHADE_MEMORY],
+            memory_cr: vec![dp.cr; SHADE_MEMORY],
+            slot: 0,
+            archive: Vec::new(),
+            capacity: size,
+            success: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// 世代の頭で集団を良い順に並べる
+    fn rank(&mut self, pop: &Population) {
+        self.order = (0..pop.size).collect();
+        self.order.sort_by(|&a, &b| deb_cmp(pop.fit[a], pop.fit[b]));
+    }
+
+    /// 履歴の1つを選び、`f` はコーシー分布（0以下なら引き直し、1で打ち切り）、
+    /// `cr` は正規分布（[0, 1] に丸める）から引く
+    fn params(&self, rng: &mut Rng) -> (f64, f64) {
+        let k = rng.usize(SHADE_MEMORY);
+        let cr = (self.memory_cr[k] + SHADE_SCALE * rng.normal()).clamp(0.0, 1.0);
+        let f = loop {
+            let f =
+                self.memory_f[k] + SHADE_SCALE * (std::f64::consts::PI * (rng.f64() - 0.5)).tan();
+            if f > 0.0 {
+                break f.min(1.0);
+            }
+        };
+        (f, cr)
+    }
+
+    /// current-to-pbest/1 + 二項交叉: `x_i + f (x_pbest - x_i) + f (x_r1 - x_r2)`
+    ///
+    /// pbest は上位 p 割（p は [2/集団, `SHADE_P_MAX`] の一様乱数）から、
+    /// r2 は集団とアーカイブを合わせた中から（i, r1 以外）選ぶ。
+    #[allow(clippy::too_many_arguments)]
+    fn crossover(
+        &self,
+        pop: &Population,
+        rng: &mut Rng,
+        f: f64,
+        cr: f64,
+        i: usize,
+        r1: usize,
+        j_rand: usize,
+        rnd_cr: &[f64],
+        lb: &[f64],
+        ub: &[f64],
+        trial: &mut [f64],
+    ) {
+        let dim = pop.dim;
+        let p_min = 2.0 / pop.size as f64;
+        let p = p_min + rng.f64() * (SHADE_P_MAX - p_min).max(0.0);
+        let top = ((p * pop.size as f64).round() as usize).clamp(1, pop.size);
+        let pbest = self.order[rng.usize(top)];
+        let r2 = loop {
+            let r = rng.usize(pop.size + self.archive.len());
+            if r != i && r != r1 {
+                break r;
+            }
+        };
+        let row = |k: usize| &pop.data[k * dim..(k + 1) * dim];
+        let (x, xp, x1) = (row(i), row(pbest), row(r1));
+        let x2 = match r2.checked_sub(pop.size) {
+            Some(a) => &self.archive[a][..],
+            None => row(r2),
+        };
+        for j in 0..dim {
+            trial[j] = if j == j_rand || rnd_cr[j] < cr {
+                let v = x[j] + f * (xp[j] - x[j]) + f * (x1[j] - x2[j]);
+                v.clamp(lb[j], ub[j])
+            } else {
+                x[j]
+            };
+        }
+    }
+
+    /// 置き換えの前に呼ぶ。厳密に改善した試行の `f` / `cr` と改善量を記録し、
+    /// 置き換えられる個体をアーカイブに入れる
+    fn record(
+        &mut self,
+        pop: &Population,
+        rng: &mut Rng,
+        i: usize,
+        f: f64,
+        cr: f64,
+        trial: (f64, f64),
+    ) {
+        if deb_cmp(trial, pop.fit[i]).is_ge() {
+            return;
+        }
+        let gain = (score_fitness(pop.fit[i]) - score_fitness(trial)).abs();
+        self.success
+            .push((f, cr, if gain.is_finite() { gain } else { 0.0 }));
+        let old = pop.data[i * pop.dim..(i + 1) * pop.dim].to_vec();
+        if self.archive.len() < self.capacity {
+            self.archive.push(old);
+        } else if self.capacity > 0 {
+            let k = rng.usize(self.capacity);
+            self.archive[k] = old;
+        }
+    }
+
+    /// 世代の終わりに、成功した値の改善量による重み付き平均で履歴を1つ書き換える
+    /// （`cr` は算術平均、`f` はLehmer平均）
+    fn update_memory(&mut self) {
+        if self.success.is_empty() {
+            return;
+        }
+        let total: f64 = self.success.iter().map(|s| s.2).sum();
+        let weight = |g: f64| {
+            if total > 0.0 {
+                g / total
+            } else {
+                1.0 / self.success.len() as f64
+            }
+        };
+        let (mut cr, mut f2, mut f1) = (0.0, 0.0, 0.0);
+        for &(f, c, g) in &self.success {
+            let w = weight(g);
+            cr += w * c;
+            f2 += w * f * f;
+            f1 += w * f;
+        }
+        self.memory_cr[self.slot] = cr;
+        if f1 > 0.0 {
+            self.memory_f[self.slot] = f2 / f1;
+        }
+        self.slot = (self.slot + 1) % SHADE_MEMORY;
+        self.success.clear();
+    }
+}
+
+/// 初期点の有限な座標を境界内に丸めて書き込む（NaN の座標は元の値を残す）
+fn overwrite_point(row: &mut [f64], warm: &[f64], lb: &[f64], ub: &[f64]) {
+    for (j, (r, &w)) in row.iter_mut().zip(warm).enumerate() {
+        if w.is_finite() {
+            *r = w.clamp(lb[j], ub[j]);
+        }
+    }
 }
 
 /// PSO群
@@ -640,6 +2768,7 @@ struct Swarm {
     vel: Vec<f64>,
     pbest: Vec<f64>,
     pbest_fit: Vec<f64>,
+    n_particles: usize,
     dim: usize,
 }
 
@@ -650,6 +2779,7 @@ impl Swarm {
             vel: vec![0.0; n_particles * dim],
             pbest: Vec::with_capacity(n_particles * dim),
             pbest_fit: Vec::with_capacity(n_particles),
+            n_particles,
             dim,
         }
     }
@@ -657,7 +2787,7 @@ impl Swarm {
     fn initialize(&mut self, rng: &mut Rng, lb: &[f64], ub: &[f64]) {
         let mut rnd_buf = vec![0.0; self.dim];
 
-        for _ in 0..N_PARTICLES {
+        for _ in 0..self.n_particles {
             rng.fill_f64(&mut rnd_buf);
             for j in 0..self.dim {
                 let p = lb[j] + rnd_buf[j] * (ub[j] - lb[j]);
@@ -669,13 +2799,22 @@ impl Swarm {
         // pbest_fitは呼び出し側で設定する
     }
 
+    /// 先頭の粒子を初期点で置き換える（pbest_fit 設定前に呼ぶ）
+    fn inject(&mut self, warm: &[Vec<f64>], lb: &[f64], ub: &[f64]) {
+        for (i, w) in warm.iter().take(self.n_particles).enumerate() {
+            let range = i * self.dim..(i + 1) * self.dim;
+            overwrite_point(&mut self.pos[range.clone()], w, lb, ub);
+            self.pbest[range.clone()].copy_from_slice(&self.pos[range]);
+        }
+    }
+
     fn find_global_best<F>(&self, mut fitness: F) -> Vec<f64>
     where
         F: FnMut(&[f64]) -> f64,
     {
         let mut best_idx = 0;
         let mut best_fit = f64::MAX;
-        for i in 0..N_PARTICLES {
+        for i in 0..self.n_particles {
             let fit = fitness(&self.pbest[i * self.dim..(i + 1) * self.dim]);
             if fit < best_fit {
                 best_fit = fit;
@@ -685,3 +2824,421 @@ impl Swarm {
         self.pbest[best_idx * self.dim..(best_idx + 1) * self.dim].to_vec()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn non_finite_evaluations_are_counted_per_solve() {
+        // 関数は定義域の外で0を返すので、積のオーバーフローで inf を出す（|x| > 1e154 で x * x = inf）
+        let model = parser::parse("var x >= -1e300, <= 1e300\n\nminimize: x * x\n").unwrap();
+
+        let first = Options::new(20, 1);
+        assert_eq!(first.non_finite_evaluations(), 0);
+        let _ = de(&model, &first);
+        let counted = first.non_finite_evaluations();
+        assert!(counted > 0);
+
+        // 新しいオプションで解き直すと前の回数を引き継がない
+        let second = Options::new(20, 1);
+        assert_eq!(second.non_finite_evaluations(), 0);
+        let _ = pso(&model, &second);
+        assert!(second.non_finite_evaluations() > 0);
+        assert_eq!(first.non_finite_evaluations(), counted);
+    }
+
+    #[test]
+    fn fitness_rounds_integers_by_reduced_index_after_presolve() {
+        // `y` が消去され、縮約後は整数変数 `n` が座標 1 になる
+        let mut model = parser::parse(
+            "var x >= 0, <= 10\nvar y >= -100, <= 100\nvar n int >= 0, <= 10\n\n\
+             minimize: (x - 3)^2 + y^2 + (n - 2.4)^2\n\nsubject to:\n    y - x - 1 == 0\n",
+        )
+        .unwrap();
+        assert!(model.has_integer_vars());
+        crate::presolve::presolve(&mut model);
+        assert_eq!(model.var_names, vec!["x".to_string(), "n".to_string()]);
+
+        let mut rounded = vec![1.4, 2.6];
+        model.round_integers(&mut rounded);
+        assert_eq!(rounded, vec![1.4, 3.0]);
+
+        // 丸め・復元は評価ごとの作業領域で行い、同じ点は何度評価しても同じ値になる
+        let opts = Options::new(1, 1);
+        let expected = model.evaluate_objective(&model.expand_solution(&rounded));
+        assert_eq!(compute_fitness(&model, &opts, &[1.4, 2.6]), expected);
+        assert_eq!(compute_fitness(&model, &opts, &[1.4, 2.6]), expected);
+        assert_eq!(compute_fitness(&model, &opts, &[1.4, 3.0]), expected);
+    }
+
+    #[test]
+    fn patience_stops_de_and_pso_on_a_flat_objective() {
+        // 目的が定数なら初期集団から改善しないので、ちょうど patience 世代で止まる
+        let model =
+            parser::parse("var x >= 0, <= 10\nvar y >= 0, <= 10\n\nminimize: 0 * x + 0 * y - 5\n")
+                .unwrap();
+        let mut opts = Options::new(1000, 1);
+        opts.patience = Some(7);
+        assert_eq!(de(&model, &opts).2, 7);
+        assert_eq!(pso(&model, &opts).2, 7);
+
+        opts.patience = None;
+        assert_eq!(de(&model, &opts).2, 1000);
+    }
+
+    #[test]
+    fn stagnation_counts_improvements_on_negative_objectives() {
+        // 負の目的でも、基準より小さくなれば改善として数え直す
+        let mut s = Stagnation::new(3, -100.0);
+        for k in 1..20 {
+            assert!(!s.stalled(-100.0 - k as f64), "stopped at {}", k);
+        }
+        assert!(!s.stalled(-119.0));
+        assert!(!s.stalled(-119.0));
+        assert!(s.stalled(-119.0));
+
+        // 相対許容差より小さい変化は改善にならない
+        let mut s = Stagnation::new(2, -1e12);
+        assert!(!s.stalled(-1e12 - 1.0));
+        assert!(s.stalled(-1e12 - 2.0));
+    }
+
+    #[test]
+    fn shade_beats_classic_de_on_rastrigin() {
+        let model = parser::parse(
+            "set I = 1..10\nvar x[I] >= -5.12, <= 5.12\n\n\
+             minimize: sum(i in I) (x[i]^2 - 10 * cos(2 * pi * x[i]) + 10)\n",
+        )
+        .unwrap();
+        let run = |strategy: DeStrategy, seed: u64| {
+            let mut opts = Options::new(300, 1);
+            opts.seed = seed;
+            opts.de.strategy = strategy;
+            de(&model, &opts).1
+        };
+        // 同じ種・同じ評価回数で比べる
+        for seed in [1, 42] {
+            let classic = run(DeStrategy::Classic, seed);
+            let shade = run(DeStrategy::Shade, seed);
+            assert!(
+                shade < classic,
+                "seed {}: shade {} vs de {}",
+                seed,
+                shade,
+                classic
+            );
+        }
+    }
+
+    #[test]
+    fn deterministic_de_ignores_the_thread_count() {
+        // 島モデルになる大きさ（PARALLEL_MIN_DIM 次元・PARALLEL_MIN_ITER 反復）で比べる。
+        // 目的を省いた既定の Sphere 関数は式を解釈しないので速い
+        let model = parser::parse(&format!(
+            "set I = 1..{}\nvar x[I] >= -5, <= 5\n",
+            PARALLEL_MIN_DIM
+        ))
+        .unwrap();
+        let run = |threads: usize| {
+            let mut opts = Options::new(PARALLEL_MIN_ITER, threads);
+            opts.deterministic = true;
+            opts.de.pop_size = 4 * DETERMINISTIC_ISLANDS;
+            // 移住はスレッドの進み具合に依存するので、決定的モードでは無視される
+            opts.migration = Some(Migration::new(10));
+            de(&model, &opts)
+        };
+        let single = run(1);
+        assert_eq!(single.2, PARALLEL_MIN_ITER);
+        for threads in [4, 8] {
+            let (best, fit, iters) = run(threads);
+            assert_eq!(best, single.0, "threads {}", threads);
+            assert_eq!(fit.to_bits(), single.1.to_bits(), "threads {}", threads);
+            assert_eq!(iters, single.2);
+        }
+    }
+
+    #[test]
+    fn release_and_deadline_windows_are_penalized() {
+        let model = parser::parse(include_str!("../../examples/release_deadline.optica")).unwrap();
+        let point = |start: [f64; 3]| {
+            let mut x = vec![2.0; model.dim];
+            for (j, s) in start.iter().enumerate() {
+                x[model.var_map[&format!("start[{}]", j + 1)]] = *s;
+            }
+            x
+        };
+        assert_eq!(penalty_time_windows(&model, &point([3.0, 5.0, 1.0])), 0.0);
+        // start[2] が release 4 より 1 早い
+        assert_eq!(penalty_time_windows(&model, &point([5.0, 3.0, 1.0])), 1.0);
+        // start[3] + duration = 4.5 が deadline 3 を 1.5 超える
+        assert_eq!(penalty_time_windows(&model, &point([0.0, 5.0, 2.5])), 1.5);
+        assert_eq!(compute_cp_penalty(&model, &point([3.0, 5.0, 1.0])), 0.0);
+
+        let (x, f, _) = de(&model, &Options::new(300, 1));
+        assert!((f - 9.0).abs() < 1e-3, "{} at {:?}", f, x);
+        assert!(penalty_time_windows(&model, &x) < 1e-6);
+
+        // 終了時刻の変数 `end[j]` があればそちらで締切を見る
+        let model = parser::parse(
+            "set T = {a, b}\nparam deadline[T] = {a: 4, b: 10};\n\
+             var start[T] >= 0, <= 10\nvar end[T] >= 0, <= 10\n\
+             minimize: sum(j in T) end[j]\n\nsubject to:\n    no_overlap(start[j], end[j])\n",
+        )
+        .unwrap();
+        let mut x = vec![0.0; model.dim];
+        x[model.var_map["end[a]"]] = 6.0;
+        x[model.var_map["end[b]"]] = 9.0;
+        assert_eq!(penalty_time_windows(&model, &x), 2.0);
+    }
+
+    #[test]
+    fn evaluate_all_matches_compute_fitness() {
+        let models = [
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nobjectives:\n\
+             \x20   minimize cost: 2 * x + y\n    maximize profit: x * y\n\
+             pareto method: \"weighted_sum\"\n    weight cost: 0.7\n    weight profit: 0.3\n\n\
+             subject to:\n    x + y >= 4\n",
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nobjectives:\n\
+             \x20   minimize cost: 2 * x + y\n    minimize co2: x - y\n\
+             pareto method: \"epsilon_constraint\"\n    primary: cost\n    epsilon:\n\
+             \x20       co2 <= 1\n\nsubject to:\n    x + y >= 4\n",
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nmaximize: x * y\n\nsubject to:\n    x + y <= 4\n",
+        ];
+        let opts = Options::new(1, 1);
+        for src in models {
+            let model = parser::parse(src).unwrap();
+            for x in [[0.0, 0.0], [1.0, 2.0], [3.0, 1.0], [6.0, 5.0]] {
+                let (scalar, raw, violation) = model.evaluate_all(&x);
+                assert_eq!(
+                    compute_fitness(&model, &opts, &x),
+                    scalar + violation,
+                    "{}",
+                    src
+                );
+                if model.objectives.is_empty() {
+                    assert_eq!(raw, vec![model.evaluate_objective(&x)]);
+                } else {
+                    for (o, v) in model.objectives.iter().zip(&raw) {
+                        assert_eq!(*v, model.evaluate_expr(&o.expr, &x, &HashMap::new()));
+                    }
+                }
+            }
+        }
+
+        // 重み付き和: 0.7 * cost - 0.3 * profit。x + y >= 4 を 1 だけ破る
+        let model = parser::parse(models[0]).unwrap();
+        let (scalar, raw, violation) = model.evaluate_all(&[1.0, 2.0]);
+        assert_eq!(raw, vec![4.0, 2.0]);
+        assert!((scalar - (0.7 * 4.0 - 0.3 * 2.0)).abs() < 1e-12);
+        assert_eq!(violation, penalty_coeff());
+        // epsilon: co2 = x - y = 2 が閾値 1 を超える分も違反に入る
+        let model = parser::parse(models[1]).unwrap();
+        let (scalar, _, violation) = model.evaluate_all(&[3.0, 1.0]);
+        assert_eq!(scalar, 7.0);
+        assert_eq!(violation, penalty_coeff());
+    }
+
+    #[test]
+    fn diversity_restarts_are_counted() {
+        // x < 0 は平坦、x >= 0 は多峰。集団が谷に収束すると多様性が崩れてリスタートする
+        let model = parser::parse(
+            "var x >= -5, <= 5\nvar y >= -5, <= 5\n\n\
+             minimize: (if x < 0 then 10 else x^2 - 5 * cos(6 * x) + 5) + y^2\n",
+        )
+        .unwrap();
+        let mut opts = Options::new(400, 1);
+        opts.restart = Some(Restart::new(RESTART_FRACTION, DIVERSITY_THRESHOLD));
+        let (_, f, _) = de(&model, &opts);
+        let at = opts.restart.as_ref().unwrap().iterations();
+        assert!(!at.is_empty());
+        assert!(
+            at.windows(2).all(|w| w[0] <= w[1]) && at[at.len() - 1] < 400,
+            "{:?}",
+            at
+        );
+        assert!(f < 1e-6, "{}", f);
+
+        // 閾値 0 では多様性が下回らないので発動しない
+        opts.restart = Some(Restart::new(RESTART_FRACTION, 0.0));
+        let _ = de(&model, &opts);
+        assert!(opts.restart.as_ref().unwrap().iterations().is_empty());
+    }
+
+    #[test]
+    fn warm_rows_are_clamped_into_the_initial_population() {
+        // 最適解 (10, 3) は境界上。範囲外の行は境界へ切り詰めて入れる
+        let model = parser::parse(
+            "var x >= 0, <= 10\nvar y >= 0, <= 10\n\nminimize: (x - 10)^2 + (y - 3)^2\n",
+        )
+        .unwrap();
+        let mut opts = Options::new(1, 1);
+        opts.warm = vec![vec![1.0, 1.0], vec![25.0, 3.0], vec![f64::NAN, 8.0]];
+        assert_eq!(de(&model, &opts).1, 0.0);
+        assert_eq!(pso(&model, &opts).1, 0.0);
+    }
+
+    #[test]
+    fn constraint_names_appear_in_the_penalty_report() {
+        let model = parser::parse(
+            "set ITEMS = {A, B}\nparam cap[ITEMS] = {A: 1, B: 5};\nvar x[ITEMS] >= 0, <= 10\n\n\
+             minimize: sum(i in ITEMS) x[i]\n\nsubject to:\n\
+             \x20   budget: x[A] + x[B] <= 4\n\
+             \x20   cap: forall i in ITEMS: x[i] <= cap[i]\n\
+             \x20   bounds:\n        x[A] >= 2\n        x[B] >= 1\n\
+             \x20   single:\n        x[A] + x[B] >= 1\n\
+             \x20   x[B] - x[A] <= 3\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = model.constraints.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "budget",
+                "cap[A]",
+                "cap[B]",
+                "bounds[1]",
+                "bounds[2]",
+                "single",
+                "c6"
+            ]
+        );
+
+        // x[A] = 3 は budget を満たし cap[A] を 2 だけ破る。x[B] = 0 は bounds[2] を 1 だけ破る
+        let rows = penalty_breakdown(&model, &[3.0, 0.0]);
+        let violated: Vec<&str> = rows
+            .iter()
+            .filter(|(_, p)| *p > 0.0)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(violated, ["cap[A]", "bounds[2]"]);
+        assert_eq!(rows.len(), names.len());
+    }
+
+    #[test]
+    fn cached_and_uncached_fitness_match() {
+        // 整数変数は丸めた値でキーを作るので、同じ点の再評価が多い
+        let model = parser::parse(
+            "set I = 1..4\nvar n[I] int >= 0, <= 5\nvar y >= -2, <= 2\n\n\
+             minimize: sum(i in I) (n[i] - i)^2 + (y - 0.5)^2\n",
+        )
+        .unwrap();
+        let plain = Options::new(200, 1);
+        let mut cached = plain.clone();
+        cached.fitness_cache = Some(FitnessCache::new(
+            FITNESS_CACHE_CAPACITY,
+            FITNESS_CACHE_QUANTUM,
+        ));
+        assert_eq!(de(&model, &plain), de(&model, &cached));
+        let (hits, lookups) = cached.fitness_cache.as_ref().unwrap().stats();
+        assert!(hits > 0 && hits < lookups, "{} / {}", hits, lookups);
+        assert_eq!(pso(&model, &plain), pso(&model, &cached));
+    }
+
+    #[test]
+    fn fitness_cache_evicts_the_least_recently_used_point() {
+        let model = parser::parse("var x >= 0, <= 10\n\nminimize: x\n").unwrap();
+        let cache = FitnessCache::new(2, 1e-9);
+        let evals = std::cell::Cell::new(0);
+        let get = |x: f64| {
+            cache.get_or_eval(&model, &[x], || {
+                evals.set(evals.get() + 1);
+                x
+            })
+        };
+        assert_eq!(get(1.0), 1.0);
+        assert_eq!(get(2.0), 2.0);
+        assert_eq!(get(1.0), 1.0); // ヒット: 1 が最近使った側になる
+        assert_eq!(evals.get(), 2);
+        assert_eq!(get(3.0), 3.0); // 2 を追い出す
+        assert_eq!(get(1.0), 1.0);
+        assert_eq!(evals.get(), 3);
+        assert_eq!(get(2.0), 2.0);
+        assert_eq!(evals.get(), 4);
+        // 量子化の幅より近い点は同じキーになる
+        assert_eq!(get(2.0 + 1e-12), 2.0);
+        assert_eq!(cache.stats(), (3, 7));
+    }
+
+    #[test]
+    fn hybrid_phases_use_the_whole_budget() {
+        for max_iter in [0, 1, 2, 3, 99, 100, 1001] {
+            for split in [0.0, 0.3, 0.5, 0.7, 1.0] {
+                let (explore, refine) = hybrid_phases(max_iter, split);
+                assert_eq!(explore + refine, max_iter, "{} x {}", max_iter, split);
+                if max_iter >= 2 {
+                    assert!(explore >= 1 && refine >= 1, "{} x {}", max_iter, split);
+                }
+            }
+        }
+        assert_eq!(hybrid_phases(1001, 0.5), (501, 500));
+        assert_eq!(hybrid_phases(100, 0.7), (70, 30));
+
+        // 整数変数のモデルは後段が PSO。目的が0にならないので早期終了せず、予算を使い切る
+        let model = parser::parse(
+            "var n int >= 0, <= 10\nvar y >= 1, <= 5\n\nminimize: (n - 3)^2 + y + 5\n",
+        )
+        .unwrap();
+        let mut opts = Options::new(101, 1);
+        opts.hybrid_split = 0.7;
+        opts.trace = Some(Trace::default());
+        let (_, f, iters) = hybrid(&model, &opts);
+        assert_eq!(iters, 101);
+        assert_eq!(opts.trace.as_ref().unwrap().values().len(), 101);
+        assert!((f - 6.0).abs() < 1e-6, "{}", f);
+    }
+
+    #[test]
+    fn least_squares_example_finds_the_fitted_line() {
+        let model = parser::parse(include_str!("../../examples/least_squares.optica")).unwrap();
+        // 正規方程式の解 a = 1.02, b = 1.94 で残差ノルムが最小
+        let fitted = [1.02, 1.94];
+        let norm = model.evaluate_objective(&fitted);
+        assert!((norm - 0.2098).abs() < 1e-4, "{}", norm);
+        // sqrt(sum(...)) の解析的な勾配は最適解で0
+        let g = model.expr_gradient(model.objective_expr.as_deref().unwrap(), &fitted);
+        assert!(g.iter().all(|v| v.abs() < 1e-9), "{:?}", g);
+
+        let mut opts = Options::new(300, 1);
+        opts.seed = 7;
+        type Solve = fn(&Model, &Options) -> (Vec<f64>, f64, usize);
+        for (name, solve) in [("de", de as Solve), ("pso", pso), ("hybrid", hybrid)] {
+            let (x, f, _) = solve(&model, &opts);
+            assert!((f - norm).abs() < 1e-3, "{}: {}", name, f);
+            assert!(
+                (x[0] - 1.02).abs() < 0.05 && (x[1] - 1.94).abs() < 0.02,
+                "{}: {:?}",
+                name,
+                x
+            );
+        }
+    }
+
+    #[test]
+    fn coordinate_descent_beats_de_on_a_separable_quadratic() {
+        let model = parser::parse(
+            "set I = 1..10\nparam c[I] = {1: -7, 2: -5, 3: -3, 4: -1, 5: 0, 6: 1, 7: 2.5, 8: 4, 9: 6, 10: 8};\n\
+             var x[I] >= -10, <= 10\n\nminimize: sum(i in I) (x[i] - c[i])^2\n",
+        )
+        .unwrap();
+        assert!(model.is_separable());
+        // 同じ反復の予算で、適合度の評価回数と到達値を比べる
+        let run = |solve: fn(&Model, &Options) -> (Vec<f64>, f64, usize)| {
+            let mut opts = Options::new(50, 1);
+            opts.seed = 3;
+            opts.fitness_cache = Some(FitnessCache::new(FITNESS_CACHE_CAPACITY, 1e-12));
+            let (x, f, iters) = solve(&model, &opts);
+            let (_, evals) = opts.fitness_cache.as_ref().unwrap().stats();
+            (x, f, iters, evals)
+        };
+        let (x, f, sweeps, evals) = run(coordinate_descent);
+        let (_, de_f, _, de_evals) = run(de);
+        assert!(f < 1e-8, "{}", f);
+        assert!((x[6] - 2.5).abs() < 1e-4, "{:?}", x);
+        // 分離可能なので数スイープで収束し、評価回数も DE より少ない
+        assert!(sweeps <= 3, "{}", sweeps);
+        assert!(evals < de_evals, "{} vs {}", evals, de_evals);
+        assert!(de_f > 1e3 * f.max(1e-12), "{} vs {}", de_f, f);
+    }
+}
@@ -9,6 +9,8 @@ pub struct Rng {
 impl Rng {
     #[inline(always)]
     pub fn new(seed: u64) -> Self {
+        // 状態が全て0だと0しか出ないので、種0は別の定数に置き換える
+        let seed = if seed == 0 { 0x2545F4914F6CDD1D } else { seed };
         Self {
             s0: seed,
             s1: seed.wrapping_mul(0x9E3779B97F4A7C15),
@@ -36,6 +38,14 @@ impl Rng {
         ((self.next_u64() as u128 * max as u128) >> 64) as usize
     }
 
+    /// 標準正規分布（Box-Muller）
+    #[inline(always)]
+    pub fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.f64(); // (0, 1]
+        let u2 = self.f64();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+
     #[inline(always)]
     pub fn fill_f64(&mut self, out: &mut [f64]) {
         for x in out {